@@ -0,0 +1,4 @@
+start:  CLR A
+loop:   INV A A
+        MOVAT [0xFF00] A
+        JMPIMM #loop
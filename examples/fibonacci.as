@@ -0,0 +1,8 @@
+start:  MOV A 1
+        MOV B 1
+loop:   MOVAT [0x8000] B
+        MOV TH B
+        ADD B
+        MOV A TH
+        JMPIMMNC #loop
+        HALT
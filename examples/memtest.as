@@ -0,0 +1,16 @@
+start:  MOV A 0xAA
+        MOVAT [0x8000] A
+        MOV B [0x8000]
+        CMP A B
+        JMPIMMNZ #fail
+        INV A A
+        MOVAT [0x8000] A
+        MOV B [0x8000]
+        CMP A B
+        JMPIMMNZ #fail
+pass:   MOV A 1
+        MOVAT [0xFF00] A
+        HALT
+fail:   MOV A 0xFF
+        MOVAT [0xFF00] A
+        HALT
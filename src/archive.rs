@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::object::{Object, OBJECT_VERSION};
+
+/// Objects bundled into a library, which the linker takes members from only
+/// when they're needed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Member {
+    /// File name of the object it was created from.
+    pub name: String,
+    pub object: Object,
+}
+
+/// A file given to the linker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Object(Object),
+    Archive(Archive),
+}
+
+/// Bundles objects, each with its file name, into an archive.
+pub fn build_archive(objects: Vec<(String, Object)>) -> Archive {
+    Archive {
+        version: OBJECT_VERSION,
+        members: objects
+            .into_iter()
+            .map(|(name, object)| Member { name, object })
+            .collect(),
+    }
+}
+
+fn exports(object: &Object) -> impl Iterator<Item = &str> {
+    object
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.exported)
+        .map(|symbol| symbol.name.as_str())
+}
+
+/// The objects to link, each with the name it's reported under: every
+/// object given on its own, followed by the archive members that export a
+/// symbol one of the others imports and nothing else exports. Members are
+/// pulled in until nothing more is missing, whatever order the archives
+/// come in, and are named `archive(member)`.
+pub fn select_objects(inputs: Vec<(String, Input)>) -> Vec<(String, Object)> {
+    let mut objects = vec![];
+    let mut archives = vec![];
+    for (name, input) in inputs {
+        match input {
+            Input::Object(object) => objects.push((name, object)),
+            Input::Archive(archive) => archives.push((name, archive)),
+        }
+    }
+
+    let mut defined = HashSet::new();
+    let mut imported = HashSet::new();
+    for (_, object) in &objects {
+        defined.extend(exports(object));
+        imported.extend(object.imports.iter().map(String::as_str));
+    }
+    let mut pulled = archives
+        .iter()
+        .map(|(_, archive)| vec![false; archive.members.len()])
+        .collect::<Vec<_>>();
+    'pull: loop {
+        for (i, (_, archive)) in archives.iter().enumerate() {
+            for (j, member) in archive.members.iter().enumerate() {
+                let needed = exports(&member.object)
+                    .any(|name| imported.contains(name) && !defined.contains(name));
+                if !pulled[i][j] && needed {
+                    pulled[i][j] = true;
+                    defined.extend(exports(&member.object));
+                    imported.extend(member.object.imports.iter().map(String::as_str));
+                    continue 'pull;
+                }
+            }
+        }
+        break;
+    }
+
+    for ((name, archive), pulled) in archives.into_iter().zip(pulled) {
+        objects.extend(
+            archive
+                .members
+                .into_iter()
+                .zip(pulled)
+                .filter(|(_, pulled)| *pulled)
+                .map(|(member, _)| (format!("{}({})", name, member.name), member.object)),
+        );
+    }
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{ObjectSymbol, Section};
+
+    fn object(exports: &[&str], imports: &[&str]) -> Object {
+        Object {
+            version: OBJECT_VERSION,
+            source: String::new(),
+            sections: vec![Section {
+                name: "code".to_string(),
+                data: vec![0; exports.len()],
+            }],
+            symbols: exports
+                .iter()
+                .enumerate()
+                .map(|(offset, name)| ObjectSymbol {
+                    name: name.to_string(),
+                    section: 0,
+                    offset,
+                    exported: true,
+                })
+                .collect(),
+            imports: imports.iter().map(|name| name.to_string()).collect(),
+            relocations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_select_objects() {
+        // puts needs putc, and nothing needs the unused routine.
+        let archive = build_archive(vec![
+            ("puts.obj".to_string(), object(&["puts"], &["putc"])),
+            ("unused.obj".to_string(), object(&["unused"], &[])),
+            ("putc.obj".to_string(), object(&["putc"], &[])),
+        ]);
+        let inputs = vec![
+            ("lib.a".to_string(), Input::Archive(archive)),
+            (
+                "main.obj".to_string(),
+                Input::Object(object(&[], &["puts"])),
+            ),
+        ];
+
+        let names = select_objects(inputs)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["main.obj", "lib.a(puts.obj)", "lib.a(putc.obj)"]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Compiles a config into Rust source for a static [`FormTable`], so a
+//! crate that only encodes, e.g. `no_std` firmware, gets its opcodes without
+//! parsing JSON at run time. From its `build.rs`:
+//!
+//! ```no_run
+//! assembler::build::compile_config("config.json", "OPCODES").unwrap();
+//! ```
+//!
+//! and in the crate, which needs this one with the `tables` feature:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
+//!
+//! encode_instruction(&OPCODES, &mnemonic, &operands, 16, &mut output)?;
+//! ```
+//!
+//! [`FormTable`]: crate::encoder::FormTable
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Config, ConfigError},
+    encoder::binary_str_to_byte,
+    specs::Operand,
+};
+
+fn operand_path(operand: &Operand) -> String {
+    match operand {
+        Operand::Register(register) => format!(
+            "::assembler::specs::Operand::Register(::assembler::specs::Register::{:?})",
+            register
+        ),
+        _ => format!("::assembler::specs::Operand::{:?}", operand),
+    }
+}
+
+/// Rust source declaring `name` as a static `FormTable` holding every
+/// instruction form of the config `content`.
+pub fn opcode_table(name: &str, content: &str) -> Result<String, ConfigError> {
+    let config = Config::from_json(content)?;
+    let mut forms = BTreeMap::<String, Vec<String>>::new();
+    for signature in config.signatures() {
+        let operands = signature
+            .operands
+            .iter()
+            .map(operand_path)
+            .collect::<Vec<_>>();
+        forms
+            .entry(signature.mnemonic.to_string())
+            .or_default()
+            .push(format!(
+                "        ::assembler::encoder::InstructionForm {{ mnemonic: {:?}, operands: &[{}], opcode: {:#04x} }},\n",
+                signature.mnemonic.name(),
+                operands.join(", "),
+                binary_str_to_byte(&signature.opcode)
+            ));
+    }
+
+    let mut source = format!(
+        "// Generated from a config by assembler::build, don't edit.\n\
+         pub static {}: ::assembler::encoder::FormTable = {{\n\
+         use ::assembler::phf;\n\
+         phf::phf_map! {{\n",
+        name
+    );
+    for (mnemonic, mut rows) in forms {
+        rows.sort();
+        source += &format!("    {:?} => &[\n{}    ],\n", mnemonic, rows.concat());
+    }
+    source += "}\n};\n";
+    Ok(source)
+}
+
+/// Writes the table of the config at `path` to `$OUT_DIR/<name>.rs`, in
+/// lowercase, and has Cargo rerun the build script when the config changes.
+/// Returns the path written.
+pub fn compile_config(path: impl AsRef<Path>, name: &str) -> Result<PathBuf, ConfigError> {
+    let path = path.as_ref();
+    println!("cargo:rerun-if-changed={}", path.display());
+    let content = fs::read_to_string(path).map_err(ConfigError::ReadFileError)?;
+    let table = opcode_table(name, &content)?;
+
+    let out_dir = env::var_os("OUT_DIR").unwrap_or_else(|| ".".into());
+    let output = Path::new(&out_dir).join(format!("{}.rs", name.to_lowercase()));
+    fs::write(&output, table).map_err(ConfigError::ReadFileError)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::DEFAULT_CONFIG,
+        encoder::{encode_instruction, FormTable, InstructionForm},
+        specs::{Mnemonic, Register},
+    };
+
+    #[test]
+    fn test_opcode_table() {
+        let table = opcode_table("OPCODES", DEFAULT_CONFIG).unwrap();
+        assert!(table.contains("pub static OPCODES: ::assembler::encoder::FormTable"));
+        assert!(table.contains(
+            "    \"HALT\" => &[\n        ::assembler::encoder::InstructionForm \
+             { mnemonic: \"HALT\", operands: &[], opcode: 0xd8 },\n    ],\n"
+        ));
+        assert!(opcode_table("OPCODES", "{").is_err());
+
+        // What the generated source expands to.
+        static OPCODES: FormTable = phf::phf_map! {
+            "MOV" => &[InstructionForm {
+                mnemonic: "MOV",
+                operands: &[Operand::Register(Register::A), Operand::Const],
+                opcode: 0x12,
+            }],
+        };
+        let mut output = vec![];
+        let mov = Mnemonic::new("MOV".to_string());
+        let operands = [(Operand::Register(Register::A), 0), (Operand::Const, 7)];
+        encode_instruction(&OPCODES, &mov, &operands, 16, &mut output).unwrap();
+        assert_eq!(output, [0x12, 7]);
+    }
+}
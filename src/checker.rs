@@ -1,14 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::mpsc};
 
+use rayon::prelude::*;
 use thiserror::Error;
 
 use crate::{
-    config::{Config, ConfigNode, NodeType},
+    config::{Config, InstructionAlignment},
+    encoder::{encode_byte, encode_operands, encode_word, EncodeErr, OpcodeTable},
+    error::{Error, WithSpan},
+    expr::Expr,
+    i18n::Message,
+    lexer::parse_number,
     parser::Line,
-    specs::Operand,
-    token::{Token, TokenType},
+    specs::{Mnemonic, Operand},
+    token::{Span, Token, TokenType},
 };
 
+// Below this, handing lines to another thread costs more than checking them.
+const MIN_LINES_PER_THREAD: usize = 4096;
+
 #[derive(PartialEq, Eq, Debug, Error)]
 pub enum WriterErr {
     #[error("Unknown mnemonic '{0}'.")]
@@ -17,17 +26,89 @@ pub enum WriterErr {
     InvalidOperand(String),
     #[error("Number should be in range [-128, 255], instead found {0}.")]
     NumberOutOfRange(i64),
+    #[error("Number should be in range [-32768, 65535], instead found {0}.")]
+    WordOutOfRange(i64),
     #[error("Unknown label '{0}'.")]
     UnknownLabel(String),
+    #[error("Instruction '{0}' is missing operands.")]
+    IncompleteInstruction(String),
+    #[error("Division by zero in '{0}'.")]
+    DivisionByZero(String),
+    #[error("Constant '{0}' is already defined.")]
+    SymbolRedefined(String),
+    #[error("'.org {0:#06x}' would move backwards from address {1:#06x}.")]
+    OrgBackwards(usize, usize),
+    #[error("Alignment should be a positive number, instead found {0}.")]
+    InvalidAlignment(i64),
+    #[error("'{0}' needs 16-bit memory, which the target doesn't have.")]
+    NoWideMemory(String),
+    #[error("'{0}' needs a stack, which the target doesn't have.")]
+    NoStack(String),
+    #[error("Address {0:#06x} is outside the target's {1}-bit address space.")]
+    AddressOutOfRange(i64, u32),
+    #[error("Instruction at {0:#06x} should start at a multiple of {1}.")]
+    MisalignedInstruction(usize, usize),
+    #[error("'{0}' isn't in the config, {1} placeholder byte(s) stand in for it.")]
+    PlaceholderInstruction(String, usize),
+    #[error("Invalid placeholder byte '{0}'.")]
+    InvalidPlaceholder(String),
+    #[error("Literal {0:#x} is written {1} bits wide, but its operand only holds {2}.")]
+    WideLiteral(i64, u32, u32),
+}
+
+pub fn parse_placeholder(arg: &str) -> Result<u8, WriterErr> {
+    parse_number(arg.trim())
+        .and_then(|byte| u8::try_from(byte).ok())
+        .ok_or_else(|| WriterErr::InvalidPlaceholder(arg.to_string()))
+}
+
+/// Help for errors about something the config's capabilities rule out.
+fn capability_help(capability: &str) -> Message {
+    Message::new("help.capability", vec![capability.to_string()])
 }
 
 #[derive(Debug)]
 pub enum CheckedLineCode {
+    /// Lines like constant definitions that emit nothing.
+    Empty,
     Byte(Vec<u8>),
     Instruction {
         mnemonic_code: u8,
         operand_codes: Vec<u8>,
+        /// Where the config lets the instruction start.
+        alignment: InstructionAlignment,
     },
+    /// Continue at this address, padding with zeros.
+    Org(usize),
+    /// Pad with zeros up to a multiple of this.
+    Align(usize),
+}
+
+impl CheckedLineCode {
+    pub fn bytes(&self) -> Vec<u8> {
+        match self {
+            CheckedLineCode::Empty | CheckedLineCode::Org(_) | CheckedLineCode::Align(_) => {
+                vec![]
+            }
+            CheckedLineCode::Byte(bytes) => bytes.clone(),
+            CheckedLineCode::Instruction {
+                mnemonic_code,
+                operand_codes,
+                ..
+            } => std::iter::once(*mnemonic_code)
+                .chain(operand_codes.iter().copied())
+                .collect(),
+        }
+    }
+
+    /// The address after the line, when it starts at `address`.
+    pub fn end(&self, address: usize) -> usize {
+        match self {
+            CheckedLineCode::Org(target) => *target,
+            CheckedLineCode::Align(alignment) => address.next_multiple_of(*alignment),
+            _ => address + self.bytes().len(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -36,166 +117,699 @@ pub struct CheckedLine<'a> {
     pub code: CheckedLineCode,
 }
 
-fn check_instruction<'a>(
-    config: &'a Config,
-    labels: &'a HashMap<&'a str, usize>,
-    mnemonic: &Token,
-    operands: &Vec<(Operand, &Token)>,
-) -> Result<CheckedLineCode, WriterErr> {
-    let mnemonic = match &mnemonic.token_type {
+/// Remembers the automaton walk for every (mnemonic, operand kinds) form, so
+/// programs repeating the same instructions only pay for a hash lookup.
+#[derive(Debug, Default)]
+pub struct EncodingCache {
+    // Keyed by mnemonic, then operands, so hits need no owned key.
+    opcodes: HashMap<Mnemonic, HashMap<Vec<Operand>, Result<u8, EncodeErr>>>,
+}
+
+impl EncodingCache {
+    fn opcode(
+        &mut self,
+        config: &Config,
+        mnemonic: &Mnemonic,
+        operands: &[Operand],
+    ) -> Result<u8, EncodeErr> {
+        if let Some(opcode) = self
+            .opcodes
+            .get(mnemonic)
+            .and_then(|forms| forms.get(operands))
+        {
+            return *opcode;
+        }
+        let opcode = config.opcode(mnemonic, operands);
+        self.opcodes
+            .entry(mnemonic.clone())
+            .or_default()
+            .insert(operands.to_vec(), opcode);
+        opcode
+    }
+}
+
+fn check_instruction(
+    config: &Config,
+    labels: &HashMap<&str, usize>,
+    cache: &mut EncodingCache,
+    mnemonic_token: &Token,
+    operands: &[(Operand, Expr)],
+) -> Result<CheckedLineCode, Error> {
+    let span = mnemonic_token.span.clone();
+    let mnemonic = match &mnemonic_token.token_type {
         TokenType::Mnemonic(mnemonic) => mnemonic,
-        _ => return Err(WriterErr::UnknownMnemonic(mnemonic.content.clone())),
+        _ => return Err(WriterErr::UnknownMnemonic(mnemonic_token.content.clone()).with_span(span)),
     };
 
-    let mut current_node = config
-        .automaton
-        .get(&NodeType::Mnemonic(mnemonic.clone()))
-        .unwrap();
-
-    let mut operand_binary_codes = vec![];
-
-    for operand in operands {
-        match operand.0 {
-            Operand::Mem8 | Operand::Const => {
-                let parsed_operand = parse_value(labels, operand.1)?;
-                operand_binary_codes.push(parsed_operand);
-            },
-            Operand::Mem16 => {
-                let parsed_operand = parse_wide_value(labels, operand.1)?;
-                let [higher, lower] = parsed_operand.to_le_bytes();
-                operand_binary_codes.push(higher);
-                operand_binary_codes.push(lower);
-            },
-            _ => {}
+    let kinds: Vec<_> = operands.iter().map(|(operand, _)| *operand).collect();
+    let opcode = cache.opcode(config, mnemonic, &kinds);
+    if let (Some(fill), Err(_)) = (config.unknown_instructions, opcode) {
+        let size = config.instruction_size(mnemonic, &kinds).unwrap_or(1);
+        return Ok(CheckedLineCode::Byte(vec![fill; size]));
+    }
+    let mnemonic_code = opcode.map_err(|err| match err {
+        EncodeErr::InvalidOperand(i) => {
+            let operand = &operands[i].1;
+            WriterErr::InvalidOperand(operand.to_string()).with_span(operand.span())
         }
-        match current_node {
-            crate::config::ConfigNode::Branch(children) => {
-                match children.get(&NodeType::Operand(operand.0)) {
-                    Some(next) => current_node = next,
-                    None => return Err(WriterErr::InvalidOperand(operand.1.content.clone())),
-                }
-            }
-            _ => {
-                unreachable!();
+        EncodeErr::Incomplete => {
+            WriterErr::IncompleteInstruction(mnemonic.to_string()).with_span(span.clone())
+        }
+        _ => WriterErr::UnknownMnemonic(mnemonic.to_string()).with_span(span.clone()),
+    })?;
+
+    let capabilities = &config.capabilities;
+    if !capabilities.has_stack && config.stack_opcodes.contains(&mnemonic_code) {
+        return Err(WriterErr::NoStack(mnemonic.to_string())
+            .with_span(span)
+            .with_help(capability_help("has-stack")));
+    }
+    for (operand, value) in operands {
+        let (err, capability) = match operand {
+            Operand::Mem16 if !capabilities.has_wide_memory => (
+                WriterErr::NoWideMemory(value.to_string()),
+                "has-wide-memory",
+            ),
+            Operand::Stc if !capabilities.has_stack => {
+                (WriterErr::NoStack(value.to_string()), "has-stack")
             }
+            _ => continue,
         };
+        return Err(err
+            .with_span(value.span())
+            .with_help(capability_help(capability)));
     }
 
-    let ConfigNode::Branch(leaf) = current_node else {
-        unreachable!();
-    };
+    let mut values = vec![];
+    for (operand, value) in operands {
+        let value = match operand {
+            Operand::Mem8 | Operand::Const | Operand::Mem16 => value.evaluate(labels)?,
+            Operand::Register(_) | Operand::Stc => 0,
+        };
+        values.push((*operand, value));
+    }
+    let mut operand_codes = vec![];
+    let width = capabilities.address_width;
+    encode_operands(&values, width, &mut operand_codes).map_err(|err| {
+        // Point at the operand that didn't fit, or the whole instruction.
+        let culprit = match err {
+            EncodeErr::OutOfRange(number) | EncodeErr::AddressOutOfRange(number) => {
+                values.iter().position(|(_, value)| *value == number)
+            }
+            _ => None,
+        };
+        let span = match culprit {
+            Some(i) => operands[i].1.span(),
+            None => span.clone(),
+        };
+        let err = match err {
+            EncodeErr::AddressOutOfRange(address) => WriterErr::AddressOutOfRange(address, width),
+            err => out_of_range(err),
+        };
+        err.with_span(span)
+    })?;
 
-    let Some(leaf) = leaf.get(&NodeType::MachineCode) else {
-        unreachable!();
-    };
+    Ok(CheckedLineCode::Instruction {
+        mnemonic_code,
+        operand_codes,
+        alignment: config.instruction_alignment(),
+    })
+}
 
-    match leaf {
-        ConfigNode::Leaf(mnemonic_code) => Ok(CheckedLineCode::Instruction {
-            mnemonic_code: binary_str_to_byte(mnemonic_code),
-            operand_codes: operand_binary_codes,
-        }),
+fn out_of_range(err: EncodeErr) -> WriterErr {
+    match err {
+        EncodeErr::OutOfRange(number) => WriterErr::NumberOutOfRange(number),
         _ => unreachable!(),
     }
 }
 
-// TODO: Check whether keeping the mnemonic_code as String is better than keeping it as u8
-//       (in terms of performance).
-fn binary_str_to_byte(binary_str: &str) -> u8 {
-    let mut byte = 0;
-    for (i, c) in binary_str.chars().rev().enumerate() {
-        if c == '1' {
-            byte |= 1 << i;
+fn parse_num(number: i64, span: &Span) -> Result<u8, Error> {
+    encode_byte(number).map_err(|err| out_of_range(err).with_span(span.clone()))
+}
+
+fn parse_wide_num(number: i64, span: &Span) -> Result<u16, Error> {
+    encode_word(number).map_err(|_| WriterErr::WordOutOfRange(number).with_span(span.clone()))
+}
+
+fn check_byte(
+    labels: &HashMap<&str, usize>,
+    declared_values: &Vec<Expr>,
+) -> Result<CheckedLineCode, Error> {
+    let mut parsed_values = vec![];
+    for value in declared_values {
+        match value.as_token().map(|token| &token.token_type) {
+            // Every character becomes a single byte, so it must be in Latin-1.
+            Some(TokenType::String(string)) => {
+                for character in string.chars() {
+                    parsed_values.push(parse_num(character as i64, &value.span())?);
+                }
+            }
+            _ => parsed_values.push(parse_num(value.evaluate(labels)?, &value.span())?),
+        }
+    }
+    Ok(CheckedLineCode::Byte(parsed_values))
+}
+
+fn check_word(
+    labels: &HashMap<&str, usize>,
+    values: &[Expr],
+    config: &Config,
+) -> Result<CheckedLineCode, Error> {
+    if let (false, Some(first)) = (config.capabilities.has_wide_memory, values.first()) {
+        let span = first.first_token().span.clone();
+        return Err(WriterErr::NoWideMemory(".word".to_string())
+            .with_span(span)
+            .with_help(capability_help("has-wide-memory")));
+    }
+    let mut bytes = vec![];
+    for value in values {
+        bytes.extend(parse_wide_num(value.evaluate(labels)?, &value.span())?.to_le_bytes());
+    }
+    Ok(CheckedLineCode::Byte(bytes))
+}
+
+fn check_string(strings: &[&Token], zero_terminated: bool) -> Result<CheckedLineCode, Error> {
+    let mut bytes = vec![];
+    for token in strings {
+        let TokenType::String(string) = &token.token_type else {
+            unreachable!();
+        };
+        for character in string.chars() {
+            bytes.push(parse_num(character as i64, &token.span)?);
+        }
+        if zero_terminated {
+            bytes.push(0);
         }
     }
-    byte
+    Ok(CheckedLineCode::Byte(bytes))
 }
 
-fn parse_num(number: i64) -> Result<u8, WriterErr> {
-    if !(-128..=255).contains(&number) {
-        return Err(WriterErr::NumberOutOfRange(number));
+/// Evaluates `.equ` constants in source order and adds them to the symbol
+/// table, so they can use labels and the constants defined before them.
+pub fn define_constants<'a>(
+    lines: &[Line<'a>],
+    symbols: &mut HashMap<&'a str, usize>,
+) -> Result<(), Vec<Error>> {
+    let mut errors = vec![];
+    for line in lines {
+        let Line::Constant { name: token, value } = line else {
+            continue;
+        };
+        let name = match &token.token_type {
+            TokenType::LabelRef(name) => name.as_str(),
+            _ => token.content.as_str(),
+        };
+        if symbols.contains_key(name) {
+            errors.push(WriterErr::SymbolRedefined(name.to_string()).with_span(token.span.clone()));
+            continue;
+        }
+        match value.evaluate(symbols) {
+            Ok(value) => {
+                symbols.insert(name, value as usize);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
     }
+    Err(errors)
+}
 
-    Ok(number as u8)
+fn check_line<'a>(
+    line: Line<'a>,
+    labels: &HashMap<&str, usize>,
+    config: &Config,
+    cache: &mut EncodingCache,
+) -> Result<CheckedLine<'a>, Error> {
+    let code = match &line {
+        Line::Byte(declared_values) => check_byte(labels, declared_values),
+        Line::Instruction { mnemonic, operands } => {
+            check_instruction(config, labels, cache, mnemonic, operands)
+        }
+        Line::Constant { .. }
+        | Line::Segment(_)
+        | Line::Barrier(_)
+        | Line::Export(_)
+        | Line::Import(_) => Ok(CheckedLineCode::Empty),
+        Line::Org(address) => {
+            let span = address.span();
+            let address = address.evaluate(labels)?;
+            let width = config.capabilities.address_width;
+            match usize::try_from(address) {
+                Ok(address) if address < config.capabilities.address_limit() => {
+                    Ok(CheckedLineCode::Org(address))
+                }
+                _ => Err(WriterErr::AddressOutOfRange(address, width).with_span(span)),
+            }
+        }
+        Line::Entry(expr) => expr.evaluate(labels).map(|_| CheckedLineCode::Empty),
+        Line::Align(expr) => match expr.evaluate(labels)? {
+            alignment if alignment > 0 => Ok(CheckedLineCode::Align(alignment as usize)),
+            alignment => Err(WriterErr::InvalidAlignment(alignment).with_span(expr.span())),
+        },
+        Line::Word(values) => check_word(labels, values, config),
+        Line::Str {
+            strings,
+            zero_terminated,
+        } => check_string(strings, *zero_terminated),
+    }?;
+    Ok(CheckedLine { line, code })
 }
 
-fn parse_labelref<'a>(
+pub fn check_semantics<'a>(
+    lines: impl IntoIterator<Item = Line<'a>>,
     labels: &'a HashMap<&'a str, usize>,
-    label: &str,
-) -> Result<u8, WriterErr> {
-    let label = labels
-        .get(label)
-        .ok_or(WriterErr::UnknownLabel(label.to_string()))?;
-    Ok(*label as u8)
+    config: &'a Config,
+) -> Result<Vec<CheckedLine<'a>>, Error> {
+    let mut cache = EncodingCache::default();
+    lines
+        .into_iter()
+        .map(|line| check_line(line, labels, config, &mut cache))
+        .collect()
 }
 
-fn parse_value<'a>(
-    labels: &'a HashMap<&'a str, usize>,
-    value: &Token,
-) -> Result<u8, WriterErr> {
-    match &value.token_type {
-        TokenType::Number(number) => parse_num(*number),
-        TokenType::LabelRef(label_ref) => parse_labelref(labels, label_ref),
-        _ => unreachable!(),
-    }
+/// Checks every line, reporting all errors rather than the first. Lines are
+/// independent once labels are resolved, so big programs are split across
+/// the threads of rayon's global pool.
+pub fn check_semantics_parallel<'a>(
+    lines: Vec<Line<'a>>,
+    labels: &HashMap<&str, usize>,
+    config: &Config,
+) -> Result<Vec<CheckedLine<'a>>, Vec<Error>> {
+    check_in_threads(lines, labels, config, None)
+}
+
+/// Like [`check_semantics_parallel`], calling `on_progress` on the calling
+/// thread with the number of lines checked so far, e.g. to show progress.
+pub fn check_semantics_with_progress<'a>(
+    lines: Vec<Line<'a>>,
+    labels: &HashMap<&str, usize>,
+    config: &Config,
+    mut on_progress: impl FnMut(usize),
+) -> Result<Vec<CheckedLine<'a>>, Vec<Error>> {
+    check_in_threads(lines, labels, config, Some(&mut on_progress))
 }
 
-fn parse_wide_num(number: i64) -> Result<u16, WriterErr> {
-    if !(-32_768..=65_535).contains(&number) {
-        return Err(WriterErr::NumberOutOfRange(number));
+fn check_in_threads<'a>(
+    lines: Vec<Line<'a>>,
+    labels: &HashMap<&str, usize>,
+    config: &Config,
+    on_progress: Option<&mut dyn FnMut(usize)>,
+) -> Result<Vec<CheckedLine<'a>>, Vec<Error>> {
+    // Workers report each checked line, only when someone's listening.
+    let (sender, receiver) = mpsc::channel();
+    let sender = on_progress.is_some().then_some(sender);
+    let check = move || {
+        lines
+            .into_par_iter()
+            .with_min_len(MIN_LINES_PER_THREAD)
+            .map_init(EncodingCache::default, |cache, line| {
+                let result = check_line(line, labels, config, cache);
+                if let Some(sender) = &sender {
+                    let _ = sender.send(());
+                }
+                result
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let results = match on_progress {
+        None => check(),
+        Some(on_progress) => {
+            let mut results = vec![];
+            rayon::in_place_scope(|scope| {
+                scope.spawn(|_| results = check());
+                // Ends once the workers are done and the sender is dropped.
+                for (done, ()) in receiver.iter().enumerate() {
+                    on_progress(done + 1);
+                }
+            });
+            results
+        }
+    };
+
+    let mut checked_lines = vec![];
+    let mut errors = vec![];
+    for result in results {
+        match result {
+            Ok(checked_line) => checked_lines.push(checked_line),
+            Err(err) => errors.push(err),
+        }
     }
 
-    Ok(number as u16)
+    if errors.is_empty() {
+        return Ok(checked_lines);
+    }
+    Err(errors)
 }
 
-fn parse_wide_labelref<'a>(
-    labels: &'a HashMap<&'a str, usize>,
-    label: &str,
-) -> Result<u16, WriterErr> {
-    let label = labels
-        .get(label)
-        .ok_or(WriterErr::UnknownLabel(label.to_string()))?;
-    Ok(*label as u16)
+/// A warning for every instruction that assembles to placeholder bytes
+/// because the config has no such form, see [`Config::set_unknown_instructions`].
+pub fn placeholder_warnings(lines: &[Line], config: &Config) -> Vec<Error> {
+    if config.unknown_instructions.is_none() {
+        return vec![];
+    }
+    let mut warnings = vec![];
+    for line in lines {
+        let Line::Instruction { mnemonic, operands } = line else {
+            continue;
+        };
+        let TokenType::Mnemonic(name) = &mnemonic.token_type else {
+            continue;
+        };
+        let kinds = operands
+            .iter()
+            .map(|(operand, _)| *operand)
+            .collect::<Vec<_>>();
+        if config.opcode(name, &kinds).is_ok() {
+            continue;
+        }
+        let form = std::iter::once(name.to_string())
+            .chain(kinds.iter().map(Operand::to_string))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let size = config.instruction_size(name, &kinds).unwrap_or(1);
+        warnings
+            .push(WriterErr::PlaceholderInstruction(form, size).with_span(mnemonic.span.clone()));
+    }
+    warnings
 }
 
-fn parse_wide_value<'a>(
-    labels: &'a HashMap<&'a str, usize>,
-    value: &Token,
-) -> Result<u16, WriterErr> {
-    match &value.token_type {
-        TokenType::Number(number) => parse_wide_num(*number),
-        TokenType::LabelRef(label_ref) => parse_wide_labelref(labels, label_ref),
-        _ => unreachable!(),
+/// A warning for every number literal written wider than the value it fills,
+/// like `MOV A 0x0005`. Only literals at least a hex digit too wide count,
+/// so octal and binary digits that merely round up past a byte don't warn.
+pub fn literal_width_warnings(lines: &[Line], config: &Config) -> Vec<Error> {
+    let mut values = vec![];
+    for line in lines {
+        match line {
+            Line::Instruction { operands, .. } => {
+                for (operand, value) in operands {
+                    let bits = match operand {
+                        Operand::Mem8 | Operand::Const => 8,
+                        Operand::Mem16 => config.capabilities.address_width,
+                        Operand::Register(_) | Operand::Stc => continue,
+                    };
+                    values.push((value, bits));
+                }
+            }
+            Line::Byte(bytes) => values.extend(bytes.iter().map(|value| (value, 8))),
+            Line::Word(words) => values.extend(words.iter().map(|value| (value, 16))),
+            _ => {}
+        }
+    }
+
+    let mut warnings = vec![];
+    for (value, bits) in values {
+        let Some(token) = value.as_token() else {
+            continue;
+        };
+        if let TokenType::Number(number, Some(width)) = token.token_type {
+            if width >= bits + 4 {
+                warnings.push(
+                    WriterErr::WideLiteral(number, width, bits).with_span(token.span.clone()),
+                );
+            }
+        }
     }
+    warnings
 }
 
-fn check_byte<'a>(
-    labels: &'a HashMap<&'a str, usize>,
-    declared_values: &Vec<&Token>,
-) -> Result<CheckedLineCode, WriterErr> {
-    let mut parsed_values = vec![];
-    for value in declared_values {
-        let parsed_value = parse_value(labels, value);
-        parsed_values.push(parsed_value?);
+/// Start address of every checked line, following `.org` and `.align`.
+pub fn layout(checked_lines: &[CheckedLine]) -> Result<Vec<usize>, Error> {
+    let mut addresses = vec![];
+    let mut address: usize = 0;
+    for checked_line in checked_lines {
+        if let CheckedLineCode::Instruction { alignment, .. } = &checked_line.code {
+            if !address.is_multiple_of(alignment.multiple) {
+                let Line::Instruction { mnemonic, .. } = &checked_line.line else {
+                    unreachable!();
+                };
+                if alignment.padding.is_none() {
+                    let err = WriterErr::MisalignedInstruction(address, alignment.multiple);
+                    return Err(err.with_span(mnemonic.span.clone()).with_help(Message::new(
+                        "help.align",
+                        vec![alignment.multiple.to_string()],
+                    )));
+                }
+                address = address.next_multiple_of(alignment.multiple);
+            }
+        }
+        addresses.push(address);
+        address = match checked_line.code {
+            CheckedLineCode::Org(target) if target < address => {
+                let Line::Org(expr) = &checked_line.line else {
+                    unreachable!();
+                };
+                return Err(WriterErr::OrgBackwards(target, address).with_span(expr.span()));
+            }
+            _ => checked_line.code.end(address),
+        };
     }
-    Ok(CheckedLineCode::Byte(parsed_values))
+    Ok(addresses)
 }
 
-pub fn check_semantics<'a>(
-    lines: Vec<Line<'a>>,
-    labels: &'a HashMap<&'a str, usize>,
-    config: &'a Config,
-) -> Result<Vec<CheckedLine<'a>>, WriterErr> {
-    let mut checked_lines: Vec<_> = vec![];
+/// Lays the checked lines out into the final image at the `addresses`
+/// [`layout`] gave them. Gaps left by `.org` and `.align` are zeros, the
+/// ones before padded instructions `NOP`s.
+pub fn build_image(checked_lines: &[CheckedLine], addresses: &[usize]) -> Vec<u8> {
+    let mut image = vec![];
+    // Where the previous line left off, before any padding.
+    let mut cursor = 0;
+    for (checked_line, &address) in checked_lines.iter().zip(addresses) {
+        image.resize(cursor.max(image.len()), 0);
+        let fill = match checked_line.code {
+            CheckedLineCode::Instruction { alignment, .. } => alignment.padding.unwrap_or(0),
+            _ => 0,
+        };
+        image.resize(address.max(image.len()), fill);
+        image.extend(checked_line.code.bytes());
+        cursor = match checked_line.code {
+            CheckedLineCode::Org(target) => target,
+            CheckedLineCode::Align(alignment) => address.next_multiple_of(alignment),
+            _ => image.len(),
+        };
+    }
+    image
+}
 
-    for line in lines {
-        let code = match &line {
-            Line::Byte(declared_values) => check_byte(labels, declared_values),
-            Line::Instruction { mnemonic, operands } => {
-                check_instruction(config, labels, mnemonic, operands)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_check_instruction_accepts_exactly_the_signatures() {
+        let config = Config::builtin();
+        let encodings = config.encodings();
+        let mut mnemonics = encodings
+            .keys()
+            .map(|(mnemonic, _)| mnemonic.name().to_string())
+            .collect::<Vec<_>>();
+        mnemonics.sort();
+        mnemonics.dedup();
+        mnemonics.push("NOSUCH".to_string());
+
+        let mut cache = EncodingCache::default();
+        let labels = HashMap::new();
+        for name in mnemonics {
+            let source = format!("{} 1", name).chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+            let mnemonic = Mnemonic::new(name.clone());
+            let arity = config.max_arity(&mnemonic).unwrap_or(0) + 1;
+
+            // Every sequence of operand kinds up to one more than the
+            // mnemonic ever takes.
+            let mut sequences = vec![vec![]];
+            let mut longest = vec![vec![]];
+            for _ in 0..arity {
+                longest = longest
+                    .iter()
+                    .flat_map(|sequence: &Vec<Operand>| {
+                        Operand::ALL.iter().map(move |operand| {
+                            let mut sequence = sequence.clone();
+                            sequence.push(*operand);
+                            sequence
+                        })
+                    })
+                    .collect();
+                sequences.extend(longest.iter().cloned());
             }
-        }?;
-        checked_lines.push(CheckedLine { line, code });
+
+            for kinds in sequences {
+                let operands = kinds
+                    .iter()
+                    .map(|kind| (*kind, Expr::Value(&tokens[1])))
+                    .collect::<Vec<_>>();
+                let checked =
+                    check_instruction(&config, &labels, &mut cache, &tokens[0], &operands);
+                let expected = encodings.get(&(mnemonic.clone(), kinds.clone()));
+                match (checked, expected) {
+                    (Ok(CheckedLineCode::Instruction { mnemonic_code, .. }), Some(opcode)) => {
+                        assert_eq!(mnemonic_code, *opcode, "{} {:?}", name, kinds)
+                    }
+                    (Err(_), None) => {}
+                    (checked, _) => panic!("{} {:?}: {:?}", name, kinds, checked),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_instructions() {
+        let mut config = Config::builtin();
+        let source = "MOV A 1\nFROB A 1\nloop: HALT";
+        assert!(crate::assemble(source, &config, Default::default()).is_err());
+
+        config.unknown_instructions = Some(0xff);
+        let assembly = crate::assemble(source, &config, Default::default()).unwrap();
+        assert_eq!(assembly.image[2..4], [0xff, 0xff]);
+        assert_eq!(assembly.symbols["loop"], 4);
+        assert_eq!(assembly.warnings.len(), 1);
+        assert_eq!(parse_placeholder("0x12"), Ok(0x12));
+        assert!(parse_placeholder("256").is_err());
+    }
+
+    #[test]
+    fn test_check_semantics_with_progress() {
+        let config = Config::builtin();
+        let source = "MOV A 1\nHALT\n".repeat(300).chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = crate::parser::parse_with_config(&tokens, &config).unwrap();
+
+        let mut reported = vec![];
+        let checked = check_semantics_with_progress(lines, &HashMap::new(), &config, |done| {
+            reported.push(done)
+        })
+        .unwrap();
+        assert_eq!(checked.len(), 600);
+        assert_eq!(reported, (1..=600).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        let config = Config::builtin();
+        let mut source = String::new();
+        for i in 0..5 * MIN_LINES_PER_THREAD {
+            source += match i % 1000 {
+                0 => "JMPIMM #missing\n",
+                500 => ".word 70000\n",
+                _ => "MOV A 1\n",
+            };
+        }
+        let source = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = crate::parser::parse_with_config(&tokens, &config).unwrap();
+        let labels = HashMap::new();
+        let diagnostics = |errors: Vec<Error>| {
+            errors
+                .iter()
+                .map(|err| (err.to_string(), err.span().line))
+                .collect::<Vec<_>>()
+        };
+
+        let mut cache = EncodingCache::default();
+        let serial = crate::parser::parse_with_config(&tokens, &config)
+            .unwrap()
+            .into_iter()
+            .filter_map(|line| check_line(line, &labels, &config, &mut cache).err())
+            .collect::<Vec<_>>();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let parallel = pool
+            .install(|| check_semantics_parallel(lines, &labels, &config))
+            .unwrap_err();
+        assert_eq!(serial.len(), 41);
+        assert_eq!(diagnostics(parallel), diagnostics(serial));
+    }
+
+    #[test]
+    fn test_encoding_cache() {
+        let mut config = Config::builtin();
+        config.unknown_instructions = Some(0xff);
+        let source = "HALT\nHALT\nFROB A\nFROB A".chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = crate::parser::parse_with_config(&tokens, &config).unwrap();
+
+        let mut cache = EncodingCache::default();
+        let labels = HashMap::new();
+        for line in lines {
+            check_line(line, &labels, &config, &mut cache).unwrap();
+        }
+        // Unknown forms are looked up through the cache too.
+        let frob = Mnemonic::new("FROB".to_string());
+        let register = Operand::Register(crate::specs::Register::A);
+        assert!(cache.opcodes[&frob][&vec![register]].is_err());
+        let halt = Mnemonic::new("HALT".to_string());
+        assert_eq!(cache.opcodes[&halt][&vec![]], Ok(0xd8));
+        assert_eq!(cache.opcodes.len(), 2);
     }
 
-    Ok(checked_lines)
+    #[test]
+    fn test_word_out_of_range() {
+        let config = Config::builtin();
+        let errors = crate::assemble(".word 65536\n", &config, Default::default()).unwrap_err();
+        assert_eq!(
+            errors[0].message(),
+            "Number should be in range [-32768, 65535], instead found 65536."
+        );
+        assert!(crate::assemble(".word -32768 65535\n", &config, Default::default()).is_ok());
+    }
+
+    #[test]
+    fn test_literal_width_warnings() {
+        let config = Config::builtin();
+        let warnings = |source: &str| {
+            let source = source.chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+            let lines = crate::parser::parse_with_config(&tokens, &config).unwrap();
+            literal_width_warnings(&lines, &config)
+                .iter()
+                .map(|warning| warning.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            warnings("MOV A 0x0005"),
+            ["Literal 0x5 is written 16 bits wide, but its operand only holds 8."]
+        );
+        assert_eq!(
+            warnings(".word 0x00001"),
+            ["Literal 0x1 is written 20 bits wide, but its operand only holds 16."]
+        );
+        assert!(warnings("MOV A 0x05\nMOV A 0377\nMOV A 5\n.word 0x0005").is_empty());
+    }
+
+    #[test]
+    fn test_stack_forms_come_from_the_config() {
+        let mut config = Config::builtin();
+        config.capabilities.has_stack = false;
+        for source in ["PUSH A", "POP T", "JMPREL #0", "JMPREL"] {
+            assert!(crate::assemble(source, &config, Default::default()).is_err());
+        }
+        assert!(crate::assemble("JMPREL 2", &config, Default::default()).is_ok());
+
+        // Only the flag counts, not what the mnemonic is called.
+        let form = |mnemonic: &str, opcode: &str, uses_stack: bool| {
+            format!(
+                r#""{mnemonic}": {{"mnemonic": "{mnemonic}", "arguments": ["A"],
+                "opcode": "{opcode}", "depend-on-flag": "", "min-cycles-number": 3,
+                "max-cycles-number": 3, "uses-stack": {uses_stack}}}"#
+            )
+        };
+        let config = Config::from_json(&format!(
+            r#"{{"capabilities": {{"has-stack": false}}, {}, {}}}"#,
+            form("SAVE", "0b00000001", true),
+            form("PUSH", "0b00000010", false)
+        ))
+        .unwrap();
+        let errors = crate::assemble("SAVE A", &config, Default::default()).unwrap_err();
+        assert_eq!(
+            errors[0].message(),
+            "'SAVE' needs a stack, which the target doesn't have."
+        );
+        assert!(crate::assemble("PUSH A", &config, Default::default()).is_ok());
+    }
 }
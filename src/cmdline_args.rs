@@ -1,44 +1,351 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::{
+    checker::parse_placeholder,
+    config::{parse_config_override, ConfigOverride},
+    defines::{parse_define, Define},
+    disassembler::parse_entry,
+    emitter::Format,
+    emulator::{
+        parse_memory_range, parse_wait_states, parse_watchpoint, MemoryRange, Mode, WaitStates,
+        WatchTarget,
+    },
+    i18n::Lang,
+    limits::Limits,
+    linker::{parse_section_base, SectionBase},
+    output::{pair_outputs, OutputErr, Split, Transform},
+    segments::{parse_segment_output, SegmentOutput},
+    symbols::{SymbolSort, SymbolsFormat},
+    trace::TraceFormat,
+    syntax::SyntaxOptions,
+};
 use std::{
     fs::File,
     io::{self, stdin, stdout, BufRead, BufReader, BufWriter, Write},
-    path::PathBuf,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
 };
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about = "Assembles programs for a CPU described by a JSON instruction config", long_about = None)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Input file name
     pub input_file: Option<PathBuf>,
 
-    /// Output file name
+    /// Output file name, may be repeated to write several formats at once
     #[arg(short, long, value_name = "output")]
-    pub output_file: Option<PathBuf>,
+    pub output_file: Vec<PathBuf>,
 
-    /// Config file
-    #[arg(short, long, value_name = "config")]
+    /// Config file, searched for in $ASSEMBLER_CONFIG, next to the input file,
+    /// the user config directory and the current directory if not given,
+    /// falling back to the built-in instruction set
+    #[arg(short, long, value_name = "config", global = true)]
     pub config_file: Option<PathBuf>,
 
-    /// Output to binary file
-    #[arg(short, long, default_value_t = false)]
+    /// Override a value of the config after loading it, e.g.
+    /// instructions.MOV-A-CONST.opcode=00010011, may be repeated
+    #[arg(long, value_name = "PATH=VALUE", value_parser = parse_config_override, global = true)]
+    pub config_set: Vec<ConfigOverride>,
+
+    /// Output one byte per line as ASCII 0/1 digits, same as --format text
+    #[arg(short, long, default_value_t = false, conflicts_with = "format")]
     pub text: bool,
+
+    /// Output file format, may be repeated with one --output-file each, in order
+    #[arg(long, value_enum, value_name = "format")]
+    pub format: Vec<Format>,
+
+    /// File of NAME=VALUE pairs injected as constants, referenced as #NAME
+    #[arg(long, value_name = "defines")]
+    pub define_from_file: Vec<PathBuf>,
+
+    /// Define a constant for conditional assembly and as #NAME, may be repeated
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]", value_parser = parse_define)]
+    pub defines: Vec<Define>,
+
+    /// Split the image into two files for parallel ROMs, named after the output file
+    #[arg(long, value_enum, requires = "output_file")]
+    pub split: Option<Split>,
+
+    /// Write the bytes of one segment, from its first address, to a file of its
+    /// own in the output format, may be repeated
+    #[arg(long, value_name = "NAME=FILE", value_parser = parse_segment_output)]
+    pub segment_out: Vec<SegmentOutput>,
+
+    /// Transform applied to the image before writing, may be repeated
+    #[arg(long, value_enum, value_name = "transform")]
+    pub transform: Vec<Transform>,
+
+    /// Write a relocatable object for the link command instead of an image
+    #[arg(long, default_value_t = false, conflicts_with_all = ["format", "text", "split", "segment_out"])]
+    pub emit_obj: bool,
+
+    /// Fail if the image is larger than the given number of bytes
+    #[arg(long, value_name = "bytes")]
+    pub max_size: Option<usize>,
+
+    /// Write a listing with the address, bytes and source of every line to this file
+    #[arg(long, value_name = "file")]
+    pub listing: Option<PathBuf>,
+
+    /// Put a comment with the source line before the bytes of each line, in the hex and logisim formats
+    #[arg(long, default_value_t = false, conflicts_with = "split")]
+    pub annotate: bool,
+
+    /// Write every label with its address, size and definition, followed by the
+    /// bytes each source file and label take up, to this file
+    #[arg(long, value_name = "file")]
+    pub symbols: Option<PathBuf>,
+
+    /// Format of the --symbols file
+    #[arg(long, value_enum, default_value_t, requires = "symbols")]
+    pub symbols_format: SymbolsFormat,
+
+    /// Print every label with its address, size and definition to stdout instead
+    /// of writing the image
+    #[arg(long, default_value_t = false, conflicts_with = "emit_obj")]
+    pub list_symbols: bool,
+
+    /// Order of --list-symbols
+    #[arg(long, value_enum, default_value_t, requires = "list_symbols")]
+    pub sort: SymbolSort,
+
+    /// List only the labels matching this pattern, where * matches any run of characters and ? any one
+    #[arg(long, value_name = "glob", requires = "list_symbols")]
+    pub filter: Option<String>,
+
+    /// Don't print the warning/error/size summary at the end of the run
+    #[arg(long, default_value_t = false)]
+    pub quiet_summary: bool,
+
+    /// Show per-pass progress on stderr when it's a terminal
+    #[arg(long, default_value_t = false)]
+    pub progress: bool,
+
+    /// Print how long each pass took on stderr
+    #[arg(long, default_value_t = false)]
+    pub timings: bool,
+
+    /// Number of threads to check lines on, one per core by default
+    #[arg(short, long, value_name = "n")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Language of diagnostics, taken from $LANG if not given
+    #[arg(long, value_enum, global = true)]
+    pub lang: Option<Lang>,
+
+    /// Warn about instructions no path from the entry point, '.entry' or
+    /// address 0, reaches
+    #[arg(long, default_value_t = false)]
+    pub warn_unreachable: bool,
+
+    /// Leave out code and data under labels nothing reachable from the entry point refers to
+    #[arg(long, default_value_t = false)]
+    pub gc_sections: bool,
+
+    /// Print stable hashes of the final image and of the normalized input, for build caches, on stdout unless the image goes there
+    #[arg(long, default_value_t = false, conflicts_with = "emit_obj")]
+    pub print_hash: bool,
+
+    /// Explain the encoding of every source line in prose after assembling
+    #[arg(long, default_value_t = false)]
+    pub teach: bool,
+
+    #[command(flatten)]
+    pub syntax: SyntaxOptions,
+
+    /// Assemble instructions the config lacks to this byte, 0xff if not given,
+    /// with a warning, e.g. while the config of a new CPU revision is unfinished
+    #[arg(long, value_name = "byte", num_args = 0..=1, default_missing_value = "0xff", value_parser = parse_placeholder)]
+    pub allow_unknown_instructions: Option<u8>,
+
+    /// Print the path each instruction takes through the config automaton
+    #[arg(long, default_value_t = false)]
+    pub explain_encoding: bool,
+
+    #[command(flatten)]
+    pub limits: Limits,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Assemble every instruction form of the config and decode it back
+    Selftest,
+    /// List and assemble the bundled example programs, or print one of them
+    Examples {
+        /// Name of the example whose source should be printed
+        name: Option<String>,
+    },
+    /// Print the instructions of a binary image, or an object as source, decoded with the config
+    Disasm {
+        /// Image to disassemble, raw, Intel HEX or S-records, or an object written with --emit-obj
+        image: PathBuf,
+        /// Decode only the code reachable from address 0 and the entry points, the rest as data
+        #[arg(long, default_value_t = false)]
+        smart: bool,
+        /// Address execution can also start at, e.g. an interrupt handler, may be repeated
+        #[arg(long, value_name = "ADDRESS", value_parser = parse_entry, requires = "smart")]
+        entry: Vec<usize>,
+        /// JSON file written with --symbols --symbols-format json whose labels name the addresses
+        #[arg(long, value_name = "file")]
+        symbols: Option<PathBuf>,
+    },
+    /// Assemble a program and execute it on the emulator
+    Run(Box<RunArgs>),
+    /// Run programs on the emulator and a reference simulator and compare their register traces
+    Difftest {
+        /// Programs to assemble and run
+        #[arg(required = true)]
+        programs: Vec<PathBuf>,
+        /// Shell command of the simulator, speaking the JSON protocol of the difftest module
+        #[arg(long, value_name = "COMMAND")]
+        simulator: String,
+        /// Stop after this many instructions
+        #[arg(long, default_value_t = 1_000_000)]
+        max_steps: usize,
+    },
+    /// Combine objects written with --emit-obj into one binary image
+    Link {
+        /// Objects and archives to link, sections of the same name are placed in this order
+        #[arg(required = true)]
+        objects: Vec<PathBuf>,
+        /// Image file to write, stdout if not given
+        #[arg(short, long, value_name = "output")]
+        output: Option<PathBuf>,
+        /// Address a section starts at, may be repeated, the others follow the one before
+        #[arg(long, value_name = "SECTION=ADDRESS", value_parser = parse_section_base)]
+        base: Vec<SectionBase>,
+        /// TOML link script with the memory regions and the sections placed in each
+        #[arg(long, value_name = "file", conflicts_with = "base")]
+        script: Option<PathBuf>,
+    },
+    /// Bundle objects into an archive the linker only takes the needed ones from
+    Archive {
+        /// Objects to bundle
+        #[arg(required = true)]
+        objects: Vec<PathBuf>,
+        /// Archive file to write
+        #[arg(short, long, value_name = "output")]
+        output: PathBuf,
+    },
+    /// Create a new project directory with a manifest, starter program and config
+    New {
+        /// Directory to create, its name is used as the project name
+        path: PathBuf,
+    },
+    /// Assemble the project of a manifest to its output, then run its post-build commands
+    Build {
+        /// Manifest of the project, asm.toml in the current directory if not given
+        #[arg(long, value_name = "file")]
+        manifest: Option<PathBuf>,
+    },
+    /// Answer assemble and check requests as JSON-RPC, one per line on stdin, until shutdown
+    Serve,
+    /// Assemble instructions as they're typed, keeping labels and constants between lines
+    Repl {
+        /// Also execute each line on the emulator and show the registers
+        #[arg(long, default_value_t = false)]
+        run: bool,
+        /// Evaluate the lines of this file instead of reading stdin, failing if any of them does,
+        /// where commands may leave out the ':'
+        #[arg(long, value_name = "file")]
+        script: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RunArgs {
+    /// Program to assemble and run
+    pub program: PathBuf,
+    /// Stop after this many clock cycles
+    #[arg(long, default_value_t = 1_000_000)]
+    pub max_cycles: usize,
+    /// Count the config's cycles and wait states, or only run the program as fast as possible
+    #[arg(long, value_enum, default_value_t)]
+    pub mode: Mode,
+    /// Extra cycles every access to memory in RANGE takes in cycle-accurate mode, may be repeated
+    #[arg(long, value_name = "RANGE=CYCLES", value_parser = parse_wait_states)]
+    pub wait_states: Vec<WaitStates>,
+    /// Print memory in START..END, or the 16 bytes from START, after the run, may be repeated
+    #[arg(long, value_name = "RANGE", value_parser = parse_memory_range)]
+    pub dump: Vec<MemoryRange>,
+    /// Stop when execution reaches this label
+    #[arg(long = "break", value_name = "LABEL")]
+    pub breakpoint: Option<String>,
+    /// Stop after an instruction reads or writes this label or address, only reads with
+    /// TARGET:r, only writes with TARGET:w, may be repeated
+    #[arg(long, value_name = "TARGET[:ACCESS]", value_parser = parse_watchpoint)]
+    pub watch: Vec<WatchTarget>,
+    /// Write the cycle, registers and memory writes of every instruction to this file
+    #[arg(long, value_name = "file")]
+    pub trace: Option<PathBuf>,
+    /// Format of the --trace file
+    #[arg(long, value_enum, default_value_t, requires = "trace")]
+    pub trace_format: TraceFormat,
+    /// Write how often each instruction ran and the cycles spent on it, by address and
+    /// next to the source, to this file
+    #[arg(long, value_name = "file")]
+    pub profile: Option<PathBuf>,
+    /// Start from the machine state saved in this file instead of the program at address 0
+    #[arg(long, value_name = "file")]
+    pub load_snapshot: Option<PathBuf>,
+    /// Save the registers, memory and cycle count to this file when the run stops
+    #[arg(long, value_name = "file")]
+    pub save_snapshot: Option<PathBuf>,
+    /// Input the program reads from the console at 0xff01, '-' for stdin
+    #[arg(long, value_name = "file")]
+    pub console_in: Option<PathBuf>,
+    /// Write what the program prints to the console at 0xff00 to this file, '-' for stdout
+    #[arg(long, value_name = "file")]
+    pub console_out: Option<PathBuf>,
+    /// Fail unless the program prints exactly the contents of this file to the console
+    #[arg(long, value_name = "file")]
+    pub expect_output: Option<PathBuf>,
+}
+
+impl RunArgs {
+    /// Whether the console device is attached to the machine.
+    pub fn console(&self) -> bool {
+        self.console_in.is_some() || self.console_out.is_some() || self.expect_output.is_some()
+    }
+}
+
+/// Opens `path` for writing, or stdout if it's `None`.
+pub fn create_output(path: Option<&Path>) -> Result<Box<dyn Write>, io::Error> {
+    Ok(match path {
+        Some(name) => Box::new(BufWriter::new(File::create(name)?)),
+        None => Box::new(BufWriter::new(stdout())),
+    })
 }
 
 pub type ReadWriteResult = Result<(Box<dyn BufRead>, Box<dyn Write>), io::Error>;
 
 impl Args {
-    pub fn get_read_write(args: &Args) -> ReadWriteResult {
-        let input: Box<dyn BufRead> = match args.input_file.as_ref() {
+    /// Every format to write and the file it goes to, `None` for stdout.
+    pub fn outputs(&self) -> Result<Vec<(Format, Option<PathBuf>)>, OutputErr> {
+        let default = match self.text {
+            true => Format::Text,
+            false => Format::Bin,
+        };
+        pair_outputs(&self.format, &self.output_file, default)
+    }
+
+    pub fn get_input(&self) -> Result<Box<dyn BufRead>, io::Error> {
+        Ok(match self.input_file.as_ref() {
             Some(name) => Box::new(BufReader::new(File::open(name)?)),
             None => Box::new(BufReader::new(stdin())),
-        };
+        })
+    }
 
-        let output: Box<dyn Write> = match args.output_file.as_ref() {
-            Some(name) => Box::new(BufWriter::new(File::create(name)?)),
-            None => Box::new(BufWriter::new(stdout())),
-        };
+    pub fn get_output(&self) -> Result<Box<dyn Write>, io::Error> {
+        create_output(self.output_file.first().map(PathBuf::as_path))
+    }
 
-        Ok((input, output))
+    pub fn get_read_write(args: &Args) -> ReadWriteResult {
+        Ok((args.get_input()?, args.get_output()?))
     }
 }
 
@@ -65,10 +372,40 @@ mod tests {
         let output_path = output_file.path().to_path_buf();
 
         let args = Args {
+            command: None,
             input_file: Some(input_path),
-            output_file: Some(output_path),
+            output_file: vec![output_path],
             config_file: None,
+            config_set: vec![],
             text: true,
+            format: vec![],
+            define_from_file: vec![],
+            defines: vec![],
+            split: None,
+            segment_out: vec![],
+            transform: vec![],
+            emit_obj: false,
+            max_size: None,
+            listing: None,
+            annotate: false,
+            symbols: None,
+            symbols_format: SymbolsFormat::Text,
+            list_symbols: false,
+            sort: SymbolSort::Addr,
+            filter: None,
+            quiet_summary: false,
+            progress: false,
+            timings: false,
+            jobs: None,
+            lang: None,
+            warn_unreachable: false,
+            gc_sections: false,
+            print_hash: false,
+            teach: false,
+            syntax: SyntaxOptions::default(),
+            allow_unknown_instructions: None,
+            limits: Limits::default(),
+            explain_encoding: false,
         };
 
         let (mut input, _) = Args::get_read_write(&args).unwrap();
@@ -79,4 +416,14 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_about_is_not_taken_from_flattened_options() {
+        use clap::CommandFactory;
+
+        let command = Args::command();
+        let about = command.get_about().unwrap().to_string();
+        assert!(about.starts_with("Assembles programs"), "{}", about);
+        assert_eq!(command.get_long_about(), None);
+    }
 }
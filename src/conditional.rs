@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    defines::Define,
+    error::{Error, WithSpan},
+    i18n::Message,
+    parser::parse_expression,
+    token::{split_lines, Span, Token, TokenType},
+};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum ConditionalErr {
+    #[error("Expected a condition after '{0}'.")]
+    MissingCondition(String),
+    #[error("Invalid condition: {0}")]
+    InvalidCondition(String),
+    #[error("'{0}' takes a single symbol name.")]
+    InvalidSymbol(String),
+    #[error("'{0}' without a matching '.if'.")]
+    Unmatched(String),
+    #[error("Conditional block has more than one '.else'.")]
+    DuplicateElse,
+    #[error("'{0}' is missing its '.endif'.")]
+    Unterminated(String),
+    #[error("Unexpected '{0}' after '{1}'.")]
+    TrailingToken(String, String),
+}
+
+struct Block<'a> {
+    opening: &'a Token,
+    enclosing_active: bool,
+    taken: bool,
+    else_branch: Option<&'a Token>,
+}
+
+impl Block<'_> {
+    fn is_active(&self) -> bool {
+        self.enclosing_active && self.taken != self.else_branch.is_some()
+    }
+}
+
+fn condition(
+    directive: &Token,
+    rest: &[Token],
+    symbols: &HashMap<&str, usize>,
+) -> Result<bool, Error> {
+    let Some(span) = Span::merge_all(rest.iter().map(|token| &token.span)) else {
+        return Err(ConditionalErr::MissingCondition(directive.content.clone())
+            .with_span(directive.span.clone()));
+    };
+
+    match directive.token_type {
+        TokenType::If => {
+            let expression = parse_expression(rest).map_err(|err| {
+                ConditionalErr::InvalidCondition(err.to_string()).with_span(span.clone())
+            })?;
+            Ok(expression.evaluate(symbols)? != 0)
+        }
+        _ => {
+            let name = match rest {
+                [token]
+                    if matches!(
+                        token.token_type,
+                        TokenType::Mnemonic(_) | TokenType::LabelRef(_)
+                    ) =>
+                {
+                    &token.content
+                }
+                _ => {
+                    return Err(
+                        ConditionalErr::InvalidSymbol(directive.content.clone()).with_span(span)
+                    )
+                }
+            };
+            let defined = symbols.contains_key(name.as_str());
+            Ok(defined == (directive.token_type == TokenType::IfDef))
+        }
+    }
+}
+
+fn trailing_token(line: &[Token]) -> Option<Error> {
+    let (directive, token) = (line.first()?, line.get(1)?);
+    Some(
+        ConditionalErr::TrailingToken(token.content.clone(), directive.content.clone())
+            .with_span(token.span.clone()),
+    )
+}
+
+/// Drops the lines of `.if`/`.ifdef`/`.ifndef` blocks whose condition
+/// doesn't hold, along with the directives themselves. Conditions only see
+/// the constants given on the command line, since nothing else is known
+/// before parsing.
+pub fn assemble_conditionals(
+    tokens: &[Token],
+    defines: &[Define],
+) -> Result<Vec<Token>, Vec<Error>> {
+    let symbols = defines
+        .iter()
+        .map(|define| (define.name.as_str(), define.value))
+        .collect::<HashMap<_, _>>();
+
+    let mut blocks: Vec<Block> = vec![];
+    let mut output = vec![];
+    let mut errors = vec![];
+
+    for line in split_lines(tokens) {
+        let active = blocks.last().is_none_or(Block::is_active);
+        let Some(directive) = line.first() else {
+            continue;
+        };
+
+        match directive.token_type {
+            TokenType::If | TokenType::IfDef | TokenType::IfNotDef => {
+                // Skipped blocks may test symbols only the other variant defines.
+                let taken = active
+                    && condition(directive, &line[1..], &symbols).unwrap_or_else(|err| {
+                        errors.push(err);
+                        false
+                    });
+                blocks.push(Block {
+                    opening: directive,
+                    enclosing_active: active,
+                    taken,
+                    else_branch: None,
+                });
+            }
+            TokenType::Else => {
+                errors.extend(trailing_token(line));
+                match blocks.last_mut() {
+                    None => errors.push(
+                        ConditionalErr::Unmatched(directive.content.clone())
+                            .with_span(directive.span.clone()),
+                    ),
+                    Some(Block {
+                        else_branch: Some(first),
+                        ..
+                    }) => errors.push(
+                        ConditionalErr::DuplicateElse
+                            .with_span(directive.span.clone())
+                            .with_label(
+                                first.span.clone(),
+                                Message::new("note.first_else", vec![]),
+                            ),
+                    ),
+                    Some(block) => block.else_branch = Some(directive),
+                }
+            }
+            TokenType::EndIf => {
+                errors.extend(trailing_token(line));
+                if blocks.pop().is_none() {
+                    errors.push(
+                        ConditionalErr::Unmatched(directive.content.clone())
+                            .with_span(directive.span.clone()),
+                    );
+                }
+            }
+            _ if active => output.extend_from_slice(line),
+            _ => {}
+        }
+    }
+
+    for block in blocks {
+        errors.push(
+            ConditionalErr::Unterminated(block.opening.content.clone())
+                .with_span(block.opening.span.clone()),
+        );
+    }
+
+    if errors.is_empty() {
+        return Ok(output);
+    }
+    Err(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{defines::parse_define, lexer::Lexer};
+
+    fn assemble(source: &str, defines: &[&str]) -> Result<Vec<Token>, Vec<Error>> {
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let defines = defines
+            .iter()
+            .map(|define| parse_define(define).unwrap())
+            .collect::<Vec<_>>();
+        assemble_conditionals(&tokens, &defines)
+    }
+
+    fn contents(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|token| token.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_nested_conditionals() {
+        let source = "\
+.ifdef DEBUG
+  MOV A 1
+  .if MODE == 2
+    MOV B 2
+  .else
+    MOV B 3
+  .endif
+.else
+  .ifndef MISSING
+    HALT
+  .endif
+.endif
+.ifndef DEBUG
+  HALT
+.endif
+";
+        let tokens = assemble(source, &["DEBUG", "MODE=0x2"]).unwrap();
+        assert_eq!(contents(&tokens), ["MOV", "A", "1", "MOV", "B", "2"]);
+
+        let tokens = assemble(source, &["DEBUG", "MODE=3"]).unwrap();
+        assert_eq!(contents(&tokens), ["MOV", "A", "1", "MOV", "B", "3"]);
+
+        let tokens = assemble(source, &[]).unwrap();
+        assert_eq!(contents(&tokens), ["HALT", "HALT"]);
+    }
+
+    #[test]
+    fn test_unbalanced_conditionals() {
+        let errors = |source: &str| {
+            assemble(source, &[])
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.to_string(), err.span().line, err.span().chars.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors("HALT\n.endif\n"),
+            [("'.endif' without a matching '.if'.".to_string(), 1, 0..6)]
+        );
+        assert_eq!(
+            errors(".if 1\n  HALT\n"),
+            [("'.if' is missing its '.endif'.".to_string(), 0, 0..3)]
+        );
+        assert_eq!(
+            errors(".if\n.endif\n"),
+            [("Expected a condition after '.if'.".to_string(), 0, 0..3)]
+        );
+        assert_eq!(
+            errors(".if 1\n.endif HALT\n"),
+            [("Unexpected 'HALT' after '.endif'.".to_string(), 1, 7..11)]
+        );
+
+        let duplicate = assemble(".if 1\n.else\n  .else\n.endif\n", &[]).unwrap_err();
+        assert_eq!(duplicate[0].span().line, 2);
+        assert_eq!(duplicate[0].span().chars, 2..7);
+        assert_eq!(duplicate[0].labels()[0].span.line, 1);
+    }
+}
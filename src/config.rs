@@ -1,23 +1,319 @@
-use std::{collections::HashMap, fs::read_to_string, hash::Hash, io, path::Path, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    fmt::Display,
+    fs::read_to_string,
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use serde::Deserialize;
-use serde_json::Value;
+use serde::{
+    de::{MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
 use thiserror::Error;
 
-use crate::specs::{Mnemonic, Operand};
-
-fn get_config_parse_prefix(line_nr: usize) -> String {
-    format!("Failed to parse config on line {}: ", line_nr + 1)
-}
+use crate::{
+    encoder::{binary_str_to_byte, EncodeErr, OpcodeTable},
+    expr::closest_name,
+    lexer::directive,
+    pseudo::PseudoInstruction,
+    specs::{Mnemonic, Operand},
+    syntax::Dialect,
+};
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("{0}.")]
     ReadFileError(io::Error),
+    #[error("Malformed config: {0}.")]
+    ParseError(serde_json::Error),
     #[error("Unknown mnemonic '{0}'.")]
     UnknownMnemonic(String),
-    #[error("Unknown operand '{0}'.")]
-    UnknownOperand(String),
+    #[error("Unknown operand '{0}'{}, expected one of {}.", nearest_hint(.1), operand_spellings())]
+    UnknownOperand(String, Option<String>),
+    #[error("No config file found, searched: {}.{}", format_paths(.0), skipped_note(.1))]
+    NotFound(Vec<PathBuf>, Vec<PathBuf>),
+    #[error("$ASSEMBLER_CONFIG names '{}', which isn't a file.", .0.display())]
+    MissingEnvConfig(PathBuf),
+    #[error("Instruction '{0}' has opcode '{1}', expected 8 binary digits like '0b00000001'.")]
+    InvalidOpcode(String, String),
+    #[error("Instructions '{0}' and '{1}' share the opcode '{2}'.")]
+    DuplicateOpcode(String, String, String),
+    #[error("Instructions '{0}' and '{1}' are both '{2}'.")]
+    DuplicateSignature(String, String, String),
+    #[error("Instruction '{0}' depends on unknown flag '{1}'.")]
+    UnknownFlag(String, String),
+    #[error("Address width should be between 1 and 24 bits, instead found {0}.")]
+    InvalidAddressWidth(u32),
+    #[error("Alias '{0}' stands for '{1}', which isn't a directive.")]
+    UnknownAliasTarget(String, String),
+    #[error("'{0}' can't be an alias or a reserved word, it isn't a name.")]
+    InvalidDialectName(String),
+    #[error("Instruction alignment should be a positive number, instead found {0}.")]
+    InvalidInstructionAlignment(usize),
+    #[error("'pad-instructions' needs a NOP instruction without operands to pad with.")]
+    NoPaddingNop,
+    #[error("Invalid config override '{0}', expected PATH=VALUE.")]
+    InvalidOverride(String),
+    #[error("Nothing in the config matches '{0}'.")]
+    UnknownOverrideTarget(String),
+    #[error("Pseudo-instruction '{0}' is invalid: {1}.")]
+    InvalidPseudoInstruction(String, String),
+}
+
+/// A `--config-set PATH=VALUE` argument, e.g.
+/// `instructions.MOV-A-CONST.opcode=00010011` or
+/// `capabilities.has-stack=false`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigOverride {
+    /// Keys from the top of the config file down to the value. Under
+    /// `instructions`, the instruction is named by its key in the file or by
+    /// its form, the mnemonic and arguments joined with `-`.
+    pub path: Vec<String>,
+    /// JSON, or else taken as a string. Opcodes may leave out the `0b`.
+    pub value: String,
+}
+
+pub fn parse_config_override(arg: &str) -> Result<ConfigOverride, ConfigError> {
+    let invalid = || ConfigError::InvalidOverride(arg.to_string());
+    let (path, value) = arg.split_once('=').ok_or_else(invalid)?;
+    let path = path
+        .trim()
+        .split('.')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if path.iter().any(String::is_empty) {
+        return Err(invalid());
+    }
+    Ok(ConfigOverride {
+        path,
+        value: value.trim().to_string(),
+    })
+}
+
+/// Whether `instruction` is the form `mnemonic-argument-...`, with any flag
+/// it depends on after the mnemonic like in source.
+fn is_form(instruction: &serde_json::Value, form: &str) -> bool {
+    let field = |key| instruction.get(key).and_then(serde_json::Value::as_str);
+    let arguments = instruction
+        .get("arguments")
+        .and_then(serde_json::Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(serde_json::Value::as_str);
+    let mnemonic = format!(
+        "{}{}",
+        field("mnemonic").unwrap_or_default(),
+        field("depend-on-flag").unwrap_or_default()
+    );
+    std::iter::once(mnemonic.as_str())
+        .chain(arguments)
+        .eq(form.split('-'))
+}
+
+impl ConfigOverride {
+    fn apply(&self, config: &mut serde_json::Value) -> Result<(), ConfigError> {
+        let unknown = || ConfigError::UnknownOverrideTarget(self.path.join("."));
+        let value = match self.path.last().map(String::as_str) {
+            Some("opcode") if !self.value.starts_with("0b") => {
+                serde_json::Value::String(format!("0b{}", self.value))
+            }
+            Some("opcode") => serde_json::Value::String(self.value.clone()),
+            _ => serde_json::from_str(&self.value)
+                .unwrap_or_else(|_| serde_json::Value::String(self.value.clone())),
+        };
+        let serde_json::Value::Object(entries) = config else {
+            return Err(unknown());
+        };
+
+        match self.path.as_slice() {
+            // Instructions only have the fields they're read with, so a typo
+            // in one can't go unnoticed.
+            [instructions, name, field] if instructions == "instructions" => {
+                let key = match entries.contains_key(name) {
+                    true => name.clone(),
+                    false => entries
+                        .iter()
+                        .find(|(_, instruction)| is_form(instruction, name))
+                        .map(|(key, _)| key.clone())
+                        .ok_or_else(unknown)?,
+                };
+                let slot = entries
+                    .get_mut(&key)
+                    .and_then(|instruction| instruction.get_mut(field))
+                    .ok_or_else(unknown)?;
+                *slot = value;
+            }
+            [section, keys @ ..]
+                if [CAPABILITIES_KEY, DIALECT_KEY, PSEUDO_KEY].contains(&section.as_str()) =>
+            {
+                let mut slot = entries
+                    .entry(section.clone())
+                    .or_insert_with(|| serde_json::json!({}));
+                for key in keys {
+                    slot = slot
+                        .as_object_mut()
+                        .ok_or_else(unknown)?
+                        .entry(key.clone())
+                        .or_insert(serde_json::Value::Null);
+                }
+                *slot = value;
+            }
+            _ => return Err(unknown()),
+        }
+        Ok(())
+    }
+}
+
+/// The operand spellings, those of one kind joined by slashes, e.g.
+/// `MEM16/MEM`.
+pub(crate) fn operand_spellings() -> String {
+    Operand::ALL
+        .iter()
+        .map(|operand| {
+            Operand::NAMES
+                .into_iter()
+                .filter(|name| Operand::from_str(name) == Ok(*operand))
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn nearest_hint(nearest: &Option<String>) -> String {
+    match nearest {
+        Some(nearest) => format!(" (did you mean '{}'?)", nearest),
+        None => String::new(),
+    }
+}
+
+impl ConfigError {
+    fn unknown_operand(operand: &str) -> Self {
+        let nearest = closest_name(&operand.to_uppercase(), Operand::NAMES.into_iter());
+        ConfigError::UnknownOperand(operand.to_string(), nearest.map(str::to_string))
+    }
+}
+
+pub(crate) fn format_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| format!("'{}'", path.display()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub const CONFIG_FILE_NAME: &str = "config.cfg";
+
+/// The instruction set used when no config file is given or found.
+pub const DEFAULT_CONFIG: &str = include_str!("../instructions.json");
+
+/// Flags an instruction can depend on, each also negated with an `N` prefix.
+pub const FLAG_NAMES: [&str; 5] = ["C", "Z", "S", "O", "P"];
+pub const CONFIG_ENV_VAR: &str = "ASSEMBLER_CONFIG";
+
+/// Widest address a target can have, in bits.
+pub const MAX_ADDRESS_WIDTH: u32 = 24;
+
+fn user_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("APPDATA").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    env::var_os("HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(|home| PathBuf::from(home).join(".config"))
+}
+
+/// Lists the places a config is looked for, in order of precedence:
+/// `$ASSEMBLER_CONFIG`, next to the input file, the user config directory
+/// and finally the current directory.
+pub fn config_search_paths(input_file: Option<&Path>) -> Vec<PathBuf> {
+    search_paths(env_config(), input_file, user_config_dir(), Path::new(""))
+}
+
+fn env_config() -> Option<PathBuf> {
+    env::var_os(CONFIG_ENV_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+fn search_paths(
+    env_config: Option<PathBuf>,
+    input_file: Option<&Path>,
+    user_dir: Option<PathBuf>,
+    current_dir: &Path,
+) -> Vec<PathBuf> {
+    let mut paths = vec![];
+
+    if let Some(path) = env_config {
+        paths.push(path);
+    }
+
+    if let Some(dir) = input_file
+        .and_then(Path::parent)
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        paths.push(dir.join(CONFIG_FILE_NAME));
+    }
+
+    if let Some(dir) = user_dir {
+        paths.push(dir.join("assembler").join(CONFIG_FILE_NAME));
+    }
+
+    paths.push(current_dir.join(CONFIG_FILE_NAME));
+    paths
+}
+
+fn skipped_note(skipped: &[PathBuf]) -> String {
+    match skipped.is_empty() {
+        true => String::new(),
+        false => format!(" Skipped {}, not JSON configs.", format_paths(skipped)),
+    }
+}
+
+/// Whether `path` holds a JSON object rather than e.g. the CSV tables older
+/// versions read from `config.cfg`. Unreadable files count as JSON, so
+/// reading them reports why they can't be read.
+fn is_json_config(path: &Path) -> bool {
+    read_to_string(path).map_or(true, |content| content.trim_start().starts_with('{'))
+}
+
+/// The first config on the search path. A `$ASSEMBLER_CONFIG` naming a file
+/// that doesn't exist is an error rather than skipped, so a typo in it
+/// doesn't silently pick another config. Files found elsewhere that aren't
+/// JSON are skipped and listed if no config is found.
+pub fn find_config(input_file: Option<&Path>) -> Result<PathBuf, ConfigError> {
+    let env_config = env_config();
+    let paths = search_paths(
+        env_config.clone(),
+        input_file,
+        user_config_dir(),
+        Path::new(""),
+    );
+    find_in(env_config.as_deref(), paths)
+}
+
+fn find_in(env_config: Option<&Path>, paths: Vec<PathBuf>) -> Result<PathBuf, ConfigError> {
+    if let Some(path) = env_config {
+        if !path.is_file() {
+            return Err(ConfigError::MissingEnvConfig(path.to_path_buf()));
+        }
+        return Ok(path.to_path_buf());
+    }
+    let mut skipped = vec![];
+    for path in paths.iter().filter(|path| path.is_file()) {
+        if is_json_config(path) {
+            return Ok(path.clone());
+        }
+        skipped.push(path.clone());
+    }
+    Err(ConfigError::NotFound(paths, skipped))
 }
 
 fn print_config_helper(prefix: String, node: &ConfigNode) {
@@ -52,9 +348,174 @@ pub enum ConfigNode {
     Branch(HashMap<NodeType, ConfigNode>),
 }
 
+/// Clock cycles an instruction takes. They only differ for conditional
+/// jumps, which take `max` when the jump is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycles {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// What the target has besides its instructions, from the optional
+/// `capabilities` entry of the config. Source that needs something the
+/// target lacks is rejected when it's checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct Capabilities {
+    /// Whether there's a stack for the STC operand to refer to.
+    pub has_stack: bool,
+    /// Whether memory takes 16-bit values and addresses, which `.word` and
+    /// MEM16 operands need.
+    pub has_wide_memory: bool,
+    /// Bits in an address: 8, 16 or 24 for the usual targets. It bounds
+    /// labels and `.org`, and address operands take as many bytes as it
+    /// needs.
+    pub address_width: u32,
+    /// Address multiple every instruction starts at, for CPUs that fetch
+    /// aligned words.
+    pub instruction_alignment: usize,
+    /// Whether an instruction that would start off its alignment is moved
+    /// up with `NOP`s rather than reported.
+    pub pad_instructions: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            has_stack: true,
+            has_wide_memory: true,
+            address_width: 16,
+            instruction_alignment: 1,
+            pad_instructions: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// One past the highest address.
+    pub fn address_limit(&self) -> usize {
+        1 << self.address_width
+    }
+
+    /// Bytes an address operand is encoded in.
+    pub fn address_bytes(&self) -> usize {
+        self.address_width.div_ceil(8) as usize
+    }
+}
+
+/// Where instructions may start: at multiples of `multiple`, padded up to
+/// with the `padding` opcode, or reported if there's none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionAlignment {
+    pub multiple: usize,
+    pub padding: Option<u8>,
+}
+
 #[derive(Debug)]
 pub struct Config {
-    pub automaton: HashMap<NodeType, ConfigNode>,
+    pub(crate) automaton: HashMap<NodeType, ConfigNode>,
+    /// Cycle counts by opcode, for the emulator.
+    #[cfg_attr(not(feature = "emulator"), allow(dead_code))]
+    pub(crate) cycles: HashMap<u8, Cycles>,
+    /// Opcodes of the forms marked `uses-stack`.
+    pub(crate) stack_opcodes: HashSet<u8>,
+    pub(crate) capabilities: Capabilities,
+    pub(crate) dialect: Dialect,
+    /// Instructions the hardware lacks, by mnemonic.
+    pub(crate) pseudo_instructions: BTreeMap<String, PseudoInstruction>,
+    /// Byte that forms missing from the config are filled with, at the size
+    /// of a known form with their operands, instead of being rejected. Set by
+    /// `--allow-unknown-instructions`, never by the config file.
+    pub(crate) unknown_instructions: Option<u8>,
+}
+
+impl OpcodeTable for Config {
+    fn opcode(&self, mnemonic: &Mnemonic, operands: &[Operand]) -> Result<u8, EncodeErr> {
+        let mut current_node = self
+            .automaton
+            .get(&NodeType::Mnemonic(mnemonic.clone()))
+            .ok_or(EncodeErr::UnknownMnemonic)?;
+
+        for (i, operand) in operands.iter().enumerate() {
+            match current_node {
+                ConfigNode::Branch(children) => match children.get(&NodeType::Operand(*operand)) {
+                    Some(next) => current_node = next,
+                    None => return Err(EncodeErr::InvalidOperand(i)),
+                },
+                _ => {
+                    unreachable!();
+                }
+            };
+        }
+
+        let ConfigNode::Branch(leaf) = current_node else {
+            unreachable!();
+        };
+
+        match leaf.get(&NodeType::MachineCode) {
+            Some(ConfigNode::Leaf(mnemonic_code)) => Ok(binary_str_to_byte(mnemonic_code)),
+            Some(_) => unreachable!(),
+            None => Err(EncodeErr::Incomplete),
+        }
+    }
+}
+
+/// A single accepted instruction form: mnemonic, operand kinds and opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub mnemonic: Mnemonic,
+    pub operands: Vec<Operand>,
+    pub opcode: String,
+    /// Bytes an address operand takes on the config's target.
+    pub address_bytes: usize,
+}
+
+impl Signature {
+    pub fn encoded_size(&self) -> usize {
+        1 + self
+            .operands
+            .iter()
+            .map(|operand| operand.encoded_size(self.address_bytes))
+            .sum::<usize>()
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        for operand in &self.operands {
+            write!(f, " {}", operand)?;
+        }
+        write!(f, " ({})", self.opcode)
+    }
+}
+
+fn collect_signatures(
+    mnemonic: &Mnemonic,
+    operands: &mut Vec<Operand>,
+    node: &ConfigNode,
+    address_bytes: usize,
+    signatures: &mut Vec<Signature>,
+) {
+    let ConfigNode::Branch(children) = node else {
+        return;
+    };
+    for (key, child) in children {
+        match (key, child) {
+            (NodeType::MachineCode, ConfigNode::Leaf(opcode)) => signatures.push(Signature {
+                mnemonic: mnemonic.clone(),
+                operands: operands.clone(),
+                opcode: opcode.clone(),
+                address_bytes,
+            }),
+            (NodeType::Operand(operand), _) => {
+                operands.push(*operand);
+                collect_signatures(mnemonic, operands, child, address_bytes, signatures);
+                operands.pop();
+            }
+            _ => {}
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -64,9 +525,264 @@ struct InstructionJsonObj {
     arguments: Vec<String>,
     opcode: String,
     depend_on_flag: String,
+    min_cycles_number: usize,
+    max_cycles_number: usize,
+    /// Whether the form pushes or pops, which targets without a stack
+    /// reject.
+    #[serde(default)]
+    uses_stack: bool,
+}
+
+/// Key of the config entry holding the capabilities instead of an
+/// instruction.
+const CAPABILITIES_KEY: &str = "capabilities";
+
+/// Key of the config entry holding the [`Dialect`].
+const DIALECT_KEY: &str = "dialect";
+
+/// Key of the config entry holding the [`PseudoInstruction`]s.
+const PSEUDO_KEY: &str = "pseudo-instructions";
+
+/// The config file, read entry by entry so instructions are deserialized
+/// straight into their objects.
+struct ConfigJson {
+    capabilities: Capabilities,
+    dialect: Dialect,
+    pseudo_instructions: BTreeMap<String, PseudoInstruction>,
+    instructions: BTreeMap<String, InstructionJsonObj>,
+}
+
+impl<'de> Deserialize<'de> for ConfigJson {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ConfigVisitor;
+
+        impl<'de> Visitor<'de> for ConfigVisitor {
+            type Value = ConfigJson;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a map of instructions by name")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ConfigJson, A::Error> {
+                let mut config = ConfigJson {
+                    capabilities: Capabilities::default(),
+                    dialect: Dialect::default(),
+                    pseudo_instructions: BTreeMap::new(),
+                    instructions: BTreeMap::new(),
+                };
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        CAPABILITIES_KEY => config.capabilities = map.next_value()?,
+                        DIALECT_KEY => config.dialect = map.next_value()?,
+                        PSEUDO_KEY => config.pseudo_instructions = map.next_value()?,
+                        _ => {
+                            config.instructions.insert(key, map.next_value()?);
+                        }
+                    }
+                }
+                Ok(config)
+            }
+        }
+
+        deserializer.deserialize_map(ConfigVisitor)
+    }
+}
+
+fn is_opcode(opcode: &str) -> bool {
+    let digits = opcode.strip_prefix("0b").unwrap_or(opcode);
+    digits.len() == 8 && digits.chars().all(|digit| digit == '0' || digit == '1')
+}
+
+fn is_flag(flag: &str) -> bool {
+    let flag = flag.strip_prefix('N').unwrap_or(flag);
+    FLAG_NAMES.contains(&flag)
+}
+
+/// Whether the lexer reads `word` as one name, optionally after a `.`.
+fn is_name(word: &str) -> bool {
+    let mut chars = word.strip_prefix('.').unwrap_or(word).chars();
+    chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|char| char.is_alphanumeric() || char == '_')
+}
+
+/// Checks what the automaton can't represent or would silently overwrite:
+/// malformed or shared opcodes, conditions on flags that don't exist and
+/// address widths that don't fit, and dialect words the lexer can't use.
+fn validate(config: &ConfigJson) -> Result<(), ConfigError> {
+    let width = config.capabilities.address_width;
+    if !(1..=MAX_ADDRESS_WIDTH).contains(&width) {
+        return Err(ConfigError::InvalidAddressWidth(width));
+    }
+    let dialect = &config.dialect;
+    let names = dialect.aliases.keys().chain(&dialect.reserved);
+    if let Some(name) = names.into_iter().find(|name| !is_name(name)) {
+        return Err(ConfigError::InvalidDialectName(name.clone()));
+    }
+    for (alias, target) in &dialect.aliases {
+        if directive(target).is_none() {
+            return Err(ConfigError::UnknownAliasTarget(
+                alias.clone(),
+                target.clone(),
+            ));
+        }
+    }
+    for (name, pseudo) in &config.pseudo_instructions {
+        let invalid =
+            |reason: &str| ConfigError::InvalidPseudoInstruction(name.clone(), reason.to_string());
+        let shadows = config.instructions.values().any(|instruction| {
+            format!("{}{}", instruction.mnemonic, instruction.depend_on_flag) == *name
+        });
+        if !is_name(name) || name.starts_with('.') {
+            return Err(invalid("its name isn't a mnemonic"));
+        }
+        if shadows {
+            return Err(invalid("an instruction already has its name"));
+        }
+        if let Some(parameter) = pseudo
+            .parameters
+            .iter()
+            .find(|parameter| !is_name(parameter))
+        {
+            return Err(invalid(&format!("parameter '{}' isn't a name", parameter)));
+        }
+        pseudo.template().map_err(|reason| invalid(&reason))?;
+    }
+    let capabilities = &config.capabilities;
+    if capabilities.instruction_alignment == 0 {
+        return Err(ConfigError::InvalidInstructionAlignment(0));
+    }
+    let has_nop = config.instructions.values().any(|instruction| {
+        instruction.mnemonic == "NOP"
+            && instruction.arguments.is_empty()
+            && instruction.depend_on_flag.is_empty()
+    });
+    if capabilities.pad_instructions && capabilities.instruction_alignment > 1 && !has_nop {
+        return Err(ConfigError::NoPaddingNop);
+    }
+    let mut opcodes = HashMap::new();
+    for (name, instruction) in &config.instructions {
+        if !is_opcode(&instruction.opcode) {
+            return Err(ConfigError::InvalidOpcode(
+                name.clone(),
+                instruction.opcode.clone(),
+            ));
+        }
+        let flag = &instruction.depend_on_flag;
+        if !flag.is_empty() && !is_flag(flag) {
+            return Err(ConfigError::UnknownFlag(name.clone(), flag.clone()));
+        }
+        if let Some(first) = opcodes.insert(binary_str_to_byte(&instruction.opcode), name) {
+            return Err(ConfigError::DuplicateOpcode(
+                first.clone(),
+                name.clone(),
+                instruction.opcode.clone(),
+            ));
+        }
+    }
+    Ok(())
 }
 
 impl Config {
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn dialect(&self) -> &Dialect {
+        &self.dialect
+    }
+
+    /// Fills forms missing from the config with `fill`, at the size of a
+    /// known form with their operands, instead of rejecting them.
+    pub fn set_unknown_instructions(&mut self, fill: Option<u8>) {
+        self.unknown_instructions = fill;
+    }
+
+    /// Enumerates every instruction form accepted by the automaton.
+    pub fn signatures(&self) -> Vec<Signature> {
+        let mut signatures = vec![];
+        for (key, node) in &self.automaton {
+            if let NodeType::Mnemonic(mnemonic) = key {
+                collect_signatures(
+                    mnemonic,
+                    &mut vec![],
+                    node,
+                    self.capabilities.address_bytes(),
+                    &mut signatures,
+                );
+            }
+        }
+        signatures
+    }
+
+    /// Every mnemonic the source may use, the pseudo-instructions' included.
+    pub fn mnemonics(&self) -> BTreeSet<String> {
+        let instructions = self.automaton.keys().filter_map(|key| match key {
+            NodeType::Mnemonic(mnemonic) => Some(mnemonic.name().to_string()),
+            _ => None,
+        });
+        instructions
+            .chain(self.pseudo_instructions.keys().cloned())
+            .collect()
+    }
+
+    /// Opcode of every accepted form, by mnemonic and operand kinds.
+    pub fn encodings(&self) -> HashMap<(Mnemonic, Vec<Operand>), u8> {
+        self.signatures()
+            .into_iter()
+            .map(|signature| {
+                let opcode = binary_str_to_byte(&signature.opcode);
+                ((signature.mnemonic, signature.operands), opcode)
+            })
+            .collect()
+    }
+
+    /// Where instructions may start, see [`Capabilities::instruction_alignment`].
+    pub fn instruction_alignment(&self) -> InstructionAlignment {
+        let capabilities = &self.capabilities;
+        let nop = || self.opcode(&Mnemonic::new("NOP".to_string()), &[]).ok();
+        InstructionAlignment {
+            multiple: capabilities.instruction_alignment,
+            padding: capabilities.pad_instructions.then(nop).flatten(),
+        }
+    }
+
+    /// Largest number of operands any form of `mnemonic` takes, or `None` if
+    /// the mnemonic isn't in the config.
+    pub fn max_arity(&self, mnemonic: &Mnemonic) -> Option<usize> {
+        fn depth(node: &ConfigNode) -> usize {
+            match node {
+                ConfigNode::Leaf(_) => 0,
+                ConfigNode::Branch(children) => children
+                    .iter()
+                    .map(|(key, child)| match key {
+                        NodeType::Operand(_) => 1 + depth(child),
+                        _ => 0,
+                    })
+                    .max()
+                    .unwrap_or(0),
+            }
+        }
+
+        self.automaton
+            .get(&NodeType::Mnemonic(mnemonic.clone()))
+            .map(depth)
+    }
+
+    /// Bytes an instruction with these operand kinds encodes to, or `None`
+    /// if no form of `mnemonic` takes them and they aren't filled in, see
+    /// [`Config::set_unknown_instructions`].
+    pub fn instruction_size(&self, mnemonic: &Mnemonic, operands: &[Operand]) -> Option<usize> {
+        if self.unknown_instructions.is_none() {
+            self.opcode(mnemonic, operands).ok()?;
+        }
+        let address_bytes = self.capabilities.address_bytes();
+        let operand_bytes = operands
+            .iter()
+            .map(|operand| operand.encoded_size(address_bytes))
+            .sum::<usize>();
+        Some(1 + operand_bytes)
+    }
+
     // fn parse_instruction(instruction: &str) -> Result<Vec<NodeType>, String> {
     //     let mut iter = instruction.split_whitespace();
     //     let mut nodes: Vec<NodeType> = vec![];
@@ -86,19 +802,52 @@ impl Config {
     //     Ok(nodes)
     // }
 
-    pub fn read_from_file(file_path: impl AsRef<Path>) -> Result<Self, ConfigError> {
-        let mut automaton = HashMap::new();
+    /// Reads a config from its JSON after applying `overrides` in order.
+    pub fn with_overrides(
+        content: &str,
+        overrides: &[ConfigOverride],
+    ) -> Result<Self, ConfigError> {
+        if overrides.is_empty() {
+            return Self::from_json(content);
+        }
+        let mut config = serde_json::from_str(content).map_err(ConfigError::ParseError)?;
+        for config_override in overrides {
+            config_override.apply(&mut config)?;
+        }
+        Self::from_json(&config.to_string())
+    }
 
+    pub fn read_from_file(file_path: impl AsRef<Path>) -> Result<Self, ConfigError> {
         let content = read_to_string(file_path).map_err(ConfigError::ReadFileError)?;
+        Self::from_json(&content)
+    }
 
-        let json_objects: HashMap<String, Value> = serde_json::from_str(&content).unwrap();
+    /// The instruction set embedded in the assembler.
+    pub fn builtin() -> Self {
+        Self::from_json(DEFAULT_CONFIG).expect("the embedded config is valid")
+    }
 
-        let instructions: Vec<InstructionJsonObj> = json_objects
-            .iter()
-            .map(|(_, v)| serde_json::from_value(v.clone()).unwrap())
-            .collect();
+    pub fn from_json(content: &str) -> Result<Self, ConfigError> {
+        let mut automaton = HashMap::new();
+        let mut cycles = HashMap::new();
+        let mut stack_opcodes = HashSet::new();
 
-        for instruction in &instructions {
+        // Deserializing straight into the instruction objects avoids building
+        // and cloning a `serde_json::Value` tree first. Sorting them by name
+        // keeps the reported error the same from run to run.
+        let config: ConfigJson = serde_json::from_str(content).map_err(ConfigError::ParseError)?;
+        validate(&config)?;
+        let ConfigJson {
+            capabilities,
+            dialect,
+            pseudo_instructions,
+            instructions,
+        } = config;
+
+        // Which instruction each form came from, to name both in an error.
+        let mut signatures = HashMap::new();
+
+        for (name, instruction) in &instructions {
             let mnemonic = Mnemonic::new(format!(
                 "{}{}",
                 instruction.mnemonic, instruction.depend_on_flag
@@ -108,11 +857,22 @@ impl Config {
                 .arguments
                 .iter()
                 .map(|operand| {
-                    Operand::from_str(operand)
-                        .map_err(|_| ConfigError::UnknownOperand(operand.clone()))
+                    Operand::from_str(operand).map_err(|_| ConfigError::unknown_operand(operand))
                 })
                 .collect::<Result<Vec<Operand>, ConfigError>>()?;
 
+            if let Some(first) = signatures.insert((mnemonic.clone(), operands.clone()), name) {
+                let form = std::iter::once(mnemonic.to_string())
+                    .chain(operands.iter().map(Operand::to_string))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                return Err(ConfigError::DuplicateSignature(
+                    first.clone(),
+                    name.clone(),
+                    form,
+                ));
+            }
+
             let mut current = &mut automaton;
 
             for part in vec![NodeType::Mnemonic(mnemonic)]
@@ -128,14 +888,283 @@ impl Config {
                 }
             }
 
-            let prev = current.insert(
+            current.insert(
                 NodeType::MachineCode,
                 ConfigNode::Leaf(instruction.opcode.clone()),
             );
 
-            assert!(prev.is_none());
+            let opcode = binary_str_to_byte(&instruction.opcode);
+            cycles.insert(
+                opcode,
+                Cycles {
+                    min: instruction.min_cycles_number,
+                    max: instruction.max_cycles_number,
+                },
+            );
+            if instruction.uses_stack {
+                stack_opcodes.insert(opcode);
+            }
         }
 
-        Ok(Self { automaton })
+        Ok(Self {
+            automaton,
+            cycles,
+            stack_opcodes,
+            capabilities,
+            dialect,
+            pseudo_instructions,
+            unknown_instructions: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::specs::Register;
+
+    fn instruction(name: &str, mnemonic: &str, opcode: &str, flag: &str) -> String {
+        format!(
+            r#""{name}": {{"mnemonic": "{mnemonic}", "arguments": [], "opcode": "{opcode}",
+            "depend-on-flag": "{flag}", "min-cycles-number": 3, "max-cycles-number": 3}}"#
+        )
+    }
+
+    fn load(instructions: &[String]) -> Result<Config, ConfigError> {
+        Config::from_json(&format!("{{{}}}", instructions.join(",")))
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(!Config::builtin().signatures().is_empty());
+
+        let nop = instruction("NOP", "NOP", "0b00000000", "");
+        assert!(load(&[nop.clone(), instruction("HALT", "HALT", "0b11111111", "")]).is_ok());
+
+        let err = load(&[nop.clone(), instruction("HALT", "HALT", "0b00000000", "")]).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::DuplicateOpcode(ref first, ref second, _)
+            if first == "HALT" && second == "NOP")
+        );
+
+        let err = load(&[nop.clone(), instruction("NOP2", "NOP", "0b00000001", "")]).unwrap_err();
+        assert!(matches!(err, ConfigError::DuplicateSignature(_, _, ref form) if form == "NOP"));
+
+        let err = load(&[instruction("NOP", "NOP", "0b0000000", "")]).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidOpcode(..)));
+
+        let err = load(&[instruction("JMPQ", "JMP", "0b00000001", "Q")]).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownFlag(_, ref flag) if flag == "Q"));
+        assert!(load(&[instruction("JMPNZ", "JMP", "0b00000001", "NZ")]).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_operand() {
+        let push = |operand: &str| {
+            instruction("PUSH", "PUSH", "0b00000001", "")
+                .replace("[]", &format!("[\"{}\"]", operand))
+        };
+        assert!(load(&[push("MEMZP")]).is_ok());
+
+        let err = load(&[push("mem16")]).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::UnknownOperand(_, Some(ref nearest)) if nearest == "MEM16")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Unknown operand 'mem16' (did you mean 'MEM16'?), expected one of \
+             A, B, F, T, TL, TH, MEM8/MEMZP, MEM16/MEM, CONST, STC."
+        );
+        let err = load(&[push("REGISTER")]).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownOperand(_, None)));
+    }
+
+    #[test]
+    fn test_config_search_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["env", "project", "user/assembler", "cwd"] {
+            std::fs::create_dir_all(path(name)).unwrap();
+        }
+        let paths = |env_config: Option<PathBuf>| {
+            search_paths(
+                env_config,
+                Some(&path("project/main.as")),
+                Some(path("user")),
+                &path("cwd"),
+            )
+        };
+
+        let env_config = path("env/isa.json");
+        assert_eq!(
+            paths(Some(env_config.clone())),
+            [
+                env_config.clone(),
+                path("project/config.cfg"),
+                path("user/assembler/config.cfg"),
+                path("cwd/config.cfg"),
+            ]
+        );
+
+        let err = find_in(None, paths(None)).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::NotFound(ref searched, ref skipped)
+            if searched.len() == 3 && skipped.is_empty())
+        );
+
+        std::fs::write(path("cwd/config.cfg"), "{}").unwrap();
+        assert_eq!(find_in(None, paths(None)).unwrap(), path("cwd/config.cfg"));
+        std::fs::write(path("user/assembler/config.cfg"), "{}").unwrap();
+        assert_eq!(
+            find_in(None, paths(None)).unwrap(),
+            path("user/assembler/config.cfg")
+        );
+        std::fs::write(path("project/config.cfg"), "{}").unwrap();
+        assert_eq!(
+            find_in(None, paths(None)).unwrap(),
+            path("project/config.cfg")
+        );
+
+        let err = find_in(Some(&env_config), paths(Some(env_config.clone()))).unwrap_err();
+        assert!(matches!(err, ConfigError::MissingEnvConfig(_)));
+        std::fs::write(&env_config, "{}").unwrap();
+        assert_eq!(
+            find_in(Some(&env_config), paths(Some(env_config.clone()))).unwrap(),
+            env_config
+        );
+    }
+
+    #[test]
+    fn test_config_search_skips_non_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join(CONFIG_FILE_NAME);
+        std::fs::write(&config, "NOP,            NOP\t        ,00000000\n").unwrap();
+        let paths = search_paths(None, None, None, dir.path());
+
+        let err = find_in(None, paths.clone()).unwrap_err();
+        assert!(
+            matches!(err, ConfigError::NotFound(_, ref skipped) if *skipped == [config.clone()])
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "No config file found, searched: '{0}'. Skipped '{0}', not JSON configs.",
+                config.display()
+            )
+        );
+
+        // A broken JSON config is still picked, to report what's wrong with it.
+        std::fs::write(&config, "{\"NOP\": ").unwrap();
+        assert_eq!(find_in(None, paths).unwrap(), config);
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(Config::builtin().capabilities, Capabilities::default());
+
+        let nop = instruction("NOP", "NOP", "0b00000000", "");
+        let config = Config::from_json(&format!(
+            r#"{{"capabilities": {{"has-wide-memory": false, "address-width": 8}}, {}}}"#,
+            nop
+        ))
+        .unwrap();
+        assert_eq!(
+            config.capabilities,
+            Capabilities {
+                has_stack: true,
+                has_wide_memory: false,
+                address_width: 8,
+                ..Capabilities::default()
+            }
+        );
+        assert_eq!(config.capabilities.address_limit(), 0x100);
+        assert_eq!(config.signatures().len(), 1);
+
+        let err = Config::from_json(r#"{"capabilities": {"address-width": 25}}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidAddressWidth(25)));
+        let err = Config::from_json(r#"{"capabilities": {"stack": true}}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::ParseError(_)));
+    }
+
+    #[test]
+    fn test_address_width() {
+        let assemble = |source: &str, width: u32| {
+            let mut config = Config::builtin();
+            config.capabilities.address_width = width;
+            crate::assemble(source, &config, Default::default()).map(|assembly| assembly.image)
+        };
+
+        let image = assemble("MOV A [0x12345]", 24).unwrap();
+        assert_eq!(image[1..], [0x45, 0x23, 0x01]);
+        let image = assemble("MOV A [0x12]", 8).unwrap();
+        assert_eq!(image[1..], [0x12]);
+        assert!(assemble("MOV A [0x123]", 8).is_err());
+        assert!(assemble(".org 0x100", 8).is_err());
+    }
+    #[test]
+    fn test_instruction_alignment() {
+        let assemble = |pad: bool| {
+            let mut config = Config::builtin();
+            config.capabilities.instruction_alignment = 2;
+            config.capabilities.pad_instructions = pad;
+            crate::assemble("MOV A 1\nHALT\nloop:\nMOV A 2", &config, Default::default())
+        };
+
+        let assembly = assemble(true).unwrap();
+        assert_eq!(assembly.image.len(), 6);
+        assert_eq!(assembly.image[3], 0xd4);
+        assert_eq!(assembly.symbols["loop"], 4);
+        assert!(assemble(false).is_err());
+
+        let err = Config::from_json(
+            r#"{"capabilities": {"instruction-alignment": 2, "pad-instructions": true}}"#,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ConfigError::NoPaddingNop));
+    }
+    #[test]
+    fn test_config_overrides() {
+        let overrides = [
+            "instructions.MOV-A-CONST.opcode=11111111",
+            "instructions.HALT.min-cycles-number=9",
+            "capabilities.has-stack=false",
+        ]
+        .map(|arg| parse_config_override(arg).unwrap());
+        let config = Config::with_overrides(DEFAULT_CONFIG, &overrides).unwrap();
+        let mov = Mnemonic::new("MOV".to_string());
+        let operands = [Operand::Register(Register::A), Operand::Const];
+        assert_eq!(config.opcode(&mov, &operands), Ok(0xff));
+        assert_eq!(config.cycles[&0xd8].min, 9);
+        assert!(!config.capabilities.has_stack);
+
+        let typo = parse_config_override("instructions.HALT.opcdoe=1").unwrap();
+        let err = Config::with_overrides(DEFAULT_CONFIG, &[typo]).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownOverrideTarget(_)));
+        assert!(parse_config_override("capabilities..has-stack=1").is_err());
+    }
+    #[test]
+    fn test_dialect() {
+        let nop = instruction("NOP", "NOP", "0b00000000", "");
+        let config = Config::from_json(&format!(
+            r#"{{"dialect": {{"aliases": {{"db": ".byte", ".dw": "word"}}, "reserved": ["end"]}}, {}}}"#,
+            nop
+        ))
+        .unwrap();
+        assert_eq!(config.dialect.aliases["db"], ".byte");
+        assert_eq!(config.dialect.reserved, ["end"]);
+
+        let err = Config::from_json(r#"{"dialect": {"aliases": {"db": ".bytes"}}}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownAliasTarget(alias, _) if alias == "db"));
+        let err = Config::from_json(r#"{"dialect": {"reserved": ["a b"]}}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidDialectName(_)));
+
+        let mut config = Config::builtin();
+        config.dialect = Dialect {
+            aliases: BTreeMap::from([("db".to_string(), ".byte".to_string())]),
+            reserved: vec!["end".to_string()],
+        };
+        let assembly = crate::assemble("db 1 2\nHALT", &config, Default::default()).unwrap();
+        assert_eq!(assembly.image[..2], [1, 2]);
+        assert!(crate::assemble("end: HALT", &config, Default::default()).is_err());
     }
 }
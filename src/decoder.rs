@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{config::Signature, encoder::binary_str_to_byte, specs::Operand};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum DecodeErr {
+    #[error("Unknown opcode {0:#010b} at address {1:#06x}.")]
+    UnknownOpcode(u8, usize),
+    #[error("Opcode {0:#010b} is shared by {1} instructions.")]
+    AmbiguousOpcode(u8, usize),
+    #[error("Instruction at address {0:#06x} is missing {1} operand byte(s).")]
+    Truncated(usize, usize),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DecodedInstruction<'a> {
+    pub address: usize,
+    pub signature: &'a Signature,
+    pub operand_bytes: Vec<u8>,
+}
+
+impl DecodedInstruction<'_> {
+    /// The operands with their values, read from the bytes after the opcode.
+    pub fn operand_values(&self) -> Vec<(Operand, usize)> {
+        let mut bytes = self.operand_bytes.iter().copied();
+        self.signature
+            .operands
+            .iter()
+            .map(|operand| {
+                let size = operand.encoded_size(self.signature.address_bytes);
+                let value = bytes
+                    .by_ref()
+                    .take(size)
+                    .enumerate()
+                    .fold(0, |value, (i, byte)| value | (byte as usize) << (8 * i));
+                (*operand, value)
+            })
+            .collect()
+    }
+}
+
+/// Inverts the config automaton into an opcode -> instruction form table.
+pub struct Decoder<'a> {
+    table: HashMap<u8, Vec<&'a Signature>>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(signatures: &'a [Signature]) -> Self {
+        let mut table: HashMap<u8, Vec<&'a Signature>> = HashMap::new();
+        for signature in signatures {
+            table
+                .entry(binary_str_to_byte(&signature.opcode))
+                .or_default()
+                .push(signature);
+        }
+        Self { table }
+    }
+
+    pub fn decode_one(
+        &self,
+        bytes: &[u8],
+        address: usize,
+    ) -> Result<DecodedInstruction<'a>, DecodeErr> {
+        let opcode = bytes[address];
+        let signature = match self.table.get(&opcode).map(Vec::as_slice) {
+            Some([signature]) => *signature,
+            Some(signatures) => return Err(DecodeErr::AmbiguousOpcode(opcode, signatures.len())),
+            None => return Err(DecodeErr::UnknownOpcode(opcode, address)),
+        };
+
+        let end = address + signature.encoded_size();
+        if end > bytes.len() {
+            return Err(DecodeErr::Truncated(address, end - bytes.len()));
+        }
+
+        Ok(DecodedInstruction {
+            address,
+            signature,
+            operand_bytes: bytes[address + 1..end].to_vec(),
+        })
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<Vec<DecodedInstruction<'a>>, DecodeErr> {
+        let mut instructions = vec![];
+        let mut address = 0;
+        while address < bytes.len() {
+            let instruction = self.decode_one(bytes, address)?;
+            address += instruction.signature.encoded_size();
+            instructions.push(instruction);
+        }
+        Ok(instructions)
+    }
+}
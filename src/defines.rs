@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs::read_to_string, io, path::Path};
+
+use thiserror::Error;
+
+use crate::lexer::parse_number;
+
+#[derive(Error, Debug)]
+pub enum DefinesError {
+    #[error("{0}.")]
+    ReadFileError(io::Error),
+    #[error("Failed to parse defines on line {0}: expected NAME=VALUE, found \"{1}\".")]
+    MalformedLine(usize, String),
+    #[error("Failed to parse defines on line {0}: invalid constant name '{1}'.")]
+    InvalidName(usize, String),
+    #[error("Failed to parse defines on line {0}: couldn't parse value '{1}'.")]
+    NumberParseError(usize, String),
+    #[error("Invalid constant name '{0}' in -D.")]
+    InvalidArgName(String),
+    #[error("Couldn't parse value '{0}' in -D.")]
+    InvalidArgValue(String),
+    #[error("Constant '{0}' is defined more than once.")]
+    DuplicateDefine(String),
+    #[error("Constant '{0}' collides with a label of the same name.")]
+    CollidesWithLabel(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Define {
+    pub name: String,
+    pub value: usize,
+}
+
+fn parse_value(value: &str) -> Option<usize> {
+    parse_number(value).and_then(|value| usize::try_from(value).ok())
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    (first.is_alphabetic() || first == '_') && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Parses `NAME=VALUE` pairs, one per line. Empty lines and lines starting
+/// with `#` or `//` are skipped.
+pub fn parse_defines(content: &str) -> Result<Vec<Define>, DefinesError> {
+    let mut defines = vec![];
+
+    for (line_nr, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let Some((name, value)) = line.split_once('=') else {
+            return Err(DefinesError::MalformedLine(line_nr + 1, line.to_string()));
+        };
+        let (name, value) = (name.trim(), value.trim());
+
+        if !is_valid_name(name) {
+            return Err(DefinesError::InvalidName(line_nr + 1, name.to_string()));
+        }
+
+        let value = parse_value(value)
+            .ok_or_else(|| DefinesError::NumberParseError(line_nr + 1, value.to_string()))?;
+
+        defines.push(Define {
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    Ok(defines)
+}
+
+/// Parses a `-D NAME[=VALUE]` argument; the value defaults to 1 so bare
+/// names can be tested with `.if`.
+pub fn parse_define(arg: &str) -> Result<Define, DefinesError> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+    let (name, value) = (name.trim(), value.trim());
+
+    if !is_valid_name(name) {
+        return Err(DefinesError::InvalidArgName(name.to_string()));
+    }
+    let value =
+        parse_value(value).ok_or_else(|| DefinesError::InvalidArgValue(value.to_string()))?;
+
+    Ok(Define {
+        name: name.to_string(),
+        value,
+    })
+}
+
+pub fn read_defines_from_file(file_path: impl AsRef<Path>) -> Result<Vec<Define>, DefinesError> {
+    let content = read_to_string(file_path).map_err(DefinesError::ReadFileError)?;
+    parse_defines(&content)
+}
+
+/// A warning for a constant wider than the target's addresses, which keep
+/// only its low `address_width` bits.
+pub fn truncation_warning(define: &Define, address_width: u32) -> Option<String> {
+    (define.value >> address_width != 0).then(|| {
+        format!(
+            "Constant '{}' = {:#x} doesn't fit in {} bits and will be truncated.",
+            define.name, define.value, address_width
+        )
+    })
+}
+
+/// Adds the constants to the resolved label table so they can be referenced
+/// with the same `#NAME` syntax as labels.
+pub fn inject_defines<'a>(
+    labels: &mut HashMap<&'a str, usize>,
+    defines: &'a [Define],
+) -> Result<(), DefinesError> {
+    for (i, define) in defines.iter().enumerate() {
+        if defines[..i].iter().any(|other| other.name == define.name) {
+            return Err(DefinesError::DuplicateDefine(define.name.clone()));
+        }
+        if labels.contains_key(define.name.as_str()) {
+            return Err(DefinesError::CollidesWithLabel(define.name.clone()));
+        }
+        labels.insert(&define.name, define.value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defines() {
+        let content = "# generated\nUART_BASE = 0x8000\n\n// comment\nLEDS=12\n";
+        let defines = parse_defines(content).unwrap();
+        assert_eq!(
+            defines,
+            [
+                Define {
+                    name: "UART_BASE".to_string(),
+                    value: 0x8000
+                },
+                Define {
+                    name: "LEDS".to_string(),
+                    value: 12
+                }
+            ]
+        );
+
+        let err = parse_defines("A=1\nB=twelve").unwrap_err();
+        assert!(matches!(err, DefinesError::NumberParseError(2, ref value) if value == "twelve"));
+        let err = parse_defines("LEDS").unwrap_err();
+        assert!(matches!(err, DefinesError::MalformedLine(1, _)));
+        let err = parse_defines("1LEDS=1").unwrap_err();
+        assert!(matches!(err, DefinesError::InvalidName(1, _)));
+    }
+
+    #[test]
+    fn test_parse_define() {
+        let define = parse_define("DEBUG").unwrap();
+        assert_eq!((define.name.as_str(), define.value), ("DEBUG", 1));
+        let define = parse_define("BASE=0x10").unwrap();
+        assert_eq!((define.name.as_str(), define.value), ("BASE", 0x10));
+        assert!(matches!(
+            parse_define("BASE=x"),
+            Err(DefinesError::InvalidArgValue(_))
+        ));
+        assert!(matches!(
+            parse_define("=1"),
+            Err(DefinesError::InvalidArgName(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncation_warning() {
+        let define = Define {
+            name: "BIG".to_string(),
+            value: 0x12345,
+        };
+        assert_eq!(
+            truncation_warning(&define, 16).unwrap(),
+            "Constant 'BIG' = 0x12345 doesn't fit in 16 bits and will be truncated."
+        );
+        assert_eq!(truncation_warning(&define, 24), None);
+    }
+}
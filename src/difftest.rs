@@ -0,0 +1,255 @@
+//! Runs a program on the emulator and on a reference simulator and compares
+//! the register state after every instruction, so a wrong config or a
+//! wrong emulator shows up as the first step where the two disagree.
+//!
+//! The simulator is a shell command. It gets one JSON line on stdin,
+//!
+//! ```json
+//! {"image": [202, 42, 216], "max_steps": 1000000}
+//! ```
+//!
+//! with the image loaded at address 0, and answers with one JSON line per
+//! instruction it executes, holding the state after it,
+//!
+//! ```json
+//! {"pc": 2, "a": 0, "b": 0, "f": 0, "th": 0, "tl": 0, "stc": 65534}
+//! ```
+//!
+//! stopping after `HALT` or `max_steps` instructions.
+
+use std::{
+    fmt::Display,
+    io::{ErrorKind, Write},
+    process::Stdio,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    decoder::Decoder,
+    emulator::{check_address_width, EmulatorErr, Machine},
+    manifest::shell,
+};
+
+#[derive(Debug, Error)]
+pub enum DifftestErr {
+    #[error("Couldn't run simulator '{0}': {1}.")]
+    Spawn(String, String),
+    #[error("Simulator '{0}' failed with {1}.")]
+    Failed(String, String),
+    #[error("Simulator '{0}' wrote an invalid trace line {1}: {2}.")]
+    InvalidTrace(String, usize, String),
+}
+
+/// The registers after one instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub a: u8,
+    pub b: u8,
+    pub f: u8,
+    pub th: u8,
+    pub tl: u8,
+    pub stc: u16,
+}
+
+impl TraceEntry {
+    fn registers(&self) -> [(&'static str, u16); 7] {
+        [
+            ("PC", self.pc),
+            ("A", self.a as u16),
+            ("B", self.b as u16),
+            ("F", self.f as u16),
+            ("TH", self.th as u16),
+            ("TL", self.tl as u16),
+            ("STC", self.stc),
+        ]
+    }
+}
+
+impl From<&Machine> for TraceEntry {
+    fn from(machine: &Machine) -> Self {
+        Self {
+            pc: machine.pc,
+            a: machine.a,
+            b: machine.b,
+            f: machine.f,
+            th: machine.th,
+            tl: machine.tl,
+            stc: machine.stc,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    image: &'a [u8],
+    max_steps: usize,
+}
+
+/// The emulator's trace of `image`, up to `HALT` or `max_steps`
+/// instructions.
+pub fn emulator_trace(
+    config: &Config,
+    image: &[u8],
+    max_steps: usize,
+) -> Result<Vec<TraceEntry>, EmulatorErr> {
+    check_address_width(config)?;
+    let signatures = config.signatures();
+    let decoder = Decoder::new(&signatures);
+    let mut machine = Machine::new(image);
+    let mut trace = vec![];
+    while trace.len() < max_steps {
+        let halted = machine.step(config, &decoder)?;
+        trace.push(TraceEntry::from(&machine));
+        if halted {
+            break;
+        }
+    }
+    Ok(trace)
+}
+
+/// The trace the simulator `command` reports for `image`.
+pub fn simulator_trace(
+    command: &str,
+    image: &[u8],
+    max_steps: usize,
+) -> Result<Vec<TraceEntry>, DifftestErr> {
+    let spawn_err = |err: std::io::Error| DifftestErr::Spawn(command.to_string(), err.to_string());
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(spawn_err)?;
+
+    let request =
+        serde_json::to_string(&Request { image, max_steps }).expect("a request always serializes");
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // A simulator that doesn't read its input is judged by its trace.
+    match writeln!(stdin, "{}", request) {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(spawn_err(err)),
+        _ => drop(stdin),
+    }
+
+    let output = child.wait_with_output().map_err(spawn_err)?;
+    if !output.status.success() {
+        return Err(DifftestErr::Failed(
+            command.to_string(),
+            output.status.to_string(),
+        ));
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|err| {
+                DifftestErr::InvalidTrace(command.to_string(), i + 1, err.to_string())
+            })
+        })
+        .collect()
+}
+
+/// The first step the traces disagree at, `None` on the side whose trace
+/// already ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: usize,
+    /// Address of the instruction executed in that step.
+    pub address: u16,
+    pub emulator: Option<TraceEntry>,
+    pub simulator: Option<TraceEntry>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "step {} at address {:#06x}: ",
+            self.step + 1,
+            self.address
+        )?;
+        let (emulator, simulator) = match (&self.emulator, &self.simulator) {
+            (Some(emulator), Some(simulator)) => (emulator, simulator),
+            (Some(_), None) => return write!(f, "the simulator stopped, the emulator didn't"),
+            (None, _) => return write!(f, "the emulator stopped, the simulator didn't"),
+        };
+        let differences = emulator
+            .registers()
+            .iter()
+            .zip(simulator.registers())
+            .filter(|((_, emulated), (_, simulated))| emulated != simulated)
+            .map(|((name, emulated), (_, simulated))| {
+                format!(
+                    "{} is {:#x} emulated, {:#x} simulated",
+                    name, emulated, simulated
+                )
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", differences.join(", "))
+    }
+}
+
+/// Compares the traces step by step.
+pub fn first_divergence(emulator: &[TraceEntry], simulator: &[TraceEntry]) -> Option<Divergence> {
+    let step =
+        (0..emulator.len().max(simulator.len())).find(|&i| emulator.get(i) != simulator.get(i))?;
+    let address = match step {
+        0 => 0,
+        _ => emulator[step - 1].pc,
+    };
+    Some(Divergence {
+        step,
+        address,
+        emulator: emulator.get(step).copied(),
+        simulator: simulator.get(step).copied(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_difftest() {
+        let config = Config::builtin();
+        // MOV A 0x2A, HALT
+        let image = crate::prelude::Assembler::new(&config)
+            .assemble("MOV A 0x2A\nHALT")
+            .unwrap();
+        let emulated = emulator_trace(&config, &image, 100).unwrap();
+        assert_eq!(emulated.len(), 2);
+        assert_eq!(emulated[0].a, 0x2A);
+
+        let line = |entry: &TraceEntry| serde_json::to_string(entry).unwrap();
+        let mut wrong = emulated[1];
+        wrong.a = 0x2B;
+        let script = format!(
+            "cat > /dev/null; echo '{}'; echo '{}'",
+            line(&emulated[0]),
+            line(&wrong)
+        );
+        let simulated = simulator_trace(&script, &image, 100).unwrap();
+        let divergence = first_divergence(&emulated, &simulated).unwrap();
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.address, emulated[0].pc);
+        assert!(divergence
+            .to_string()
+            .ends_with("A is 0x2a emulated, 0x2b simulated"));
+
+        assert_eq!(first_divergence(&emulated, &emulated), None);
+        assert_eq!(
+            first_divergence(&emulated, &emulated[..1])
+                .unwrap()
+                .simulator,
+            None
+        );
+        assert!(matches!(
+            simulator_trace("echo nonsense", &image, 100),
+            Err(DifftestErr::InvalidTrace(_, 1, _))
+        ));
+    }
+}
@@ -0,0 +1,664 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    config::Signature,
+    decoder::{DecodeErr, DecodedInstruction, Decoder},
+    lexer::parse_number,
+    reachability::{decoded_displacement, decoded_flow, Flow},
+    specs::Operand,
+    symbols::Symbol,
+};
+#[cfg(feature = "serde")]
+use crate::object::{Object, Relocation, Target};
+
+#[derive(Debug, Error)]
+pub enum DisasmErr {
+    #[error("Invalid entry point '{0}', expected an address.")]
+    InvalidEntry(String),
+    #[error("Couldn't read symbols from '{0}': {1}.")]
+    InvalidSymbols(String, String),
+}
+
+pub fn parse_entry(arg: &str) -> Result<usize, DisasmErr> {
+    parse_number(arg.trim())
+        .and_then(|address| usize::try_from(address).ok())
+        .ok_or_else(|| DisasmErr::InvalidEntry(arg.to_string()))
+}
+
+/// The part of a JSON `--symbols` file the disassembler needs.
+#[derive(Deserialize)]
+struct SymbolFile {
+    symbols: Vec<Symbol>,
+}
+
+/// Names by address from a JSON `--symbols` file, the first by name where
+/// several labels share an address.
+pub fn read_symbols(path: &Path) -> Result<BTreeMap<usize, String>, DisasmErr> {
+    let invalid = |err: String| DisasmErr::InvalidSymbols(path.display().to_string(), err);
+    let content = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    let file: SymbolFile =
+        serde_json::from_str(&content).map_err(|err| invalid(err.to_string()))?;
+    let mut names = BTreeMap::new();
+    for symbol in file.symbols {
+        names
+            .entry(symbol.address)
+            .and_modify(|name: &mut String| {
+                if symbol.name < *name {
+                    *name = symbol.name.clone();
+                }
+            })
+            .or_insert(symbol.name);
+    }
+    Ok(names)
+}
+
+enum Item<'a> {
+    Instruction(DecodedInstruction<'a>),
+    /// A byte that doesn't start a known instruction, kept as data.
+    Byte(usize, u8, DecodeErr),
+    /// Bytes no traced path executes.
+    Data(usize, Vec<u8>),
+}
+
+fn label(address: usize) -> String {
+    format!("label_{:04x}", address)
+}
+
+/// Labels for the addresses in `targets`, named after `symbols` where
+/// they have a name and `label_XXXX` otherwise, plus every one of
+/// `symbols`.
+fn label_names(
+    symbols: &BTreeMap<usize, String>,
+    targets: impl Iterator<Item = usize>,
+) -> BTreeMap<usize, String> {
+    let mut labels = symbols.clone();
+    for target in targets {
+        labels.entry(target).or_insert_with(|| label(target));
+    }
+    labels
+}
+
+/// Renders an operand the way it's written, addresses with as many digits
+/// as `address_bytes` hold, since that's the width the assembler reads
+/// back from a literal.
+fn render_operand(
+    operand: Operand,
+    value: usize,
+    address_bytes: usize,
+    labels: &BTreeMap<usize, String>,
+) -> String {
+    match operand {
+        Operand::Register(register) => format!("{:?}", register),
+        Operand::Const => format!("{:#04x}", value),
+        Operand::Mem8 => format!("[{:#04x}]", value),
+        Operand::Mem16 if labels.contains_key(&value) => format!("#{}", labels[&value]),
+        Operand::Mem16 => format!("[{:#0width$x}]", value, width = 2 + 2 * address_bytes),
+        Operand::Stc => operand.to_string(),
+    }
+}
+
+/// A relative jump's offset byte read as signed and the address it lands
+/// on, by name when it has one, e.g. `-2 -> loop`.
+pub(crate) fn describe_displacement(offset: u8, target: usize, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{:+} -> {}", offset as i8, name),
+        None => format!("{:+} -> {:#06x}", offset as i8, target),
+    }
+}
+
+/// The mnemonic and operands, each rendered from its offset within the
+/// instruction, its kind and its value.
+fn render_instruction(
+    instruction: &DecodedInstruction,
+    render_operand: impl Fn(usize, Operand, usize) -> String,
+) -> String {
+    let mut text = instruction.signature.mnemonic.to_string();
+    let mut offset = 1;
+    for (operand, value) in instruction.operand_values() {
+        text += " ";
+        text += &render_operand(offset, operand, value);
+        offset += operand.encoded_size(instruction.signature.address_bytes);
+    }
+    text
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lists the instructions of an image, one per line after its address and
+/// bytes. 16-bit addresses that point at the start of a decoded instruction
+/// get a `label_XXXX` label, or the name `symbols` give the address, which
+/// also label their own addresses and name the operands pointing at them;
+/// bytes that don't decode are listed as `byte` with the reason.
+pub fn disassemble(
+    signatures: &[Signature],
+    image: &[u8],
+    symbols: &BTreeMap<usize, String>,
+) -> String {
+    let decoder = Decoder::new(signatures);
+
+    let mut items = vec![];
+    let mut address = 0;
+    while address < image.len() {
+        match decoder.decode_one(image, address) {
+            Ok(instruction) => {
+                address += instruction.signature.encoded_size();
+                items.push(Item::Instruction(instruction));
+            }
+            Err(err) => {
+                items.push(Item::Byte(address, image[address], err));
+                address += 1;
+            }
+        }
+    }
+
+    let starts = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Instruction(instruction) => Some(instruction.address),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let targets = mem16_values(&items)
+        .chain(relative_targets(&items))
+        .filter(|value| starts.contains(value));
+    let labels = label_names(symbols, targets);
+    render_items(&items, image, &labels)
+}
+
+/// Values of the 16-bit address operands of the instructions.
+fn mem16_values<'a>(items: &'a [Item]) -> impl Iterator<Item = usize> + 'a {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Instruction(instruction) => Some(instruction.operand_values()),
+            _ => None,
+        })
+        .flatten()
+        .filter(|(operand, _)| *operand == Operand::Mem16)
+        .map(|(_, value)| value)
+}
+
+/// Addresses the relative jumps among the instructions land on.
+fn relative_targets<'a>(items: &'a [Item]) -> impl Iterator<Item = usize> + 'a {
+    items.iter().filter_map(|item| match item {
+        Item::Instruction(instruction) => {
+            decoded_displacement(instruction).map(|(_, target)| target)
+        }
+        _ => None,
+    })
+}
+
+fn render_items(items: &[Item], image: &[u8], labels: &BTreeMap<usize, String>) -> String {
+    let mut listing = String::new();
+    for item in items {
+        let (address, bytes, text) = match item {
+            Item::Instruction(instruction) => {
+                let end = instruction.address + instruction.signature.encoded_size();
+                (
+                    instruction.address,
+                    format_bytes(&image[instruction.address..end]),
+                    render_instruction(instruction, |offset, operand, value| {
+                        let address_bytes = instruction.signature.address_bytes;
+                        let text = render_operand(operand, value, address_bytes, labels);
+                        match decoded_displacement(instruction) {
+                            Some((byte, target)) if offset == 1 => {
+                                let name = labels.get(&target).map(String::as_str);
+                                format!("{}  ({})", text, describe_displacement(byte, target, name))
+                            }
+                            _ => text,
+                        }
+                    }),
+                )
+            }
+            Item::Byte(address, byte, err) => (
+                *address,
+                format_bytes(&[*byte]),
+                format!("byte {:#04x}  ({})", byte, err),
+            ),
+            Item::Data(address, bytes) => {
+                let values = bytes
+                    .iter()
+                    .map(|byte| format!("{:#04x}", byte))
+                    .collect::<Vec<_>>();
+                (
+                    *address,
+                    format_bytes(bytes),
+                    format!("byte {}", values.join(" ")),
+                )
+            }
+        };
+        if let Some(name) = labels.get(&address) {
+            listing += &format!("{:16}{}:\n", "", name);
+        }
+        listing += &format!("{:04x}  {:<8}  {}\n", address, bytes, text);
+    }
+    listing
+}
+
+/// Like [`disassemble`], but only decodes what execution can reach from
+/// the entry points, following fall-through, jumps and calls, and lists
+/// every other byte as `byte` data, e.g. the tables embedded in a ROM.
+/// Jumps through T can't be followed, code only reached that way needs an
+/// entry point of its own. Data that an instruction addresses is labelled
+/// like the jump targets, and `symbols` name labels as in [`disassemble`].
+pub fn disassemble_reachable(
+    signatures: &[Signature],
+    image: &[u8],
+    entries: &[usize],
+    symbols: &BTreeMap<usize, String>,
+) -> String {
+    let decoder = Decoder::new(signatures);
+
+    let mut code = BTreeMap::new();
+    let mut covered = vec![false; image.len()];
+    let mut pending = entries.to_vec();
+    while let Some(address) = pending.pop() {
+        if address >= image.len() || covered[address] {
+            continue;
+        }
+        let Ok(instruction) = decoder.decode_one(image, address) else {
+            continue;
+        };
+        // Code overlapping an instruction already found is left out.
+        let end = address + instruction.signature.encoded_size();
+        if covered[address..end].contains(&true) {
+            continue;
+        }
+        covered[address..end].fill(true);
+        match decoded_flow(&instruction) {
+            Flow::Next => pending.push(end),
+            Flow::Jump {
+                target,
+                falls_through,
+            } => {
+                pending.extend(target);
+                if falls_through {
+                    pending.push(end);
+                }
+            }
+            Flow::Stop => {}
+        }
+        code.insert(address, instruction);
+    }
+
+    let starts = code.keys().copied().collect::<HashSet<_>>();
+    let mut items = code
+        .into_values()
+        .map(Item::Instruction)
+        .collect::<Vec<_>>();
+    let targets = mem16_values(&items)
+        .filter(|value| starts.contains(value) || covered.get(*value) == Some(&false))
+        .chain(relative_targets(&items).filter(|target| starts.contains(target)));
+    let labels = label_names(symbols, targets);
+
+    for address in (0..image.len()).filter(|address| !covered[*address]) {
+        match items.last_mut() {
+            Some(Item::Data(start, bytes))
+                if *start + bytes.len() == address
+                    && bytes.len() < 8
+                    && !labels.contains_key(&address) =>
+            {
+                bytes.push(image[address])
+            }
+            _ => items.push(Item::Data(address, vec![image[address]])),
+        }
+    }
+    items.sort_by_key(|item| match item {
+        Item::Instruction(instruction) => instruction.address,
+        Item::Byte(address, ..) | Item::Data(address, _) => *address,
+    });
+    render_items(&items, image, &labels)
+}
+
+#[cfg(feature = "serde")]
+/// `#name` plus or minus what's left of a value.
+fn symbol_expr(name: &str, addend: i64) -> String {
+    match addend {
+        0 => format!("#{}", name),
+        addend if addend < 0 => format!("#{} - {}", name, -addend),
+        addend => format!("#{} + {}", name, addend),
+    }
+}
+
+#[cfg(feature = "serde")]
+/// Labels of each section by offset, with one made up for every place a
+/// relocation points at that no symbol is at or before.
+fn object_labels(object: &Object) -> Vec<BTreeMap<usize, Vec<String>>> {
+    let mut labels = vec![BTreeMap::new(); object.sections.len()];
+    for symbol in &object.symbols {
+        labels[symbol.section]
+            .entry(symbol.offset)
+            .or_insert_with(Vec::new)
+            .push(symbol.name.clone());
+    }
+    for relocation in &object.relocations {
+        let Target::Section(section) = relocation.target else {
+            continue;
+        };
+        let offset = relocation.addend.max(0) as usize;
+        if labels[section].range(..=offset).next().is_none() {
+            let name = format!("{}_{:04x}", object.sections[section].name, offset);
+            labels[section].insert(offset, vec![name]);
+        }
+    }
+    labels
+}
+
+#[cfg(feature = "serde")]
+/// The symbol a relocation's value is written relative to, and what's left
+/// to add to it.
+fn relocated_symbol<'a>(
+    relocation: &'a Relocation,
+    labels: &'a [BTreeMap<usize, Vec<String>>],
+) -> (&'a str, i64) {
+    match &relocation.target {
+        Target::Symbol(name) => (name, relocation.addend),
+        Target::Section(section) => {
+            let offset = relocation.addend.max(0) as usize;
+            let (at, names) = labels[*section]
+                .range(..=offset)
+                .next_back()
+                .expect("relocated places have a label at or before them");
+            (&names[0], relocation.addend - *at as i64)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+enum Piece {
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+#[cfg(feature = "serde")]
+/// Writes an object back as source that assembles to it again. Operands
+/// and data the object relocates refer to their symbols; labels are the
+/// object's symbols, plus `<section>_XXXX` ones for places only a
+/// relocation points at. Bytes that aren't a whole instruction, or whose
+/// relocations an operand can't express, are listed as `byte` data.
+pub fn disassemble_object(signatures: &[Signature], object: &Object) -> String {
+    let decoder = Decoder::new(signatures);
+    let labels = object_labels(object);
+
+    let mut source = String::new();
+    let imports = object.imports.join(" ");
+    let exports = object
+        .symbols
+        .iter()
+        .filter(|symbol| symbol.exported)
+        .map(|symbol| symbol.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !imports.is_empty() {
+        source += &format!(".import {}\n", imports);
+    }
+    if !exports.is_empty() {
+        source += &format!(".export {}\n", exports);
+    }
+
+    for (i, section) in object.sections.iter().enumerate() {
+        let data = &section.data;
+        let relocations = object
+            .relocations
+            .iter()
+            .filter(|relocation| relocation.section == i)
+            .map(|relocation| (relocation.offset, relocation))
+            .collect::<BTreeMap<_, _>>();
+        let labels_in = |start: usize, end: usize| labels[i].range(start..end).next().is_some();
+
+        let mut pieces: Vec<(usize, Piece)> = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            if let Some(relocation) = relocations.get(&offset) {
+                let keyword = match relocation.width {
+                    1 => "byte",
+                    _ => ".word",
+                };
+                let (name, addend) = relocated_symbol(relocation, &labels);
+                let text = format!("{} {}", keyword, symbol_expr(name, addend));
+                pieces.push((offset, Piece::Text(text)));
+                offset += relocation.width;
+                continue;
+            }
+
+            let instruction = decoder.decode_one(data, offset).ok().filter(|instruction| {
+                let end = offset + instruction.signature.encoded_size();
+                let mut fields = vec![];
+                let mut field = offset + 1;
+                for operand in &instruction.signature.operands {
+                    if *operand == Operand::Mem16 {
+                        fields.push(field);
+                    }
+                    field += operand.encoded_size(instruction.signature.address_bytes);
+                }
+                !labels_in(offset + 1, end)
+                    && relocations.range(offset..end).all(|(at, relocation)| {
+                        fields.contains(at)
+                            && relocation.width == instruction.signature.address_bytes
+                    })
+            });
+            let Some(instruction) = instruction else {
+                match pieces.last_mut() {
+                    Some((_, Piece::Bytes(bytes)))
+                        if bytes.len() < 8 && !labels_in(offset, offset + 1) =>
+                    {
+                        bytes.push(data[offset])
+                    }
+                    _ => pieces.push((offset, Piece::Bytes(vec![data[offset]]))),
+                }
+                offset += 1;
+                continue;
+            };
+            // A bare label is an address, with an addend it needs brackets.
+            let text = render_instruction(&instruction, |field, operand, value| {
+                let relocation = relocations
+                    .get(&(offset + field))
+                    .filter(|_| operand == Operand::Mem16);
+                match relocation.map(|relocation| relocated_symbol(relocation, &labels)) {
+                    Some((name, 0)) => symbol_expr(name, 0),
+                    Some((name, addend)) => format!("[{}]", symbol_expr(name, addend)),
+                    None => {
+                        let address_bytes = instruction.signature.address_bytes;
+                        render_operand(operand, value, address_bytes, &BTreeMap::new())
+                    }
+                }
+            });
+            pieces.push((offset, Piece::Text(text)));
+            offset += instruction.signature.encoded_size();
+        }
+
+        source += &format!("\n.segment {}\n", section.name);
+        let mut pieces = pieces.into_iter().peekable();
+        for (at, names) in &labels[i] {
+            while let Some((_, piece)) = pieces.next_if(|(start, _)| start < at) {
+                source += &render_piece(piece);
+            }
+            for name in names {
+                source += &format!("{}:\n", name);
+            }
+        }
+        for (_, piece) in pieces {
+            source += &render_piece(piece);
+        }
+    }
+    source
+}
+
+#[cfg(feature = "serde")]
+fn render_piece(piece: Piece) -> String {
+    let text = match piece {
+        Piece::Bytes(bytes) => {
+            let bytes = bytes
+                .iter()
+                .map(|byte| format!("{:#04x}", byte))
+                .collect::<Vec<_>>();
+            format!("byte {}", bytes.join(" "))
+        }
+        Piece::Text(text) => text,
+    };
+    format!("        {}\n", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        prelude::{assemble, AssembleOptions},
+    };
+    #[cfg(feature = "serde")]
+    use crate::{
+        checker::{check_semantics, layout},
+        error::FileContext,
+        lexer::Lexer,
+        object::build_object,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_disassemble_object() {
+        let source = ".import putc\n\
+            .export start\n\
+            \n\
+            .segment code\n\
+            start:\n\
+            \x20       JMPREL #putc\n\
+            \x20       MOV A [#table + 1]\n\
+            loop:\n\
+            \x20       JMPIMM #loop\n\
+            \n\
+            .segment data\n\
+            table:\n\
+            \x20       .word #start\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let config = Config::builtin();
+        let labels = get_resolved_labels(&tokens, &config);
+        let mut symbols = labels.clone();
+        symbols.insert("putc", 0);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &symbols, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        let context = FileContext::new(None, source);
+        let object = build_object(
+            &checked_lines,
+            &addresses,
+            &tokens,
+            &labels,
+            &symbols,
+            &context,
+            config.capabilities.address_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(disassemble_object(&config.signatures(), &object), source);
+    }
+
+    #[test]
+    fn test_disassemble_reachable() {
+        let config = Config::builtin();
+        let source = "JMPIMM #start\ntable: .byte 1 2\nstart: MOV A [#table]\nHALT\n.byte 3\n";
+        let image = assemble(source, &config, AssembleOptions::default())
+            .unwrap()
+            .image;
+
+        let listing = disassemble_reachable(&config.signatures(), &image, &[0], &BTreeMap::new());
+        let text = listing
+            .lines()
+            .map(|line| line.split("  ").last().unwrap().trim())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            text,
+            [
+                "JMPIMM #label_0005",
+                "label_0003:",
+                "byte 0x01 0x02",
+                "label_0005:",
+                "MOV A #label_0003",
+                "HALT",
+                "byte 0x03"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relative_displacement() {
+        let config = Config::builtin();
+        let source = "loop: MOV A 1\nJMPREL 0xfc\nHALT\n";
+        let image = assemble(source, &config, AssembleOptions::default())
+            .unwrap()
+            .image;
+
+        let listing = disassemble(&config.signatures(), &image, &BTreeMap::new());
+        assert!(listing.contains("JMPREL 0xfc  (-4 -> label_0000)\n"));
+        let symbols = BTreeMap::from([(0, "loop".to_string())]);
+        let listing = disassemble(&config.signatures(), &image, &symbols);
+        assert!(listing.contains("JMPREL 0xfc  (-4 -> loop)\n"));
+    }
+
+    #[test]
+    fn test_literal_widths() {
+        let config = Config::builtin();
+        let source = "MOV A 0x0005\nJMPIMM 0x0010\nHALT\n";
+        let image = assemble(source, &config, AssembleOptions::default())
+            .unwrap()
+            .image;
+
+        // Immediates take a byte's digits and addresses the address width's,
+        // however they were written, so the listing assembles back the same.
+        let listing = disassemble(&config.signatures(), &image, &BTreeMap::new());
+        let text = listing
+            .lines()
+            .map(|line| line.split("  ").last().unwrap().trim())
+            .collect::<Vec<_>>();
+        assert_eq!(text, ["MOV A 0x05", "JMPIMM [0x0010]", "HALT"]);
+        let reassembled = assemble(&text.join("\n"), &config, AssembleOptions::default())
+            .unwrap()
+            .image;
+        assert_eq!(reassembled, image);
+    }
+
+    #[test]
+    fn test_read_symbols() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let symbol = |name: &str, address: usize| {
+            format!(
+                r#"{{"name": "{}", "address": {}, "size": 1, "file": "a.as", "line": 1}}"#,
+                name, address
+            )
+        };
+        let content = format!(
+            r#"{{"build": {{}}, "symbols": [{}, {}, {}]}}"#,
+            symbol("start", 0),
+            symbol("loop", 2),
+            symbol("again", 2)
+        );
+        fs::write(file.path(), content).unwrap();
+        let symbols = read_symbols(file.path()).unwrap();
+        assert_eq!(symbols[&2], "again");
+
+        let config = Config::builtin();
+        let image = assemble("MOV A 1\nJMPIMM [2]\n", &config, AssembleOptions::default())
+            .unwrap()
+            .image;
+        let listing = disassemble(&config.signatures(), &image, &symbols);
+        assert!(listing.contains("start:\n"));
+        assert!(listing.contains("again:\n"));
+        assert!(listing.contains("JMPIMM #again"));
+    }
+}
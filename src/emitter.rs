@@ -0,0 +1,216 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+/// Bytes per data record or line in the textual formats.
+const BYTES_PER_LINE: usize = 16;
+
+/// Writes an assembled image in some file format.
+pub trait Emitter {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()>;
+
+    /// Like [`Emitter::emit`], with a comment before the bytes starting at
+    /// each address of `annotations` in the formats that have comments.
+    fn emit_annotated(
+        &self,
+        image: &[u8],
+        _annotations: &BTreeMap<usize, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        self.emit(image, output)
+    }
+}
+
+/// The raw bytes.
+pub struct Binary;
+
+/// One byte per line as eight ASCII `0`/`1` digits.
+pub struct BinaryText;
+
+/// Intel HEX, as read by most EEPROM programmers.
+pub struct IntelHex;
+
+/// Space separated hex bytes, 16 per line.
+pub struct HexText;
+
+/// A Logisim "v2.0 raw" memory image, loadable into a RAM or ROM component.
+pub struct Logisim;
+
+impl Emitter for Binary {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(image)
+    }
+}
+
+impl Emitter for BinaryText {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        for byte in image {
+            writeln!(output, "{:08b}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_hex_lines(image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+    for line in image.chunks(BYTES_PER_LINE) {
+        let bytes = line
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>();
+        writeln!(output, "{}", bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// [`write_hex_lines`] with the lines broken at every annotated address
+/// and the annotation written before them after `comment`.
+fn write_annotated_hex_lines(
+    image: &[u8],
+    annotations: &BTreeMap<usize, String>,
+    comment: &str,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let mut starts = annotations
+        .range(..image.len())
+        .map(|(address, _)| *address)
+        .collect::<Vec<_>>();
+    if starts.first() != Some(&0) {
+        starts.insert(0, 0);
+    }
+    let ends = starts.iter().skip(1).copied().chain([image.len()]);
+    for (start, end) in starts.iter().copied().zip(ends) {
+        if let Some(annotation) = annotations.get(&start) {
+            writeln!(output, "{} {}", comment, annotation)?;
+        }
+        write_hex_lines(&image[start..end], output)?;
+    }
+    Ok(())
+}
+
+impl Emitter for HexText {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        write_hex_lines(image, output)
+    }
+
+    fn emit_annotated(
+        &self,
+        image: &[u8],
+        annotations: &BTreeMap<usize, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        // `$readmemh` and most hex loaders skip `//` comments.
+        write_annotated_hex_lines(image, annotations, "//", output)
+    }
+}
+
+impl Emitter for Logisim {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "v2.0 raw")?;
+        write_hex_lines(image, output)
+    }
+
+    fn emit_annotated(
+        &self,
+        image: &[u8],
+        annotations: &BTreeMap<usize, String>,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        writeln!(output, "v2.0 raw")?;
+        write_annotated_hex_lines(image, annotations, "#", output)
+    }
+}
+
+fn write_record(output: &mut dyn Write, address: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let [address_high, address_low] = address.to_be_bytes();
+    let sum = [data.len() as u8, address_high, address_low, kind]
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+    write!(output, ":{:02X}{:04X}{:02X}", data.len(), address, kind)?;
+    for byte in data {
+        write!(output, "{:02X}", byte)?;
+    }
+    writeln!(output, "{:02X}", sum.wrapping_neg())
+}
+
+impl Emitter for IntelHex {
+    fn emit(&self, image: &[u8], output: &mut dyn Write) -> io::Result<()> {
+        for (i, record) in image.chunks(BYTES_PER_LINE).enumerate() {
+            let address = i * BYTES_PER_LINE;
+            // Records only hold 16 address bits, anything above goes in an
+            // extended linear address record whenever it changes.
+            if address > 0 && address.is_multiple_of(0x10000) {
+                let upper = (address >> 16) as u16;
+                write_record(output, 0, 0x04, &upper.to_be_bytes())?;
+            }
+            write_record(output, address as u16, 0x00, record)?;
+        }
+        write_record(output, 0, 0x01, &[])
+    }
+}
+
+/// Output file formats selectable with `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Format {
+    /// Raw bytes
+    #[default]
+    Bin,
+    /// One byte per line as ASCII 0/1 digits
+    Text,
+    /// Intel HEX records
+    Ihex,
+    /// Space separated hex bytes, with `//` comments under --annotate
+    Hex,
+    /// Logisim memory image, with `#` comments under --annotate
+    Logisim,
+    /// Address, bytes and source of every line, like --listing
+    Listing,
+}
+
+impl Format {
+    /// How to write the image, or `None` for the listing, which is made from
+    /// the source rather than the image.
+    pub fn emitter(&self) -> Option<&'static dyn Emitter> {
+        match self {
+            Format::Bin => Some(&Binary),
+            Format::Text => Some(&BinaryText),
+            Format::Ihex => Some(&IntelHex),
+            Format::Hex => Some(&HexText),
+            Format::Logisim => Some(&Logisim),
+            Format::Listing => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intel_hex_records() {
+        let mut output = vec![];
+        IntelHex.emit(&[0x11, 0x2a, 0xd8], &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            ":03000000112AD8EA\n:00000001FF\n"
+        );
+    }
+    #[test]
+    fn test_annotated_hex() {
+        let annotations = BTreeMap::from([
+            (0, "main.asm:1: MOV A 42".to_string()),
+            (2, "main.asm:2: HALT".to_string()),
+        ]);
+        let mut output = vec![];
+        HexText
+            .emit_annotated(&[0x11, 0x2a, 0xd8], &annotations, &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "// main.asm:1: MOV A 42\n11 2a\n// main.asm:2: HALT\nd8\n"
+        );
+    }
+}
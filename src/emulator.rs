@@ -0,0 +1,1050 @@
+//! Runs an assembled image. Instructions are decoded with the config, so
+//! the emulator follows whatever encoding it describes, and cycle counts
+//! come from the config too, unless a run is only functional. What each
+//! mnemonic does is defined here.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
+
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    decoder::{DecodeErr, DecodedInstruction, Decoder},
+    lexer::parse_number,
+    specs::{Operand, Register},
+};
+
+/// Addresses are 16 bits wide: the PC, the stack pointer and every
+/// address operand. Configs with wider addresses can't be run.
+const ADDRESS_WIDTH: u32 = 16;
+const MEMORY_SIZE: usize = 1 << ADDRESS_WIDTH;
+
+/// Bytes written here go to the console, when one is attached.
+pub const CONSOLE_OUT: u16 = 0xFF00;
+/// Reading here takes the next byte of console input, 0 once it runs out.
+pub const CONSOLE_IN: u16 = 0xFF01;
+/// Reads 1 while console input is left and 0 after.
+pub const CONSOLE_STATUS: u16 = 0xFF02;
+
+/// Bits of the F register. The config only names the flags, so the layout
+/// is the emulator's own.
+const FLAGS: [(&str, u8); 5] = [
+    ("C", 1),
+    ("Z", 1 << 1),
+    ("S", 1 << 2),
+    ("O", 1 << 3),
+    ("P", 1 << 4),
+];
+
+fn flag_bit(name: &str) -> Option<u8> {
+    FLAGS
+        .iter()
+        .find(|(flag, _)| *flag == name)
+        .map(|(_, bit)| *bit)
+}
+
+#[derive(Debug, Error)]
+pub enum EmulatorErr {
+    #[error("{0}")]
+    Decode(DecodeErr),
+    #[error("Don't know how to execute '{0}' at address {1:#06x}.")]
+    Unsupported(String, u16),
+    #[error("Unknown label '{0}'.")]
+    UnknownLabel(String),
+    #[error("Invalid memory range '{0}', expected START or START..END.")]
+    InvalidRange(String),
+    #[error("The emulator only supports 16-bit addresses, but the config's address width is {0}.")]
+    AddressWidth(u32),
+    #[error("Breakpoint '{0}' at address {1:#x} is outside the emulator's 16-bit memory.")]
+    BreakpointOutOfRange(String, usize),
+    #[error("Invalid watchpoint '{0}', expected ADDRESS or LABEL, optionally followed by :r, :w or :rw.")]
+    InvalidWatchpoint(String),
+    #[error("Watchpoint '{0}' at address {1:#x} is outside the emulator's 16-bit memory.")]
+    WatchpointOutOfRange(String, usize),
+    #[error("Console output differs from '{0}' starting at byte {1}.")]
+    UnexpectedOutput(String, usize),
+    #[error("Invalid wait states '{0}', expected RANGE=CYCLES.")]
+    InvalidWaitStates(String),
+}
+
+/// Fails unless `config`'s addresses fit the emulator's memory.
+pub fn check_address_width(config: &Config) -> Result<(), EmulatorErr> {
+    let width = config.capabilities.address_width;
+    if width > ADDRESS_WIDTH {
+        return Err(EmulatorErr::AddressWidth(width));
+    }
+    Ok(())
+}
+
+/// Addresses `start..end` to print after a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Parses `START..END`, or `START` alone for the 16 bytes from there.
+pub fn parse_memory_range(arg: &str) -> Result<MemoryRange, EmulatorErr> {
+    let invalid = || EmulatorErr::InvalidRange(arg.to_string());
+    let address = |number: &str| {
+        parse_number(number.trim())
+            .and_then(|number| usize::try_from(number).ok())
+            .filter(|number| *number <= MEMORY_SIZE)
+            .ok_or_else(invalid)
+    };
+
+    let (start, end) = match arg.split_once("..") {
+        Some((start, end)) => (address(start)?, address(end)?),
+        None => {
+            let start = address(arg)?;
+            (start, (start + 16).min(MEMORY_SIZE))
+        }
+    };
+    if start >= end {
+        return Err(invalid());
+    }
+    Ok(MemoryRange { start, end })
+}
+
+/// How the emulator counts clock cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Mode {
+    /// One cycle per instruction, without looking up the config's counts.
+    Functional,
+    /// The config's cycle counts plus the wait states of the memory accessed.
+    #[default]
+    CycleAccurate,
+}
+
+/// Extra cycles every data access to `range` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitStates {
+    pub range: MemoryRange,
+    pub cycles: usize,
+}
+
+/// Parses `RANGE=CYCLES`, with RANGE as in [`parse_memory_range`].
+pub fn parse_wait_states(arg: &str) -> Result<WaitStates, EmulatorErr> {
+    let invalid = || EmulatorErr::InvalidWaitStates(arg.to_string());
+    let (range, cycles) = arg.split_once('=').ok_or_else(invalid)?;
+    let range = parse_memory_range(range.trim()).map_err(|_| invalid())?;
+    let cycles = parse_number(cycles.trim())
+        .and_then(|cycles| usize::try_from(cycles).ok())
+        .ok_or_else(invalid)?;
+    Ok(WaitStates { range, cycles })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub mode: Mode,
+    /// Only counted in [`Mode::CycleAccurate`].
+    pub wait_states: Vec<WaitStates>,
+}
+
+impl Timing {
+    /// Wait states of the memory accessed at `address`, those given first
+    /// winning where ranges overlap.
+    fn wait_states(&self, address: u16) -> usize {
+        let address = address as usize;
+        self.wait_states
+            .iter()
+            .find(|wait| wait.range.start <= address && address < wait.range.end)
+            .map_or(0, |wait| wait.cycles)
+    }
+}
+
+/// Memory accesses a watchpoint stops at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::ReadWrite => write!(f, "read/write"),
+        }
+    }
+}
+
+/// A `--watch` argument before its target is resolved to an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchTarget {
+    /// A label or an address.
+    pub target: String,
+    pub access: Access,
+}
+
+/// Parses `TARGET[:ACCESS]`, where ACCESS is `r`, `w` or `rw`, the default.
+pub fn parse_watchpoint(arg: &str) -> Result<WatchTarget, EmulatorErr> {
+    let invalid = || EmulatorErr::InvalidWatchpoint(arg.to_string());
+    let (target, access) = match arg.rsplit_once(':') {
+        Some((target, "r")) => (target, Access::Read),
+        Some((target, "w")) => (target, Access::Write),
+        Some((target, "rw")) => (target, Access::ReadWrite),
+        Some(_) => return Err(invalid()),
+        None => (arg, Access::ReadWrite),
+    };
+    let target = target.trim();
+    if target.is_empty() {
+        return Err(invalid());
+    }
+    Ok(WatchTarget {
+        target: target.to_string(),
+        access,
+    })
+}
+
+impl WatchTarget {
+    /// The watchpoint on the address of the label or number it names.
+    pub fn resolve(&self, labels: &HashMap<&str, usize>) -> Result<Watchpoint, EmulatorErr> {
+        let address = labels
+            .get(self.target.as_str())
+            .copied()
+            .or_else(|| parse_number(&self.target).and_then(|n| usize::try_from(n).ok()))
+            .ok_or_else(|| EmulatorErr::UnknownLabel(self.target.clone()))?;
+        let address = u16::try_from(address)
+            .map_err(|_| EmulatorErr::WatchpointOutOfRange(self.target.clone(), address))?;
+        Ok(Watchpoint {
+            address,
+            access: self.access,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub access: Access,
+}
+
+/// A watched access: what was done to which address by the instruction at
+/// `pc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub access: Access,
+    pub pc: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    Halted,
+    Breakpoint,
+    Watchpoint(WatchHit),
+    CycleLimit,
+}
+
+impl Display for Stop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stop::Halted => write!(f, "halted"),
+            Stop::Breakpoint => write!(f, "stopped at breakpoint"),
+            Stop::Watchpoint(hit) => write!(
+                f,
+                "stopped at {} of {:#06x} by the instruction at {:#06x}",
+                hit.access, hit.address, hit.pc
+            ),
+            Stop::CycleLimit => write!(f, "stopped after reaching the cycle limit"),
+        }
+    }
+}
+
+pub struct RunOptions {
+    pub max_cycles: usize,
+    /// Address at which to stop once execution jumps or falls through to it.
+    pub breakpoint: Option<u16>,
+    /// Memory accesses to stop after, once the instruction making them is done.
+    pub watchpoints: Vec<Watchpoint>,
+    pub timing: Timing,
+}
+
+/// The console peripheral: input for the program to read and what it has
+/// printed so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Console {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl Console {
+    pub fn new(input: &[u8]) -> Self {
+        Self {
+            input: input.iter().copied().collect(),
+            output: vec![],
+        }
+    }
+
+    /// Fails at the first byte where the output differs from `expected`,
+    /// read from the file `name`.
+    pub fn expect_output(&self, expected: &[u8], name: &str) -> Result<(), EmulatorErr> {
+        let common = self
+            .output
+            .iter()
+            .zip(expected)
+            .take_while(|(a, b)| a == b)
+            .count();
+        match common == self.output.len() && common == expected.len() {
+            true => Ok(()),
+            false => Err(EmulatorErr::UnexpectedOutput(name.to_string(), common)),
+        }
+    }
+}
+
+/// Registers, flags and memory of the machine. T is the TH:TL pair and the
+/// stack grows down from the top of memory, with STC pointing at the next
+/// free byte.
+pub struct Machine {
+    pub a: u8,
+    pub b: u8,
+    pub f: u8,
+    pub th: u8,
+    pub tl: u8,
+    pub pc: u16,
+    /// Address of the instruction executed last.
+    pub last_pc: u16,
+    pub stc: u16,
+    pub memory: Vec<u8>,
+    pub cycles: usize,
+    pub instructions: usize,
+    /// Addresses and bytes written by the last instruction, in order.
+    pub writes: Vec<(u16, u8)>,
+    /// Addresses the last instruction read its operands from, in order.
+    pub reads: Vec<u16>,
+    /// Without one, the console addresses are plain memory.
+    pub console: Option<Console>,
+}
+
+impl Machine {
+    /// A machine with the image loaded at address 0.
+    pub fn new(image: &[u8]) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE];
+        let len = image.len().min(MEMORY_SIZE);
+        memory[..len].copy_from_slice(&image[..len]);
+        Self {
+            a: 0,
+            b: 0,
+            f: 0,
+            th: 0,
+            tl: 0,
+            pc: 0,
+            last_pc: 0,
+            stc: 0xFFFF,
+            memory,
+            cycles: 0,
+            instructions: 0,
+            writes: vec![],
+            reads: vec![],
+            console: None,
+        }
+    }
+
+    fn t(&self) -> u16 {
+        u16::from_be_bytes([self.th, self.tl])
+    }
+
+    fn set_t(&mut self, value: u16) {
+        [self.th, self.tl] = value.to_be_bytes();
+    }
+
+    fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::F => self.f,
+            Register::TH => self.th,
+            Register::TL => self.tl,
+            Register::T => self.tl,
+        }
+    }
+
+    fn set_register(&mut self, register: Register, value: u8) {
+        match register {
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+            Register::F => self.f = value,
+            Register::TH => self.th = value,
+            Register::TL => self.tl = value,
+            Register::T => self.set_t(value as u16),
+        }
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        self.reads.push(address);
+        match (&mut self.console, address) {
+            (Some(console), CONSOLE_IN) => console.input.pop_front().unwrap_or(0),
+            (Some(console), CONSOLE_STATUS) => !console.input.is_empty() as u8,
+            _ => self.memory[address as usize],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        self.writes.push((address, value));
+        if let (Some(console), CONSOLE_OUT) = (&mut self.console, address) {
+            console.output.push(value);
+        }
+    }
+
+    fn push(&mut self, value: u8) {
+        self.write(self.stc, value);
+        self.stc = self.stc.wrapping_sub(1);
+    }
+
+    fn pop(&mut self) -> u8 {
+        self.stc = self.stc.wrapping_add(1);
+        self.read(self.stc)
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        match name.strip_prefix('N') {
+            Some(name) if !name.is_empty() => flag_bit(name).map(|bit| self.f & bit == 0),
+            _ => flag_bit(name).map(|bit| self.f & bit != 0),
+        }
+    }
+
+    fn set_flags(&mut self, result: u16, sign_bit: u16, carry: bool, overflow: bool) {
+        let flags = [
+            carry,
+            result == 0,
+            result & sign_bit != 0,
+            overflow,
+            (result as u8).count_ones().is_multiple_of(2),
+        ];
+        self.f = FLAGS
+            .iter()
+            .zip(flags)
+            .filter(|(_, set)| *set)
+            .fold(0, |f, ((_, bit), _)| f | bit);
+    }
+
+    fn add(&mut self, x: u8, y: u8) -> u8 {
+        let (result, carry) = x.overflowing_add(y);
+        let overflow = (x as i8).overflowing_add(y as i8).1;
+        self.set_flags(result as u16, 0x80, carry, overflow);
+        result
+    }
+
+    fn sub(&mut self, x: u8, y: u8) -> u8 {
+        let (result, borrow) = x.overflowing_sub(y);
+        let overflow = (x as i8).overflowing_sub(y as i8).1;
+        self.set_flags(result as u16, 0x80, borrow, overflow);
+        result
+    }
+
+    fn logic(&mut self, result: u8, carry: bool) -> u8 {
+        self.set_flags(result as u16, 0x80, carry, false);
+        result
+    }
+
+    /// Reads a source operand. Memory operands are addresses, 8-bit ones
+    /// in the zero page.
+    fn load(&mut self, (operand, value): (Operand, usize)) -> Option<u8> {
+        match operand {
+            Operand::Register(register) => Some(self.register(register)),
+            Operand::Const => Some(value as u8),
+            Operand::Mem8 | Operand::Mem16 => Some(self.read(value as u16)),
+            Operand::Stc => None,
+        }
+    }
+
+    /// Writes a destination operand, `STC` pushes.
+    fn store(&mut self, (operand, value): (Operand, usize), byte: u8) -> Option<()> {
+        match operand {
+            Operand::Register(register) => self.set_register(register, byte),
+            Operand::Mem8 | Operand::Mem16 => self.write(value as u16, byte),
+            Operand::Stc => self.push(byte),
+            Operand::Const => return None,
+        }
+        Some(())
+    }
+
+    /// The address a `MOVAT` stores to: T, a zero page address in an 8-bit
+    /// register, or an immediate one.
+    fn address(&self, (operand, value): (Operand, usize)) -> Option<u16> {
+        match operand {
+            Operand::Register(Register::T) => Some(self.t()),
+            Operand::Register(register) => Some(self.register(register) as u16),
+            Operand::Mem8 | Operand::Mem16 => Some(value as u16),
+            Operand::Const | Operand::Stc => None,
+        }
+    }
+
+    /// Executes one instruction. Returns whether it branched, for the cycle
+    /// count, or `None` when the form has no known meaning.
+    fn execute(&mut self, instruction: &DecodedInstruction, halted: &mut bool) -> Option<bool> {
+        let name = instruction.signature.mnemonic.name();
+        let operands = instruction.operand_values();
+        let next = self
+            .pc
+            .wrapping_add(instruction.signature.encoded_size() as u16);
+        self.pc = next;
+
+        // Conditional jumps are the unconditional mnemonic with the flag
+        // they test appended, e.g. `JMPIMMNZ`.
+        let (name, taken) = match ["JMPIMM", "JMPREL"].map(|jump| name.strip_prefix(jump)) {
+            [Some(""), _] => ("JMPIMM", true),
+            [_, Some("")] => ("JMPREL", true),
+            [Some(flag), _] => ("JMPIMM", self.flag(flag)?),
+            [_, Some(flag)] => ("JMPREL", self.flag(flag)?),
+            _ => (name, false),
+        };
+
+        match (name, operands.as_slice()) {
+            ("NOP" | "SKIP", []) => {}
+            ("SKIP1", []) => self.pc = next.wrapping_add(1),
+            ("SKIP2", []) => self.pc = next.wrapping_add(2),
+            ("HALT", []) => *halted = true,
+            ("MOV", [dst, src]) => {
+                let value = self.load(*src)?;
+                self.store(*dst, value)?;
+            }
+            ("MOVAT", [target, src]) => {
+                let value = self.load(*src)?;
+                let address = self.address(*target)?;
+                self.write(address, value);
+            }
+            ("ADD" | "AND" | "OR" | "XOR", [dst]) => {
+                let (a, b) = (self.a, self.b);
+                let result = match name {
+                    "ADD" => self.add(a, b),
+                    "AND" => self.logic(a & b, false),
+                    "OR" => self.logic(a | b, false),
+                    _ => self.logic(a ^ b, false),
+                };
+                self.store(*dst, result)?;
+            }
+            ("SUB", [dst, x, y]) => {
+                let (x, y) = (self.load(*x)?, self.load(*y)?);
+                let result = self.sub(x, y);
+                self.store(*dst, result)?;
+            }
+            ("NEG" | "INV" | "SHL" | "SHR" | "DIV2", [dst, src]) => {
+                let value = self.load(*src)?;
+                let result = match name {
+                    "NEG" => self.sub(0, value),
+                    "INV" => self.logic(!value, false),
+                    "SHL" => self.logic(value << 1, value & 0x80 != 0),
+                    "SHR" => self.logic(value >> 1, value & 1 != 0),
+                    _ => self.logic(((value as i8) >> 1) as u8, value & 1 != 0),
+                };
+                self.store(*dst, result)?;
+            }
+            ("CMP", [x, y]) => {
+                let (x, y) = (self.load(*x)?, self.load(*y)?);
+                self.sub(x, y);
+            }
+            ("CLR", [dst]) => self.store(*dst, 0)?,
+            ("INC" | "DEC", [(Operand::Register(Register::T), _)]) => {
+                let t = self.t();
+                let (result, carry) = match name {
+                    "INC" => t.overflowing_add(1),
+                    _ => t.overflowing_sub(1),
+                };
+                let overflow = match name {
+                    "INC" => (t as i16).overflowing_add(1).1,
+                    _ => (t as i16).overflowing_sub(1).1,
+                };
+                self.set_flags(result, 0x8000, carry, overflow);
+                self.set_t(result);
+            }
+            ("INC" | "DEC", [dst]) => {
+                let value = self.load(*dst)?;
+                let result = match name {
+                    "INC" => self.add(value, 1),
+                    _ => self.sub(value, 1),
+                };
+                self.store(*dst, result)?;
+            }
+            ("JMPIMM", [(Operand::Register(Register::T), _)]) => {
+                if taken {
+                    self.pc = self.t();
+                }
+                return Some(taken);
+            }
+            ("JMPIMM", [(Operand::Mem16, target)]) => {
+                if taken {
+                    self.pc = *target as u16;
+                }
+                return Some(taken);
+            }
+            ("JMPREL", [(Operand::Const | Operand::Mem8, offset)]) => {
+                if taken {
+                    self.pc = next.wrapping_add_signed(*offset as u8 as i8 as i16);
+                }
+                return Some(taken);
+            }
+            ("JMPREL", [(Operand::Register(Register::T), _)]) => {
+                if taken {
+                    self.pc = next.wrapping_add(self.t());
+                }
+                return Some(taken);
+            }
+            ("JMPREL", [(Operand::Mem16, target)]) => {
+                let [high, low] = next.to_be_bytes();
+                self.push(low);
+                self.push(high);
+                self.pc = *target as u16;
+            }
+            ("JMPREL", []) => {
+                let high = self.pop();
+                let low = self.pop();
+                self.pc = u16::from_be_bytes([high, low]);
+            }
+            ("PUSH", [(Operand::Register(Register::T), _)]) => {
+                self.push(self.th);
+                self.push(self.tl);
+            }
+            ("PUSH", [src]) => {
+                let value = self.load(*src)?;
+                self.push(value);
+            }
+            ("POP", [(Operand::Register(Register::T), _)]) => {
+                self.tl = self.pop();
+                self.th = self.pop();
+            }
+            ("POP", [dst]) => {
+                let value = self.pop();
+                self.store(*dst, value)?;
+            }
+            _ => return None,
+        }
+        Some(false)
+    }
+
+    /// Executes the instruction at PC, returning whether it was `HALT`.
+    pub fn step(&mut self, config: &Config, decoder: &Decoder) -> Result<bool, EmulatorErr> {
+        self.step_timed(config, decoder, &Timing::default())
+    }
+
+    /// [`Machine::step`], counting the cycles as `timing` says.
+    pub fn step_timed(
+        &mut self,
+        config: &Config,
+        decoder: &Decoder,
+        timing: &Timing,
+    ) -> Result<bool, EmulatorErr> {
+        let address = self.pc;
+        let instruction = decoder
+            .decode_one(&self.memory, address as usize)
+            .map_err(EmulatorErr::Decode)?;
+
+        self.writes.clear();
+        self.reads.clear();
+        self.last_pc = address;
+        let mut halted = false;
+        let branched = self
+            .execute(&instruction, &mut halted)
+            .ok_or_else(|| EmulatorErr::Unsupported(instruction.signature.to_string(), address))?;
+
+        match timing.mode {
+            Mode::Functional => self.cycles += 1,
+            Mode::CycleAccurate => {
+                let opcode = self.memory[address as usize];
+                if let Some(cycles) = config.cycles.get(&opcode) {
+                    self.cycles += if branched { cycles.max } else { cycles.min };
+                }
+                let accesses = self.reads.iter().chain(self.writes.iter().map(|(a, _)| a));
+                self.cycles += accesses.map(|a| timing.wait_states(*a)).sum::<usize>();
+            }
+        }
+        self.instructions += 1;
+        Ok(halted)
+    }
+
+    /// The first access of the last instruction a watchpoint is on.
+    pub fn watch_hit(&self, watchpoints: &[Watchpoint]) -> Option<WatchHit> {
+        let reads = self.reads.iter().map(|address| (*address, Access::Read));
+        let writes = self
+            .writes
+            .iter()
+            .map(|(address, _)| (*address, Access::Write));
+        reads.chain(writes).find_map(|(address, access)| {
+            watchpoints
+                .iter()
+                .any(|watchpoint| {
+                    watchpoint.address == address && watchpoint.access.includes(access)
+                })
+                .then_some(WatchHit {
+                    address,
+                    access,
+                    pc: self.last_pc,
+                })
+        })
+    }
+
+    /// Hex dump of a memory range, 16 bytes per line after the address.
+    pub fn dump(&self, range: &MemoryRange) -> String {
+        let mut dump = String::new();
+        for (i, line) in self.memory[range.start..range.end].chunks(16).enumerate() {
+            let bytes = line
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>();
+            dump += &format!("{:04x}  {}\n", range.start + i * 16, bytes.join(" "));
+        }
+        dump
+    }
+}
+
+impl Display for Machine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let flags = FLAGS
+            .iter()
+            .map(|(name, bit)| match self.f & bit {
+                0 => "-",
+                _ => name,
+            })
+            .collect::<String>();
+        writeln!(
+            f,
+            "A={:02x} B={:02x} TH={:02x} TL={:02x} F={:02x} [{}]",
+            self.a, self.b, self.th, self.tl, self.f, flags
+        )?;
+        writeln!(f, "PC={:04x} STC={:04x}", self.pc, self.stc)?;
+        write!(
+            f,
+            "{} instructions, {} cycles",
+            self.instructions, self.cycles
+        )
+    }
+}
+
+/// Runs until `HALT`, the breakpoint or the cycle limit.
+pub fn run(
+    config: &Config,
+    machine: &mut Machine,
+    options: &RunOptions,
+) -> Result<Stop, EmulatorErr> {
+    run_with(config, machine, options, |_, _| {})
+}
+
+/// Like [`run`], calling `on_step` with the machine and the cycle the
+/// instruction started at after every instruction.
+pub fn run_with(
+    config: &Config,
+    machine: &mut Machine,
+    options: &RunOptions,
+    mut on_step: impl FnMut(&Machine, usize),
+) -> Result<Stop, EmulatorErr> {
+    check_address_width(config)?;
+    let signatures = config.signatures();
+    let decoder = Decoder::new(&signatures);
+
+    loop {
+        if machine.cycles >= options.max_cycles {
+            return Ok(Stop::CycleLimit);
+        }
+        let start = machine.cycles;
+        let halted = machine.step_timed(config, &decoder, &options.timing)?;
+        on_step(machine, start);
+        if let Some(hit) = machine.watch_hit(&options.watchpoints) {
+            return Ok(Stop::Watchpoint(hit));
+        }
+        if halted {
+            return Ok(Stop::Halted);
+        }
+        if options.breakpoint == Some(machine.pc) {
+            return Ok(Stop::Breakpoint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assembles `source` with the built-in config and runs it, stopping at
+    /// the `breakpoint` label if there is one.
+    fn run_source(source: &str, breakpoint: Option<&str>) -> (Machine, Stop) {
+        let config = Config::builtin();
+        let assembly = crate::assemble(source, &config, Default::default()).unwrap();
+        let options = RunOptions {
+            max_cycles: 1000,
+            breakpoint: breakpoint.map(|label| assembly.symbols[label] as u16),
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        let mut machine = Machine::new(&assembly.image);
+        let stop = run(&config, &mut machine, &options).unwrap();
+        (machine, stop)
+    }
+
+    #[test]
+    fn test_parse_memory_range() {
+        assert_eq!(
+            parse_memory_range("0x8000..0x8004").unwrap(),
+            MemoryRange {
+                start: 0x8000,
+                end: 0x8004
+            }
+        );
+        assert_eq!(
+            parse_memory_range("0xFFF8").unwrap(),
+            MemoryRange {
+                start: 0xFFF8,
+                end: 0x10000
+            }
+        );
+        assert!(parse_memory_range("4..4").is_err());
+        assert!(parse_memory_range("0x10001").is_err());
+    }
+
+    #[test]
+    fn test_wide_addresses_are_rejected() {
+        let mut config = Config::builtin();
+        config.capabilities.address_width = 24;
+        let mut machine = Machine::new(&[0xd8]);
+        let options = RunOptions {
+            max_cycles: 100,
+            breakpoint: None,
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        let err = run(&config, &mut machine, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The emulator only supports 16-bit addresses, but the config's address width is 24."
+        );
+    }
+
+    #[test]
+    fn test_add_and_sub_flags() {
+        let (machine, _) = run_source("MOV A 0xFF\nMOV B 1\nADD A\nHALT", None);
+        assert_eq!(machine.a, 0);
+        assert_eq!(
+            (machine.flag("C"), machine.flag("Z")),
+            (Some(true), Some(true))
+        );
+
+        let (machine, _) = run_source("MOV A 2\nMOV B 3\nADD A\nHALT", None);
+        assert_eq!(machine.a, 5);
+        assert_eq!(
+            (machine.flag("C"), machine.flag("Z")),
+            (Some(false), Some(false))
+        );
+
+        let (machine, _) = run_source("MOV A 3\nMOV B 3\nSUB A A B\nHALT", None);
+        assert_eq!(machine.a, 0);
+        assert_eq!(
+            (machine.flag("C"), machine.flag("Z")),
+            (Some(false), Some(true))
+        );
+
+        // Borrowing sets the carry.
+        let (machine, _) = run_source("MOV A 1\nMOV B 2\nSUB A A B\nHALT", None);
+        assert_eq!(machine.a, 0xFF);
+        assert_eq!(
+            (machine.flag("C"), machine.flag("S")),
+            (Some(true), Some(true))
+        );
+    }
+
+    #[test]
+    fn test_conditional_jumps() {
+        let source = "\
+            MOV A 1\n\
+            MOV B 1\n\
+            CMP A B\n\
+            JMPIMMZ #taken\n\
+            MOV A 0x11\n\
+            HALT\n\
+            taken: JMPIMMNZ #skipped\n\
+            MOV B 0x22\n\
+            HALT\n\
+            skipped: HALT";
+        let (machine, _) = run_source(source, None);
+        assert_eq!((machine.a, machine.b), (1, 0x22));
+    }
+
+    #[test]
+    fn test_call_and_return() {
+        let source = "JMPREL #sub\nMOV B 2\nHALT\nsub: MOV A 1\nJMPREL";
+        let (machine, stop) = run_source(source, Some("sub"));
+        assert_eq!(stop, Stop::Breakpoint);
+        // The return address, after the 3-byte call, high byte on top.
+        assert_eq!(machine.stc, 0xFFFD);
+        assert_eq!(machine.memory[0xFFFE..], [0x00, 0x03]);
+
+        let (machine, stop) = run_source(source, None);
+        assert_eq!(stop, Stop::Halted);
+        assert_eq!((machine.a, machine.b), (1, 2));
+        assert_eq!(machine.stc, 0xFFFF);
+    }
+
+    #[test]
+    fn test_push_and_pop_t() {
+        let source = "MOV TH 0x12\nMOV TL 0x34\nPUSH T\nCLR T\nPOP T\nHALT";
+        let (machine, _) = run_source(source, None);
+        assert_eq!((machine.th, machine.tl), (0x12, 0x34));
+        assert_eq!(machine.stc, 0xFFFF);
+        assert_eq!(machine.memory[0xFFFE..], [0x34, 0x12]);
+    }
+
+    #[test]
+    fn test_halt() {
+        let (machine, stop) = run_source("HALT\nMOV A 1", None);
+        assert_eq!(stop, Stop::Halted);
+        assert_eq!((machine.pc, machine.instructions, machine.a), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let source = "MOV A 1\nJMPIMM #there\nMOV A 2\nthere: MOV B 3\nHALT";
+        let (machine, stop) = run_source(source, Some("there"));
+        assert_eq!(stop, Stop::Breakpoint);
+        assert_eq!((machine.a, machine.b), (1, 0));
+        assert_eq!(machine.pc, 7);
+    }
+
+    #[test]
+    fn test_console() {
+        let config = Config::builtin();
+        let source = "MOV A [0xff01]\nINC A\nMOVAT [0xff00] A\nMOV A [0xff01]\nMOVAT [0xff00] A\n\
+                      MOV B [0xff02]\nMOV A [0xff01]\nHALT";
+        let image = crate::assemble(source, &config, Default::default())
+            .unwrap()
+            .image;
+        let options = RunOptions {
+            max_cycles: 1000,
+            breakpoint: None,
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        let mut machine = Machine::new(&image);
+        machine.console = Some(Console::new(b"ab"));
+        assert_eq!(run(&config, &mut machine, &options).unwrap(), Stop::Halted);
+        let console = machine.console.unwrap();
+        assert_eq!(console.output, b"bb");
+        assert_eq!((machine.a, machine.b), (0, 0));
+        assert!(console.expect_output(b"bb", "out.txt").is_ok());
+        assert!(matches!(
+            console.expect_output(b"bc", "out.txt"),
+            Err(EmulatorErr::UnexpectedOutput(_, 1))
+        ));
+        assert!(matches!(
+            console.expect_output(b"bbb", "out.txt"),
+            Err(EmulatorErr::UnexpectedOutput(_, 2))
+        ));
+
+        // Without a console the addresses are plain memory.
+        let mut machine = Machine::new(&image);
+        run(&config, &mut machine, &options).unwrap();
+        assert_eq!(machine.memory[0xff00], 0);
+    }
+
+    #[test]
+    fn test_timing() {
+        let config = Config::builtin();
+        let source = "MOV A 3\nloop: DEC A\nJMPIMMNZ #loop\nMOVAT [0x8000] A\nMOV B [0x8000]\nHALT";
+        let image = crate::assemble(source, &config, Default::default())
+            .unwrap()
+            .image;
+        let run_timed = |timing| {
+            let options = RunOptions {
+                max_cycles: 1000,
+                breakpoint: None,
+                watchpoints: vec![],
+                timing,
+            };
+            let mut machine = Machine::new(&image);
+            assert_eq!(run(&config, &mut machine, &options).unwrap(), Stop::Halted);
+            machine
+        };
+
+        let functional = run_timed(Timing {
+            mode: Mode::Functional,
+            wait_states: vec![parse_wait_states("0x8000..0x8001=3").unwrap()],
+        });
+        assert_eq!(functional.instructions, 10);
+        assert_eq!(functional.cycles, 10);
+
+        let accurate = run_timed(Timing::default());
+        assert_eq!(accurate.instructions, 10);
+        assert!(accurate.cycles > 10);
+        let waiting = run_timed(Timing {
+            mode: Mode::CycleAccurate,
+            wait_states: vec![parse_wait_states("0x8000..0x8001=3").unwrap()],
+        });
+        assert_eq!(waiting.cycles, accurate.cycles + 6);
+        assert_eq!((waiting.a, waiting.b), (accurate.a, accurate.b));
+
+        assert_eq!(
+            parse_wait_states("0x8000 = 2").unwrap(),
+            WaitStates {
+                range: MemoryRange {
+                    start: 0x8000,
+                    end: 0x8010
+                },
+                cycles: 2
+            }
+        );
+        assert!(parse_wait_states("0x8000").is_err());
+        assert!(parse_wait_states("0x8000=-1").is_err());
+        assert!(parse_wait_states("nowhere=1").is_err());
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let config = Config::builtin();
+        let source = "MOV A 5\nMOVAT [0x8000] A\nMOV B [0x8000]\nHALT";
+        let image = crate::assemble(source, &config, Default::default())
+            .unwrap()
+            .image;
+        let run_watching = |arg: &str| {
+            let watchpoint = parse_watchpoint(arg)
+                .unwrap()
+                .resolve(&HashMap::new())
+                .unwrap();
+            let options = RunOptions {
+                max_cycles: 1000,
+                breakpoint: None,
+                watchpoints: vec![watchpoint],
+                timing: Timing::default(),
+            };
+            let mut machine = Machine::new(&image);
+            run(&config, &mut machine, &options).unwrap()
+        };
+
+        let hit = |address, access, pc| {
+            Stop::Watchpoint(WatchHit {
+                address,
+                access,
+                pc,
+            })
+        };
+        assert_eq!(run_watching("0x8000"), hit(0x8000, Access::Write, 2));
+        assert_eq!(run_watching("0x8000:w"), hit(0x8000, Access::Write, 2));
+        assert_eq!(run_watching("0x8000:r"), hit(0x8000, Access::Read, 5));
+        assert_eq!(run_watching("0x8001"), Stop::Halted);
+
+        assert_eq!(
+            parse_watchpoint("buffer:rw").unwrap(),
+            WatchTarget {
+                target: "buffer".to_string(),
+                access: Access::ReadWrite
+            }
+        );
+        assert!(parse_watchpoint("buffer:x").is_err());
+        assert!(parse_watchpoint(":r").is_err());
+        let err = parse_watchpoint("nowhere")
+            .unwrap()
+            .resolve(&HashMap::new())
+            .unwrap_err();
+        assert!(matches!(err, EmulatorErr::UnknownLabel(_)));
+    }
+}
@@ -0,0 +1,168 @@
+//! The instruction encoding on its own, using only `core` and `alloc` so it
+//! also builds without the `std` feature, e.g. for a microcontroller-based
+//! ROM programmer that assembles source it receives over serial.
+//!
+//! Opcodes come from an [`OpcodeTable`]: the config automaton on hosts, or a
+//! static slice of [`InstructionForm`]s where there's no file system. With
+//! the `tables` feature a [`FormTable`] generated at build time also works.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::specs::{Mnemonic, Operand};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeErr {
+    UnknownMnemonic,
+    /// The operand at this index isn't accepted after the ones before it.
+    InvalidOperand(usize),
+    /// The operands are a prefix of a form, but more are needed.
+    Incomplete,
+    OutOfRange(i64),
+    /// An address that doesn't fit in the target's address width.
+    AddressOutOfRange(i64),
+}
+
+impl Display for EncodeErr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeErr::UnknownMnemonic => write!(f, "Unknown mnemonic."),
+            EncodeErr::InvalidOperand(i) => write!(f, "Invalid operand {}.", i + 1),
+            EncodeErr::Incomplete => write!(f, "Instruction is missing operands."),
+            EncodeErr::OutOfRange(number) => write!(f, "Number {} is out of range.", number),
+            EncodeErr::AddressOutOfRange(address) => {
+                write!(f, "Address {} is out of range.", address)
+            }
+        }
+    }
+}
+
+/// Maps an instruction form to its opcode.
+pub trait OpcodeTable {
+    fn opcode(&self, mnemonic: &Mnemonic, operands: &[Operand]) -> Result<u8, EncodeErr>;
+}
+
+/// One row of a static opcode table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionForm<'a> {
+    pub mnemonic: &'a str,
+    pub operands: &'a [Operand],
+    pub opcode: u8,
+}
+
+impl OpcodeTable for [InstructionForm<'_>] {
+    fn opcode(&self, mnemonic: &Mnemonic, operands: &[Operand]) -> Result<u8, EncodeErr> {
+        let mut forms = self
+            .iter()
+            .filter(|form| form.mnemonic == mnemonic.name())
+            .peekable();
+        if forms.peek().is_none() {
+            return Err(EncodeErr::UnknownMnemonic);
+        }
+
+        let mut longest_prefix = 0;
+        for form in forms {
+            if form.operands == operands {
+                return Ok(form.opcode);
+            }
+            let prefix = form
+                .operands
+                .iter()
+                .zip(operands)
+                .take_while(|(expected, found)| expected == found)
+                .count();
+            longest_prefix = longest_prefix.max(prefix);
+        }
+
+        match longest_prefix == operands.len() {
+            true => Err(EncodeErr::Incomplete),
+            false => Err(EncodeErr::InvalidOperand(longest_prefix)),
+        }
+    }
+}
+
+/// The forms of every mnemonic, as generated by `build::opcode_table`.
+#[cfg(feature = "tables")]
+pub type FormTable = phf::Map<&'static str, &'static [InstructionForm<'static>]>;
+
+#[cfg(feature = "tables")]
+impl OpcodeTable for FormTable {
+    fn opcode(&self, mnemonic: &Mnemonic, operands: &[Operand]) -> Result<u8, EncodeErr> {
+        self.get(mnemonic.name())
+            .ok_or(EncodeErr::UnknownMnemonic)?
+            .opcode(mnemonic, operands)
+    }
+}
+
+pub fn binary_str_to_byte(binary_str: &str) -> u8 {
+    let mut byte = 0;
+    for (i, c) in binary_str.chars().rev().enumerate() {
+        if c == '1' {
+            byte |= 1 << i;
+        }
+    }
+    byte
+}
+
+/// A value that has to fit in a byte, signed or unsigned.
+pub fn encode_byte(number: i64) -> Result<u8, EncodeErr> {
+    if !(-128..=255).contains(&number) {
+        return Err(EncodeErr::OutOfRange(number));
+    }
+
+    Ok(number as u8)
+}
+
+/// A value that has to fit in a 16-bit word, signed or unsigned.
+pub fn encode_word(number: i64) -> Result<u16, EncodeErr> {
+    if !(-32_768..=65_535).contains(&number) {
+        return Err(EncodeErr::OutOfRange(number));
+    }
+
+    Ok(number as u16)
+}
+
+/// An address of `width` bits, signed or unsigned, low byte first in as
+/// many bytes as the width needs.
+pub fn encode_address(number: i64, width: u32) -> Result<Vec<u8>, EncodeErr> {
+    if !(-(1 << (width - 1))..(1 << width)).contains(&number) {
+        return Err(EncodeErr::AddressOutOfRange(number));
+    }
+
+    let bytes = width.div_ceil(8) as usize;
+    Ok(number.to_le_bytes()[..bytes].to_vec())
+}
+
+/// Appends the opcode and operand bytes of an instruction whose operand
+/// values are already known. Addresses are `address_width` bits, stored low
+/// byte first.
+pub fn encode_instruction(
+    table: &(impl OpcodeTable + ?Sized),
+    mnemonic: &Mnemonic,
+    operands: &[(Operand, i64)],
+    address_width: u32,
+    output: &mut Vec<u8>,
+) -> Result<(), EncodeErr> {
+    let kinds = operands
+        .iter()
+        .map(|(operand, _)| *operand)
+        .collect::<Vec<_>>();
+    output.push(table.opcode(mnemonic, &kinds)?);
+    encode_operands(operands, address_width, output)
+}
+
+/// Appends the bytes following the opcode for every operand that has any.
+pub fn encode_operands(
+    operands: &[(Operand, i64)],
+    address_width: u32,
+    output: &mut Vec<u8>,
+) -> Result<(), EncodeErr> {
+    for (operand, value) in operands {
+        match operand {
+            Operand::Mem8 | Operand::Const => output.push(encode_byte(*value)?),
+            Operand::Mem16 => output.extend(encode_address(*value, address_width)?),
+            Operand::Register(_) | Operand::Stc => {}
+        }
+    }
+    Ok(())
+}
@@ -1,60 +1,212 @@
-use std::{fmt::Display, path::Path, process};
+use owo_colors::{OwoColorize, Stream, Style};
+use std::{fmt::Display, path::Path};
 use thiserror::Error;
 
-use crate::{lexer::LexerErr, parser::ParseErr, resolver::ResolveErr, token::Span};
+use crate::{
+    checker::WriterErr,
+    conditional::ConditionalErr,
+    i18n::{text, Lang, Localize, Message},
+    include::IncludeErr,
+    lexer::LexerErr,
+    limits::LimitErr,
+    linkage::LinkErr,
+    macros::MacroErr,
+    parser::ParserErr,
+    pseudo::PseudoErr,
+    resolver::ResolveErr,
+    segments::SegmentErr,
+    token::{statement_at, Expansion, Span},
+};
+#[cfg(feature = "serde")]
+use crate::object::ObjectErr;
+
+const MAX_BACKTRACE_FRAMES: usize = 4;
+
+/// `text` in `style` if `stream` is a terminal that shows colours, plain
+/// when it's piped or `NO_COLOR` is set.
+pub fn paint(text: impl Display, style: Style, stream: Stream) -> String {
+    text.if_supports_color(stream, |text| text.style(style))
+        .to_string()
+}
+
+fn margin(text: impl Display) -> String {
+    paint(text, Style::new().bold().blue(), Stream::Stderr)
+}
+
+fn note_tag(note: &str) -> String {
+    paint(note, Style::new().bold().cyan(), Stream::Stderr)
+}
 
 #[derive(Debug, Error)]
 pub struct ContextError<'a> {
     inner: Error,
-    context: &'a FileContext<'a>,
+    context: &'a FileContext,
+}
+
+impl ContextError<'_> {
+    /// The line `span` lies on with its columns underlined, e.g.
+    ///
+    /// ```text
+    ///  --> main.asm:3:12
+    ///    |
+    ///  3 |     JMPIMM #lop
+    ///    |            ^^^^
+    /// ```
+    fn snippet(&self, span: &Span) -> String {
+        let line_number = (span.line + 1).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let source_line = self.context.source_line(span);
+        // Keep tabs so the carets line up however wide the terminal shows them.
+        let indent = source_line
+            .chars()
+            .take(span.chars.start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let carets = "^".repeat(span.chars.len().max(1));
+
+        format!(
+            "{gutter}{} {}:{}:{}\n{gutter} {}\n{} {} {}\n{gutter} {} {}{}",
+            margin("-->"),
+            self.context.file_name(span),
+            span.line + 1,
+            span.chars.start + 1,
+            margin("|"),
+            margin(&line_number),
+            margin("|"),
+            source_line.trim_end(),
+            margin("|"),
+            indent,
+            paint(carets, Style::new().bold().red(), Stream::Stderr),
+        )
+    }
+
+    fn render(&self, message: &str, lang: Lang) -> String {
+        let note = text(lang, "note", &[]);
+        let mut rendered = format!("{}\n{}", message, self.snippet(&self.inner.span));
+        if let Some(help) = &self.inner.help {
+            rendered += &format!(
+                "\n  {} {}: {}",
+                margin("="),
+                paint(text(lang, "help", &[]), Style::new().bold(), Stream::Stderr),
+                help.localize(lang)
+            );
+        }
+        rendered += &self.backtrace(&self.inner.span, lang);
+        for label in &self.inner.labels {
+            rendered += &format!(
+                "\n{}: {}\n{}",
+                note_tag(&note),
+                label.message.localize(lang),
+                self.snippet(&label.span)
+            );
+            // Otherwise a label in another expansion of the same macro looks
+            // like it points at the error itself.
+            if label.span.expansion != self.inner.span.expansion {
+                rendered += &self.backtrace(&label.span, lang);
+            }
+        }
+        rendered
+    }
+
+    /// Notes on the macro invocations `span` was expanded from.
+    fn backtrace(&self, span: &Span, lang: Lang) -> String {
+        let note = text(lang, "note", &[]);
+        let mut rendered = String::new();
+        // A runaway recursive macro would otherwise print one frame per level.
+        let frames = span.expansions().count();
+        for frame in span.expansions().take(MAX_BACKTRACE_FRAMES) {
+            let name = [frame.macro_name.to_string()];
+            rendered += &format!(
+                "\n{}: {}\n{}",
+                note_tag(&note),
+                text(lang, "note.expansion", &name),
+                self.snippet(&frame.invocation)
+            );
+            rendered += &format!(
+                "\n{}: {}\n{}",
+                note_tag(&note),
+                text(lang, "note.macro_defined_named", &name),
+                self.snippet(&frame.definition)
+            );
+        }
+        if frames > MAX_BACKTRACE_FRAMES {
+            let more = [(frames - MAX_BACKTRACE_FRAMES).to_string()];
+            rendered += &format!("\n{}", text(lang, "note.more_expansions", &more));
+        }
+        rendered
+    }
 }
 
 impl Display for ContextError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let span = &self.inner.span;
-        let content = self.context.content(span);
-
-        use owo_colors::OwoColorize;
-        write!(
-            f,
-            "{}:{}:{}..{}: {}: {}: {}",
-            self.context.file_name.bold(),
-            (span.line + 1).bold(),
-            (span.chars.start + 1).bold(),
-            (span.chars.end).bold(),
-            "error".bold().red(),
-            self.inner,
-            content
-        )
+        write!(f, "{}", self.render(&self.inner.to_string(), Lang::En))
     }
 }
 
-impl ContextError<'_> {
-    pub fn throw(&self) -> ! {
-        eprintln!("{}", self);
-        process::exit(1)
+impl Localize for ContextError<'_> {
+    fn localize(&self, lang: Lang) -> String {
+        self.render(&self.inner.kind.localize(lang), lang)
     }
 }
 
+/// Secondary span attached to a diagnostic, e.g. "first defined here".
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: Message,
+}
+
 #[derive(Debug, Error)]
 #[error("{kind}")]
 pub struct Error {
-    kind: ErrorKind,
+    kind: Box<ErrorKind>,
     span: Span,
+    labels: Vec<Label>,
+    help: Option<Box<Message>>,
 }
 
 impl Error {
+    pub fn with_label(mut self, span: Span, message: impl Into<Message>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Attaches a suggestion, e.g. the closest name to an unknown one.
+    pub fn with_help(mut self, help: impl Into<Message>) -> Self {
+        self.help = Some(Box::new(help.into()));
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    /// The macro expansions the error lies in, innermost first.
+    pub fn backtrace(&self) -> impl Iterator<Item = &Expansion> {
+        self.span.expansions()
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref().map(Message::as_str)
+    }
+
     pub fn with_ctx<'a>(self, context: &'a FileContext) -> ContextError<'a> {
         ContextError {
             inner: self,
             context,
         }
     }
-
-    pub fn throw_with_ctx(self, context: &FileContext) -> ! {
-        let ctx_err = self.with_ctx(context);
-        ctx_err.throw()
-    }
 }
 
 #[derive(Debug, Error)]
@@ -62,9 +214,48 @@ pub enum ErrorKind {
     #[error(transparent)]
     LexerErr(#[from] LexerErr),
     #[error(transparent)]
-    ParseErr(#[from] ParseErr),
+    ParserErr(#[from] ParserErr),
+    #[error(transparent)]
+    WriterErr(#[from] WriterErr),
     #[error(transparent)]
     ResolveErr(#[from] ResolveErr),
+    #[error(transparent)]
+    LimitErr(#[from] LimitErr),
+    #[error(transparent)]
+    MacroErr(#[from] MacroErr),
+    #[error(transparent)]
+    IncludeErr(#[from] IncludeErr),
+    #[error(transparent)]
+    ConditionalErr(#[from] ConditionalErr),
+    #[error(transparent)]
+    LinkErr(#[from] LinkErr),
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    ObjectErr(#[from] ObjectErr),
+    #[error(transparent)]
+    SegmentErr(#[from] SegmentErr),
+    #[error(transparent)]
+    PseudoErr(#[from] PseudoErr),
+}
+
+impl Localize for ErrorKind {
+    fn localize(&self, lang: Lang) -> String {
+        match self {
+            ErrorKind::LexerErr(err) => err.localize(lang),
+            ErrorKind::ParserErr(err) => err.localize(lang),
+            ErrorKind::WriterErr(err) => err.localize(lang),
+            ErrorKind::ResolveErr(err) => err.localize(lang),
+            ErrorKind::LimitErr(err) => err.localize(lang),
+            ErrorKind::MacroErr(err) => err.localize(lang),
+            ErrorKind::IncludeErr(err) => err.localize(lang),
+            ErrorKind::ConditionalErr(err) => err.localize(lang),
+            ErrorKind::LinkErr(err) => err.localize(lang),
+            #[cfg(feature = "serde")]
+            ErrorKind::ObjectErr(err) => err.localize(lang),
+            ErrorKind::SegmentErr(err) => err.localize(lang),
+            ErrorKind::PseudoErr(err) => err.localize(lang),
+        }
+    }
 }
 
 pub trait WithSpan {
@@ -73,25 +264,31 @@ pub trait WithSpan {
 
 impl<T: Into<ErrorKind>> WithSpan for T {
     fn with_span(self, span: Span) -> Error {
+        let kind = self.into();
+        let help = match &kind {
+            ErrorKind::LexerErr(err) => err.help().map(Box::new),
+            _ => None,
+        };
         Error {
-            kind: self.into(),
+            kind: Box::new(kind),
             span,
+            labels: vec![],
+            help,
         }
     }
 }
 
-pub trait ErrorGroup {
-    fn throw_all_with_ctx(self, context: &FileContext<'_>) -> !;
-}
-
-impl ErrorGroup for Vec<Error> {
-    fn throw_all_with_ctx(self, context: &FileContext<'_>) -> ! {
-        for err in self {
-            let ctx_err = err.with_ctx(context);
-            eprintln!("{}", ctx_err);
-        }
-        process::exit(1)
-    }
+/// Orders errors by file, line and column, dropping repeats of the same
+/// message at the same place, e.g. from a macro expanded many times.
+pub fn sort_errors(mut errors: Vec<Error>) -> Vec<Error> {
+    errors.sort_by_cached_key(|err| {
+        let span = err.span();
+        (span.file, span.line, span.chars.start, err.to_string())
+    });
+    errors.dedup_by(|later, earlier| {
+        later.span.same_location(&earlier.span) && later.to_string() == earlier.to_string()
+    });
+    errors
 }
 
 pub trait ResultSplit<T> {
@@ -112,27 +309,111 @@ impl<T, I: Iterator<Item = Result<T, Error>>> ResultSplit<T> for I {
     }
 }
 
+/// The source files of a program, for quoting the code an error points at.
+#[derive(Debug)]
+pub struct FileContext {
+    files: Vec<SourceFile>,
+}
+
 #[derive(Debug)]
-pub struct FileContext<'a> {
-    file_name: String,
-    file_content: &'a str,
+struct SourceFile {
+    name: String,
+    content: String,
 }
 
-impl<'a> FileContext<'a> {
-    pub fn new(path: Option<&Path>, file_content: &'a str) -> Self {
+impl FileContext {
+    pub fn new(path: Option<&Path>, file_content: &str) -> Self {
         FileContext {
-            file_name: path
-                .map(|path| path.to_string_lossy().to_string())
-                .unwrap_or("stdin".to_string()),
-            file_content,
+            files: vec![SourceFile {
+                name: path
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or("stdin".to_string()),
+                content: file_content.to_string(),
+            }],
         }
     }
 
-    fn content(&self, span: &Span) -> &str {
-        self.file_content
+    /// Registers an included file, returning the index its spans should use.
+    pub fn add_file(&mut self, path: &Path, file_content: &str) -> usize {
+        self.files.push(SourceFile {
+            name: path.to_string_lossy().to_string(),
+            content: file_content.to_string(),
+        });
+        self.files.len() - 1
+    }
+
+    /// Name of the file being assembled, as opposed to the ones it includes.
+    pub fn main_file_name(&self) -> &str {
+        &self.files[0].name
+    }
+
+    pub fn file_name(&self, span: &Span) -> &str {
+        &self.files[span.file].name
+    }
+
+    /// The whole source line `span` lies on.
+    pub fn source_line(&self, span: &Span) -> &str {
+        self.files[span.file]
+            .content
             .lines()
             .nth(span.line)
-            .and_then(|line| line.get(span.chars.clone()))
-            .expect("Something is wrong...")
+            .unwrap_or_default()
+    }
+
+    /// The statement `span` starts in, which is its whole source line unless
+    /// that holds several.
+    pub fn source_statement(&self, span: &Span) -> &str {
+        statement_at(self.source_line(span), span.chars.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_sort_errors() {
+        let err = |name: &str, file, line, column| {
+            WriterErr::UnknownLabel(name.to_string())
+                .with_span(Span::new(line, column..column + 1).in_file(file))
+        };
+        let expansion = Arc::new(Expansion {
+            macro_name: "m".to_string(),
+            invocation: Span::new(0, 0..1),
+            definition: Span::new(0, 0..1),
+        });
+        let mut expanded = Span::new(2, 4..5);
+        expanded.expansion = Some(expansion);
+
+        let errors = vec![
+            err("c", 1, 0, 0),
+            err("b", 0, 2, 4),
+            err("a", 0, 2, 0),
+            err("b", 0, 2, 4),
+            // The same message at the same place from a macro expansion.
+            WriterErr::UnknownLabel("b".to_string()).with_span(expanded),
+            err("d", 0, 2, 4),
+            err("e", 0, 0, 9),
+        ];
+        let sorted = sort_errors(errors)
+            .iter()
+            .map(|err| {
+                let span = err.span();
+                (err.to_string(), span.file, span.line, span.chars.start)
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            ("e", 0, 0, 9),
+            ("a", 0, 2, 0),
+            ("b", 0, 2, 4),
+            ("d", 0, 2, 4),
+            ("c", 1, 0, 0),
+        ]
+        .map(|(name, file, line, column)| {
+            (format!("Unknown label '{}'.", name), file, line, column)
+        });
+        assert_eq!(sorted, expected);
     }
 }
@@ -0,0 +1,54 @@
+use crate::{config::Config, selftest::assemble_snippet};
+
+/// A program bundled with the assembler to check an installation end to end.
+pub struct Example {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub source: &'static str,
+}
+
+pub static EXAMPLES: &[Example] = &[
+    Example {
+        name: "blink",
+        description: "Toggles the byte at 0xFF00 forever",
+        source: include_str!("../examples/blink.as"),
+    },
+    Example {
+        name: "fibonacci",
+        description: "Writes Fibonacci numbers to 0x8000 until they overflow a byte",
+        source: include_str!("../examples/fibonacci.as"),
+    },
+    Example {
+        name: "memtest",
+        description: "Writes and reads back two patterns, reporting the result at 0xFF00",
+        source: include_str!("../examples/memtest.as"),
+    },
+];
+
+pub fn find_example(name: &str) -> Option<&'static Example> {
+    EXAMPLES.iter().find(|example| example.name == name)
+}
+
+impl Example {
+    pub fn assemble(&self, config: &Config) -> Result<Vec<u8>, String> {
+        assemble_snippet(self.source, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples_assemble() {
+        let config = Config::builtin();
+        for example in EXAMPLES {
+            let image = example
+                .assemble(&config)
+                .unwrap_or_else(|err| panic!("{}: {}", example.name, err));
+            assert!(!image.is_empty(), "{}", example.name);
+            assert_eq!(find_example(example.name).unwrap().source, example.source);
+        }
+        assert!(find_example("nonexistent").is_none());
+    }
+}
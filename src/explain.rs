@@ -0,0 +1,157 @@
+use std::fmt::Display;
+
+use crate::{
+    config::{Config, ConfigNode, NodeType},
+    parser::Line,
+    specs::Operand,
+    token::{Token, TokenType},
+};
+
+#[derive(Debug)]
+pub enum ExplainOutcome {
+    Encoded(String),
+    UnknownMnemonic,
+    NoTransition(Vec<String>),
+    Incomplete(Vec<String>),
+}
+
+/// The path an instruction took through the config automaton, from the
+/// mnemonic over each operand kind to the opcode (or the point it got stuck).
+#[derive(Debug)]
+pub struct EncodingExplanation<'a> {
+    pub mnemonic: &'a Token,
+    pub taken: Vec<(Operand, String)>,
+    pub rejected: Option<(Operand, String)>,
+    pub outcome: ExplainOutcome,
+}
+
+fn expected_transitions(node: &ConfigNode) -> Vec<String> {
+    let ConfigNode::Branch(children) = node else {
+        return vec![];
+    };
+    let mut expected = children
+        .keys()
+        .map(|key| match key {
+            NodeType::Operand(operand) => operand.to_string(),
+            NodeType::MachineCode => "end of instruction".to_string(),
+            NodeType::Mnemonic(mnemonic) => mnemonic.to_string(),
+        })
+        .collect::<Vec<_>>();
+    expected.sort();
+    expected
+}
+
+pub fn explain_encoding<'a>(config: &Config, line: &Line<'a>) -> Option<EncodingExplanation<'a>> {
+    let Line::Instruction { mnemonic, operands } = line else {
+        return None;
+    };
+
+    let mut explanation = EncodingExplanation {
+        mnemonic,
+        taken: vec![],
+        rejected: None,
+        outcome: ExplainOutcome::UnknownMnemonic,
+    };
+
+    let TokenType::Mnemonic(name) = &mnemonic.token_type else {
+        return Some(explanation);
+    };
+    let Some(mut current_node) = config.automaton.get(&NodeType::Mnemonic(name.clone())) else {
+        return Some(explanation);
+    };
+
+    for (operand, value) in operands {
+        let operand = *operand;
+        let next = match current_node {
+            ConfigNode::Branch(children) => children.get(&NodeType::Operand(operand)),
+            ConfigNode::Leaf(_) => None,
+        };
+        let Some(next) = next else {
+            explanation.rejected = Some((operand, value.to_string()));
+            explanation.outcome = ExplainOutcome::NoTransition(expected_transitions(current_node));
+            return Some(explanation);
+        };
+        explanation.taken.push((operand, value.to_string()));
+        current_node = next;
+    }
+
+    explanation.outcome = match current_node {
+        ConfigNode::Branch(children) => match children.get(&NodeType::MachineCode) {
+            Some(ConfigNode::Leaf(opcode)) => ExplainOutcome::Encoded(opcode.clone()),
+            _ => ExplainOutcome::Incomplete(expected_transitions(current_node)),
+        },
+        ConfigNode::Leaf(opcode) => ExplainOutcome::Encoded(opcode.clone()),
+    };
+    Some(explanation)
+}
+
+impl Display for EncodingExplanation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: {}",
+            self.mnemonic.span.line + 1,
+            self.mnemonic.content
+        )?;
+        for (operand, value) in &self.taken {
+            write!(f, " -> {} [{}]", value, operand)?;
+        }
+
+        match &self.outcome {
+            ExplainOutcome::Encoded(opcode) => write!(f, " => {}", opcode),
+            ExplainOutcome::UnknownMnemonic => write!(f, " => unknown mnemonic"),
+            ExplainOutcome::NoTransition(expected) => {
+                let (operand, value) = self.rejected.as_ref().unwrap();
+                write!(
+                    f,
+                    " => no transition for {} [{}], expected one of: {}",
+                    value,
+                    operand,
+                    expected.join(", ")
+                )
+            }
+            ExplainOutcome::Incomplete(expected) => write!(
+                f,
+                " => incomplete, expected one of: {}",
+                expected.join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::parse_with_config};
+
+    fn explain(source: &str) -> Vec<String> {
+        let config = Config::builtin();
+        let source = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        lines
+            .iter()
+            .filter_map(|line| explain_encoding(&config, line))
+            .map(|explanation| explanation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_explain_encoding() {
+        assert_eq!(
+            explain("start: MOV A 42\nbyte 1\nHALT"),
+            [
+                "line 1: MOV -> A [A] -> 42 [CONST] => 0b00010001",
+                "line 3: HALT => 0b11011000",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_rejected_encoding() {
+        assert_eq!(
+            explain("MOV 1 A"),
+            ["line 1: MOV => no transition for 1 [CONST], expected one of: A, B, TH, TL"]
+        );
+    }
+}
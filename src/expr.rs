@@ -0,0 +1,274 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    checker::WriterErr,
+    error::{Error, WithSpan},
+    i18n::Message,
+    token::{Operator, Span, Token, TokenType},
+};
+
+/// An operand or data value: a single token, or arithmetic over numbers,
+/// labels and constants that's evaluated once labels are resolved.
+#[derive(Debug, Clone)]
+pub enum Expr<'a> {
+    Value(&'a Token),
+    /// A parenthesized expression, with its parentheses.
+    Group(&'a Token, Box<Expr<'a>>, &'a Token),
+    /// A prefix operator, with its token.
+    Unary(&'a Token, Operator, Box<Expr<'a>>),
+    Binary(Operator, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+impl<'a> Expr<'a> {
+    /// The leftmost token of the expression, for locating it in the source.
+    pub fn first_token(&self) -> &'a Token {
+        match self {
+            Expr::Value(token) | Expr::Group(token, _, _) | Expr::Unary(token, _, _) => token,
+            Expr::Binary(_, lhs, _) => lhs.first_token(),
+        }
+    }
+
+    fn last_token(&self) -> &'a Token {
+        match self {
+            Expr::Value(token) | Expr::Group(_, _, token) => token,
+            Expr::Unary(_, _, inner) | Expr::Binary(_, _, inner) => inner.last_token(),
+        }
+    }
+
+    /// Where the whole expression is in the source.
+    pub fn span(&self) -> Span {
+        self.first_token().span.clone() + self.last_token().span.clone()
+    }
+
+    pub fn as_token(&self) -> Option<&'a Token> {
+        match self {
+            Expr::Value(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression refers to a `#label`, making it an address.
+    pub fn has_label_ref(&self) -> bool {
+        match self {
+            Expr::Value(token) => matches!(token.token_type, TokenType::LabelRef(_)),
+            Expr::Group(_, inner, _) | Expr::Unary(_, _, inner) => inner.has_label_ref(),
+            Expr::Binary(_, lhs, rhs) => lhs.has_label_ref() || rhs.has_label_ref(),
+        }
+    }
+
+    /// Bits a lone number literal holds as written, see
+    /// [`TokenType::Number`].
+    pub fn written_width(&self) -> Option<u32> {
+        match self {
+            Expr::Value(token) => match token.token_type {
+                TokenType::Number(_, width) => width,
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn evaluate(&self, symbols: &HashMap<&str, usize>) -> Result<i64, Error> {
+        match self {
+            Expr::Value(token) => {
+                match &token.token_type {
+                    TokenType::Number(number, _) => Ok(*number),
+                    TokenType::LabelRef(_) | TokenType::Mnemonic(_) => {
+                        let name = match &token.token_type {
+                            TokenType::LabelRef(name) => name.as_str(),
+                            _ => token.content.as_str(),
+                        };
+                        symbols
+                            .get(name)
+                            .map(|value| *value as i64)
+                            .ok_or_else(|| unknown_label(name, token, symbols))
+                    }
+                    _ => Err(WriterErr::InvalidOperand(token.content.clone())
+                        .with_span(token.span.clone())),
+                }
+            }
+            Expr::Group(_, inner, _) => inner.evaluate(symbols),
+            Expr::Unary(_, operator, inner) => {
+                let value = inner.evaluate(symbols)?;
+                match operator {
+                    Operator::Minus => Ok(value.wrapping_neg()),
+                    Operator::Tilde => Ok(!value),
+                    _ => Ok(value),
+                }
+            }
+            Expr::Binary(operator, lhs, rhs) => {
+                let lhs = lhs.evaluate(symbols)?;
+                let rhs = rhs.evaluate(symbols)?;
+                Ok(match operator {
+                    Operator::Plus => lhs.wrapping_add(rhs),
+                    Operator::Minus => lhs.wrapping_sub(rhs),
+                    Operator::Star => lhs.wrapping_mul(rhs),
+                    Operator::Slash | Operator::Percent if rhs == 0 => {
+                        return Err(
+                            WriterErr::DivisionByZero(self.to_string()).with_span(self.span())
+                        )
+                    }
+                    Operator::Slash => lhs.wrapping_div(rhs),
+                    Operator::Percent => lhs.wrapping_rem(rhs),
+                    Operator::Ampersand => lhs & rhs,
+                    Operator::Pipe => lhs | rhs,
+                    Operator::Caret => lhs ^ rhs,
+                    Operator::ShiftLeft => lhs.wrapping_shl(rhs as u32),
+                    Operator::ShiftRight => lhs.wrapping_shr(rhs as u32),
+                    Operator::Equal => (lhs == rhs) as i64,
+                    Operator::NotEqual => (lhs != rhs) as i64,
+                    Operator::Less => (lhs < rhs) as i64,
+                    Operator::LessEqual => (lhs <= rhs) as i64,
+                    Operator::Greater => (lhs > rhs) as i64,
+                    Operator::GreaterEqual => (lhs >= rhs) as i64,
+                    Operator::Tilde => unreachable!(),
+                })
+            }
+        }
+    }
+}
+
+/// Number of single character insertions, deletions and substitutions
+/// turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The known name `name` is most likely a typo of, if any is close enough.
+pub(crate) fn closest_name<'a>(
+    name: &str,
+    known: impl Iterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    known
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn unknown_label(name: &str, token: &Token, symbols: &HashMap<&str, usize>) -> Error {
+    let err = WriterErr::UnknownLabel(name.to_string()).with_span(token.span.clone());
+    match closest_name(name, symbols.keys().copied()) {
+        Some(closest) => {
+            err.with_help(Message::new("help.did_you_mean", vec![closest.to_string()]))
+        }
+        None => err,
+    }
+}
+
+impl Display for Expr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Value(token) => match token.lexeme.is_empty() {
+                true => write!(f, "{}", token.content),
+                false => write!(f, "{}", token.lexeme),
+            },
+            Expr::Group(_, inner, _) => write!(f, "({})", inner),
+            Expr::Unary(_, operator, inner) => write!(f, "{}{}", operator, inner),
+            Expr::Binary(operator, lhs, rhs) => write!(f, "{} {} {}", lhs, operator, rhs),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::check_semantics,
+        config::Config,
+        error::ErrorKind,
+        lexer::Lexer,
+        parser::{parse_expression, parse_with_config},
+        resolver::get_resolved_labels,
+    };
+
+    fn evaluate(text: &str, symbols: &HashMap<&str, usize>) -> Result<i64, Error> {
+        let chars = text.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        parse_expression(&tokens).unwrap().evaluate(symbols)
+    }
+
+    /// The error assembling `text` stops at.
+    fn check(text: &str) -> Error {
+        let config = Config::builtin();
+        let chars = text.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        check_semantics(lines, &labels, &config).unwrap_err()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let symbols = HashMap::new();
+        assert_eq!(evaluate("2 + 3 * 4", &symbols).unwrap(), 14);
+        assert_eq!(evaluate("(2 + 3) * 4", &symbols).unwrap(), 20);
+        assert_eq!(evaluate("10 - 4 - 3", &symbols).unwrap(), 3);
+        assert_eq!(evaluate("1 << 2 + 1", &symbols).unwrap(), 8);
+        assert_eq!(evaluate("0xF0 | 0x0F & 0x3C", &symbols).unwrap(), 0xFC);
+        assert_eq!(evaluate("-3 + 5", &symbols).unwrap(), 2);
+        assert_eq!(evaluate("-(3 + 5)", &symbols).unwrap(), -8);
+        assert_eq!(evaluate("2 * -3", &symbols).unwrap(), -6);
+        assert_eq!(evaluate("~0 & 0xFF", &symbols).unwrap(), 0xFF);
+        assert_eq!(evaluate("7 % 3 == 1", &symbols).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_label_arithmetic() {
+        let symbols = HashMap::from([("start", 0x10), ("end", 0x18)]);
+        assert_eq!(evaluate("#end - #start", &symbols).unwrap(), 8);
+        assert_eq!(evaluate("#start + 2 * 3", &symbols).unwrap(), 0x16);
+        assert_eq!(evaluate("(#end >> 8) & 0xFF", &symbols).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        let err = evaluate("4 / (2 - 2)", &HashMap::new()).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WriterErr(WriterErr::DivisionByZero(expr)) if expr == "4 / (2 - 2)"
+        ));
+        assert_eq!(err.span().chars, 0..11);
+        assert!(evaluate("4 % 0", &HashMap::new()).is_err());
+
+        // Expressions evaluate to any 64-bit value and only fail to fit
+        // where they're used.
+        let err = check("MOV A 200 + 100");
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WriterErr(WriterErr::NumberOutOfRange(300))
+        ));
+        assert_eq!(err.span().chars, 6..15);
+        let err = check(".word 0x8000 * 2");
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::WriterErr(WriterErr::WordOutOfRange(0x10000))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_label_points_at_it_and_suggests_closest() {
+        let chars = "2 * #lop".chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let expression = parse_expression(&tokens).unwrap();
+        let symbols = HashMap::from([("loop", 0), ("start", 4)]);
+
+        let err = expression.evaluate(&symbols).unwrap_err();
+        assert_eq!(err.span().chars, 4..8);
+        assert_eq!(err.help(), Some("did you mean 'loop'?"));
+
+        let symbols = HashMap::from([("start", 4)]);
+        assert_eq!(expression.evaluate(&symbols).unwrap_err().help(), None);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::Config,
+    parser::{parse_with_config, Line},
+    reachability::falls_through,
+    token::{split_lines, Span, Token, TokenType},
+};
+
+/// Code or data under a label that `--gc-sections` left out of the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DroppedSection {
+    pub label: String,
+    pub span: Span,
+    /// Lines of code and data it held.
+    pub lines: usize,
+}
+
+/// The lines from one label up to the next.
+#[derive(Default)]
+struct Section<'a> {
+    labels: Vec<&'a Token>,
+    lines: Vec<&'a [Token]>,
+    /// Labels its code and data refer to.
+    references: Vec<&'a str>,
+    /// Number of lines of code and data, the rest are directives.
+    emitting: usize,
+    /// Whether its last line of code can run on into the next section.
+    falls_through: bool,
+}
+
+fn label_name(token: &Token) -> Option<&str> {
+    match &token.token_type {
+        TokenType::Label(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn symbol_name(token: &Token) -> &str {
+    match &token.token_type {
+        TokenType::LabelRef(name) => name,
+        _ => &token.content,
+    }
+}
+
+fn references(line: &[Token]) -> impl Iterator<Item = &str> {
+    line.iter()
+        .enumerate()
+        .filter_map(|(i, token)| match &token.token_type {
+            TokenType::LabelRef(name) => Some(name.as_str()),
+            // Bare names after the first token, e.g. the return address of
+            // a pseudo-instruction.
+            TokenType::Mnemonic(name) if i > 0 => Some(name.name()),
+            _ => None,
+        })
+}
+
+/// Drops the code and data of every section no path from the entry point
+/// refers to, where a section runs from one label to the next. The entry
+/// point is the first section with code or data. Sections stay when a kept
+/// one refers to their labels or can run on into them, and so do exported
+/// ones and whatever directives such as `.equ` refer to. Directives in
+/// dropped sections are kept, since they place nothing themselves.
+pub fn gc_sections(tokens: &[Token], config: &Config) -> (Vec<Token>, Vec<DroppedSection>) {
+    let mut sections = vec![Section {
+        falls_through: true,
+        ..Default::default()
+    }];
+    let mut roots = vec![];
+
+    for line in split_lines(tokens) {
+        let label_count = line
+            .iter()
+            .take_while(|token| label_name(token).is_some())
+            .count();
+        if label_count > 0 {
+            sections.push(Section {
+                labels: line[..label_count].iter().collect(),
+                falls_through: true,
+                ..Default::default()
+            });
+        }
+        let section = sections.last_mut().unwrap();
+        section.lines.push(line);
+
+        let rest = &line[label_count..];
+        if rest.is_empty() {
+            continue;
+        }
+        // Lines that don't parse are kept and reported later.
+        let parsed = parse_with_config(rest, config)
+            .ok()
+            .and_then(|lines| lines.into_iter().next());
+        match &parsed {
+            Some(Line::Export(names)) => roots.extend(names.iter().map(|name| symbol_name(name))),
+            Some(
+                Line::Constant { .. }
+                | Line::Org(_)
+                | Line::Align(_)
+                | Line::Entry(_)
+                | Line::Segment(_)
+                | Line::Barrier(_)
+                | Line::Import(_),
+            ) => roots.extend(references(rest)),
+            _ => {
+                section.references.extend(references(rest));
+                section.emitting += 1;
+                section.falls_through = parsed.as_ref().is_none_or(falls_through);
+            }
+        }
+    }
+
+    let section_of = sections
+        .iter()
+        .enumerate()
+        .flat_map(|(i, section)| {
+            section
+                .labels
+                .iter()
+                .map(move |label| (label_name(label), i))
+        })
+        .filter_map(|(name, i)| Some((name?, i)))
+        .collect::<HashMap<_, _>>();
+
+    let mut pending = roots
+        .iter()
+        .filter_map(|name| section_of.get(name).copied())
+        .collect::<Vec<_>>();
+    pending.extend(sections.iter().position(|section| section.emitting > 0));
+    let mut kept = HashSet::new();
+    while let Some(i) = pending.pop() {
+        if !kept.insert(i) {
+            continue;
+        }
+        let section = &sections[i];
+        pending.extend(
+            section
+                .references
+                .iter()
+                .filter_map(|name| section_of.get(name)),
+        );
+        if section.falls_through && i + 1 < sections.len() {
+            pending.push(i + 1);
+        }
+    }
+
+    let mut kept_tokens = vec![];
+    let mut dropped = vec![];
+    for (i, section) in sections.iter().enumerate() {
+        if kept.contains(&i) || section.emitting == 0 {
+            kept_tokens.extend(section.lines.iter().flat_map(|line| line.iter().cloned()));
+            continue;
+        }
+        let first = section.labels[0];
+        dropped.push(DroppedSection {
+            label: label_name(first).unwrap_or_default().to_string(),
+            span: first.span.clone(),
+            lines: section.emitting,
+        });
+        for line in &section.lines {
+            let rest = line
+                .iter()
+                .skip_while(|token| label_name(token).is_some())
+                .collect::<Vec<_>>();
+            let directive = rest.first().is_some_and(|token| {
+                matches!(
+                    token.token_type,
+                    TokenType::Equ
+                        | TokenType::Org
+                        | TokenType::Align
+                        | TokenType::Entry
+                        | TokenType::Segment
+                        | TokenType::Barrier
+                        | TokenType::Export
+                        | TokenType::Import
+                )
+            });
+            if directive {
+                kept_tokens.extend(rest.into_iter().cloned());
+            }
+        }
+    }
+    (kept_tokens, dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_gc_sections() {
+        let source = "start:  JMPREL #used\n\
+            \x20       HALT\n\
+            unused: MOV A 1\n\
+            .equ LIMIT 10\n\
+            \x20       JMPREL\n\
+            used:   MOV A 2\n\
+            loop:   JMPIMM #table\n\
+            table:  byte 1 2\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+
+        let (kept, dropped) = gc_sections(&tokens, &Config::builtin());
+        let summary = dropped
+            .iter()
+            .map(|section| (section.label.as_str(), section.span.line, section.lines))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, [("unused", 2, 2)]);
+        let labels = kept.iter().filter_map(label_name).collect::<Vec<_>>();
+        assert_eq!(labels, ["start", "used", "loop", "table"]);
+        // The constant stays for whatever else refers to it.
+        assert!(kept.iter().any(|token| token.content == "LIMIT"));
+    }
+}
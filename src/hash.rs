@@ -0,0 +1,126 @@
+use crate::{
+    config::Config,
+    defines::Define,
+    encoder::binary_str_to_byte,
+    token::{split_lines, Token},
+};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its values never change between
+/// runs or Rust releases, so build systems can store them.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// The capabilities and then the instruction forms of a config, one per
+/// line and sorted, since the config lists them in no particular order.
+fn config_forms(config: &Config) -> String {
+    let capabilities = &config.capabilities;
+    let mut forms = config
+        .signatures()
+        .iter()
+        .map(|signature| {
+            let operands = signature
+                .operands
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let stack = config
+                .stack_opcodes
+                .contains(&binary_str_to_byte(&signature.opcode));
+            format!(
+                "form {} {} {}{}\n",
+                signature.mnemonic.name(),
+                operands.join(" "),
+                signature.opcode,
+                if stack { " stack" } else { "" }
+            )
+        })
+        .collect::<Vec<_>>();
+    forms.sort();
+    let dialect = &config.dialect;
+    let aliases = dialect
+        .aliases
+        .iter()
+        .map(|(alias, target)| format!("alias {} {}\n", alias, target));
+    let reserved = dialect
+        .reserved
+        .iter()
+        .map(|word| format!("reserved {}\n", word));
+    let pseudo = config.pseudo_instructions.iter().map(|(name, pseudo)| {
+        format!(
+            "pseudo {} {}: {}\n",
+            name,
+            pseudo.parameters.join(" "),
+            pseudo.expansion.join("; ")
+        )
+    });
+    format!(
+        "capabilities stack={} wide-memory={} address-width={} instruction-alignment={} pad={}\n{}{}{}{}",
+        capabilities.has_stack,
+        capabilities.has_wide_memory,
+        capabilities.address_width,
+        capabilities.instruction_alignment,
+        capabilities.pad_instructions,
+        aliases.collect::<String>(),
+        reserved.collect::<String>(),
+        pseudo.collect::<String>(),
+        forms.concat()
+    )
+}
+
+/// Identifies what a config encodes, whatever file it was read from.
+pub fn config_hash(config: &Config) -> u64 {
+    fnv1a(config_forms(config).as_bytes())
+}
+
+/// What the image is assembled from: the assembler version, the instruction
+/// forms of the config, the defines and the source after includes,
+/// conditionals and macros, one line per source line with its tokens
+/// separated by single spaces. Layout changes don't change it.
+pub fn normalized_input(tokens: &[Token], config: &Config, defines: &[Define]) -> String {
+    let mut input = format!("version {}\n", env!("CARGO_PKG_VERSION"));
+    input += &config_forms(config);
+    for define in defines {
+        input += &format!("define {}={}\n", define.name, define.value);
+    }
+    for line in split_lines(tokens) {
+        let line = line
+            .iter()
+            .map(|token| match token.lexeme.is_empty() {
+                true => token.content.as_str(),
+                false => token.lexeme.as_str(),
+            })
+            .collect::<Vec<_>>();
+        input += &line.join(" ");
+        input += "\n";
+    }
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn input_hash(source: &str) -> u64 {
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        fnv1a(normalized_input(&tokens, &Config::builtin(), &[]).as_bytes())
+    }
+
+    #[test]
+    fn test_hashes() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+
+        let hash = input_hash("start: MOV A 1\nHALT\n");
+        assert_eq!(hash, input_hash("start:   MOV  A 1\n\n    HALT"));
+        assert_ne!(hash, input_hash("start: MOV A 2\nHALT\n"));
+        assert_ne!(hash, input_hash("start: MOV A 1 HALT\n"));
+    }
+}
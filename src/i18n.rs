@@ -0,0 +1,1237 @@
+use std::{env, fmt::Display, io};
+
+use phf::phf_map;
+
+use crate::{
+    checker::WriterErr,
+    conditional::ConditionalErr,
+    config::{format_paths, operand_spellings, ConfigError},
+    defines::DefinesError,
+    disassembler::DisasmErr,
+    include::IncludeErr,
+    lexer::LexerErr,
+    limits::LimitErr,
+    linkage::LinkErr,
+    loader::LoadErr,
+    macros::MacroErr,
+    output::{format_contributors, OutputErr, SizeExceeded},
+    parser::ParserErr,
+    pseudo::PseudoErr,
+    resolver::ResolveErr,
+    segments::SegmentErr,
+};
+#[cfg(feature = "cli")]
+use crate::{difftest::DifftestErr, manifest::ManifestErr, scaffold::ScaffoldError};
+#[cfg(feature = "emulator")]
+use crate::{emulator::EmulatorErr, snapshot::SnapshotErr};
+#[cfg(feature = "serde")]
+use crate::{linker::LinkerErr, object::ObjectErr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Lang {
+    #[default]
+    En,
+    Pl,
+}
+
+impl Lang {
+    /// Picks the language from `$LANG` (e.g. `pl_PL.UTF-8`), defaulting to English.
+    pub fn from_env() -> Self {
+        match env::var("LANG") {
+            Ok(lang) if lang.starts_with("pl") => Lang::Pl,
+            _ => Lang::En,
+        }
+    }
+
+    fn catalog(&self) -> Option<&'static phf::Map<&'static str, &'static str>> {
+        match self {
+            Lang::En => None,
+            Lang::Pl => Some(&CATALOG_PL),
+        }
+    }
+}
+
+// English text of errors lives in their `#[error]` attributes, the rest in
+// `CATALOG_EN`. Arguments are `{0}`, `{1}`, ...
+static CATALOG_EN: phf::Map<&'static str, &'static str> = phf_map! {
+    "fatal_error" => "fatal error:",
+    "warning" => "warning:",
+    "help" => "help",
+    "note" => "note",
+
+    "config_using" => "using '{0}'",
+    "config_builtin" => "{0} Using the built-in instruction set.",
+    "gc_dropped" => "{0}:{1}: dropped '{2}' ({3} line(s)), nothing reachable refers to it.",
+    "selftest_summary" => "{0} instruction forms checked, {1} skipped, {2} failed.",
+    "unknown_example" => "Unknown example '{0}'.",
+    "repl_intro" => "Type instructions to assemble them, :help for commands.",
+    "repl_failures" => "{0} line(s) failed.",
+
+    "note.expansion" => "in this expansion of macro '{0}'",
+    "note.macro_defined_named" => "macro '{0}' defined here",
+    "note.more_expansions" => "... and {0} more expansion(s)",
+    "note.first_defined" => "first defined here",
+    "note.label_defined" => "label defined here",
+    "note.constant_defined" => "constant defined here",
+    "note.defined" => "defined here",
+    "note.referenced" => "referenced here",
+    "note.enclosing_macro_defined" => "enclosing macro defined here",
+    "note.macro_defined" => "macro defined here",
+    "note.first_else" => "first '.else' here",
+    "note.in_segment" => "in segment '{0}'",
+
+    "help.capability" => "the config sets '{0}' to false in its capabilities",
+    "help.align" => "put '.align {0}' before it, or set 'pad-instructions' in the config's capabilities",
+    "help.name_the_distance" => "if the distance is meant, give one address a name with '.const' and subtract that",
+    "help.did_you_mean" => "did you mean '{0}'?",
+    "help.raise_max_output_bytes" => "raise --max-output-bytes if the program really is this large",
+    "help.malformed_literal" => "names can't start with a digit; write '{0} {1}' if these are a number and a name",
+};
+
+static CATALOG_PL: phf::Map<&'static str, &'static str> = phf_map! {
+    "fatal_error" => "błąd krytyczny:",
+    "warning" => "ostrzeżenie:",
+    "summary" => "ostrzeżenia: {0}, błędy: {1}, wyemitowane bajty: {2}",
+    "help" => "pomoc",
+    "note" => "uwaga",
+
+    "config_using" => "używam '{0}'",
+    "config_builtin" => "{0} Używam wbudowanego zestawu instrukcji.",
+    "gc_dropped" => "{0}:{1}: usunięto '{2}' ({3} linii), nic osiągalnego się do tego nie odwołuje.",
+    "selftest_summary" => "Sprawdzono form instrukcji: {0}, pominięto: {1}, niepowodzenia: {2}.",
+    "unknown_example" => "Nieznany przykład '{0}'.",
+    "repl_intro" => "Wpisuj instrukcje, aby je asemblować, :help wyświetla polecenia.",
+    "repl_failures" => "Nie powiodło się linii: {0}.",
+
+    "note.expansion" => "w tym rozwinięciu makra '{0}'",
+    "note.macro_defined_named" => "makro '{0}' zdefiniowano tutaj",
+    "note.more_expansions" => "... i jeszcze {0} rozwinięć",
+    "note.first_defined" => "pierwsza definicja tutaj",
+    "note.label_defined" => "etykietę zdefiniowano tutaj",
+    "note.constant_defined" => "stałą zdefiniowano tutaj",
+    "note.defined" => "zdefiniowano tutaj",
+    "note.referenced" => "użyto tutaj",
+    "note.enclosing_macro_defined" => "zewnętrzne makro zdefiniowano tutaj",
+    "note.macro_defined" => "makro zdefiniowano tutaj",
+    "note.first_else" => "pierwsze '.else' tutaj",
+    "note.in_segment" => "w segmencie '{0}'",
+
+    "help.capability" => "konfiguracja ustawia '{0}' na false w swoich możliwościach",
+    "help.align" => "umieść przed nią '.align {0}' albo ustaw 'pad-instructions' w możliwościach konfiguracji",
+    "help.name_the_distance" => "jeśli chodzi o odległość, nazwij jeden z adresów przez '.const' i odejmij go",
+    "help.did_you_mean" => "czy chodziło o '{0}'?",
+    "help.raise_max_output_bytes" => "zwiększ --max-output-bytes, jeśli program naprawdę jest tak duży",
+    "help.malformed_literal" => "nazwy nie mogą zaczynać się od cyfry; napisz '{0} {1}', jeśli to liczba i nazwa",
+
+    "lexer.unknown_token" => "Nieznany token '{0}'.",
+    "lexer.number_parse" => "Nie udało się sparsować liczby '{0}'.",
+    "lexer.label_parse" => "Etykieta '{0}:' powinna znajdować się na początku linii.",
+    "lexer.unterminated_literal" => "Niezakończony literał {0}.",
+    "lexer.invalid_escape" => "Nieznana sekwencja ucieczki '{0}'.",
+    "lexer.invalid_char_literal" => "Literał znakowy {0} powinien zawierać dokładnie jeden znak.",
+    "lexer.unknown_directive" => "Nieznana dyrektywa '{0}'.",
+    "lexer.malformed_literal" => "Niepoprawny literał lub identyfikator '{0}{1}'.",
+    "lexer.reserved_word" => "'{0}' jest słowem zastrzeżonym i nie może być nazwą.",
+
+    "parser.unexpected_token" => "Oczekiwano: \"{0}\", znaleziono \"{1}\".",
+    "parser.unexpected_line_beginning" => "Linia powinna zaczynać się od mnemonika, 'byte' lub etykiety, znaleziono \"{0}\".",
+    "parser.end_of_line" => "Oczekiwano: \"{0}\", napotkano koniec linii.",
+    "parser.too_many_operands" => "Instrukcja '{0}' przyjmuje co najwyżej {1} operand(y).",
+    "parser.no_values" => "'{0}' nie ma żadnych wartości.",
+    "parser.inert_barrier" => "'.barrier' na razie nic nie robi, bo żaden przebieg nie przestawia ani nie przepisuje kodu.",
+
+    "resolver.duplicate_label" => "Etykieta '{0}' została zdefiniowana więcej niż raz.",
+    "resolver.duplicate_constant" => "Stała '{0}' została zdefiniowana więcej niż raz.",
+    "resolver.constant_shadows_label" => "Stała '{0}' przesłania etykietę o tej samej nazwie.",
+    "resolver.label_shadows_constant" => "Etykieta '{0}' ma tę samą nazwę co stała.",
+    "resolver.label_named_register" => "Etykieta '{0}' ma nazwę rejestru.",
+    "resolver.constant_named_register" => "Stała '{0}' ma nazwę rejestru.",
+    "resolver.depends_on_later_symbol" => "'{0}' używa symbolu zdefiniowanego dalej, więc adres nie jest jeszcze znany.",
+
+    "checker.unknown_mnemonic" => "Nieznany mnemonik '{0}'.",
+    "checker.invalid_operand" => "Niepoprawny operand '{0}'.",
+    "checker.number_out_of_range" => "Liczba powinna należeć do przedziału [-128, 255], znaleziono {0}.",
+    "checker.word_out_of_range" => "Liczba powinna należeć do przedziału [-32768, 65535], znaleziono {0}.",
+    "checker.unknown_label" => "Nieznana etykieta '{0}'.",
+    "checker.incomplete_instruction" => "Instrukcji '{0}' brakuje operandów.",
+    "checker.division_by_zero" => "Dzielenie przez zero w '{0}'.",
+    "checker.symbol_redefined" => "Stała '{0}' jest już zdefiniowana.",
+    "checker.org_backwards" => "'.org {0}' cofnęłoby adres z {1}.",
+    "checker.invalid_alignment" => "Wyrównanie powinno być liczbą dodatnią, znaleziono {0}.",
+    "checker.placeholder_instruction" => "'{0}' nie występuje w konfiguracji, zastępuje ją {1} bajt(ów) wypełnienia.",
+    "checker.invalid_placeholder" => "Niepoprawny bajt wypełnienia '{0}'.",
+    "checker.wide_literal" => "Literał {0} zapisano na {1} bitach, a jego operand mieści tylko {2}.",
+    "checker.misaligned_instruction" => "Instrukcja pod {0} powinna zaczynać się od wielokrotności {1}.",
+    "checker.no_wide_memory" => "'{0}' wymaga 16-bitowej pamięci, której platforma docelowa nie ma.",
+    "checker.no_stack" => "'{0}' wymaga stosu, którego platforma docelowa nie ma.",
+    "checker.address_out_of_range" => "Adres {0} jest poza {1}-bitową przestrzenią adresową platformy docelowej.",
+
+    "limits.include_depth" => "Dołączenia są zagnieżdżone głębiej niż {0} poziomów, czy występuje cykl dołączeń?",
+    "limits.macro_depth" => "Rozwinięcie makra jest zagnieżdżone głębiej niż {0} poziomów, czy '{1}' jest rekurencyjne?",
+    "limits.expansion_size" => "Rozwinięcia makr wygenerowały więcej niż {0} tokenów.",
+    "limits.line_length" => "Linia ma {0} znaków, więcej niż limit {1}.",
+    "limits.output_size" => "Wyjście urosłoby tutaj do {0} bajtów, więcej niż limit {1}.",
+    "limits.non_ascii" => "Znak spoza ASCII {0} (U+{1}) poza ciągiem znaków.",
+
+    "macros.missing_name" => "Oczekiwano nazwy makra po '.macro'.",
+    "macros.invalid_parameter" => "Parametr makra '{0}' powinien być zwykłym identyfikatorem.",
+    "macros.duplicate" => "Makro '{0}' zostało zdefiniowane więcej niż raz.",
+    "macros.nested_definition" => "Makro '{0}' zostało zdefiniowane wewnątrz innego makra.",
+    "macros.unterminated" => "Makru '{0}' brakuje '.endm'.",
+    "macros.unexpected_end" => "'.endm' bez odpowiadającego '.macro'.",
+    "macros.argument_count" => "Makro '{0}' przyjmuje {1} argument(y), znaleziono {2}.",
+    "include.missing_path" => "Oczekiwano nazwy pliku w cudzysłowie po '.include'.",
+    "include.read" => "Nie udało się odczytać dołączanego pliku '{0}': {1}.",
+    "include.cycle" => "Plik '{0}' dołącza sam siebie.",
+    "conditional.missing_condition" => "Oczekiwano warunku po '{0}'.",
+    "conditional.invalid_condition" => "Niepoprawny warunek: {0}",
+    "conditional.invalid_symbol" => "'{0}' przyjmuje pojedynczą nazwę symbolu.",
+    "conditional.unmatched" => "'{0}' bez odpowiadającego '.if'.",
+    "conditional.duplicate_else" => "Blok warunkowy ma więcej niż jedno '.else'.",
+    "conditional.unterminated" => "'{0}' nie ma odpowiadającego '.endif'.",
+    "conditional.trailing_token" => "Nieoczekiwane '{0}' po '{1}'.",
+
+    "config.parse" => "Niepoprawny plik konfiguracyjny: {0}.",
+    "config.unknown_mnemonic" => "Nieznany mnemonik '{0}' w konfiguracji.",
+    "config.unknown_operand" => "Nieznany operand '{0}' w konfiguracji, oczekiwano jednego z: {1}.",
+    "config.unknown_operand_nearest" => "Nieznany operand '{0}' w konfiguracji (czy chodziło o '{2}'?), oczekiwano jednego z: {1}.",
+    "config.not_found" => "Nie znaleziono pliku konfiguracyjnego, przeszukano: {0}.",
+    "config.not_found_skipped" => "Nie znaleziono pliku konfiguracyjnego, przeszukano: {0}. Pominięto {1}, to nie są konfiguracje JSON.",
+    "config.missing_env_config" => "$ASSEMBLER_CONFIG wskazuje na '{0}', który nie jest plikiem.",
+    "config.invalid_opcode" => "Instrukcja '{0}' ma kod '{1}', oczekiwano 8 cyfr binarnych, np. '0b00000001'.",
+    "config.duplicate_opcode" => "Instrukcje '{0}' i '{1}' mają ten sam kod '{2}'.",
+    "config.duplicate_signature" => "Instrukcje '{0}' i '{1}' to obie '{2}'.",
+    "config.unknown_flag" => "Instrukcja '{0}' zależy od nieznanej flagi '{1}'.",
+    "config.unknown_alias_target" => "Alias '{0}' oznacza '{1}', co nie jest dyrektywą.",
+    "config.invalid_dialect_name" => "'{0}' nie może być aliasem ani słowem zastrzeżonym, bo nie jest nazwą.",
+    "config.invalid_instruction_alignment" => "Wyrównanie instrukcji powinno być liczbą dodatnią, znaleziono {0}.",
+    "config.invalid_override" => "Niepoprawne nadpisanie konfiguracji '{0}', oczekiwano ŚCIEŻKA=WARTOŚĆ.",
+    "config.unknown_override_target" => "Nic w konfiguracji nie pasuje do '{0}'.",
+    "config.invalid_pseudo_instruction" => "Pseudoinstrukcja '{0}' jest niepoprawna: {1}.",
+    "config.no_padding_nop" => "'pad-instructions' wymaga instrukcji NOP bez operandów do wypełniania.",
+    "config.invalid_address_width" => "Szerokość adresu powinna wynosić od 1 do 24 bitów, znaleziono {0}.",
+
+    "defines.malformed_line" => "Błąd w pliku stałych w linii {0}: oczekiwano NAZWA=WARTOŚĆ, znaleziono \"{1}\".",
+    "defines.invalid_name" => "Błąd w pliku stałych w linii {0}: niepoprawna nazwa stałej '{1}'.",
+    "defines.number_parse" => "Błąd w pliku stałych w linii {0}: nie udało się sparsować wartości '{1}'.",
+    "defines.invalid_arg_name" => "Niepoprawna nazwa stałej '{0}' w -D.",
+    "defines.invalid_arg_value" => "Nie udało się sparsować wartości '{0}' w -D.",
+    "defines.duplicate" => "Stała '{0}' została zdefiniowana więcej niż raz.",
+    "defines.collides_with_label" => "Stała '{0}' koliduje z etykietą o tej samej nazwie.",
+
+    "difftest.spawn" => "Nie udało się uruchomić symulatora '{0}': {1}.",
+    "difftest.failed" => "Symulator '{0}' zakończył się błędem: {1}.",
+    "difftest.invalid_trace" => "Symulator '{0}' wypisał niepoprawną linię śladu {1}: {2}.",
+
+    "disasm.invalid_entry" => "Niepoprawny punkt wejścia '{0}', oczekiwano adresu.",
+    "disasm.invalid_symbols" => "Nie udało się odczytać symboli z '{0}': {1}.",
+
+    "emulator.unsupported" => "Nie wiadomo, jak wykonać '{0}' pod adresem {1}.",
+    "emulator.unknown_label" => "Nieznana etykieta '{0}'.",
+    "emulator.invalid_range" => "Niepoprawny zakres pamięci '{0}', oczekiwano START lub START..KONIEC.",
+    "emulator.address_width" => "Emulator obsługuje tylko adresy 16-bitowe, a szerokość adresu w konfiguracji to {0}.",
+    "emulator.breakpoint_out_of_range" => "Punkt przerwania '{0}' pod adresem {1} leży poza 16-bitową pamięcią emulatora.",
+    "emulator.invalid_watchpoint" => "Niepoprawny punkt obserwacji '{0}', oczekiwano ADRESU lub ETYKIETY, opcjonalnie z :r, :w lub :rw.",
+    "emulator.watchpoint_out_of_range" => "Punkt obserwacji '{0}' pod adresem {1} leży poza 16-bitową pamięcią emulatora.",
+    "emulator.unexpected_output" => "Wyjście konsoli różni się od '{0}' począwszy od bajtu {1}.",
+    "emulator.invalid_wait_states" => "Niepoprawne cykle oczekiwania '{0}', oczekiwano ZAKRES=CYKLE.",
+
+    "output.mismatched_formats" => "Podano {0} formatów wyjścia dla {1} plików wyjściowych, podaj jeden format na plik.",
+    "output.formats_to_stdout" => "Podano {0} formatów wyjścia bez pliku wyjściowego, tylko jeden może trafić na stdout.",
+    "linkage.undefined_export" => "Eksportowany symbol '{0}' nie jest zdefiniowany.",
+    "linkage.import_defined_locally" => "Importowany symbol '{0}' jest też zdefiniowany w tym pliku.",
+    "linkage.unresolved_import" => "Nierozwiązany import '{0}', żaden obiekt go nie eksportuje.",
+    "linker.invalid_base" => "Niepoprawny adres sekcji '{0}', oczekiwano NAZWA=ADRES.",
+    "linker.read" => "Nie udało się odczytać obiektu '{0}': {1}.",
+    "linker.unsupported_version" => "'{0}' jest obiektem w wersji {1}, oczekiwano {2}.",
+    "linker.unknown_section" => "Żaden obiekt nie ma sekcji '{0}' do umieszczenia.",
+    "linker.duplicate_symbol" => "Symbol '{0}' jest eksportowany zarówno przez {1}, jak i {2}.",
+    "linker.undefined_symbol" => "Niezdefiniowany symbol '{0}', importowany przez {1}.",
+    "linker.sections_overlap" => "Sekcje '{0}' i '{1}' nachodzą na siebie pod adresem {2}.",
+    "linker.value_out_of_range" => "Relokowana wartość {0} nie mieści się w {1} bajtach w {2}.",
+    "linker.invalid_script" => "Niepoprawny skrypt linkera '{0}': {1}.",
+    "linker.unplaced_section" => "Skrypt linkera nie umieszcza sekcji '{0}' z {1}.",
+    "linker.region_overflow" => "Region '{0}' jest przepełniony o {1} bajtów.",
+    "loader.read" => "Nie udało się odczytać obrazu '{0}': {1}.",
+    "loader.invalid_record" => "Niepoprawny rekord w linii {1} pliku '{0}'.",
+    "loader.checksum" => "Niezgodna suma kontrolna w linii {1} pliku '{0}'.",
+    "loader.address_too_large" => "Rekord w linii {1} pliku '{0}' kończy się pod adresem {2}, poza najszerszą przestrzenią adresową.",
+    "manifest.read" => "Nie udało się odczytać manifestu '{0}': {1}.",
+    "manifest.invalid" => "Niepoprawny manifest '{0}': {1}.",
+    "manifest.hook_spawn" => "Nie udało się uruchomić polecenia po budowaniu '{0}': {1}.",
+    "manifest.hook_failed" => "Polecenie po budowaniu '{0}' zakończyło się błędem: {1}.",
+    "object.not_relocatable" => "Nie można relokować '{0}', relokować można tylko pojedynczą etykietę plus lub minus stała.",
+    "pseudo.argument_count" => "Pseudoinstrukcja '{0}' przyjmuje {1} argument(ów), znaleziono {2}.",
+    "pseudo.invalid_template" => "Pseudoinstrukcja '{0}' jest niepoprawna: {1}.",
+    "snapshot.read" => "Nie udało się odczytać migawki '{0}': {1}.",
+    "snapshot.invalid" => "Niepoprawna migawka '{0}': {1}.",
+    "snapshot.unsupported_version" => "'{0}' jest migawką w wersji {1}, oczekiwano {2}.",
+    "segments.invalid_output" => "Niepoprawne wyjście segmentu '{0}', oczekiwano NAZWA=PLIK.",
+    "segments.unknown_segment" => "Nieznany segment '{0}', program ma: {1}.",
+    "segments.cross_segment_difference" => "'{0}' odejmuje adresy z segmentów '{1}' i '{2}', co nie ma sensu.",
+    "output.size_exceeded" => "Obraz ma {0} bajtów i przekracza limit {1} bajtów o {2}. Największe składowe: {3}.",
+};
+
+fn fill(template: &str, args: &[String]) -> String {
+    args.iter()
+        .enumerate()
+        .fold(template.to_string(), |message, (i, arg)| {
+            message.replace(&format!("{{{}}}", i), arg)
+        })
+}
+
+/// Looks up a driver message (not tied to an error type) in the catalog.
+pub fn translate(lang: Lang, key: &str, args: &[String]) -> Option<String> {
+    lang.catalog()
+        .and_then(|catalog| catalog.get(key))
+        .map(|template| fill(template, args))
+}
+
+/// A driver message, help or note in `lang`, English where it has no
+/// translation.
+pub fn text(lang: Lang, key: &str, args: &[String]) -> String {
+    translate(lang, key, args)
+        .unwrap_or_else(|| fill(CATALOG_EN.get(key).copied().unwrap_or(key), args))
+}
+
+/// Help or a note attached to a diagnostic, from the catalog or, for text
+/// that has no translation, as given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    key: Option<&'static str>,
+    args: Vec<String>,
+    english: String,
+}
+
+impl Message {
+    pub fn new(key: &'static str, args: Vec<String>) -> Self {
+        Self {
+            english: text(Lang::En, key, &args),
+            key: Some(key),
+            args,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.english
+    }
+}
+
+impl From<String> for Message {
+    fn from(english: String) -> Self {
+        Self {
+            key: None,
+            args: vec![],
+            english,
+        }
+    }
+}
+
+impl From<&str> for Message {
+    fn from(english: &str) -> Self {
+        english.to_string().into()
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.english)
+    }
+}
+
+impl Localize for Message {
+    fn message_key(&self) -> Option<&'static str> {
+        self.key
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        self.args.clone()
+    }
+}
+
+/// Diagnostics that can be rendered from the message catalog. Types without
+/// a key, or keys missing from a catalog, fall back to the English `Display`.
+pub trait Localize: Display {
+    fn message_key(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn localize(&self, lang: Lang) -> String {
+        self.message_key()
+            .and_then(|key| translate(lang, key, &self.message_args()))
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+impl Localize for io::Error {}
+
+#[cfg(feature = "cli")]
+impl Localize for ScaffoldError {}
+
+impl Localize for LexerErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            LexerErr::UnknownToken(_) => "lexer.unknown_token",
+            LexerErr::NumberParseError(_) => "lexer.number_parse",
+            LexerErr::LabelParseError(_) => "lexer.label_parse",
+            LexerErr::UnterminatedLiteral(_) => "lexer.unterminated_literal",
+            LexerErr::InvalidEscape(_) => "lexer.invalid_escape",
+            LexerErr::InvalidCharLiteral(_) => "lexer.invalid_char_literal",
+            LexerErr::UnknownDirective(_) => "lexer.unknown_directive",
+            LexerErr::ReservedWord(_) => "lexer.reserved_word",
+            LexerErr::MalformedLiteral(_, _) => "lexer.malformed_literal",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            LexerErr::UnknownToken(arg)
+            | LexerErr::NumberParseError(arg)
+            | LexerErr::LabelParseError(arg)
+            | LexerErr::UnterminatedLiteral(arg)
+            | LexerErr::InvalidEscape(arg)
+            | LexerErr::InvalidCharLiteral(arg)
+            | LexerErr::UnknownDirective(arg)
+            | LexerErr::ReservedWord(arg) => vec![arg.clone()],
+            LexerErr::MalformedLiteral(number, rest) => vec![number.clone(), rest.clone()],
+        }
+    }
+}
+
+impl Localize for ParserErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            ParserErr::UnexpectedToken(_, _) => "parser.unexpected_token",
+            ParserErr::UnexpectedLineBeginning(_) => "parser.unexpected_line_beginning",
+            ParserErr::EndOfLine(_) => "parser.end_of_line",
+            ParserErr::TooManyOperands(_, _) => "parser.too_many_operands",
+            ParserErr::NoValues(_) => "parser.no_values",
+            ParserErr::InertBarrier => "parser.inert_barrier",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ParserErr::UnexpectedToken(expected, found) => vec![expected.clone(), found.clone()],
+            ParserErr::UnexpectedLineBeginning(found) => vec![found.clone()],
+            ParserErr::EndOfLine(expected) => vec![expected.clone()],
+            ParserErr::TooManyOperands(mnemonic, max_arity) => {
+                vec![mnemonic.clone(), max_arity.to_string()]
+            }
+            ParserErr::NoValues(directive) => vec![directive.clone()],
+            ParserErr::InertBarrier => vec![],
+        }
+    }
+}
+
+impl Localize for ResolveErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            ResolveErr::DuplicateLabel(_) => "resolver.duplicate_label",
+            ResolveErr::DuplicateConstant(_) => "resolver.duplicate_constant",
+            ResolveErr::ConstantShadowsLabel(_) => "resolver.constant_shadows_label",
+            ResolveErr::LabelShadowsConstant(_) => "resolver.label_shadows_constant",
+            ResolveErr::LabelNamedRegister(_) => "resolver.label_named_register",
+            ResolveErr::ConstantNamedRegister(_) => "resolver.constant_named_register",
+            ResolveErr::DependsOnLaterSymbol(_) => "resolver.depends_on_later_symbol",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ResolveErr::DuplicateLabel(name)
+            | ResolveErr::DuplicateConstant(name)
+            | ResolveErr::ConstantShadowsLabel(name)
+            | ResolveErr::LabelShadowsConstant(name)
+            | ResolveErr::LabelNamedRegister(name)
+            | ResolveErr::ConstantNamedRegister(name)
+            | ResolveErr::DependsOnLaterSymbol(name) => vec![name.clone()],
+        }
+    }
+}
+
+impl Localize for WriterErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            WriterErr::UnknownMnemonic(_) => "checker.unknown_mnemonic",
+            WriterErr::InvalidOperand(_) => "checker.invalid_operand",
+            WriterErr::NumberOutOfRange(_) => "checker.number_out_of_range",
+            WriterErr::WordOutOfRange(_) => "checker.word_out_of_range",
+            WriterErr::UnknownLabel(_) => "checker.unknown_label",
+            WriterErr::IncompleteInstruction(_) => "checker.incomplete_instruction",
+            WriterErr::DivisionByZero(_) => "checker.division_by_zero",
+            WriterErr::SymbolRedefined(_) => "checker.symbol_redefined",
+            WriterErr::OrgBackwards(_, _) => "checker.org_backwards",
+            WriterErr::InvalidAlignment(_) => "checker.invalid_alignment",
+            WriterErr::NoWideMemory(_) => "checker.no_wide_memory",
+            WriterErr::NoStack(_) => "checker.no_stack",
+            WriterErr::AddressOutOfRange(_, _) => "checker.address_out_of_range",
+            WriterErr::MisalignedInstruction(_, _) => "checker.misaligned_instruction",
+            WriterErr::PlaceholderInstruction(_, _) => "checker.placeholder_instruction",
+            WriterErr::InvalidPlaceholder(_) => "checker.invalid_placeholder",
+            WriterErr::WideLiteral(_, _, _) => "checker.wide_literal",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            WriterErr::UnknownMnemonic(arg)
+            | WriterErr::InvalidOperand(arg)
+            | WriterErr::UnknownLabel(arg)
+            | WriterErr::IncompleteInstruction(arg)
+            | WriterErr::DivisionByZero(arg)
+            | WriterErr::SymbolRedefined(arg)
+            | WriterErr::NoWideMemory(arg)
+            | WriterErr::NoStack(arg)
+            | WriterErr::InvalidPlaceholder(arg) => vec![arg.clone()],
+            WriterErr::AddressOutOfRange(address, width) => {
+                vec![format!("{:#06x}", address), width.to_string()]
+            }
+            WriterErr::NumberOutOfRange(number)
+            | WriterErr::WordOutOfRange(number)
+            | WriterErr::InvalidAlignment(number) => {
+                vec![number.to_string()]
+            }
+            WriterErr::OrgBackwards(target, address) => {
+                vec![format!("{:#06x}", target), format!("{:#06x}", address)]
+            }
+            WriterErr::MisalignedInstruction(address, multiple) => {
+                vec![format!("{:#06x}", address), multiple.to_string()]
+            }
+            WriterErr::PlaceholderInstruction(form, size) => vec![form.clone(), size.to_string()],
+            WriterErr::WideLiteral(number, width, bits) => {
+                vec![
+                    format!("{:#x}", number),
+                    width.to_string(),
+                    bits.to_string(),
+                ]
+            }
+        }
+    }
+}
+
+impl Localize for LimitErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            LimitErr::IncludeDepth(_) => "limits.include_depth",
+            LimitErr::MacroDepth(_, _) => "limits.macro_depth",
+            LimitErr::ExpansionSize(_) => "limits.expansion_size",
+            LimitErr::LineLength(_, _) => "limits.line_length",
+            LimitErr::NonAscii(_, _) => "limits.non_ascii",
+            LimitErr::OutputSize(_, _) => "limits.output_size",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            LimitErr::IncludeDepth(max) | LimitErr::ExpansionSize(max) => vec![max.to_string()],
+            LimitErr::MacroDepth(max, name) => vec![max.to_string(), name.clone()],
+            LimitErr::LineLength(length, max) | LimitErr::OutputSize(length, max) => {
+                vec![length.to_string(), max.to_string()]
+            }
+            LimitErr::NonAscii(c, code) => vec![format!("{:?}", c), format!("{:04X}", code)],
+        }
+    }
+}
+
+impl Localize for IncludeErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            IncludeErr::MissingPath => "include.missing_path",
+            IncludeErr::Read(_, _) => "include.read",
+            IncludeErr::Cycle(_) => "include.cycle",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            IncludeErr::MissingPath => vec![],
+            IncludeErr::Read(path, err) => vec![path.clone(), err.clone()],
+            IncludeErr::Cycle(path) => vec![path.clone()],
+        }
+    }
+}
+
+impl Localize for ConditionalErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            ConditionalErr::MissingCondition(_) => "conditional.missing_condition",
+            ConditionalErr::InvalidCondition(_) => "conditional.invalid_condition",
+            ConditionalErr::InvalidSymbol(_) => "conditional.invalid_symbol",
+            ConditionalErr::Unmatched(_) => "conditional.unmatched",
+            ConditionalErr::DuplicateElse => "conditional.duplicate_else",
+            ConditionalErr::Unterminated(_) => "conditional.unterminated",
+            ConditionalErr::TrailingToken(_, _) => "conditional.trailing_token",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ConditionalErr::DuplicateElse => vec![],
+            ConditionalErr::MissingCondition(arg)
+            | ConditionalErr::InvalidCondition(arg)
+            | ConditionalErr::InvalidSymbol(arg)
+            | ConditionalErr::Unmatched(arg)
+            | ConditionalErr::Unterminated(arg) => vec![arg.clone()],
+            ConditionalErr::TrailingToken(token, directive) => {
+                vec![token.clone(), directive.clone()]
+            }
+        }
+    }
+}
+
+impl Localize for MacroErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            MacroErr::MissingName => "macros.missing_name",
+            MacroErr::InvalidParameter(_) => "macros.invalid_parameter",
+            MacroErr::DuplicateMacro(_) => "macros.duplicate",
+            MacroErr::NestedDefinition(_) => "macros.nested_definition",
+            MacroErr::Unterminated(_) => "macros.unterminated",
+            MacroErr::UnexpectedEnd => "macros.unexpected_end",
+            MacroErr::ArgumentCount(_, _, _) => "macros.argument_count",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            MacroErr::MissingName | MacroErr::UnexpectedEnd => vec![],
+            MacroErr::InvalidParameter(arg)
+            | MacroErr::DuplicateMacro(arg)
+            | MacroErr::NestedDefinition(arg)
+            | MacroErr::Unterminated(arg) => vec![arg.clone()],
+            MacroErr::ArgumentCount(name, expected, found) => {
+                vec![name.clone(), expected.to_string(), found.to_string()]
+            }
+        }
+    }
+}
+
+impl Localize for ConfigError {
+    fn message_key(&self) -> Option<&'static str> {
+        match self {
+            ConfigError::ReadFileError(_) => None,
+            ConfigError::ParseError(_) => Some("config.parse"),
+            ConfigError::UnknownMnemonic(_) => Some("config.unknown_mnemonic"),
+            ConfigError::UnknownOperand(_, None) => Some("config.unknown_operand"),
+            ConfigError::UnknownOperand(_, Some(_)) => Some("config.unknown_operand_nearest"),
+            ConfigError::NotFound(_, skipped) if skipped.is_empty() => Some("config.not_found"),
+            ConfigError::NotFound(_, _) => Some("config.not_found_skipped"),
+            ConfigError::MissingEnvConfig(_) => Some("config.missing_env_config"),
+            ConfigError::InvalidOpcode(_, _) => Some("config.invalid_opcode"),
+            ConfigError::DuplicateOpcode(_, _, _) => Some("config.duplicate_opcode"),
+            ConfigError::DuplicateSignature(_, _, _) => Some("config.duplicate_signature"),
+            ConfigError::UnknownFlag(_, _) => Some("config.unknown_flag"),
+            ConfigError::InvalidAddressWidth(_) => Some("config.invalid_address_width"),
+            ConfigError::UnknownAliasTarget(_, _) => Some("config.unknown_alias_target"),
+            ConfigError::InvalidDialectName(_) => Some("config.invalid_dialect_name"),
+            ConfigError::InvalidInstructionAlignment(_) => {
+                Some("config.invalid_instruction_alignment")
+            }
+            ConfigError::NoPaddingNop => Some("config.no_padding_nop"),
+            ConfigError::InvalidOverride(_) => Some("config.invalid_override"),
+            ConfigError::UnknownOverrideTarget(_) => Some("config.unknown_override_target"),
+            ConfigError::InvalidPseudoInstruction(_, _) => {
+                Some("config.invalid_pseudo_instruction")
+            }
+        }
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ConfigError::ReadFileError(_) => vec![],
+            ConfigError::ParseError(err) => vec![err.to_string()],
+            ConfigError::UnknownMnemonic(arg) => vec![arg.clone()],
+            ConfigError::UnknownOperand(operand, nearest) => vec![
+                operand.clone(),
+                operand_spellings(),
+                nearest.clone().unwrap_or_default(),
+            ],
+            ConfigError::NotFound(paths, skipped) => {
+                vec![format_paths(paths), format_paths(skipped)]
+            }
+            ConfigError::MissingEnvConfig(path) => vec![path.display().to_string()],
+            ConfigError::InvalidOpcode(name, value) | ConfigError::UnknownFlag(name, value) => {
+                vec![name.clone(), value.clone()]
+            }
+            ConfigError::DuplicateOpcode(first, second, detail)
+            | ConfigError::DuplicateSignature(first, second, detail) => {
+                vec![first.clone(), second.clone(), detail.clone()]
+            }
+            ConfigError::InvalidAddressWidth(width) => vec![width.to_string()],
+            ConfigError::UnknownAliasTarget(alias, target) => vec![alias.clone(), target.clone()],
+            ConfigError::InvalidDialectName(name) => vec![name.clone()],
+            ConfigError::InvalidInstructionAlignment(alignment) => vec![alignment.to_string()],
+            ConfigError::NoPaddingNop => vec![],
+            ConfigError::InvalidOverride(arg) | ConfigError::UnknownOverrideTarget(arg) => {
+                vec![arg.clone()]
+            }
+            ConfigError::InvalidPseudoInstruction(name, reason) => {
+                vec![name.clone(), reason.clone()]
+            }
+        }
+    }
+}
+
+impl Localize for DefinesError {
+    fn message_key(&self) -> Option<&'static str> {
+        match self {
+            DefinesError::ReadFileError(_) => None,
+            DefinesError::MalformedLine(_, _) => Some("defines.malformed_line"),
+            DefinesError::InvalidName(_, _) => Some("defines.invalid_name"),
+            DefinesError::NumberParseError(_, _) => Some("defines.number_parse"),
+            DefinesError::InvalidArgName(_) => Some("defines.invalid_arg_name"),
+            DefinesError::InvalidArgValue(_) => Some("defines.invalid_arg_value"),
+            DefinesError::DuplicateDefine(_) => Some("defines.duplicate"),
+            DefinesError::CollidesWithLabel(_) => Some("defines.collides_with_label"),
+        }
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            DefinesError::ReadFileError(_) => vec![],
+            DefinesError::MalformedLine(line, arg)
+            | DefinesError::InvalidName(line, arg)
+            | DefinesError::NumberParseError(line, arg) => vec![line.to_string(), arg.clone()],
+            DefinesError::InvalidArgName(arg)
+            | DefinesError::InvalidArgValue(arg)
+            | DefinesError::DuplicateDefine(arg)
+            | DefinesError::CollidesWithLabel(arg) => vec![arg.clone()],
+        }
+    }
+}
+
+impl Localize for DisasmErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            DisasmErr::InvalidEntry(_) => "disasm.invalid_entry",
+            DisasmErr::InvalidSymbols(_, _) => "disasm.invalid_symbols",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            DisasmErr::InvalidEntry(entry) => vec![entry.clone()],
+            DisasmErr::InvalidSymbols(path, err) => vec![path.clone(), err.clone()],
+        }
+    }
+}
+
+#[cfg(feature = "emulator")]
+impl Localize for EmulatorErr {
+    fn message_key(&self) -> Option<&'static str> {
+        match self {
+            EmulatorErr::Decode(_) => None,
+            EmulatorErr::Unsupported(_, _) => Some("emulator.unsupported"),
+            EmulatorErr::UnknownLabel(_) => Some("emulator.unknown_label"),
+            EmulatorErr::InvalidRange(_) => Some("emulator.invalid_range"),
+            EmulatorErr::AddressWidth(_) => Some("emulator.address_width"),
+            EmulatorErr::BreakpointOutOfRange(_, _) => Some("emulator.breakpoint_out_of_range"),
+            EmulatorErr::InvalidWatchpoint(_) => Some("emulator.invalid_watchpoint"),
+            EmulatorErr::WatchpointOutOfRange(_, _) => Some("emulator.watchpoint_out_of_range"),
+            EmulatorErr::UnexpectedOutput(_, _) => Some("emulator.unexpected_output"),
+            EmulatorErr::InvalidWaitStates(_) => Some("emulator.invalid_wait_states"),
+        }
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            EmulatorErr::Decode(_) => vec![],
+            EmulatorErr::Unsupported(instruction, address) => {
+                vec![instruction.clone(), format!("{:#06x}", address)]
+            }
+            EmulatorErr::UnknownLabel(label) => vec![label.clone()],
+            EmulatorErr::InvalidRange(range) => vec![range.clone()],
+            EmulatorErr::AddressWidth(width) => vec![width.to_string()],
+            EmulatorErr::InvalidWatchpoint(arg) | EmulatorErr::InvalidWaitStates(arg) => {
+                vec![arg.clone()]
+            }
+            EmulatorErr::BreakpointOutOfRange(label, address)
+            | EmulatorErr::WatchpointOutOfRange(label, address) => {
+                vec![label.clone(), format!("{:#x}", address)]
+            }
+            EmulatorErr::UnexpectedOutput(name, offset) => vec![name.clone(), offset.to_string()],
+        }
+    }
+}
+
+impl Localize for OutputErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            OutputErr::MismatchedFormats(_, _) => "output.mismatched_formats",
+            OutputErr::FormatsToStdout(_) => "output.formats_to_stdout",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            OutputErr::MismatchedFormats(formats, files) => {
+                vec![formats.to_string(), files.to_string()]
+            }
+            OutputErr::FormatsToStdout(formats) => vec![formats.to_string()],
+        }
+    }
+}
+
+impl Localize for LinkErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            LinkErr::UndefinedExport(_) => "linkage.undefined_export",
+            LinkErr::ImportDefinedLocally(_) => "linkage.import_defined_locally",
+            LinkErr::UnresolvedImport(_) => "linkage.unresolved_import",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            LinkErr::UndefinedExport(name)
+            | LinkErr::ImportDefinedLocally(name)
+            | LinkErr::UnresolvedImport(name) => vec![name.clone()],
+        }
+    }
+}
+
+impl Localize for LoadErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            LoadErr::Read(_, _) => "loader.read",
+            LoadErr::InvalidRecord(_, _) => "loader.invalid_record",
+            LoadErr::Checksum(_, _) => "loader.checksum",
+            LoadErr::AddressTooLarge(_, _, _) => "loader.address_too_large",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            LoadErr::Read(path, err) => vec![path.clone(), err.clone()],
+            LoadErr::InvalidRecord(path, line) | LoadErr::Checksum(path, line) => {
+                vec![path.clone(), line.to_string()]
+            }
+            LoadErr::AddressTooLarge(path, line, end) => {
+                vec![path.clone(), line.to_string(), format!("{:#x}", end)]
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Localize for DifftestErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            DifftestErr::Spawn(_, _) => "difftest.spawn",
+            DifftestErr::Failed(_, _) => "difftest.failed",
+            DifftestErr::InvalidTrace(_, _, _) => "difftest.invalid_trace",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            DifftestErr::Spawn(command, err) | DifftestErr::Failed(command, err) => {
+                vec![command.clone(), err.clone()]
+            }
+            DifftestErr::InvalidTrace(command, line, err) => {
+                vec![command.clone(), line.to_string(), err.clone()]
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Localize for ManifestErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            ManifestErr::Read(_, _) => "manifest.read",
+            ManifestErr::Invalid(_, _) => "manifest.invalid",
+            ManifestErr::HookSpawn(_, _) => "manifest.hook_spawn",
+            ManifestErr::HookFailed(_, _) => "manifest.hook_failed",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ManifestErr::Read(first, second)
+            | ManifestErr::Invalid(first, second)
+            | ManifestErr::HookSpawn(first, second)
+            | ManifestErr::HookFailed(first, second) => vec![first.clone(), second.clone()],
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Localize for LinkerErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            LinkerErr::InvalidBase(_) => "linker.invalid_base",
+            LinkerErr::Read(_, _) => "linker.read",
+            LinkerErr::UnsupportedVersion(_, _, _) => "linker.unsupported_version",
+            LinkerErr::UnknownSection(_) => "linker.unknown_section",
+            LinkerErr::DuplicateSymbol(_, _, _) => "linker.duplicate_symbol",
+            LinkerErr::UndefinedSymbol(_, _) => "linker.undefined_symbol",
+            LinkerErr::SectionsOverlap(_, _, _) => "linker.sections_overlap",
+            LinkerErr::ValueOutOfRange(_, _, _) => "linker.value_out_of_range",
+            LinkerErr::InvalidScript(_, _) => "linker.invalid_script",
+            LinkerErr::UnplacedSection(_, _) => "linker.unplaced_section",
+            LinkerErr::RegionOverflow(_, _) => "linker.region_overflow",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            LinkerErr::InvalidBase(name) | LinkerErr::UnknownSection(name) => vec![name.clone()],
+            LinkerErr::Read(path, err) | LinkerErr::InvalidScript(path, err) => {
+                vec![path.clone(), err.clone()]
+            }
+            LinkerErr::UnplacedSection(name, object) => vec![name.clone(), object.clone()],
+            LinkerErr::RegionOverflow(name, excess) => vec![name.clone(), excess.to_string()],
+            LinkerErr::UnsupportedVersion(path, version, expected) => {
+                vec![path.clone(), version.to_string(), expected.to_string()]
+            }
+            LinkerErr::DuplicateSymbol(name, first, second) => {
+                vec![name.clone(), first.clone(), second.clone()]
+            }
+            LinkerErr::UndefinedSymbol(name, importers) => vec![name.clone(), importers.clone()],
+            LinkerErr::SectionsOverlap(first, second, address) => {
+                vec![first.clone(), second.clone(), format!("{:#06x}", address)]
+            }
+            LinkerErr::ValueOutOfRange(value, width, location) => {
+                vec![value.to_string(), width.to_string(), location.clone()]
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Localize for ObjectErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            ObjectErr::NotRelocatable(_) => "object.not_relocatable",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            ObjectErr::NotRelocatable(value) => vec![value.clone()],
+        }
+    }
+}
+
+impl Localize for SegmentErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            SegmentErr::InvalidOutput(_) => "segments.invalid_output",
+            SegmentErr::UnknownSegment(_, _) => "segments.unknown_segment",
+            SegmentErr::CrossSegmentDifference(_, _, _) => "segments.cross_segment_difference",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            SegmentErr::InvalidOutput(arg) => vec![arg.clone()],
+            SegmentErr::UnknownSegment(name, known) => vec![name.clone(), known.clone()],
+            SegmentErr::CrossSegmentDifference(expr, lhs, rhs) => {
+                vec![expr.clone(), lhs.clone(), rhs.clone()]
+            }
+        }
+    }
+}
+
+#[cfg(feature = "emulator")]
+impl Localize for SnapshotErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            SnapshotErr::Read(_, _) => "snapshot.read",
+            SnapshotErr::Invalid(_, _) => "snapshot.invalid",
+            SnapshotErr::UnsupportedVersion(_, _, _) => "snapshot.unsupported_version",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            SnapshotErr::Read(path, err) | SnapshotErr::Invalid(path, err) => {
+                vec![path.clone(), err.clone()]
+            }
+            SnapshotErr::UnsupportedVersion(path, version, expected) => {
+                vec![path.clone(), version.to_string(), expected.to_string()]
+            }
+        }
+    }
+}
+
+impl Localize for PseudoErr {
+    fn message_key(&self) -> Option<&'static str> {
+        Some(match self {
+            PseudoErr::ArgumentCount(_, _, _) => "pseudo.argument_count",
+            PseudoErr::InvalidTemplate(_, _) => "pseudo.invalid_template",
+        })
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        match self {
+            PseudoErr::ArgumentCount(name, expected, found) => {
+                vec![name.clone(), expected.to_string(), found.to_string()]
+            }
+            PseudoErr::InvalidTemplate(name, reason) => vec![name.clone(), reason.clone()],
+        }
+    }
+}
+
+impl Localize for SizeExceeded {
+    fn message_key(&self) -> Option<&'static str> {
+        Some("output.size_exceeded")
+    }
+
+    fn message_args(&self) -> Vec<String> {
+        vec![
+            self.size.to_string(),
+            self.max.to_string(),
+            (self.size - self.max).to_string(),
+            format_contributors(&self.contributors),
+        ]
+    }
+}
+
+// The samples cover the errors of every tool, so all of them are needed.
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use std::{collections::BTreeSet, path::PathBuf};
+
+    use super::*;
+
+    /// The `{0}`, `{1}`, ... a template fills in.
+    fn placeholders(template: &str) -> BTreeSet<usize> {
+        template
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}')?.0.parse().ok())
+            .collect()
+    }
+
+    /// One of every error with a catalog key, with arguments that don't
+    /// appear in the message by chance.
+    fn errors() -> Vec<Box<dyn Localize>> {
+        let s = |text: &str| text.to_string();
+        let path = |text: &str| PathBuf::from(text);
+        vec![
+            Box::new(LexerErr::UnknownToken(s("tok"))),
+            Box::new(LexerErr::NumberParseError(s("0xzz"))),
+            Box::new(LexerErr::LabelParseError(s("lbl"))),
+            Box::new(LexerErr::UnterminatedLiteral(s("\"abc"))),
+            Box::new(LexerErr::InvalidEscape(s("\\q"))),
+            Box::new(LexerErr::InvalidCharLiteral(s("'ab'"))),
+            Box::new(LexerErr::UnknownDirective(s(".foo"))),
+            Box::new(LexerErr::ReservedWord(s("byte"))),
+            Box::new(LexerErr::MalformedLiteral(s("5"), s("abc"))),
+            Box::new(ParserErr::UnexpectedToken(s("exp"), s("fnd"))),
+            Box::new(ParserErr::UnexpectedLineBeginning(s("fnd"))),
+            Box::new(ParserErr::EndOfLine(s("exp"))),
+            Box::new(ParserErr::TooManyOperands(s("MOV"), 7)),
+            Box::new(ParserErr::NoValues(s(".db"))),
+            Box::new(ParserErr::InertBarrier),
+            Box::new(ResolveErr::DuplicateLabel(s("name"))),
+            Box::new(ResolveErr::DuplicateConstant(s("name"))),
+            Box::new(ResolveErr::ConstantShadowsLabel(s("name"))),
+            Box::new(ResolveErr::LabelShadowsConstant(s("name"))),
+            Box::new(ResolveErr::LabelNamedRegister(s("name"))),
+            Box::new(ResolveErr::ConstantNamedRegister(s("name"))),
+            Box::new(ResolveErr::DependsOnLaterSymbol(s("#end"))),
+            Box::new(WriterErr::UnknownMnemonic(s("FOO"))),
+            Box::new(WriterErr::InvalidOperand(s("opd"))),
+            Box::new(WriterErr::NumberOutOfRange(777)),
+            Box::new(WriterErr::WordOutOfRange(77777)),
+            Box::new(WriterErr::UnknownLabel(s("lbl"))),
+            Box::new(WriterErr::IncompleteInstruction(s("MOV"))),
+            Box::new(WriterErr::DivisionByZero(s("7 / 0"))),
+            Box::new(WriterErr::SymbolRedefined(s("name"))),
+            Box::new(WriterErr::OrgBackwards(0x10, 0x20)),
+            Box::new(WriterErr::InvalidAlignment(-7)),
+            Box::new(WriterErr::NoWideMemory(s("LDW"))),
+            Box::new(WriterErr::NoStack(s("PUSH"))),
+            Box::new(WriterErr::AddressOutOfRange(0x12345, 13)),
+            Box::new(WriterErr::MisalignedInstruction(0x21, 4)),
+            Box::new(WriterErr::PlaceholderInstruction(s("FOO A"), 3)),
+            Box::new(WriterErr::InvalidPlaceholder(s("zz"))),
+            Box::new(WriterErr::WideLiteral(0x7f, 32, 8)),
+            Box::new(LimitErr::IncludeDepth(77)),
+            Box::new(LimitErr::MacroDepth(77, s("mac"))),
+            Box::new(LimitErr::ExpansionSize(777)),
+            Box::new(LimitErr::LineLength(777, 99)),
+            Box::new(LimitErr::NonAscii('é', 0xe9)),
+            Box::new(LimitErr::OutputSize(777, 99)),
+            Box::new(IncludeErr::MissingPath),
+            Box::new(IncludeErr::Read(s("inc.asm"), s("gone"))),
+            Box::new(IncludeErr::Cycle(s("inc.asm"))),
+            Box::new(ConditionalErr::MissingCondition(s(".if"))),
+            Box::new(ConditionalErr::InvalidCondition(s("bad"))),
+            Box::new(ConditionalErr::InvalidSymbol(s(".ifdef"))),
+            Box::new(ConditionalErr::Unmatched(s(".endif"))),
+            Box::new(ConditionalErr::DuplicateElse),
+            Box::new(ConditionalErr::Unterminated(s(".if"))),
+            Box::new(ConditionalErr::TrailingToken(s("tok"), s(".else"))),
+            Box::new(MacroErr::MissingName),
+            Box::new(MacroErr::InvalidParameter(s("prm"))),
+            Box::new(MacroErr::DuplicateMacro(s("mac"))),
+            Box::new(MacroErr::NestedDefinition(s("mac"))),
+            Box::new(MacroErr::Unterminated(s("mac"))),
+            Box::new(MacroErr::UnexpectedEnd),
+            Box::new(MacroErr::ArgumentCount(s("mac"), 3, 5)),
+            Box::new(ConfigError::ParseError(
+                serde_json::from_str::<u8>("x").unwrap_err(),
+            )),
+            Box::new(ConfigError::UnknownMnemonic(s("FOO"))),
+            Box::new(ConfigError::UnknownOperand(s("Q"), None)),
+            Box::new(ConfigError::UnknownOperand(s("Q"), Some(s("A")))),
+            Box::new(ConfigError::NotFound(vec![path("a.cfg")], vec![])),
+            Box::new(ConfigError::NotFound(
+                vec![path("a.cfg")],
+                vec![path("b.cfg")],
+            )),
+            Box::new(ConfigError::MissingEnvConfig(path("env.cfg"))),
+            Box::new(ConfigError::InvalidOpcode(s("MOV"), s("0b2"))),
+            Box::new(ConfigError::DuplicateOpcode(s("MOV"), s("ADD"), s("0b1"))),
+            Box::new(ConfigError::DuplicateSignature(
+                s("MOV"),
+                s("ADD"),
+                s("sig"),
+            )),
+            Box::new(ConfigError::UnknownFlag(s("JMP"), s("Q"))),
+            Box::new(ConfigError::InvalidAddressWidth(77)),
+            Box::new(ConfigError::UnknownAliasTarget(s("als"), s("tgt"))),
+            Box::new(ConfigError::InvalidDialectName(s("1x"))),
+            Box::new(ConfigError::InvalidInstructionAlignment(0)),
+            Box::new(ConfigError::NoPaddingNop),
+            Box::new(ConfigError::InvalidOverride(s("ovr"))),
+            Box::new(ConfigError::UnknownOverrideTarget(s("tgt"))),
+            Box::new(ConfigError::InvalidPseudoInstruction(s("CALL"), s("why"))),
+            Box::new(DefinesError::MalformedLine(77, s("arg"))),
+            Box::new(DefinesError::InvalidName(77, s("arg"))),
+            Box::new(DefinesError::NumberParseError(77, s("arg"))),
+            Box::new(DefinesError::InvalidArgName(s("arg"))),
+            Box::new(DefinesError::InvalidArgValue(s("arg"))),
+            Box::new(DefinesError::DuplicateDefine(s("arg"))),
+            Box::new(DefinesError::CollidesWithLabel(s("arg"))),
+            Box::new(DisasmErr::InvalidEntry(s("ent"))),
+            Box::new(DisasmErr::InvalidSymbols(s("sym.json"), s("why"))),
+            Box::new(EmulatorErr::Unsupported(s("FOO"), 0x1234)),
+            Box::new(EmulatorErr::UnknownLabel(s("lbl"))),
+            Box::new(EmulatorErr::InvalidRange(s("rng"))),
+            Box::new(EmulatorErr::AddressWidth(77)),
+            Box::new(EmulatorErr::BreakpointOutOfRange(s("lbl"), 0x12345)),
+            Box::new(EmulatorErr::InvalidWatchpoint(s("wch"))),
+            Box::new(EmulatorErr::WatchpointOutOfRange(s("lbl"), 0x12345)),
+            Box::new(EmulatorErr::UnexpectedOutput(s("out.txt"), 77)),
+            Box::new(EmulatorErr::InvalidWaitStates(s("wst"))),
+            Box::new(OutputErr::MismatchedFormats(7, 9)),
+            Box::new(OutputErr::FormatsToStdout(7)),
+            Box::new(LinkErr::UndefinedExport(s("sym"))),
+            Box::new(LinkErr::ImportDefinedLocally(s("sym"))),
+            Box::new(LinkErr::UnresolvedImport(s("sym"))),
+            Box::new(LoadErr::Read(s("img.hex"), s("why"))),
+            Box::new(LoadErr::InvalidRecord(s("img.hex"), 77)),
+            Box::new(LoadErr::Checksum(s("img.hex"), 77)),
+            Box::new(LoadErr::AddressTooLarge(s("img.hex"), 77, 0x1234567)),
+            Box::new(DifftestErr::Spawn(s("sim"), s("why"))),
+            Box::new(DifftestErr::Failed(s("sim"), s("why"))),
+            Box::new(DifftestErr::InvalidTrace(s("sim"), 77, s("why"))),
+            Box::new(ManifestErr::Read(s("asm.toml"), s("why"))),
+            Box::new(ManifestErr::Invalid(s("asm.toml"), s("why"))),
+            Box::new(ManifestErr::HookSpawn(s("cmd"), s("why"))),
+            Box::new(ManifestErr::HookFailed(s("cmd"), s("why"))),
+            Box::new(LinkerErr::InvalidBase(s("base"))),
+            Box::new(LinkerErr::Read(s("a.obj"), s("why"))),
+            Box::new(LinkerErr::UnsupportedVersion(s("a.obj"), 7, 9)),
+            Box::new(LinkerErr::UnknownSection(s("sec"))),
+            Box::new(LinkerErr::DuplicateSymbol(s("sym"), s("a.obj"), s("b.obj"))),
+            Box::new(LinkerErr::UndefinedSymbol(s("sym"), s("a.obj"))),
+            Box::new(LinkerErr::SectionsOverlap(s("sec"), s("tion"), 0x1234)),
+            Box::new(LinkerErr::ValueOutOfRange(77777, 1, s("a.obj"))),
+            Box::new(LinkerErr::InvalidScript(s("link.toml"), s("why"))),
+            Box::new(LinkerErr::UnplacedSection(s("sec"), s("a.obj"))),
+            Box::new(LinkerErr::RegionOverflow(s("rom"), 77)),
+            Box::new(ObjectErr::NotRelocatable(s("a * b"))),
+            Box::new(SegmentErr::InvalidOutput(s("seg"))),
+            Box::new(SegmentErr::UnknownSegment(s("seg"), s("code, data"))),
+            Box::new(SegmentErr::CrossSegmentDifference(
+                s("a - b"),
+                s("code"),
+                s("data"),
+            )),
+            Box::new(PseudoErr::ArgumentCount(s("CALL"), 3, 5)),
+            Box::new(PseudoErr::InvalidTemplate(s("CALL"), s("why"))),
+            Box::new(SnapshotErr::Read(s("snap.json"), s("why"))),
+            Box::new(SnapshotErr::Invalid(s("snap.json"), s("why"))),
+            Box::new(SnapshotErr::UnsupportedVersion(s("snap.json"), 7, 9)),
+            Box::new(SizeExceeded {
+                size: 777,
+                max: 99,
+                contributors: vec![(Some(s("main")), 700)],
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_polish_catalog() {
+        for (key, english) in CATALOG_EN.entries() {
+            let polish = CATALOG_PL
+                .get(key)
+                .unwrap_or_else(|| panic!("no Polish for '{}'", key));
+            assert_eq!(placeholders(polish), placeholders(english), "{}", key);
+        }
+
+        let mut error_keys = BTreeSet::new();
+        for err in errors() {
+            let key = err.message_key().unwrap();
+            let polish = CATALOG_PL
+                .get(key)
+                .unwrap_or_else(|| panic!("no Polish for '{}'", key));
+            // The arguments the English message shows are the ones the
+            // translation has to.
+            let english = err.to_string();
+            let shown = err
+                .message_args()
+                .iter()
+                .enumerate()
+                .filter(|(_, arg)| !arg.is_empty() && english.contains(arg.as_str()))
+                .map(|(i, _)| i)
+                .collect::<BTreeSet<_>>();
+            assert_eq!(placeholders(polish), shown, "{}", key);
+            error_keys.insert(key);
+        }
+
+        // Every key the `Localize` impls give has a sample above.
+        let source = include_str!("i18n.rs");
+        let impls = &source[source.find("impl Localize for LexerErr").unwrap()
+            ..source.find("mod tests {").unwrap()];
+        let used = impls
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .filter(|literal| literal.contains('.') && !literal.contains(' '))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(used, error_keys);
+
+        // And nothing is translated that isn't used.
+        for key in CATALOG_PL.keys() {
+            assert!(
+                *key == "summary" || CATALOG_EN.contains_key(key) || error_keys.contains(key),
+                "unused Polish entry '{}'",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let help = Message::new("help.did_you_mean", vec!["loop".to_string()]);
+        assert_eq!(help.to_string(), "did you mean 'loop'?");
+        assert_eq!(help.localize(Lang::Pl), "czy chodziło o 'loop'?");
+        let untranslated = Message::from("as given");
+        assert_eq!(untranslated.localize(Lang::Pl), "as given");
+        assert_eq!(text(Lang::Pl, "note", &[]), "uwaga");
+        assert_eq!(text(Lang::En, "note", &[]), "note");
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    error::{Error, ErrorKind, FileContext, WithSpan},
+    lexer::{normalize_source, Lexer},
+    limits::Limits,
+    syntax::SyntaxOptions,
+    token::{split_lines, Span, Token, TokenType},
+};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum IncludeErr {
+    #[error("Expected a quoted file name after '.include'.")]
+    MissingPath,
+    #[error("Couldn't read included file '{0}': {1}.")]
+    Read(String, String),
+    #[error("File '{0}' includes itself.")]
+    Cycle(String),
+}
+
+struct Includer<'a> {
+    syntax: &'a SyntaxOptions,
+    limits: &'a Limits,
+    context: &'a mut FileContext,
+    // Canonical paths of the files currently being included, outermost first.
+    stack: Vec<PathBuf>,
+    depth: usize,
+    errors: Vec<Error>,
+    warnings: Vec<Error>,
+}
+
+impl Includer<'_> {
+    fn include_lines(&mut self, tokens: &[Token], dir: &Path, output: &mut Vec<Token>) {
+        for line in split_lines(tokens) {
+            let Some(directive) = line
+                .first()
+                .filter(|token| token.token_type == TokenType::Include)
+            else {
+                output.extend_from_slice(line);
+                continue;
+            };
+
+            let path = match line {
+                [_, Token {
+                    token_type: TokenType::String(path),
+                    ..
+                }] => dir.join(path),
+                _ => {
+                    self.errors
+                        .push(IncludeErr::MissingPath.with_span(directive.span.clone()));
+                    continue;
+                }
+            };
+            let span = Span::merge_all(line.iter().map(|token| &token.span)).unwrap();
+
+            let start = output.len();
+            if let Err(err) = self.include_file(&path, output) {
+                self.errors.push(err.with_span(span));
+                continue;
+            }
+            // A file without a final newline would otherwise run into the
+            // line after the directive.
+            let end_of_line = line.last().map(|token| token.trailing_trivia.clone());
+            if let (Some(last), Some(end_of_line)) = (output[start..].last_mut(), end_of_line) {
+                if !last.trailing_trivia.contains('\n') {
+                    last.trailing_trivia += &end_of_line;
+                }
+            }
+        }
+    }
+
+    fn include_file(&mut self, path: &Path, output: &mut Vec<Token>) -> Result<(), ErrorKind> {
+        let display = path.to_string_lossy().to_string();
+        let read_err = |err: std::io::Error| IncludeErr::Read(display.clone(), err.to_string());
+
+        let canonical = path.canonicalize().map_err(read_err)?;
+        if self.stack.contains(&canonical) {
+            return Err(IncludeErr::Cycle(display.clone()).into());
+        }
+        self.limits.check_include_depth(self.depth + 1)?;
+        let content = normalize_source(&read_to_string(path).map_err(read_err)?);
+
+        let file = self.context.add_file(path, &content);
+        let (errors, warnings) = self.limits.check_source(&content, file);
+        self.warnings.extend(warnings);
+        if !errors.is_empty() {
+            self.errors.extend(errors);
+            return Ok(());
+        }
+        let chars = content.chars().collect::<Vec<_>>();
+        let mut lexer = Lexer::with_syntax(&chars, self.syntax.clone());
+        let mut tokens = vec![];
+        while let Some(token) = lexer.next() {
+            match token {
+                Ok(mut token) => {
+                    token.span = token.span.in_file(file);
+                    tokens.push(token);
+                }
+                Err(err) => self
+                    .errors
+                    .push(err.with_span(lexer.error_span().in_file(file))),
+            }
+        }
+
+        let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.stack.push(canonical);
+        self.depth += 1;
+        self.include_lines(&tokens, &dir, output);
+        self.depth -= 1;
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// Replaces every `.include "file"` line with the tokens of that file.
+/// Paths are relative to the including file, or to the working directory
+/// for a program read from stdin. Included tokens carry the index of their
+/// file, registered in `context`, in their spans. Included files are checked
+/// against `limits` like the main one, their warnings are returned with the
+/// tokens.
+pub fn expand_includes(
+    tokens: &[Token],
+    path: Option<&Path>,
+    syntax: &SyntaxOptions,
+    limits: &Limits,
+    context: &mut FileContext,
+) -> Result<(Vec<Token>, Vec<Error>), Vec<Error>> {
+    let mut includer = Includer {
+        syntax,
+        limits,
+        context,
+        stack: path
+            .and_then(|path| path.canonicalize().ok())
+            .into_iter()
+            .collect(),
+        depth: 0,
+        errors: vec![],
+        warnings: vec![],
+    };
+    let dir = path
+        .and_then(Path::parent)
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+
+    let mut output = vec![];
+    includer.include_lines(tokens, &dir, &mut output);
+
+    if includer.errors.is_empty() {
+        return Ok((output, includer.warnings));
+    }
+    Err(includer.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::LimitErr;
+    use std::fs;
+    use tempfile::TempDir;
+
+    type Expanded = Result<(Vec<Token>, Vec<Error>), Vec<Error>>;
+
+    /// Writes `files` to a new directory and expands the includes of the
+    /// first one.
+    fn expand(files: &[(&str, &str)], limits: &Limits) -> (TempDir, FileContext, Expanded) {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let (name, content) = files[0];
+        let path = dir.path().join(name);
+        let chars = content.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let mut context = FileContext::new(Some(&path), content);
+        let syntax = SyntaxOptions::default();
+        let result = expand_includes(&tokens, Some(&path), &syntax, limits, &mut context);
+        (dir, context, result)
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let files = [
+            ("main.asm", "HALT\n.include \"a.asm\"\n"),
+            ("a.asm", ".include \"main.asm\"\n"),
+        ];
+        let (_dir, context, result) = expand(&files, &Limits::default());
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0].kind(),
+            ErrorKind::IncludeErr(IncludeErr::Cycle(path)) if path.ends_with("main.asm")
+        ));
+        assert!(context.file_name(errors[0].span()).ends_with("a.asm"));
+        assert_eq!(errors[0].span().line, 0);
+    }
+
+    #[test]
+    fn test_include_depth_limit() {
+        let files = [
+            ("main.asm", ".include \"a.asm\"\n"),
+            ("a.asm", ".include \"b.asm\"\n"),
+            ("b.asm", ".include \"c.asm\"\n"),
+            ("c.asm", "HALT\n"),
+        ];
+        let limits = Limits {
+            max_include_depth: 2,
+            ..Limits::default()
+        };
+        let (_dir, context, result) = expand(&files, &limits);
+        let errors = result.unwrap_err();
+        assert!(matches!(
+            errors[0].kind(),
+            ErrorKind::LimitErr(LimitErr::IncludeDepth(2))
+        ));
+        assert!(context.file_name(errors[0].span()).ends_with("b.asm"));
+
+        let (_dir, _, result) = expand(&files, &Limits::default());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_included_spans() {
+        let files = [
+            ("main.asm", "HALT\n.include \"lib.asm\"\nHALT\n"),
+            ("lib.asm", "MOV A 1\nMOV B 2"),
+        ];
+        let (_dir, context, result) = expand(&files, &Limits::default());
+        let (tokens, _) = result.unwrap();
+        let lines = split_lines(&tokens)
+            .map(|line| (context.file_name(&line[0].span), line[0].span.line))
+            .map(|(name, line)| (name.ends_with("lib.asm"), line))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(false, 0), (true, 0), (true, 1), (false, 2)]);
+
+        let files = [
+            ("main.asm", "HALT\n.include \"lib.asm\"\n"),
+            ("lib.asm", "HALT\nMOV A 1 $\n"),
+        ];
+        let (_dir, context, result) = expand(&files, &Limits::default());
+        let errors = result.unwrap_err();
+        assert!(context.file_name(errors[0].span()).ends_with("lib.asm"));
+        assert_eq!(
+            (errors[0].span().line, errors[0].span().chars.start),
+            (1, 8)
+        );
+        assert_eq!(context.source_line(errors[0].span()), "MOV A 1 $");
+    }
+}
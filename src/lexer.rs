@@ -3,8 +3,10 @@ use std::str::FromStr;
 use thiserror::Error;
 
 use crate::{
+    i18n::Message,
     specs::{Mnemonic, Register},
-    token::{Token, TokenType},
+    syntax::SyntaxOptions,
+    token::{Operator, Span, Token, TokenType, STATEMENT_SEPARATOR},
 };
 
 use phf::phf_map;
@@ -13,7 +15,31 @@ static KEYWORDS: phf::Map<&'static str, TokenType> = phf_map! {
     "byte" => TokenType::Byte,
 };
 
-// TODO: Add number lines and character ranges to the error output
+static DIRECTIVES: phf::Map<&'static str, TokenType> = phf_map! {
+    ".macro" => TokenType::MacroStart,
+    ".endm" => TokenType::MacroEnd,
+    ".equ" => TokenType::Equ,
+    ".const" => TokenType::Equ,
+    ".org" => TokenType::Org,
+    ".byte" => TokenType::Byte,
+    ".word" => TokenType::Word,
+    ".string" => TokenType::Ascii,
+    ".ascii" => TokenType::Ascii,
+    ".asciiz" => TokenType::Asciiz,
+    ".align" => TokenType::Align,
+    ".entry" => TokenType::Entry,
+    ".segment" => TokenType::Segment,
+    ".barrier" => TokenType::Barrier,
+    ".export" => TokenType::Export,
+    ".import" => TokenType::Import,
+    ".include" => TokenType::Include,
+    ".if" => TokenType::If,
+    ".ifdef" => TokenType::IfDef,
+    ".ifndef" => TokenType::IfNotDef,
+    ".else" => TokenType::Else,
+    ".endif" => TokenType::EndIf,
+};
+
 #[derive(PartialEq, Eq, Debug, Error)]
 pub enum LexerErr {
     #[error("Unknown token '{0}'.")]
@@ -22,6 +48,99 @@ pub enum LexerErr {
     NumberParseError(String),
     #[error("Label '{0}:' should be at the beginning of the line.")]
     LabelParseError(String),
+    #[error("Unterminated literal {0}.")]
+    UnterminatedLiteral(String),
+    #[error("Unknown escape sequence '{0}'.")]
+    InvalidEscape(String),
+    #[error("Character literal {0} should contain exactly one character.")]
+    InvalidCharLiteral(String),
+    #[error("Unknown directive '{0}'.")]
+    UnknownDirective(String),
+    #[error("'{0}' is a reserved word and can't be used as a name.")]
+    ReservedWord(String),
+    /// A literal that starts as a number and runs on into letters, with the
+    /// number and the rest.
+    #[error("Malformed literal or identifier '{0}{1}'.")]
+    MalformedLiteral(String, String),
+}
+
+impl LexerErr {
+    /// How to fix the error, where there's more to say than the message.
+    pub fn help(&self) -> Option<Message> {
+        match self {
+            LexerErr::MalformedLiteral(number, rest) => Some(Message::new(
+                "help.malformed_literal",
+                vec![number.clone(), rest.clone()],
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a literal like `5abc` or `0x1g`, that isn't a number, into the
+/// longest number it starts with and the letters after it.
+fn split_malformed(literal: &str) -> Option<(&str, &str)> {
+    (1..literal.len()).rev().find_map(|i| {
+        let (number, rest) = literal.split_at_checked(i)?;
+        let radix_prefix = number == "0" && (rest.starts_with('x') || rest.starts_with('b'));
+        let letter = rest.starts_with(|c: char| c.is_alphabetic());
+        (letter && !radix_prefix && parse_number(number).is_some()).then_some((number, rest))
+    })
+}
+
+/// The token the directive `name`, with or without its leading `.`, lexes to.
+pub fn directive(name: &str) -> Option<TokenType> {
+    let dotted = format!(".{}", name.strip_prefix('.').unwrap_or(name));
+    DIRECTIVES.get(&dotted).cloned()
+}
+
+/// Strips a leading byte order mark and turns `\r\n` and lone `\r` line
+/// endings into `\n`, so spans and columns don't depend on the editor that
+/// saved the file. Sources are normalized before they're lexed or shown in
+/// diagnostics.
+pub fn normalize_source(source: &str) -> String {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    source.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Bits the digits of a number literal hold as written: four per hex digit,
+/// three per octal and one per binary digit, leading zeros included.
+/// Decimal literals have no such width.
+pub fn literal_width(literal: &str) -> Option<u32> {
+    let (digits, bits) = if let Some(digits) = literal.strip_prefix("0x") {
+        (digits, 4)
+    } else if let Some(digits) = literal.strip_prefix("0b") {
+        (digits, 1)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        (&literal[1..], 3)
+    } else {
+        return None;
+    };
+    Some(digits.chars().filter(|digit| *digit != '_').count() as u32 * bits)
+}
+
+/// Parses an unsigned integer literal: decimal, `0x` hex, `0b` binary or
+/// `0`-prefixed octal, with `_` allowed between digits. Shared by the lexer
+/// and `-D`/defines files so a number means the same everywhere.
+pub fn parse_number(literal: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(digits) = literal.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = literal.strip_prefix("0b") {
+        (digits, 2)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        (&literal[1..], 8)
+    } else {
+        (literal, 10)
+    };
+
+    if digits.is_empty()
+        || digits.starts_with('_')
+        || digits.ends_with('_')
+        || !digits.chars().all(|c| c == '_' || c.is_digit(radix))
+    {
+        return None;
+    }
+    i64::from_str_radix(&digits.replace('_', ""), radix).ok()
 }
 
 // TODO: See if String can be used instead of [char], (possible utf-8 support(?))
@@ -29,17 +148,56 @@ pub struct Lexer<'a> {
     content: &'a [char],
     current_line: usize,
     current_char: usize,
+    syntax: SyntaxOptions,
+    // Non-label tokens emitted so far on `current_line`.
+    tokens_on_line: usize,
+    // Column the last token or error started at.
+    token_start: usize,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(content: &'a [char]) -> Self {
+        Self::with_syntax(content, SyntaxOptions::default())
+    }
+
+    pub fn with_syntax(content: &'a [char], syntax: SyntaxOptions) -> Self {
         Self {
             content,
             current_line: 0,
             current_char: 0,
+            syntax,
+            tokens_on_line: 0,
+            token_start: 0,
         }
     }
 
+    /// Line the lexer is on, e.g. to locate the error it just returned.
+    pub fn line(&self) -> usize {
+        self.current_line
+    }
+
+    /// The characters the error just returned was lexed from.
+    pub fn error_span(&self) -> Span {
+        Span::new(self.current_line, self.token_start..self.current_char)
+    }
+
+    /// The directive `name` is an alias of in the dialect.
+    fn aliased(&self, name: &str) -> Option<TokenType> {
+        directive(self.syntax.dialect.aliases.get(name)?)
+    }
+
+    fn is_reserved(&self, name: &str) -> bool {
+        self.syntax.dialect.reserved.iter().any(|word| word == name)
+    }
+
+    /// Whether only blanks are left before the end of the statement.
+    fn at_statement_end(&self) -> bool {
+        self.content
+            .iter()
+            .find(|x| !x.is_whitespace() || **x == '\n')
+            .is_none_or(|x| *x == '\n' || *x == STATEMENT_SEPARATOR)
+    }
+
     fn peek(&self, offset: usize) -> Option<char> {
         if self.content.len() <= offset {
             return None;
@@ -47,18 +205,6 @@ impl<'a> Lexer<'a> {
         Some(self.content[offset])
     }
 
-    fn match_str(&self, pattern: String) -> bool {
-        let pattern_len = pattern.len();
-        if self.content.len() < pattern_len {
-            return false;
-        }
-
-        pattern
-            .chars()
-            .zip(self.content.iter())
-            .all(|(a, b)| a == *b)
-    }
-
     fn chop(&mut self, len: usize) -> String {
         let lexeme = self.content[0..len].iter().collect();
         self.current_char += len;
@@ -77,43 +223,59 @@ impl<'a> Lexer<'a> {
         self.chop(i)
     }
 
-    fn trim_while<P>(&mut self, mut predicate: P)
+    fn trim_while<P>(&mut self, mut predicate: P) -> String
     where
         P: FnMut(&char) -> bool,
     {
+        let mut trimmed = String::new();
         while !self.content.is_empty() && predicate(&self.content[0]) {
             if self.content[0] == '\n' {
                 self.current_char = 0;
                 self.current_line += 1;
+                self.tokens_on_line = 0;
+            } else {
+                self.current_char += 1;
             }
-            // self.current_char += 1;
+            trimmed.push(self.content[0]);
             self.content = &self.content[1..]
         }
+        trimmed
+    }
+
+    fn trailing_trivia(&mut self) -> String {
+        let mut trivia = self.trim_while(|x| x.is_whitespace() && *x != '\n');
+        if let Some(STATEMENT_SEPARATOR) = self.peek(0) {
+            // Empty statements between separators are skipped too.
+            trivia += &self
+                .trim_while(|x| *x == STATEMENT_SEPARATOR || (x.is_whitespace() && *x != '\n'));
+            self.tokens_on_line = 0;
+        }
+        if let Some('\n') = self.peek(0) {
+            let mut first = true;
+            trivia += &self.trim_while(|_| std::mem::take(&mut first));
+        }
+        if self.content.iter().all(|x| x.is_whitespace()) {
+            trivia += &self.trim_while(|x| x.is_whitespace());
+        }
+        trivia
     }
 
     fn parse_number(&mut self) -> Result<Token, LexerErr> {
         let start = self.current_char;
-
-        let (prefix, radix) = if self.match_str(String::from("0x")) {
-            (self.chop(2), 16)
-        } else if self.match_str(String::from("0b")) {
-            (self.chop(2), 2)
-        } else if self.match_str(String::from("0")) {
-            (self.chop(1), 8)
-        } else {
-            (String::new(), 10)
-        };
-
-        let str = self.chop_while(|x| x.is_ascii_hexdigit());
-        let number = i64::from_str_radix(&str, radix);
-
-        let Ok(number) = number else {
-            return Err(LexerErr::NumberParseError(prefix + &str));
+        let literal = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
+
+        let Some(number) = parse_number(&literal) else {
+            return Err(match split_malformed(&literal) {
+                Some((number, rest)) => {
+                    LexerErr::MalformedLiteral(number.to_string(), rest.to_string())
+                }
+                None => LexerErr::NumberParseError(literal),
+            });
         };
 
         Ok(Token::new(
-            TokenType::Number(number),
-            prefix + &str,
+            TokenType::Number(number, literal_width(&literal)),
+            literal,
             self.current_line,
             start..self.current_char,
         ))
@@ -122,7 +284,7 @@ impl<'a> Lexer<'a> {
     fn parse_label(&mut self, start: usize, str: String) -> Result<Token, LexerErr> {
         self.chop(1);
 
-        if start != 0 {
+        if self.tokens_on_line > 0 {
             return Err(LexerErr::LabelParseError(str));
         }
 
@@ -135,8 +297,123 @@ impl<'a> Lexer<'a> {
     }
 
     pub fn next_token(&mut self) -> Option<Result<Token, LexerErr>> {
-        self.trim_while(|x| x.is_whitespace());
+        let leading_trivia = self.trim_while(|x| x.is_whitespace());
+        self.token_start = self.current_char;
+
+        let remaining = self.content;
+        let token = self.lex_token()?;
+        let lexeme = remaining[..remaining.len() - self.content.len()]
+            .iter()
+            .collect();
+
+        Some(token.map(|mut token| {
+            if !matches!(token.token_type, TokenType::Label(_)) {
+                self.tokens_on_line += 1;
+            }
+            token.lexeme = lexeme;
+            token.leading_trivia = leading_trivia;
+            token.trailing_trivia = self.trailing_trivia();
+            token
+        }))
+    }
+
+    fn parse_escape(&mut self, raw: &mut String) -> Result<char, LexerErr> {
+        *raw += &self.chop(1);
+        let Some(escape) = self.peek(0).filter(|x| *x != '\n') else {
+            return Err(LexerErr::UnterminatedLiteral(raw.clone()));
+        };
+        *raw += &self.chop(1);
+
+        let character = match escape {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' | '\'' | '"' => escape,
+            'x' => {
+                let mut digits = 0;
+                let hex = self.chop_while(|x| {
+                    digits += 1;
+                    digits <= 2 && x.is_ascii_hexdigit()
+                });
+                *raw += &hex;
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) if hex.len() == 2 => char::from(byte),
+                    _ => return Err(LexerErr::InvalidEscape(format!("\\x{}", hex))),
+                }
+            }
+            _ => return Err(LexerErr::InvalidEscape(format!("\\{}", escape))),
+        };
+        Ok(character)
+    }
+
+    /// Lexes `'c'` as the number it encodes and `"text"` as a string,
+    /// both with C-style escapes.
+    fn parse_quoted(&mut self, start: usize, quote: char) -> Result<Token, LexerErr> {
+        let mut raw = self.chop(1);
+        let mut value = String::new();
+
+        loop {
+            match self.peek(0) {
+                None | Some('\n') => return Err(LexerErr::UnterminatedLiteral(raw)),
+                Some(x) if x == quote => {
+                    raw += &self.chop(1);
+                    break;
+                }
+                Some('\\') => match self.parse_escape(&mut raw) {
+                    Ok(character) => value.push(character),
+                    Err(err) => {
+                        // Skip the rest of the literal so its closing quote
+                        // isn't taken as the start of another one.
+                        self.chop_while(|x| *x != quote && *x != '\n');
+                        if self.peek(0) == Some(quote) {
+                            self.chop(1);
+                        }
+                        return Err(err);
+                    }
+                },
+                Some(_) => {
+                    let character = self.chop(1);
+                    raw += &character;
+                    value += &character;
+                }
+            }
+        }
+
+        let token_type = match quote {
+            '"' => TokenType::String(value),
+            _ => {
+                let mut characters = value.chars();
+                match (characters.next(), characters.next()) {
+                    (Some(character), None) => TokenType::Number(character as i64, Some(8)),
+                    _ => return Err(LexerErr::InvalidCharLiteral(raw)),
+                }
+            }
+        };
+
+        Ok(Token::new(
+            token_type,
+            raw,
+            self.current_line,
+            start..self.current_char,
+        ))
+    }
+
+    fn parse_labelref(&mut self, start: usize) -> Result<Token, LexerErr> {
+        self.chop(1);
+        let str = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
+        if self.is_reserved(&str) {
+            return Err(LexerErr::ReservedWord(str));
+        }
+        Ok(Token::new(
+            TokenType::LabelRef(str.clone()),
+            str,
+            self.current_line,
+            start..self.current_char,
+        ))
+    }
 
+    fn lex_token(&mut self) -> Option<Result<Token, LexerErr>> {
         let start = self.current_char;
 
         if self.content.is_empty() {
@@ -151,9 +428,9 @@ impl<'a> Lexer<'a> {
         }
 
         if self.content[0].is_alphabetic() {
-            let str = self.chop_while(|x| x.is_alphabetic());
+            let str = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
 
-            if let Some(keyword) = KEYWORDS.get(&str).cloned() {
+            if let Some(keyword) = KEYWORDS.get(&str).cloned().or_else(|| self.aliased(&str)) {
                 return Some(Ok(Token::new(
                     keyword,
                     str,
@@ -162,10 +439,31 @@ impl<'a> Lexer<'a> {
                 )));
             }
 
+            if self.is_reserved(&str) {
+                // The colon of a reserved label goes with the error.
+                if let Some(':') = self.peek(0) {
+                    self.chop(1);
+                }
+                return Some(Err(LexerErr::ReservedWord(str)));
+            }
+
             if let Some(':') = self.peek(0) {
                 return Some(self.parse_label(start, str));
             }
 
+            if self.syntax.colon_free_labels
+                && start == 0
+                && self.at_statement_end()
+                && !self.syntax.mnemonics.contains(&str)
+            {
+                return Some(Ok(Token::new(
+                    TokenType::Label(str.clone()),
+                    str,
+                    self.current_line,
+                    start..self.current_char,
+                )));
+            }
+
             if let Ok(register) = Register::from_str(&str) {
                 return Some(Ok(Token::new(
                     TokenType::Register(register),
@@ -175,6 +473,17 @@ impl<'a> Lexer<'a> {
                 )));
             }
 
+            // Only the first token of a line can be a mnemonic, so anything
+            // after it is unambiguously an operand.
+            if self.syntax.bare_label_refs && self.tokens_on_line > 0 {
+                return Some(Ok(Token::new(
+                    TokenType::LabelRef(str.clone()),
+                    str,
+                    self.current_line,
+                    start..self.current_char,
+                )));
+            }
+
             return Some(Ok(Token::new(
                 TokenType::Mnemonic(Mnemonic::new(str.clone())),
                 str,
@@ -183,28 +492,71 @@ impl<'a> Lexer<'a> {
             )));
         }
 
-        let character = match self.content[0] {
-            '[' => Some((self.chop(1), TokenType::LeftSquareBracket)),
-            ']' => Some((self.chop(1), TokenType::RightSquareBracket)),
-            '#' => {
-                self.chop(1);
-                let str = self.chop_while(|x| x.is_alphanumeric());
-                return Some(Ok(Token::new(
-                    TokenType::LabelRef(str.clone()),
+        if self.content[0] == '.' && self.peek(1).is_some_and(|x| x.is_alphabetic()) {
+            let str = self.chop(1) + &self.chop_while(|x| x.is_alphanumeric() || *x == '_');
+            let directive = DIRECTIVES.get(&str).cloned().or_else(|| self.aliased(&str));
+            return Some(match directive {
+                Some(directive) => Ok(Token::new(
+                    directive,
                     str,
                     self.current_line,
                     start..self.current_char,
-                )));
-            }
+                )),
+                None => Err(LexerErr::UnknownDirective(str)),
+            });
+        }
+
+        if self.content[0] == self.syntax.label_sigil {
+            return Some(self.parse_labelref(start));
+        }
+
+        if let quote @ ('\'' | '"') = self.content[0] {
+            return Some(self.parse_quoted(start, quote));
+        }
+
+        let operator = match (self.content[0], self.peek(1)) {
+            ('<', Some('<')) => Some(Operator::ShiftLeft),
+            ('>', Some('>')) => Some(Operator::ShiftRight),
+            ('<', Some('=')) => Some(Operator::LessEqual),
+            ('>', Some('=')) => Some(Operator::GreaterEqual),
+            ('=', Some('=')) => Some(Operator::Equal),
+            ('!', Some('=')) => Some(Operator::NotEqual),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            return Some(Ok(Token::new(
+                TokenType::Operator(operator),
+                self.chop(2),
+                self.current_line,
+                start..self.current_char,
+            )));
+        }
+
+        let character = match self.content[0] {
+            '[' => Some(TokenType::LeftSquareBracket),
+            ']' => Some(TokenType::RightSquareBracket),
+            '(' => Some(TokenType::LeftParen),
+            ')' => Some(TokenType::RightParen),
+            '+' => Some(TokenType::Operator(Operator::Plus)),
+            '-' => Some(TokenType::Operator(Operator::Minus)),
+            '*' => Some(TokenType::Operator(Operator::Star)),
+            '/' => Some(TokenType::Operator(Operator::Slash)),
+            '%' => Some(TokenType::Operator(Operator::Percent)),
+            '&' => Some(TokenType::Operator(Operator::Ampersand)),
+            '|' => Some(TokenType::Operator(Operator::Pipe)),
+            '^' => Some(TokenType::Operator(Operator::Caret)),
+            '~' => Some(TokenType::Operator(Operator::Tilde)),
+            '<' => Some(TokenType::Operator(Operator::Less)),
+            '>' => Some(TokenType::Operator(Operator::Greater)),
             _ => None,
         };
 
-        if let Some((str, token_type)) = character {
+        if let Some(token_type) = character {
             return Some(Ok(Token::new(
                 token_type,
-                str,
+                self.chop(1),
                 self.current_line,
-                self.current_char - 1..self.current_char,
+                start..self.current_char,
             )));
         };
 
@@ -212,6 +564,7 @@ impl<'a> Lexer<'a> {
         //       the right string that actually caused trouble. For example
         //       when you make a typo like "JMPx" instead of "JMP", then
         //       the error printed will say "Unknown token 'J'" instead of "JMPx".
+        self.chop(1);
         Some(Err(LexerErr::UnknownToken(String::from(initial_character))))
     }
 }
@@ -223,3 +576,279 @@ impl<'a> Iterator for Lexer<'a> {
         self.next_token()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        token::{render_tokens, split_lines, statement_at},
+    };
+
+    fn round_trip(source: &str) -> String {
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        render_tokens(&tokens)
+    }
+
+    #[test]
+    fn test_round_trip_preserves_trivia() {
+        let source = "\n  start:  MOV A 42\n\n\tADD [#start]   \nbyte 0x05 012\n\n";
+        assert_eq!(round_trip(source), source);
+    }
+
+    // Inputs that tripped up the two number parsers this replaced, paired
+    // with the value they should lex to, `None` for an error.
+    const NUMBER_CORPUS: &[(&str, Option<i64>)] = &[
+        ("0", Some(0)),
+        ("00", Some(0)),
+        ("7", Some(7)),
+        ("42", Some(42)),
+        ("255", Some(255)),
+        ("0x0", Some(0)),
+        ("0xff", Some(255)),
+        ("0xFF", Some(255)),
+        ("0xFF00", Some(0xFF00)),
+        ("0b0", Some(0)),
+        ("0b101", Some(5)),
+        ("017", Some(15)),
+        ("0_17", None),
+        ("010", Some(8)),
+        ("1_000", Some(1000)),
+        ("0xFF_00", Some(0xFF00)),
+        ("0b1010_0101", Some(0xA5)),
+        ("9223372036854775807", Some(i64::MAX)),
+        ("9223372036854775808", None),
+        ("0x8000000000000000", None),
+        ("0x", None),
+        ("0b", None),
+        ("0x_", None),
+        ("0x_1", None),
+        ("1_", None),
+        ("0b102", None),
+        ("08", None),
+        ("09", None),
+        ("12ab", None),
+        ("0xfg", None),
+        ("0x+1", None),
+        ("0b-1", None),
+        ("0bad", None),
+        ("1__0", Some(10)),
+    ];
+
+    #[test]
+    fn test_number_corpus() {
+        for (literal, expected) in NUMBER_CORPUS {
+            assert_eq!(parse_number(literal), *expected, "parsing {:?}", literal);
+
+            let chars = literal.chars().collect::<Vec<_>>();
+            let lexed = Lexer::new(&chars).next().unwrap();
+            match expected {
+                Some(number) => {
+                    let token_type = lexed.unwrap().token_type;
+                    assert!(matches!(token_type, TokenType::Number(value, _) if value == *number));
+                }
+                None => assert!(
+                    matches!(
+                        lexed,
+                        Err(LexerErr::NumberParseError(_) | LexerErr::MalformedLiteral(..))
+                    ),
+                    "lexing {:?}",
+                    literal
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_literal_width() {
+        assert_eq!(literal_width("5"), None);
+        assert_eq!(literal_width("0x05"), Some(8));
+        assert_eq!(literal_width("0x0005"), Some(16));
+        assert_eq!(literal_width("0b0000_0101"), Some(8));
+        assert_eq!(literal_width("017"), Some(6));
+
+        // How wide a literal is written doesn't make it an address where an
+        // immediate fits, only where the instruction takes no immediate.
+        let config = crate::config::Config::builtin();
+        let image = |source: &str| {
+            crate::assemble(source, &config, Default::default())
+                .unwrap()
+                .image
+        };
+        assert_eq!(image("MOV A 0x0005"), image("MOV A 5"));
+        assert_eq!(image("MOV A 0100"), [0x11, 0x40]);
+        assert_eq!(image("MOV A 0377"), image("MOV A 255"));
+        assert_eq!(image("MOV A 0b000000001"), image("MOV A 1"));
+        assert_eq!(image("JMPIMM 0x0010"), image("JMPIMM [16]"));
+        assert!(crate::assemble("JMPIMM 0x10", &config, Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_malformed_literal() {
+        let lex = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            Lexer::new(&chars).collect::<Result<Vec<_>, _>>()
+        };
+        let err = lex("MOV A 5abc").unwrap_err();
+        assert_eq!(
+            err,
+            LexerErr::MalformedLiteral("5".to_string(), "abc".to_string())
+        );
+        assert_eq!(
+            err.help().unwrap().as_str(),
+            "names can't start with a digit; write '5 abc' if these are a number and a name"
+        );
+        assert!(matches!(
+            lex("byte 0x1fg"),
+            Err(LexerErr::MalformedLiteral(number, _)) if number == "0x1f"
+        ));
+        assert!(matches!(
+            lex("byte 0xzz"),
+            Err(LexerErr::NumberParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_normalize_source() {
+        let source = normalize_source("\u{feff}start: MOV A 1\r\nHALT\rbyte 1\n\r\n");
+        assert_eq!(source, "start: MOV A 1\nHALT\nbyte 1\n\n");
+
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let spans = tokens
+            .iter()
+            .map(|token| {
+                (
+                    token.content.as_str(),
+                    token.span.line,
+                    token.span.chars.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                ("start", 0, 0..6),
+                ("MOV", 0, 7..10),
+                ("A", 0, 11..12),
+                ("1", 0, 13..14),
+                ("HALT", 1, 0..4),
+                ("byte", 2, 0..4),
+                ("1", 2, 5..6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_trivia_ends_at_newline() {
+        let chars = "MOV A 1  \n  HALT".chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(tokens[2].trailing_trivia, "  \n");
+        assert_eq!(tokens[3].leading_trivia, "  ");
+    }
+
+    #[test]
+    fn test_statement_separator() {
+        let source = "PUSH A \\ PUSH B \\\\ loop: JMP [#loop]\n";
+        assert_eq!(round_trip(source), source);
+
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let statements = split_lines(&tokens)
+            .map(|line| line.iter().map(|token| token.span.chars.clone()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            statements,
+            [
+                vec![0..4, 5..6],
+                vec![9..13, 14..15],
+                vec![19..24, 25..28, 29..30, 30..35, 35..36]
+            ]
+        );
+        assert_eq!(statement_at(source.trim_end(), 25), "loop: JMP [#loop]");
+        assert_eq!(statement_at(source.trim_end(), 2), "PUSH A");
+        assert_eq!(statement_at(source.trim_end(), 9), "PUSH B");
+    }
+
+    #[test]
+    fn test_label_sigil() {
+        let chars = "JMPIMM $start + 1\nMOV A #1".chars().collect::<Vec<_>>();
+        let syntax = SyntaxOptions {
+            label_sigil: '$',
+            ..Default::default()
+        };
+        let tokens = Lexer::with_syntax(&chars, syntax).collect::<Vec<_>>();
+        assert_eq!(
+            tokens[1].as_ref().unwrap().token_type,
+            TokenType::LabelRef("start".to_string())
+        );
+        assert_eq!(
+            tokens[6].as_ref().unwrap_err(),
+            &LexerErr::UnknownToken("#".to_string())
+        );
+    }
+
+    #[test]
+    fn test_bare_label_refs() {
+        let chars = "loop: JMPIMM loop\nMOV A B\nADD [data + 1]"
+            .chars()
+            .collect::<Vec<_>>();
+        let syntax = SyntaxOptions {
+            bare_label_refs: true,
+            ..Default::default()
+        };
+        let tokens = Lexer::with_syntax(&chars, syntax)
+            .map(|token| token.unwrap().token_type)
+            .collect::<Vec<_>>();
+        assert!(matches!(&tokens[1], TokenType::Mnemonic(m) if m.to_string() == "JMPIMM"));
+        assert_eq!(tokens[2], TokenType::LabelRef("loop".to_string()));
+        // Registers keep their meaning, and the mnemonic starts each line.
+        assert!(matches!(&tokens[3], TokenType::Mnemonic(m) if m.to_string() == "MOV"));
+        assert!(matches!(tokens[4], TokenType::Register(_)));
+        assert!(matches!(tokens[5], TokenType::Register(_)));
+        assert!(matches!(&tokens[6], TokenType::Mnemonic(m) if m.to_string() == "ADD"));
+        assert_eq!(tokens[8], TokenType::LabelRef("data".to_string()));
+    }
+
+    #[test]
+    fn test_colon_free_labels() {
+        let lex = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            let syntax = SyntaxOptions {
+                colon_free_labels: true,
+                mnemonics: Config::builtin().mnemonics(),
+                ..Default::default()
+            };
+            Lexer::with_syntax(&chars, syntax)
+                .map(|token| token.unwrap().token_type)
+                .collect::<Vec<_>>()
+        };
+
+        let tokens = lex("start  \n  MOV A 1\nJMPIMM #start");
+        assert_eq!(tokens[0], TokenType::Label("start".to_string()));
+        assert!(matches!(&tokens[1], TokenType::Mnemonic(m) if m.to_string() == "MOV"));
+        // A mnemonic at column 0 is still an instruction when operands follow.
+        assert!(matches!(&tokens[4], TokenType::Mnemonic(m) if m.to_string() == "JMPIMM"));
+        assert_eq!(tokens[5], TokenType::LabelRef("start".to_string()));
+
+        let tokens = lex("loop \\ HALT\nend");
+        assert_eq!(tokens[0], TokenType::Label("loop".to_string()));
+        assert!(matches!(&tokens[1], TokenType::Mnemonic(m) if m.to_string() == "HALT"));
+        assert_eq!(tokens[2], TokenType::Label("end".to_string()));
+
+        // Nor is a mnemonic the config knows a label when nothing follows it.
+        let tokens = lex("start:\n  MOV A 1\nHALT");
+        assert!(matches!(&tokens[4], TokenType::Mnemonic(m) if m.to_string() == "HALT"));
+        let assembly = crate::assemble(
+            "start:\n  MOV A 1\nHALT",
+            &Config::builtin(),
+            crate::AssembleOptions {
+                colon_free_labels: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(assembly.image, [0x11, 0x01, 0xd8]);
+    }
+}
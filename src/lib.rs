@@ -1,8 +1,125 @@
+//! To embed the assembler, call [`assemble`]: it returns the machine code,
+//! symbols and diagnostics as data and never prints or exits.
+//!
+//! Without the default `std` feature only the encoding core, [`specs`] and
+//! [`encoder`], is built, as a `no_std` crate that still needs `alloc`.
+//! With the `build` feature, [`build`] turns a config into a static opcode
+//! table such a crate can encode with, see the `tables` feature.
+//! The `emulator`, `serve` and `serde` features add the emulator, the
+//! JSON-RPC `serve` mode and object files with the linker. The default
+//! `cli` feature builds the command line with all of them.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub use prelude::{assemble, AssembleOptions, Assembly, Diagnostic, Instruction, SourceSpan};
+
+#[cfg(feature = "tables")]
+pub use phf;
+
+#[cfg(feature = "serde")]
+pub mod archive;
+#[cfg(feature = "build")]
+pub mod build;
+#[cfg(feature = "std")]
 pub mod checker;
+#[cfg(feature = "cli")]
 pub mod cmdline_args;
+#[cfg(feature = "std")]
+pub mod conditional;
+#[cfg(feature = "std")]
 pub mod config;
+#[cfg(feature = "std")]
+pub mod decoder;
+#[cfg(feature = "std")]
+pub mod defines;
+#[cfg(feature = "cli")]
+pub mod difftest;
+#[cfg(feature = "std")]
+pub mod disassembler;
+#[cfg(feature = "std")]
+pub mod emitter;
+#[cfg(feature = "emulator")]
+pub mod emulator;
+pub mod encoder;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod examples;
+#[cfg(feature = "std")]
+pub mod explain;
+#[cfg(feature = "std")]
+pub mod expr;
+#[cfg(feature = "std")]
+pub mod gc;
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "std")]
+pub mod i18n;
+#[cfg(feature = "std")]
+pub mod include;
+#[cfg(feature = "std")]
 pub mod lexer;
+#[cfg(feature = "std")]
+pub mod limits;
+#[cfg(feature = "std")]
+pub mod linkage;
+#[cfg(feature = "serde")]
+pub mod linker;
+#[cfg(feature = "serde")]
+pub mod linkscript;
+#[cfg(feature = "std")]
+pub mod listing;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod macros;
+#[cfg(feature = "cli")]
+pub mod manifest;
+#[cfg(feature = "serde")]
+pub mod object;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
 pub mod parser;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod prelude;
+#[cfg(feature = "emulator")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod provenance;
+#[cfg(feature = "std")]
+pub mod pseudo;
+#[cfg(feature = "std")]
+pub mod reachability;
+#[cfg(feature = "emulator")]
+pub mod repl;
+#[cfg(feature = "std")]
 pub mod resolver;
+#[cfg(feature = "cli")]
+pub mod scaffold;
+#[cfg(feature = "std")]
+pub mod segments;
+#[cfg(feature = "std")]
+pub mod selftest;
+#[cfg(feature = "serve")]
+pub mod serve;
+#[cfg(feature = "emulator")]
+pub mod snapshot;
 pub mod specs;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod syntax;
+#[cfg(feature = "std")]
+pub mod teach;
+#[cfg(feature = "std")]
 pub mod token;
+#[cfg(feature = "emulator")]
+pub mod trace;
@@ -0,0 +1,309 @@
+use thiserror::Error;
+
+use crate::{
+    checker::CheckedLine,
+    error::{Error, WithSpan},
+    i18n::Message,
+    token::Span,
+};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum LimitErr {
+    #[error("Includes are nested more than {0} levels deep, is there an include cycle?")]
+    IncludeDepth(usize),
+    #[error("Macro expansion is nested more than {0} levels deep, is '{1}' recursive?")]
+    MacroDepth(usize, String),
+    #[error("Macro expansion produced more than {0} tokens.")]
+    ExpansionSize(usize),
+    #[error("Line is {0} characters long, more than the limit of {1}.")]
+    LineLength(usize, usize),
+    #[error("Non-ASCII character {0:?} (U+{1:04X}) outside a string.")]
+    NonAscii(char, u32),
+    #[error("Output would grow to {0} bytes here, more than the limit of {1}.")]
+    OutputSize(usize, usize),
+}
+
+/// What to do about non-ASCII characters outside string and character
+/// literals, e.g. accented labels or a zero-width space pasted from a web
+/// page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum NonAsciiPolicy {
+    /// Accept them
+    Allow,
+    /// Accept them with a warning
+    #[default]
+    Warn,
+    /// Reject them before the source is lexed
+    Deny,
+}
+
+/// Bounds on the source, on include and macro expansion and on the output,
+/// so a cycle, a runaway macro, generated input or a typo in an address
+/// fails with a diagnostic instead of exhausting memory or confusing the
+/// lexer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct Limits {
+    /// Maximum nesting depth of included files
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "depth", default_value_t = 32, global = true)
+    )]
+    pub max_include_depth: usize,
+
+    /// Maximum nesting depth of macro invocations
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "depth", default_value_t = 64, global = true)
+    )]
+    pub max_macro_depth: usize,
+
+    /// Maximum number of tokens all macro expansions may produce
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "tokens",
+            default_value_t = 1_000_000,
+            global = true
+        )
+    )]
+    pub max_expansion_tokens: usize,
+
+    /// Maximum number of characters on a source line
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, value_name = "chars", default_value_t = 4096, global = true)
+    )]
+    pub max_line_length: usize,
+
+    /// Maximum number of bytes the output may span, counting gaps left by .org and .align
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "bytes",
+            default_value_t = 16 * 1024 * 1024,
+            global = true
+        )
+    )]
+    pub max_output_bytes: usize,
+
+    /// What to do about non-ASCII characters outside strings
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "policy",
+            value_enum,
+            default_value_t,
+            global = true
+        )
+    )]
+    pub non_ascii: NonAsciiPolicy,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_include_depth: 32,
+            max_macro_depth: 64,
+            max_expansion_tokens: 1_000_000,
+            max_line_length: 4096,
+            max_output_bytes: 16 * 1024 * 1024,
+            non_ascii: NonAsciiPolicy::default(),
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_include_depth(&self, depth: usize) -> Result<(), LimitErr> {
+        match depth > self.max_include_depth {
+            true => Err(LimitErr::IncludeDepth(self.max_include_depth)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn check_macro_depth(&self, depth: usize, macro_name: &str) -> Result<(), LimitErr> {
+        match depth > self.max_macro_depth {
+            true => Err(LimitErr::MacroDepth(
+                self.max_macro_depth,
+                macro_name.to_string(),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    pub fn check_expansion_size(&self, tokens: usize) -> Result<(), LimitErr> {
+        match tokens > self.max_expansion_tokens {
+            true => Err(LimitErr::ExpansionSize(self.max_expansion_tokens)),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that the laid out program stays within the output limit before
+    /// the image is built, pointing at the first line that passes it.
+    pub fn check_output_size(
+        &self,
+        checked_lines: &[CheckedLine],
+        addresses: &[usize],
+    ) -> Result<(), Error> {
+        for (checked_line, address) in checked_lines.iter().zip(addresses) {
+            let end = checked_line.code.end(*address);
+            if end <= self.max_output_bytes {
+                continue;
+            }
+            if let Some(token) = checked_line.line.first_token() {
+                let err = LimitErr::OutputSize(end, self.max_output_bytes);
+                return Err(err
+                    .with_span(token.span.clone())
+                    .with_help(Message::new("help.raise_max_output_bytes", vec![])));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a source file, the `file`th of the program, before it's
+    /// lexed. Returns the errors and then the warnings: lines over the
+    /// length limit are errors, and so is the first non-ASCII character
+    /// outside a string or character literal on a line unless the policy
+    /// makes it a warning or allows it.
+    pub fn check_source(&self, source: &str, file: usize) -> (Vec<Error>, Vec<Error>) {
+        let mut errors = vec![];
+        let mut warnings = vec![];
+        for (line, text) in source.lines().enumerate() {
+            let length = text.chars().count();
+            if length > self.max_line_length {
+                let span = Span::new(line, self.max_line_length..length).in_file(file);
+                errors.push(LimitErr::LineLength(length, self.max_line_length).with_span(span));
+            }
+            if self.non_ascii == NonAsciiPolicy::Allow {
+                continue;
+            }
+
+            let mut quote = None;
+            let mut escaped = false;
+            for (column, c) in text.chars().enumerate() {
+                match quote {
+                    Some(_) if escaped => escaped = false,
+                    Some(_) if c == '\\' => escaped = true,
+                    Some(open) if c == open => quote = None,
+                    Some(_) => {}
+                    None if c == '"' || c == '\'' => quote = Some(c),
+                    None if !c.is_ascii() => {
+                        let span = Span::new(line, column..column + 1).in_file(file);
+                        let err = LimitErr::NonAscii(c, c as u32).with_span(span);
+                        match self.non_ascii {
+                            NonAsciiPolicy::Deny => errors.push(err),
+                            _ => warnings.push(err),
+                        }
+                        break;
+                    }
+                    None => {}
+                }
+            }
+        }
+        (errors, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[test]
+    fn test_check_source() {
+        let source = "caf\u{e9}: MOV A 1\nbyte \"na\u{ef}ve\" '\u{e9}'\n\u{200b}HALT\nbyte 1 2 3\n";
+        let limits = Limits {
+            max_line_length: 8,
+            ..Default::default()
+        };
+
+        let (errors, warnings) = limits.check_source(source, 0);
+        let summary = |errors: &[Error]| {
+            errors
+                .iter()
+                .map(|err| (err.to_string(), err.span().line, err.span().chars.start))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&errors),
+            [
+                (
+                    "Line is 13 characters long, more than the limit of 8.".to_string(),
+                    0,
+                    8
+                ),
+                (
+                    "Line is 16 characters long, more than the limit of 8.".to_string(),
+                    1,
+                    8
+                ),
+                (
+                    "Line is 10 characters long, more than the limit of 8.".to_string(),
+                    3,
+                    8
+                ),
+            ]
+        );
+        // Strings and character literals may hold anything.
+        assert_eq!(
+            summary(&warnings),
+            [
+                (
+                    "Non-ASCII character '\u{e9}' (U+00E9) outside a string.".to_string(),
+                    0,
+                    3
+                ),
+                (
+                    r"Non-ASCII character '\u{200b}' (U+200B) outside a string.".to_string(),
+                    2,
+                    0
+                ),
+            ]
+        );
+
+        let limits = Limits {
+            non_ascii: NonAsciiPolicy::Deny,
+            ..Default::default()
+        };
+        let (errors, warnings) = limits.check_source(source, 0);
+        assert_eq!((errors.len(), warnings.len()), (2, 0));
+    }
+
+    #[test]
+    fn test_check_output_size() {
+        let source = "HALT\n.org 0x100\n.align 0x4000\nHALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let config = Config::builtin();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+
+        let limits = Limits {
+            max_output_bytes: 0x1000,
+            ..Default::default()
+        };
+        let err = limits
+            .check_output_size(&checked_lines, &addresses)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Output would grow to 16384 bytes here, more than the limit of 4096."
+        );
+        assert_eq!(err.span().line, 2);
+        assert!(Limits::default()
+            .check_output_size(&checked_lines, &addresses)
+            .is_ok());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    error::{Error, WithSpan},
+    i18n::Message,
+    parser::Line,
+    token::{Token, TokenType},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LinkErr {
+    #[error("Exported symbol '{0}' is not defined.")]
+    UndefinedExport(String),
+    #[error("Imported symbol '{0}' is also defined in this file.")]
+    ImportDefinedLocally(String),
+    #[error("Unresolved import '{0}', no object exports it.")]
+    UnresolvedImport(String),
+}
+
+fn symbol_name(token: &Token) -> &str {
+    match &token.token_type {
+        TokenType::LabelRef(name) => name,
+        _ => &token.content,
+    }
+}
+
+/// Names the `.import` lines declare, each once.
+pub fn imported_names<'a: 'b, 'b>(lines: impl IntoIterator<Item = &'b Line<'a>>) -> Vec<&'a str> {
+    let mut names = vec![];
+    for line in lines {
+        if let Line::Import(tokens) = line {
+            names.extend(tokens.iter().map(|token| symbol_name(token)));
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Checks the `.export` and `.import` lines against the symbols this
+/// assembly defines. Unless the program is `relocatable`, leaving its
+/// imports to the linker, every import that nothing resolves is reported
+/// with the places that reference it.
+pub fn check_linkage(
+    tokens: &[Token],
+    lines: &[Line],
+    symbols: &HashMap<&str, usize>,
+    relocatable: bool,
+) -> Vec<Error> {
+    let mut errors = vec![];
+    let mut declarations: Vec<&Token> = vec![];
+
+    for line in lines {
+        match line {
+            Line::Export(names) => {
+                declarations.extend(names.iter().copied());
+                for name in names {
+                    if !symbols.contains_key(symbol_name(name)) {
+                        errors.push(
+                            LinkErr::UndefinedExport(symbol_name(name).to_string())
+                                .with_span(name.span.clone()),
+                        );
+                    }
+                }
+            }
+            Line::Import(names) => declarations.extend(names.iter().copied()),
+            _ => {}
+        }
+    }
+
+    let mut imported = vec![];
+    for line in lines {
+        let Line::Import(names) = line else {
+            continue;
+        };
+        for token in names {
+            let name = symbol_name(token);
+            if imported.contains(&name) {
+                continue;
+            }
+            imported.push(name);
+
+            if symbols.contains_key(name) {
+                let mut err =
+                    LinkErr::ImportDefinedLocally(name.to_string()).with_span(token.span.clone());
+                let definition = tokens.iter().find(
+                    |other| matches!(&other.token_type, TokenType::Label(label) if label == name),
+                );
+                if let Some(definition) = definition {
+                    err = err.with_label(
+                        definition.span.clone(),
+                        Message::new("note.defined", vec![]),
+                    );
+                }
+                errors.push(err);
+                continue;
+            }
+            if relocatable {
+                continue;
+            }
+
+            let references = tokens.iter().filter(|other| {
+                matches!(&other.token_type, TokenType::LabelRef(label) if label == name)
+                    && !declarations
+                        .iter()
+                        .any(|declaration| std::ptr::eq(*declaration, *other))
+            });
+            errors.push(references.fold(
+                LinkErr::UnresolvedImport(name.to_string()).with_span(token.span.clone()),
+                |err, reference| {
+                    err.with_label(
+                        reference.span.clone(),
+                        Message::new("note.referenced", vec![]),
+                    )
+                },
+            ));
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, lexer::Lexer, parser::parse, resolver::get_resolved_labels};
+
+    #[test]
+    fn test_check_linkage() {
+        let source = ".export start missing\n.import putc start\nstart:\n    JMPIMM #putc\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = parse(&tokens).unwrap();
+        let symbols = get_resolved_labels(&tokens, &Config::builtin());
+
+        let errors = check_linkage(&tokens, &lines, &symbols, false);
+        let summary = errors
+            .iter()
+            .map(|err| (err.to_string(), err.span().line, err.labels().len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "Exported symbol 'missing' is not defined.".to_string(),
+                    0,
+                    0
+                ),
+                (
+                    "Unresolved import 'putc', no object exports it.".to_string(),
+                    1,
+                    1
+                ),
+                (
+                    "Imported symbol 'start' is also defined in this file.".to_string(),
+                    1,
+                    1
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use thiserror::Error;
+
+use crate::{
+    archive::{Archive, Input},
+    encoder::{encode_address, encode_byte},
+    lexer::parse_number,
+    linkscript::LinkScript,
+    object::{Object, Target, OBJECT_VERSION},
+};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LinkerErr {
+    #[error("Invalid section base '{0}', expected NAME=ADDRESS.")]
+    InvalidBase(String),
+    #[error("Couldn't read object '{0}': {1}.")]
+    Read(String, String),
+    #[error("'{0}' is an object of version {1}, expected {2}.")]
+    UnsupportedVersion(String, u32, u32),
+    #[error("No object has a section '{0}' to place.")]
+    UnknownSection(String),
+    #[error("Symbol '{0}' is exported by both {1} and {2}.")]
+    DuplicateSymbol(String, String, String),
+    #[error("Undefined symbol '{0}', imported by {1}.")]
+    UndefinedSymbol(String, String),
+    #[error("Sections '{0}' and '{1}' overlap at {2:#06x}.")]
+    SectionsOverlap(String, String, usize),
+    #[error("Relocated value {0} doesn't fit in {1} byte(s) at {2}.")]
+    ValueOutOfRange(i64, usize, String),
+    #[error("Invalid link script '{0}': {1}.")]
+    InvalidScript(String, String),
+    #[error("The link script doesn't place section '{0}' of {1}.")]
+    UnplacedSection(String, String),
+    #[error("Region '{0}' overflows by {1} byte(s).")]
+    RegionOverflow(String, usize),
+}
+
+/// How the linker decides where sections go.
+#[derive(Debug, Clone, Copy)]
+pub enum Layout<'a> {
+    /// Sections follow one another, from the given bases.
+    Bases(&'a [SectionBase]),
+    Script(&'a LinkScript),
+}
+
+/// A `--base NAME=ADDRESS` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionBase {
+    pub name: String,
+    pub address: usize,
+}
+
+pub fn parse_section_base(arg: &str) -> Result<SectionBase, LinkerErr> {
+    let invalid = || LinkerErr::InvalidBase(arg.to_string());
+    let (name, address) = arg.split_once('=').ok_or_else(invalid)?;
+    let address = parse_number(address.trim())
+        .and_then(|address| usize::try_from(address).ok())
+        .ok_or_else(invalid)?;
+    match name.trim() {
+        "" => Err(invalid()),
+        name => Ok(SectionBase {
+            name: name.to_string(),
+            address,
+        }),
+    }
+}
+
+/// Whether every symbol and relocation lies within the object's sections.
+fn in_bounds(object: &Object) -> bool {
+    let section_len = |section: usize| object.sections.get(section).map(|s| s.data.len());
+    let symbols = object
+        .symbols
+        .iter()
+        .all(|symbol| section_len(symbol.section).is_some_and(|len| symbol.offset <= len));
+    let relocations = object.relocations.iter().all(|relocation| {
+        let target = match relocation.target {
+            Target::Section(section) => section < object.sections.len(),
+            Target::Symbol(_) => true,
+        };
+        target
+            && matches!(relocation.width, 1 | 2)
+            && section_len(relocation.section)
+                .is_some_and(|len| relocation.offset + relocation.width <= len)
+    });
+    symbols && relocations
+}
+
+fn check_object(name: &str, object: &Object) -> Result<(), LinkerErr> {
+    if object.version != OBJECT_VERSION {
+        return Err(LinkerErr::UnsupportedVersion(
+            name.to_string(),
+            object.version,
+            OBJECT_VERSION,
+        ));
+    }
+    if !in_bounds(object) {
+        return Err(LinkerErr::Read(
+            name.to_string(),
+            "it refers to bytes outside its sections".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reads an object or, if it has members, an archive of them.
+pub fn read_input(path: &Path) -> Result<Input, LinkerErr> {
+    let name = path.display().to_string();
+    let content =
+        fs::read_to_string(path).map_err(|err| LinkerErr::Read(name.clone(), err.to_string()))?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|err| LinkerErr::Read(name.clone(), err.to_string()))?;
+    if value.get("members").is_none() {
+        let object: Object = serde_json::from_value(value)
+            .map_err(|err| LinkerErr::Read(name.clone(), err.to_string()))?;
+        check_object(&name, &object)?;
+        return Ok(Input::Object(object));
+    }
+
+    let archive: Archive = serde_json::from_value(value)
+        .map_err(|err| LinkerErr::Read(name.clone(), err.to_string()))?;
+    if archive.version != OBJECT_VERSION {
+        return Err(LinkerErr::UnsupportedVersion(
+            name,
+            archive.version,
+            OBJECT_VERSION,
+        ));
+    }
+    for member in &archive.members {
+        check_object(&format!("{}({})", name, member.name), &member.object)?;
+    }
+    Ok(Input::Archive(archive))
+}
+
+pub fn read_object(path: &Path) -> Result<Object, LinkerErr> {
+    match read_input(path)? {
+        Input::Object(object) => Ok(object),
+        Input::Archive(_) => Err(LinkerErr::Read(
+            path.display().to_string(),
+            "it is an archive, not an object".to_string(),
+        )),
+    }
+}
+
+/// Places sections of the same name one after another in the order of the
+/// objects, and sections of different names in the order they first appear.
+/// A section starts at its base if one is given and right after the
+/// previous one otherwise.
+fn place_by_bases(
+    objects: &[(String, Object)],
+    bases: &[SectionBase],
+) -> Result<HashMap<(usize, usize), usize>, Vec<LinkerErr>> {
+    let mut errors = vec![];
+
+    let mut names: Vec<&str> = vec![];
+    for (_, object) in objects {
+        for section in &object.sections {
+            if !names.contains(&section.name.as_str()) {
+                names.push(&section.name);
+            }
+        }
+    }
+    for base in bases {
+        if !names.contains(&base.name.as_str()) {
+            errors.push(LinkerErr::UnknownSection(base.name.clone()));
+        }
+    }
+
+    // Address of every object's sections, and the range of each name.
+    let mut placed = HashMap::new();
+    let mut ranges = vec![];
+    let mut cursor = 0;
+    for name in names {
+        let start = bases
+            .iter()
+            .find(|base| base.name == name)
+            .map_or(cursor, |base| base.address);
+        let mut address = start;
+        for (i, (_, object)) in objects.iter().enumerate() {
+            for (j, section) in object.sections.iter().enumerate() {
+                if section.name == name {
+                    placed.insert((i, j), address);
+                    address += section.data.len();
+                }
+            }
+        }
+        ranges.push((name, start, address));
+        cursor = address;
+    }
+    for (i, (first, start, end)) in ranges.iter().enumerate() {
+        for (second, other_start, other_end) in &ranges[i + 1..] {
+            if start < other_end && other_start < end {
+                errors.push(LinkerErr::SectionsOverlap(
+                    first.to_string(),
+                    second.to_string(),
+                    *start.max(other_start),
+                ));
+            }
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(placed),
+        false => Err(errors),
+    }
+}
+
+/// Combines objects, each with the name it's reported under, into one
+/// image, with the sections placed as `layout` says. Only exported symbols
+/// are visible to other objects.
+pub fn link(objects: &[(String, Object)], layout: Layout) -> Result<Vec<u8>, Vec<LinkerErr>> {
+    let placed = match layout {
+        Layout::Bases(bases) => place_by_bases(objects, bases)?,
+        Layout::Script(script) => script.place(objects)?,
+    };
+    let mut errors = vec![];
+
+    let mut exports: HashMap<&str, (&str, usize)> = HashMap::new();
+    for (i, (file, object)) in objects.iter().enumerate() {
+        for symbol in object.symbols.iter().filter(|symbol| symbol.exported) {
+            let address = placed[&(i, symbol.section)] + symbol.offset;
+            if let Some((first, _)) = exports.insert(&symbol.name, (file, address)) {
+                errors.push(LinkerErr::DuplicateSymbol(
+                    symbol.name.clone(),
+                    first.to_string(),
+                    file.clone(),
+                ));
+            }
+        }
+    }
+
+    let mut undefined: Vec<(&str, Vec<String>)> = vec![];
+    for (file, object) in objects {
+        for import in object
+            .imports
+            .iter()
+            .filter(|name| !exports.contains_key(name.as_str()))
+        {
+            let locations = object
+                .relocations
+                .iter()
+                .filter(|relocation| relocation.target == Target::Symbol(import.clone()))
+                .map(|relocation| relocation.location.as_str())
+                .collect::<Vec<_>>();
+            let importer = match locations.is_empty() {
+                true => file.clone(),
+                false => format!("{} ({})", file, locations.join(", ")),
+            };
+            match undefined.iter_mut().find(|(name, _)| name == import) {
+                Some((_, importers)) => importers.push(importer),
+                None => undefined.push((import, vec![importer])),
+            }
+        }
+    }
+    errors.extend(undefined.into_iter().map(|(name, importers)| {
+        LinkerErr::UndefinedSymbol(name.to_string(), importers.join(", "))
+    }));
+
+    let size = placed
+        .iter()
+        .map(|((i, j), address)| address + objects[*i].1.sections[*j].data.len())
+        .max()
+        .unwrap_or(0);
+    let mut image = vec![0; size];
+    for ((i, j), address) in &placed {
+        let data = &objects[*i].1.sections[*j].data;
+        image[*address..address + data.len()].copy_from_slice(data);
+    }
+
+    for (i, (file, object)) in objects.iter().enumerate() {
+        for relocation in &object.relocations {
+            let target = match &relocation.target {
+                Target::Section(section) => placed[&(i, *section)],
+                Target::Symbol(name) => match exports.get(name.as_str()) {
+                    Some((_, address)) => *address,
+                    None => continue,
+                },
+            };
+            let value = target as i64 + relocation.addend;
+            let bytes = match relocation.width {
+                1 => encode_byte(value).map(|byte| vec![byte]),
+                width => encode_address(value, 8 * width as u32),
+            };
+            let Ok(bytes) = bytes else {
+                errors.push(LinkerErr::ValueOutOfRange(
+                    value,
+                    relocation.width,
+                    format!("{} ({})", file, relocation.location),
+                ));
+                continue;
+            };
+            let site = placed[&(i, relocation.section)] + relocation.offset;
+            image[site..site + bytes.len()].copy_from_slice(&bytes);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{ObjectSymbol, Relocation, Section};
+
+    fn object(
+        name: &str,
+        sections: &[(&str, &[u8])],
+        symbols: &[(&str, usize, usize, bool)],
+        imports: &[&str],
+        relocations: &[(usize, usize, Target, i64)],
+    ) -> (String, Object) {
+        let object = Object {
+            version: OBJECT_VERSION,
+            source: format!("{}.as", name),
+            sections: sections
+                .iter()
+                .map(|(name, data)| Section {
+                    name: name.to_string(),
+                    data: data.to_vec(),
+                })
+                .collect(),
+            symbols: symbols
+                .iter()
+                .map(|(name, section, offset, exported)| ObjectSymbol {
+                    name: name.to_string(),
+                    section: *section,
+                    offset: *offset,
+                    exported: *exported,
+                })
+                .collect(),
+            imports: imports.iter().map(|name| name.to_string()).collect(),
+            relocations: relocations
+                .iter()
+                .map(|(section, offset, target, addend)| Relocation {
+                    section: *section,
+                    offset: *offset,
+                    width: 2,
+                    target: target.clone(),
+                    addend: *addend,
+                    location: format!("{}.as:1", name),
+                })
+                .collect(),
+        };
+        (format!("{}.obj", name), object)
+    }
+
+    #[test]
+    fn test_link() {
+        // main jumps to putc in lib and reads a word of its own data.
+        let main = object(
+            "main",
+            &[("code", &[0xa0, 0, 0]), ("data", &[0, 0])],
+            &[("start", 0, 0, false), ("table", 1, 0, false)],
+            &["putc"],
+            &[
+                (0, 1, Target::Symbol("putc".to_string()), 0),
+                (1, 0, Target::Section(1), 1),
+            ],
+        );
+        let lib = object(
+            "lib",
+            &[("code", &[0xff])],
+            &[("putc", 0, 0, true)],
+            &[],
+            &[],
+        );
+        let bases = [SectionBase {
+            name: "data".to_string(),
+            address: 0x10,
+        }];
+
+        let image = link(&[main.clone(), lib.clone()], Layout::Bases(&bases)).unwrap();
+        assert_eq!(image[..4], [0xa0, 0x03, 0x00, 0xff]);
+        assert_eq!(image[0x10..], [0x11, 0x00]);
+
+        let errors = link(&[main.clone(), lib.clone(), lib], Layout::Bases(&[])).unwrap_err();
+        assert_eq!(
+            errors,
+            [LinkerErr::DuplicateSymbol(
+                "putc".to_string(),
+                "lib.obj".to_string(),
+                "lib.obj".to_string()
+            )]
+        );
+        let errors = link(&[main], Layout::Bases(&[])).unwrap_err();
+        assert_eq!(
+            errors,
+            [LinkerErr::UndefinedSymbol(
+                "putc".to_string(),
+                "main.obj (main.as:1)".to_string()
+            )]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{linker::LinkerErr, object::Object};
+
+/// A TOML file telling the linker which memory regions there are and which
+/// sections go into each, e.g.
+///
+/// ```toml
+/// [[region]]
+/// name = "rom"
+/// start = 0x0000
+/// size = 0x4000
+///
+/// [[place]]
+/// section = "code"
+/// region = "rom"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkScript {
+    #[serde(rename = "region", default)]
+    pub regions: Vec<Region>,
+    #[serde(rename = "place", default)]
+    pub placements: Vec<Placement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+    pub name: String,
+    pub start: usize,
+    pub size: usize,
+}
+
+/// Puts the sections of one name into a region, those of every object or
+/// only of the listed ones.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Placement {
+    pub section: String,
+    pub region: String,
+    #[serde(default)]
+    pub objects: Vec<String>,
+}
+
+/// Whether `pattern` names the object reported as `name`, by its path or
+/// file name or, for an archive member, by the archive's or the member's.
+fn names_object(pattern: &str, name: &str) -> bool {
+    let (file, member) = match name.strip_suffix(')').and_then(|name| name.split_once('(')) {
+        Some((archive, member)) => (archive, Some(member)),
+        None => (name, None),
+    };
+    let file_name = Path::new(file).file_name().and_then(|name| name.to_str());
+    file == pattern || file_name == Some(pattern) || member == Some(pattern)
+}
+
+impl LinkScript {
+    pub fn read(path: &Path) -> Result<LinkScript, LinkerErr> {
+        let name = path.display().to_string();
+        let content = fs::read_to_string(path)
+            .map_err(|err| LinkerErr::InvalidScript(name.clone(), err.to_string()))?;
+        let script: LinkScript = toml::from_str(&content)
+            .map_err(|err| LinkerErr::InvalidScript(name.clone(), err.message().to_string()))?;
+
+        let mut errors = vec![];
+        for (i, region) in script.regions.iter().enumerate() {
+            for other in &script.regions[i + 1..] {
+                if region.name == other.name {
+                    errors.push(format!("region '{}' is defined twice", region.name));
+                } else if region.start < other.start + other.size
+                    && other.start < region.start + region.size
+                {
+                    errors.push(format!(
+                        "regions '{}' and '{}' overlap",
+                        region.name, other.name
+                    ));
+                }
+            }
+        }
+        for placement in &script.placements {
+            if !script
+                .regions
+                .iter()
+                .any(|region| region.name == placement.region)
+            {
+                errors.push(format!(
+                    "section '{}' is placed in an undefined region '{}'",
+                    placement.section, placement.region
+                ));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(script),
+            false => Err(LinkerErr::InvalidScript(name, errors.join(", "))),
+        }
+    }
+
+    /// Where each object's sections start, keyed by the index of the object
+    /// and of the section. Every section goes to the first placement that
+    /// names it and its object, and each region is filled from its start in
+    /// the order of the placements and then of the objects.
+    pub fn place(
+        &self,
+        objects: &[(String, Object)],
+    ) -> Result<HashMap<(usize, usize), usize>, Vec<LinkerErr>> {
+        let mut errors = vec![];
+        let mut chosen = vec![vec![]; self.placements.len()];
+        for (i, (name, object)) in objects.iter().enumerate() {
+            for (j, section) in object.sections.iter().enumerate() {
+                let placement = self.placements.iter().position(|placement| {
+                    placement.section == section.name
+                        && (placement.objects.is_empty()
+                            || placement
+                                .objects
+                                .iter()
+                                .any(|pattern| names_object(pattern, name)))
+                });
+                match placement {
+                    Some(placement) => chosen[placement].push((i, j)),
+                    None => errors.push(LinkerErr::UnplacedSection(
+                        section.name.clone(),
+                        name.clone(),
+                    )),
+                }
+            }
+        }
+        for placement in &self.placements {
+            let exists = objects.iter().any(|(_, object)| {
+                object
+                    .sections
+                    .iter()
+                    .any(|section| section.name == placement.section)
+            });
+            if !exists {
+                errors.push(LinkerErr::UnknownSection(placement.section.clone()));
+            }
+        }
+
+        let mut placed = HashMap::new();
+        for region in &self.regions {
+            let mut address = region.start;
+            for (placement, sections) in self.placements.iter().zip(&chosen) {
+                if placement.region != region.name {
+                    continue;
+                }
+                for (i, j) in sections {
+                    placed.insert((*i, *j), address);
+                    address += objects[*i].1.sections[*j].data.len();
+                }
+            }
+            let end = region.start + region.size;
+            if address > end {
+                errors.push(LinkerErr::RegionOverflow(
+                    region.name.clone(),
+                    address - end,
+                ));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(placed),
+            false => Err(errors),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::{Section, OBJECT_VERSION};
+
+    fn object(sections: &[(&str, usize)]) -> Object {
+        Object {
+            version: OBJECT_VERSION,
+            source: String::new(),
+            sections: sections
+                .iter()
+                .map(|(name, size)| Section {
+                    name: name.to_string(),
+                    data: vec![0; *size],
+                })
+                .collect(),
+            symbols: vec![],
+            imports: vec![],
+            relocations: vec![],
+        }
+    }
+
+    #[test]
+    fn test_place() {
+        let script: LinkScript = toml::from_str(
+            r#"
+            [[region]]
+            name = "rom"
+            start = 0x0000
+            size = 0x10
+
+            [[region]]
+            name = "ram"
+            start = 0x8000
+            size = 4
+
+            [[place]]
+            section = "data"
+            region = "rom"
+            objects = ["lib.a"]
+
+            [[place]]
+            section = "code"
+            region = "rom"
+
+            [[place]]
+            section = "data"
+            region = "ram"
+            "#,
+        )
+        .unwrap();
+        let objects = vec![
+            (
+                "build/main.obj".to_string(),
+                object(&[("code", 6), ("data", 2)]),
+            ),
+            ("lib.a(table.obj)".to_string(), object(&[("data", 3)])),
+        ];
+
+        let placed = script.place(&objects).unwrap();
+        assert_eq!(placed[&(1, 0)], 0);
+        assert_eq!(placed[&(0, 0)], 3);
+        assert_eq!(placed[&(0, 1)], 0x8000);
+
+        let objects = vec![("main.obj".to_string(), object(&[("data", 6)]))];
+        let errors = script.place(&objects).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                LinkerErr::UnknownSection("code".to_string()),
+                LinkerErr::RegionOverflow("ram".to_string(), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    checker::{CheckedLine, CheckedLineCode},
+    disassembler::describe_displacement,
+    error::FileContext,
+    provenance::Provenance,
+    reachability::line_displacement,
+};
+
+/// Bytes shown next to a source line, longer data continues on extra rows.
+const BYTES_PER_ROW: usize = 8;
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A classic assembler listing: what built it, then every line with its
+/// address, the bytes it emitted and its source text, followed by the labels
+/// sorted by address. Relative jumps also show their signed offset and the
+/// address, or label, they land on. `addresses` is the layout of
+/// `checked_lines`.
+pub fn listing(
+    checked_lines: &[CheckedLine],
+    addresses: &[usize],
+    source: &FileContext,
+    labels: &HashMap<&str, usize>,
+    provenance: &Provenance,
+) -> String {
+    let mut listing = provenance.header();
+
+    for (checked_line, address) in checked_lines.iter().zip(addresses) {
+        let address = match checked_line.code {
+            CheckedLineCode::Org(target) => target,
+            _ => *address,
+        };
+        let text = checked_line
+            .line
+            .first_token()
+            .map(|token| source.source_statement(&token.span))
+            .unwrap_or_default();
+        let text = match line_displacement(checked_line, address) {
+            Some((offset, target)) => {
+                let name = labels
+                    .iter()
+                    .filter(|(_, address)| **address == target)
+                    .map(|(label, _)| *label)
+                    .min();
+                let displacement = describe_displacement(offset, target, name);
+                format!("{}  ({})", text, displacement)
+            }
+            None => text.to_string(),
+        };
+
+        let bytes = checked_line.code.bytes();
+        let mut rows = bytes.chunks(BYTES_PER_ROW);
+        let first = format_bytes(rows.next().unwrap_or_default());
+        listing += &format!("{:04x}  {:<23}  {}\n", address, first, text);
+        for (i, row) in rows.enumerate() {
+            let row_address = address + (i + 1) * BYTES_PER_ROW;
+            listing += &format!("{:04x}  {}\n", row_address, format_bytes(row));
+        }
+    }
+
+    let mut labels = labels.iter().collect::<Vec<_>>();
+    labels.sort_by_key(|(label, address)| (**address, **label));
+    if !labels.is_empty() {
+        listing += "\nLabels:\n";
+    }
+    for (label, address) in labels {
+        listing += &format!("{:04x}  {}\n", address, label);
+    }
+
+    listing
+}
+
+/// Where the bytes at each address come from, `file:line: source` for
+/// every line that emits any, for `--annotate`.
+pub fn source_annotations(
+    checked_lines: &[CheckedLine],
+    addresses: &[usize],
+    source: &FileContext,
+) -> BTreeMap<usize, String> {
+    let mut annotations = BTreeMap::new();
+    for (checked_line, address) in checked_lines.iter().zip(addresses) {
+        if checked_line.code.bytes().is_empty() {
+            continue;
+        }
+        let Some(token) = checked_line.line.first_token() else {
+            continue;
+        };
+        let annotation = format!(
+            "{}:{}: {}",
+            source.file_name(&token.span),
+            token.span.line + 1,
+            source.source_statement(&token.span).trim()
+        );
+        annotations.insert(*address, annotation);
+    }
+    annotations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[test]
+    fn test_listing() {
+        let config = Config::builtin();
+        let text = "start: MOV A 1\nJMPREL 0xfc\ndata: .byte 1 2 3 4 5 6 7 8 9\nHALT\n";
+        let source = text.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        let provenance = Provenance {
+            assembler: "0.1.0".to_string(),
+            config: "0123456789abcdef".to_string(),
+            command: "assembler -l prog.as".to_string(),
+        };
+
+        let listing = listing(
+            &checked_lines,
+            &addresses,
+            &FileContext::new(None, text),
+            &labels,
+            &provenance,
+        );
+        assert_eq!(
+            listing,
+            "Assembler: 0.1.0\n\
+             Config:    0123456789abcdef\n\
+             Command:   assembler -l prog.as\n\
+             \n\
+             0000  11 01                    start: MOV A 1\n\
+             0002  ae fc                    JMPREL 0xfc  (-4 -> start)\n\
+             0004  01 02 03 04 05 06 07 08  data: .byte 1 2 3 4 5 6 7 8 9\n\
+             000c  09\n\
+             000d  d8                       HALT\n\
+             \n\
+             Labels:\n\
+             0000  start\n\
+             0004  data\n"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::{fs, path::Path};
+
+use thiserror::Error;
+
+use crate::config::MAX_ADDRESS_WIDTH;
+
+#[derive(Error, Debug)]
+pub enum LoadErr {
+    #[error("Couldn't read the image '{0}': {1}.")]
+    Read(String, String),
+    #[error("Malformed record on line {1} of '{0}'.")]
+    InvalidRecord(String, usize),
+    #[error("Checksum mismatch on line {1} of '{0}'.")]
+    Checksum(String, usize),
+    #[error("Record on line {1} of '{0}' ends at {2:#x}, past the widest address space.")]
+    AddressTooLarge(String, usize, usize),
+}
+
+/// How an image file holds its bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Bin,
+    /// Intel HEX, as written by `--format ihex`.
+    Ihex,
+    /// Motorola S-records.
+    Srec,
+}
+
+impl ImageFormat {
+    /// The format named by the extension of `path`, or else the one the
+    /// content looks like: text where every line is a `:` record is Intel
+    /// HEX and one where every line is an `S<digit>` record S-records.
+    pub fn detect(path: &Path, content: &[u8]) -> ImageFormat {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("hex" | "ihex" | "ihx") => return ImageFormat::Ihex,
+            Some("srec" | "s19" | "s28" | "s37" | "mot") => return ImageFormat::Srec,
+            _ => {}
+        }
+
+        let Ok(text) = std::str::from_utf8(content) else {
+            return ImageFormat::Bin;
+        };
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let Some(first) = lines.next() else {
+            return ImageFormat::Bin;
+        };
+        let is_srec = |line: &str| {
+            let mut chars = line.chars();
+            chars.next() == Some('S') && chars.next().is_some_and(|kind| kind.is_ascii_digit())
+        };
+        let mut lines = std::iter::once(first).chain(lines);
+        if first.starts_with(':') && lines.all(|line| line.starts_with(':')) {
+            ImageFormat::Ihex
+        } else if is_srec(first) && lines.all(is_srec) {
+            ImageFormat::Srec
+        } else {
+            ImageFormat::Bin
+        }
+    }
+}
+
+/// Reads an image as raw bytes, Intel HEX or S-records, see
+/// [`ImageFormat::detect`]. Bytes no record covers are zero.
+pub fn read_image(path: &Path) -> Result<Vec<u8>, LoadErr> {
+    let name = path.display().to_string();
+    let content = fs::read(path).map_err(|err| LoadErr::Read(name.clone(), err.to_string()))?;
+    match ImageFormat::detect(path, &content) {
+        ImageFormat::Bin => Ok(content),
+        format => parse_records(&String::from_utf8_lossy(&content), format, &name),
+    }
+}
+
+/// The bytes after the one or two character record mark, with the sum of
+/// all of them, or `None` if they aren't hex digit pairs.
+fn record_bytes(hex: &str) -> Option<(Vec<u8>, u8)> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    Some((bytes, sum))
+}
+
+fn parse_records(text: &str, format: ImageFormat, name: &str) -> Result<Vec<u8>, LoadErr> {
+    let mut image = vec![];
+    // Added to Intel HEX record addresses by extended address records.
+    let mut base = 0;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || LoadErr::InvalidRecord(name.to_string(), number + 1);
+        let (address, data) = match format {
+            ImageFormat::Ihex => {
+                let (bytes, sum) = line
+                    .strip_prefix(':')
+                    .and_then(record_bytes)
+                    .filter(|(bytes, _)| bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5)
+                    .ok_or_else(invalid)?;
+                if sum != 0 {
+                    return Err(LoadErr::Checksum(name.to_string(), number + 1));
+                }
+                let data = &bytes[4..bytes.len() - 1];
+                let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+                match (bytes[3], data) {
+                    (0x00, _) => (base + address, data.to_vec()),
+                    (0x01, _) => break,
+                    (0x02, [high, low]) => {
+                        base = (u16::from_be_bytes([*high, *low]) as usize) << 4;
+                        continue;
+                    }
+                    (0x04, [high, low]) => {
+                        base = (u16::from_be_bytes([*high, *low]) as usize) << 16;
+                        continue;
+                    }
+                    // Start addresses.
+                    (0x03 | 0x05, _) => continue,
+                    _ => return Err(invalid()),
+                }
+            }
+            ImageFormat::Srec => {
+                let kind = line.get(1..2).ok_or_else(invalid)?;
+                let (bytes, sum) = line
+                    .get(2..)
+                    .and_then(record_bytes)
+                    .filter(|(bytes, _)| bytes.len() >= 2 && bytes.len() == bytes[0] as usize + 1)
+                    .ok_or_else(invalid)?;
+                if sum != 0xff {
+                    return Err(LoadErr::Checksum(name.to_string(), number + 1));
+                }
+                let address_bytes = match kind {
+                    "1" => 2,
+                    "2" => 3,
+                    "3" => 4,
+                    // The header, record counts and start addresses.
+                    "0" | "5" | "6" | "7" | "8" | "9" => continue,
+                    _ => return Err(invalid()),
+                };
+                let Some(data) = bytes.get(1 + address_bytes..bytes.len() - 1) else {
+                    return Err(invalid());
+                };
+                let address = bytes[1..1 + address_bytes]
+                    .iter()
+                    .fold(0, |address, byte| address << 8 | *byte as usize);
+                (address, data.to_vec())
+            }
+            ImageFormat::Bin => unreachable!("raw images have no records"),
+        };
+
+        let end = address + data.len();
+        if end > 1 << MAX_ADDRESS_WIDTH {
+            return Err(LoadErr::AddressTooLarge(name.to_string(), number + 1, end));
+        }
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[address..end].copy_from_slice(&data);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emitter::{Emitter, IntelHex};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_read_records() {
+        let image = (0..40).collect::<Vec<u8>>();
+        let mut hex = vec![];
+        IntelHex.emit(&image, &mut hex).unwrap();
+        let path = PathBuf::from("dump.txt");
+        assert_eq!(ImageFormat::detect(&path, &hex), ImageFormat::Ihex);
+        let text = String::from_utf8(hex).unwrap();
+        assert_eq!(
+            parse_records(&text, ImageFormat::Ihex, "dump").unwrap(),
+            image
+        );
+
+        let srec = "S00600004844521B\nS1060002112AD8E4\nS9030000FC\n";
+        assert_eq!(
+            ImageFormat::detect(&path, srec.as_bytes()),
+            ImageFormat::Srec
+        );
+        assert_eq!(
+            parse_records(srec, ImageFormat::Srec, "dump").unwrap(),
+            [0, 0, 0x11, 0x2a, 0xd8]
+        );
+        assert!(matches!(
+            parse_records("S1060002112AD8E3\n", ImageFormat::Srec, "dump"),
+            Err(LoadErr::Checksum(_, 1))
+        ));
+
+        assert_eq!(
+            ImageFormat::detect(&PathBuf::from("rom.S19"), b""),
+            ImageFormat::Srec
+        );
+        assert_eq!(ImageFormat::detect(&path, &[0x11, 0x2a]), ImageFormat::Bin);
+    }
+}
@@ -0,0 +1,418 @@
+use std::{collections::HashMap, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    error::{Error, WithSpan},
+    i18n::Message,
+    limits::Limits,
+    token::{split_lines, Expansion, Span, Token, TokenType},
+};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum MacroErr {
+    #[error("Expected a macro name after '.macro'.")]
+    MissingName,
+    #[error("Macro parameter '{0}' should be a plain identifier.")]
+    InvalidParameter(String),
+    #[error("Macro '{0}' is defined more than once.")]
+    DuplicateMacro(String),
+    #[error("Macro '{0}' is defined inside another macro.")]
+    NestedDefinition(String),
+    #[error("Macro '{0}' is missing its '.endm'.")]
+    Unterminated(String),
+    #[error("'.endm' without a matching '.macro'.")]
+    UnexpectedEnd,
+    #[error("Macro '{0}' takes {1} argument(s), found {2}.")]
+    ArgumentCount(String, usize, usize),
+}
+
+#[derive(Debug)]
+struct Macro {
+    name: String,
+    params: Vec<String>,
+    body: Vec<Token>,
+    definition: Span,
+}
+
+fn identifier(token: &Token) -> Option<&str> {
+    match &token.token_type {
+        TokenType::Mnemonic(_) | TokenType::LabelRef(_) => Some(&token.content),
+        _ => None,
+    }
+}
+
+fn starts_with(line: &[Token], token_type: TokenType) -> bool {
+    line.first()
+        .is_some_and(|token| token.token_type == token_type)
+}
+
+/// Splits the `.macro` / `.endm` blocks out of the token stream, returning the
+/// macros and the lines outside of them.
+fn collect_macros(tokens: &[Token]) -> (HashMap<String, Macro>, Vec<&[Token]>, Vec<Error>) {
+    let mut macros: HashMap<String, Macro> = HashMap::new();
+    let mut lines = vec![];
+    let mut errors = vec![];
+
+    let mut source_lines = split_lines(tokens);
+    while let Some(line) = source_lines.next() {
+        if starts_with(line, TokenType::MacroEnd) {
+            errors.push(MacroErr::UnexpectedEnd.with_span(line[0].span.clone()));
+            continue;
+        }
+        if !starts_with(line, TokenType::MacroStart) {
+            lines.push(line);
+            continue;
+        }
+
+        let header = &line[0];
+        let Some(name) = line.get(1).and_then(identifier) else {
+            errors.push(MacroErr::MissingName.with_span(header.span.clone()));
+            continue;
+        };
+        let definition = Span::merge_all([&header.span, &line[1].span]).unwrap();
+
+        let mut params = vec![];
+        for param in &line[2..] {
+            match identifier(param) {
+                Some(param) => params.push(param.to_string()),
+                None => errors.push(
+                    MacroErr::InvalidParameter(param.content.clone())
+                        .with_span(param.span.clone()),
+                ),
+            }
+        }
+
+        let mut body = vec![];
+        let mut terminated = false;
+        for line in source_lines.by_ref() {
+            if starts_with(line, TokenType::MacroEnd) {
+                terminated = true;
+                break;
+            }
+            if starts_with(line, TokenType::MacroStart) {
+                let nested = line.get(1).map_or("", |token| &token.content);
+                errors.push(
+                    MacroErr::NestedDefinition(nested.to_string())
+                        .with_span(line[0].span.clone())
+                        .with_label(
+                            definition.clone(),
+                            Message::new("note.enclosing_macro_defined", vec![]),
+                        ),
+                );
+                continue;
+            }
+            body.extend_from_slice(line);
+        }
+        if !terminated {
+            errors.push(MacroErr::Unterminated(name.to_string()).with_span(definition));
+            continue;
+        }
+
+        if let Some(first) = macros.get(name) {
+            errors.push(
+                MacroErr::DuplicateMacro(name.to_string())
+                    .with_span(definition)
+                    .with_label(
+                        first.definition.clone(),
+                        Message::new("note.first_defined", vec![]),
+                    ),
+            );
+            continue;
+        }
+
+        macros.insert(
+            name.to_string(),
+            Macro {
+                name: name.to_string(),
+                params,
+                body,
+                definition,
+            },
+        );
+    }
+
+    (macros, lines, errors)
+}
+
+/// Groups the operands of an invocation, keeping `[x]` together as one.
+pub(crate) fn split_arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    let mut arguments = vec![];
+    let mut rest = tokens;
+    while !rest.is_empty() {
+        let len = match rest[0].token_type {
+            TokenType::LeftSquareBracket => rest
+                .iter()
+                .position(|token| token.token_type == TokenType::RightSquareBracket)
+                .map_or(rest.len(), |end| end + 1),
+            _ => 1,
+        };
+        let (argument, tail) = rest.split_at(len);
+        arguments.push(argument);
+        rest = tail;
+    }
+    arguments
+}
+
+/// The argument inherits the trivia of the parameter it replaces, so the
+/// line structure of the macro body is kept.
+pub(crate) fn substitute(param: &Token, argument: &[Token], output: &mut Vec<Token>) {
+    let start = output.len();
+    output.extend_from_slice(argument);
+    if let Some(first) = output.get_mut(start) {
+        first.leading_trivia = param.leading_trivia.clone();
+    }
+    if let Some(last) = output.last_mut() {
+        last.trailing_trivia = param.trailing_trivia.clone();
+    }
+}
+
+struct Expander<'a> {
+    macros: &'a HashMap<String, Macro>,
+    limits: &'a Limits,
+    expanded_tokens: usize,
+}
+
+impl Expander<'_> {
+    fn expand_line(
+        &mut self,
+        line: &[Token],
+        depth: usize,
+        output: &mut Vec<Token>,
+    ) -> Result<(), Error> {
+        let labels = line
+            .iter()
+            .take_while(|token| matches!(token.token_type, TokenType::Label(_)))
+            .count();
+        let (labels, rest) = line.split_at(labels);
+
+        let invoked = match rest.first() {
+            Some(token @ Token {
+                token_type: TokenType::Mnemonic(mnemonic),
+                ..
+            }) => self
+                .macros
+                .get(mnemonic.name())
+                .map(|invoked| (token, invoked)),
+            _ => None,
+        };
+        let Some((invocation, invoked)) = invoked else {
+            output.extend_from_slice(line);
+            return Ok(());
+        };
+        output.extend_from_slice(labels);
+
+        let arguments = split_arguments(&rest[1..]);
+        if arguments.len() != invoked.params.len() {
+            return Err(MacroErr::ArgumentCount(
+                invoked.name.clone(),
+                invoked.params.len(),
+                arguments.len(),
+            )
+            .with_span(invocation.span.clone())
+            .with_label(
+                invoked.definition.clone(),
+                Message::new("note.macro_defined", vec![]),
+            ));
+        }
+
+        self.limits
+            .check_macro_depth(depth + 1, &invoked.name)
+            .map_err(|err| err.with_span(invocation.span.clone()))?;
+
+        // Arguments keep the spans of the invocation they were written in.
+        let expansion = Arc::new(Expansion {
+            macro_name: invoked.name.clone(),
+            invocation: invocation.span.clone(),
+            definition: invoked.definition.clone(),
+        });
+        let mut body = vec![];
+        for token in &invoked.body {
+            let argument = identifier(token)
+                .and_then(|name| invoked.params.iter().position(|param| param == name))
+                .map(|i| arguments[i]);
+            match argument {
+                Some(argument) => substitute(token, argument, &mut body),
+                None => {
+                    let mut token = token.clone();
+                    token.span.expansion = Some(expansion.clone());
+                    body.push(token);
+                }
+            }
+        }
+
+        self.expanded_tokens += body.len();
+        self.limits
+            .check_expansion_size(self.expanded_tokens)
+            .map_err(|err| err.with_span(invocation.span.clone()))?;
+
+        for body_line in split_lines(&body) {
+            self.expand_line(body_line, depth + 1, output)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Removes macro definitions from the token stream and replaces every
+/// invocation with the macro's body, arguments substituted for parameters.
+pub fn expand_macros(tokens: &[Token], limits: &Limits) -> Result<Vec<Token>, Vec<Error>> {
+    let (macros, lines, mut errors) = collect_macros(tokens);
+
+    let mut expander = Expander {
+        macros: &macros,
+        limits,
+        expanded_tokens: 0,
+    };
+    let mut output = vec![];
+    for line in lines {
+        if let Err(err) = expander.expand_line(line, 0, &mut output) {
+            errors.push(err);
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(output);
+    }
+    Err(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{ErrorKind, FileContext},
+        lexer::Lexer,
+        limits::LimitErr,
+        parser::parse,
+    };
+
+    fn lex(source: &str) -> Vec<Token> {
+        let chars = source.chars().collect::<Vec<_>>();
+        Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap()
+    }
+
+    fn expand_with(source: &str, limits: &Limits) -> Result<Vec<Token>, Vec<Error>> {
+        expand_macros(&lex(source), limits)
+    }
+
+    fn expand(source: &str) -> Result<Vec<Token>, Vec<Error>> {
+        expand_with(source, &Limits::default())
+    }
+
+    fn messages(errors: Vec<Error>) -> Vec<String> {
+        errors.iter().map(ToString::to_string).collect()
+    }
+
+    fn contents(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|token| token.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_collect_macros() {
+        let tokens = lex(".macro load r v\n  MOV r v\n.endm\nHALT\n");
+        let (macros, lines, errors) = collect_macros(&tokens);
+        assert!(errors.is_empty());
+        assert_eq!(macros["load"].params, ["r", "v"]);
+        assert_eq!(contents(&macros["load"].body), ["MOV", "r", "v"]);
+        assert_eq!(lines.len(), 1);
+
+        let errors = |source: &str| messages(expand(source).unwrap_err());
+        assert_eq!(
+            errors(".macro\n.endm\n"),
+            [
+                "Expected a macro name after '.macro'.",
+                "'.endm' without a matching '.macro'."
+            ]
+        );
+        assert_eq!(
+            errors(".macro outer\n.macro inner\n.endm\n"),
+            ["Macro 'inner' is defined inside another macro."]
+        );
+        assert_eq!(
+            errors("HALT\n.macro open\nHALT\n"),
+            ["Macro 'open' is missing its '.endm'."]
+        );
+
+        let duplicate = expand(".macro m\n.endm\n.macro m\n.endm\n").unwrap_err();
+        assert_eq!(duplicate[0].span().line, 2);
+        assert_eq!(duplicate[0].labels()[0].span.line, 0);
+        assert_eq!(
+            messages(duplicate),
+            ["Macro 'm' is defined more than once."]
+        );
+    }
+
+    #[test]
+    fn test_split_arguments() {
+        let tokens = lex("A [0x10] #x 5");
+        let lengths = split_arguments(&tokens)
+            .iter()
+            .map(|argument| argument.len())
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, [1, 3, 1, 1]);
+        assert!(split_arguments(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let source = ".macro load r v\n  MOV r v\n.endm\nstart: load A [0x10]\nHALT\n";
+        let tokens = expand(source).unwrap();
+        assert_eq!(
+            contents(&tokens),
+            ["start", "MOV", "A", "[", "0x10", "]", "HALT"]
+        );
+        // Arguments keep their own spans, the rest points into the body.
+        assert_eq!((tokens[1].span.line, tokens[2].span.line), (1, 3));
+        assert_eq!(split_lines(&tokens).count(), 2);
+
+        let errors = expand(".macro load r v\n  MOV r v\n.endm\nload A\n").unwrap_err();
+        assert_eq!(
+            messages(errors),
+            ["Macro 'load' takes 2 argument(s), found 1."]
+        );
+    }
+
+    #[test]
+    fn test_expansion_limits() {
+        let limits = Limits {
+            max_macro_depth: 3,
+            max_expansion_tokens: 4,
+            ..Limits::default()
+        };
+        let errors = expand_with(".macro r\n  r\n.endm\nr\n", &limits).unwrap_err();
+        assert!(matches!(
+            errors[0].kind(),
+            ErrorKind::LimitErr(LimitErr::MacroDepth(3, _))
+        ));
+        assert_eq!(errors[0].backtrace().count(), 3);
+
+        let source = ".macro two\n  HALT\n  HALT\n.endm\ntwo\ntwo\ntwo\n";
+        let errors = expand_with(source, &limits).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span().line, 6);
+        assert!(matches!(
+            errors[0].kind(),
+            ErrorKind::LimitErr(LimitErr::ExpansionSize(4))
+        ));
+    }
+
+    #[test]
+    fn test_errors_in_expansions_have_a_backtrace() {
+        let source =
+            ".macro load r\n  MOV A r\n  MOV A ]\n.endm\n.macro twice\n  load 1\n.endm\ntwice\n";
+        let tokens = expand(source).unwrap();
+        let errors = parse(&tokens).unwrap_err();
+        let backtrace = errors[0]
+            .backtrace()
+            .map(|frame| (frame.macro_name.as_str(), frame.invocation.line))
+            .collect::<Vec<_>>();
+        assert_eq!(backtrace, [("load", 5), ("twice", 7)]);
+
+        let context = FileContext::new(None, source);
+        let error = errors.into_iter().next().unwrap();
+        let rendered = error.with_ctx(&context).to_string();
+        assert!(rendered.contains("in this expansion of macro 'load'"));
+        assert!(rendered.contains("in this expansion of macro 'twice'"));
+    }
+}
@@ -1,39 +1,170 @@
-use std::{error::Error, io::read_to_string, io::Write, process::exit};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{read_to_string, stdin, stdout, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
 
 use assembler::{
-    checker::{check_semantics, CheckedLine, CheckedLineCode},
-    cmdline_args::Args,
-    config::{print_config, Config},
-    lexer::Lexer,
-    parser::parse,
-    resolver::get_resolved_labels,
+    archive::{build_archive, select_objects},
+    checker::{build_image, CheckedLine},
+    cmdline_args::{create_output, Args, Command, RunArgs},
+    config::{find_config, Config, ConfigError, DEFAULT_CONFIG},
+    defines::{inject_defines, read_defines_from_file, truncation_warning},
+    difftest::{emulator_trace, first_divergence, simulator_trace},
+    disassembler::{disassemble, disassemble_object, disassemble_reachable, read_symbols},
+    emitter::{Binary, Emitter},
+    emulator::{
+        check_address_width, run_with, Console, EmulatorErr, Machine, RunOptions, Stop, Timing,
+    },
+    error::{paint, sort_errors, Error, FileContext},
+    examples::{find_example, EXAMPLES},
+    explain::explain_encoding,
+    gc::gc_sections,
+    hash::{fnv1a, normalized_input},
+    i18n::{text, translate, Lang, Localize},
+    include::expand_includes,
+    lexer::normalize_source,
+    linkage::imported_names,
+    linker::{link, read_input, read_object, Layout, SectionBase},
+    linkscript::LinkScript,
+    listing::{listing, source_annotations},
+    loader::read_image,
+    manifest::{run_post_build, Manifest, Project},
+    object::build_object,
+    output::{split_output_paths, SizeExceeded},
+    pipeline,
+    prelude::Assembler,
+    profile::Profile,
+    progress::{Progress, Timings},
+    provenance::Provenance,
+    reachability::find_unreachable,
+    repl::{run_script, Repl},
+    resolver::resolve_labels,
+    scaffold::{create_project, MANIFEST_FILE_NAME},
+    segments::{segments, SegmentErr},
+    snapshot::{load_snapshot, save_snapshot},
+    selftest::run_selftest,
+    serve::serve,
+    symbols::{list_symbols, render_symbols, size_report, symbols},
+    teach::teach,
+    trace::{render_trace, TraceStep},
 };
 use clap::Parser;
-use owo_colors::OwoColorize;
+use owo_colors::{Stream, Style};
+
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+static PRINT_SUMMARY: AtomicBool = AtomicBool::new(false);
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+fn lang() -> Lang {
+    LANG.get().copied().unwrap_or_default()
+}
+
+/// A driver message in the language of the run.
+fn note(key: &str, args: &[String]) -> String {
+    text(lang(), key, args)
+}
 
 trait ConsumeError<T, E> {
     fn consume_error(self) -> T;
 }
 
-fn print_error<E: Error + std::fmt::Display>(error: E) {
+fn print_error<E: Localize>(error: E) {
+    ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
     eprintln!(
         "{} {} {}",
-        "assembly:".bold(),
-        "fatal error:".red().bold(),
-        error
+        paint("assembly:", Style::new().bold(), Stream::Stderr),
+        paint(note("fatal_error", &[]), Style::new().red().bold(), Stream::Stderr),
+        error.localize(lang())
     );
 }
 
+fn print_warning(warning: impl std::fmt::Display) {
+    WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
+    eprintln!(
+        "{} {} {}",
+        paint("assembly:", Style::new().bold(), Stream::Stderr),
+        paint(note("warning", &[]), Style::new().yellow().bold(), Stream::Stderr),
+        warning
+    );
+}
+
+/// Warnings about the source itself, e.g. non-ASCII characters, quoted
+/// like errors.
+fn print_source_warnings(warnings: Vec<Error>, file_context: &FileContext) {
+    for warning in sort_errors(warnings) {
+        print_warning(warning.with_ctx(file_context).localize(lang()));
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("{} {}", count, noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+fn print_summary(bytes_emitted: usize) {
+    if !PRINT_SUMMARY.load(Ordering::Relaxed) {
+        return;
+    }
+    let warnings = WARNING_COUNT.load(Ordering::Relaxed);
+    let errors = ERROR_COUNT.load(Ordering::Relaxed);
+    let counts = [warnings, errors, bytes_emitted].map(|count| count.to_string());
+    match translate(lang(), "summary", &counts) {
+        Some(summary) => eprintln!("{}", summary),
+        None => eprintln!(
+            "{}, {}, {} emitted",
+            plural(warnings, "warning"),
+            plural(errors, "error"),
+            plural(bytes_emitted, "byte")
+        ),
+    }
+}
+
+fn fail() -> ! {
+    print_summary(0);
+    exit(1);
+}
+
+/// Prints spanned errors sorted by position, under a heading per file when
+/// they come from more than one, and exits.
+fn fail_with_errors(errors: Vec<Error>, file_context: &FileContext) -> ! {
+    let errors = sort_errors(errors);
+    let grouped = errors
+        .windows(2)
+        .any(|pair| pair[0].span().file != pair[1].span().file);
+
+    let mut current_file = None;
+    for err in errors {
+        let file = err.span().file;
+        if grouped && current_file != Some(file) {
+            current_file = Some(file);
+            let file_name = format!("{}:", file_context.file_name(err.span()));
+            eprintln!("{}", paint(file_name, Style::new().underline(), Stream::Stderr));
+        }
+        print_error(err.with_ctx(file_context));
+    }
+    fail();
+}
+
 impl<T, E> ConsumeError<T, E> for Result<T, E>
 where
-    E: Error + std::fmt::Display,
+    E: Localize,
 {
     fn consume_error(self) -> T {
         match self {
             Ok(value) => value,
             Err(err) => {
                 print_error(err);
-                exit(1);
+                fail();
             }
         }
     }
@@ -45,7 +176,7 @@ trait ConsumeErrorVec<T, E> {
 
 impl<T, E> ConsumeErrorVec<T, E> for Result<Vec<T>, Vec<E>>
 where
-    E: Error + std::fmt::Display,
+    E: Localize,
 {
     fn consume_errors(self) -> Vec<T> {
         let errors = match self {
@@ -55,13 +186,13 @@ where
         for err in errors {
             print_error(err);
         }
-        exit(1);
+        fail();
     }
 }
 
 impl<T, E> ConsumeErrorVec<T, E> for Vec<Result<T, E>>
 where
-    E: Error + std::fmt::Display,
+    E: Localize,
 {
     fn consume_errors(self) -> Vec<T> {
         let mut ts = Vec::new();
@@ -76,7 +207,7 @@ where
             }
         }
         if found_error {
-            exit(1);
+            fail();
         }
         ts
     }
@@ -100,83 +231,786 @@ impl<T, E, I: Iterator<Item = Result<T, E>>> ResultSplit<T, E> for I {
     }
 }
 
-fn output_bytes_as_text(checked_lines: &[CheckedLine], output: &mut Box<dyn Write>) {
-    for checked_line in checked_lines {
-        match &checked_line.code {
-            assembler::checker::CheckedLineCode::Byte(bytes) => {
-                for byte in bytes {
-                    output
-                        .write_all(format!("{:08b}", byte).as_bytes())
-                        .unwrap();
-                    output.write_all(&[b'\n']).unwrap();
-                }
+/// The config file to use, `None` for the built-in instruction set. Says
+/// which one on stderr unless it was given with `--config`.
+fn config_file(args: &Args, input_file: Option<&Path>) -> Option<PathBuf> {
+    if let Some(config_file) = &args.config_file {
+        return Some(config_file.clone());
+    }
+    let tag = paint("config:", Style::new().bold(), Stream::Stderr);
+    match find_config(input_file) {
+        Ok(config_file) => {
+            let using = note("config_using", &[config_file.display().to_string()]);
+            eprintln!("{} {}", tag, using);
+            Some(config_file)
+        }
+        Err(err @ ConfigError::NotFound(..)) => {
+            let builtin = note("config_builtin", &[err.localize(lang())]);
+            eprintln!("{} {}", tag, builtin);
+            None
+        }
+        Err(err) => {
+            print_error(err);
+            fail()
+        }
+    }
+}
+
+fn load_config(args: &Args) -> Config {
+    let content = match config_file(args, args.input_file.as_deref()) {
+        Some(config_file) => fs::read_to_string(config_file)
+            .map_err(ConfigError::ReadFileError)
+            .consume_error(),
+        None => DEFAULT_CONFIG.to_string(),
+    };
+
+    Config::with_overrides(&content, &args.config_set).consume_error()
+}
+
+fn selftest(args: &Args) {
+    let config = load_config(args);
+    let report = run_selftest(&config);
+
+    for failure in &report.failures {
+        let tag = paint("selftest:", Style::new().red().bold(), Stream::Stderr);
+        eprintln!("{} {}", tag, failure);
+    }
+    let counts = [report.checked, report.skipped.len(), report.failures.len()];
+    eprintln!("{}", note("selftest_summary", &counts.map(|count| count.to_string())));
+
+    if !report.failures.is_empty() {
+        exit(1);
+    }
+}
+
+fn examples(args: &Args, name: Option<&str>) {
+    if let Some(name) = name {
+        match find_example(name) {
+            Some(example) => print!("{}", example.source),
+            None => {
+                let unknown = note("unknown_example", &[name.to_string()]);
+                let tag = paint("examples:", Style::new().red().bold(), Stream::Stderr);
+                eprintln!("{} {}", tag, unknown);
+                exit(1);
             }
-            assembler::checker::CheckedLineCode::Instruction {
-                mnemonic_code,
-                operand_codes,
-            } => {
-                // TODO: Find a sane way to do that
-                output
-                    .write_all(format!("{:08b}", mnemonic_code).as_bytes())
-                    .unwrap();
-                output.write_all(&[b'\n']).unwrap();
-                for operand_code in operand_codes {
-                    output
-                        .write_all(format!("{:08b}", operand_code).as_bytes())
-                        .unwrap();
-                    output.write_all(&[b'\n']).unwrap();
-                }
+        }
+        return;
+    }
+
+    let config = load_config(args);
+    let mut failed = false;
+    for example in EXAMPLES {
+        match example.assemble(&config) {
+            Ok(bytes) => println!(
+                "{:<12} {} {:>4} bytes  {}",
+                example.name,
+                paint("ok", Style::new().green().bold(), Stream::Stdout),
+                bytes.len(),
+                example.description
+            ),
+            Err(err) => {
+                failed = true;
+                let status = paint("failed", Style::new().red().bold(), Stream::Stdout);
+                println!("{:<12} {} {}", example.name, status, err);
             }
         }
     }
+
+    if failed {
+        exit(1);
+    }
 }
 
-fn output_to_binary(checked_lines: &[CheckedLine], output: &mut Box<dyn Write>) {
-    let mut output_bytes = Vec::new();
+fn difftest(args: &Args, programs: &[PathBuf], simulator: &str, max_steps: usize) {
+    let config = load_config(args);
+    let mut failed = false;
+    for program in programs {
+        let name = program.display();
+        let source = fs::read_to_string(program).consume_error();
+        let image = match Assembler::new(&config).assemble(&source) {
+            Ok(image) => image,
+            Err(diagnostics) => {
+                failed = true;
+                let status = paint("failed", Style::new().red().bold(), Stream::Stdout);
+                println!("{} {} {}", name, status, diagnostics);
+                continue;
+            }
+        };
+        let emulated = emulator_trace(&config, &image, max_steps).consume_error();
+        let simulated = simulator_trace(simulator, &image, max_steps).consume_error();
+        match first_divergence(&emulated, &simulated) {
+            None => {
+                let status = paint("ok", Style::new().green().bold(), Stream::Stdout);
+                println!("{} {} {} steps", name, status, emulated.len())
+            }
+            Some(divergence) => {
+                failed = true;
+                let status = paint("diverged", Style::new().red().bold(), Stream::Stdout);
+                println!("{} {} {}", name, status, divergence);
+            }
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
+}
 
-    for checked_line in checked_lines {
-        match &checked_line.code {
-            CheckedLineCode::Byte(bytes) => {
-                for byte in bytes {
-                    output_bytes.push(*byte);
-                }
+fn disasm(args: &Args, path: &Path, smart: bool, entries: &[usize], symbols: Option<&Path>) {
+    let config = load_config(args);
+    let symbols = match symbols {
+        Some(symbols) => read_symbols(symbols).consume_error(),
+        None => BTreeMap::new(),
+    };
+    let content = fs::read(path).consume_error();
+    // Objects are JSON, which no image of machine code is.
+    if serde_json::from_slice::<serde_json::Value>(&content).is_ok() {
+        let object = read_object(path).consume_error();
+        print!("{}", disassemble_object(&config.signatures(), &object));
+        return;
+    }
+    let image = read_image(path).consume_error();
+    if smart {
+        let entries = [&[0], entries].concat();
+        print!(
+            "{}",
+            disassemble_reachable(&config.signatures(), &image, &entries, &symbols)
+        );
+        return;
+    }
+    print!("{}", disassemble(&config.signatures(), &image, &symbols));
+}
+
+/// The assembled program `run` executes, for reports that point into its
+/// source.
+struct Program<'a> {
+    image: &'a [u8],
+    labels: &'a HashMap<&'a str, usize>,
+    checked_lines: &'a [CheckedLine<'a>],
+    addresses: &'a [usize],
+    source: &'a FileContext,
+}
+
+impl Program<'_> {
+    /// `file:line: source` of the instruction at `address`.
+    fn source_line(&self, address: usize) -> Option<String> {
+        let (checked_line, _) = self
+            .checked_lines
+            .iter()
+            .zip(self.addresses)
+            .find(|(checked_line, start)| {
+                **start == address && !checked_line.code.bytes().is_empty()
+            })?;
+        let span = &checked_line.line.first_token()?.span;
+        Some(format!(
+            "{}:{}: {}",
+            self.source.file_name(span),
+            span.line + 1,
+            self.source.source_statement(span).trim()
+        ))
+    }
+}
+
+fn run_program(config: &Config, program: &Program, run: &RunArgs) {
+    if let Err(err) = check_address_width(config) {
+        print_error(err);
+        fail();
+    }
+    let breakpoint = run.breakpoint.as_deref().map(|label| match program.labels.get(label) {
+        Some(&address) => u16::try_from(address).unwrap_or_else(|_| {
+            print_error(EmulatorErr::BreakpointOutOfRange(label.to_string(), address));
+            fail();
+        }),
+        None => {
+            print_error(EmulatorErr::UnknownLabel(label.to_string()));
+            fail();
+        }
+    });
+    let watchpoints = run
+        .watch
+        .iter()
+        .map(|target| target.resolve(program.labels).consume_error())
+        .collect();
+    let options = RunOptions {
+        max_cycles: run.max_cycles,
+        breakpoint,
+        watchpoints,
+        timing: Timing {
+            mode: run.mode,
+            wait_states: run.wait_states.clone(),
+        },
+    };
+
+    let mut machine = match &run.load_snapshot {
+        Some(path) => load_snapshot(path).consume_error(),
+        None => Machine::new(program.image),
+    };
+    if run.console() {
+        let input = match run.console_in.as_deref() {
+            Some(path) if path == Path::new("-") => {
+                let mut input = vec![];
+                stdin().read_to_end(&mut input).consume_error();
+                input
             }
-            CheckedLineCode::Instruction {
-                mnemonic_code,
-                operand_codes,
-            } => {
-                output_bytes.push(*mnemonic_code);
-                for operand_code in operand_codes {
-                    output_bytes.push(*operand_code);
-                }
+            Some(path) => fs::read(path).consume_error(),
+            None => vec![],
+        };
+        machine.console = Some(Console::new(&input));
+    }
+    let mut steps = vec![];
+    let mut profile = Profile::new();
+    let stop = run_with(config, &mut machine, &options, |machine, start| {
+        if run.trace.is_some() {
+            steps.push(TraceStep::new(machine, start));
+        }
+        if run.profile.is_some() {
+            profile.record(machine, start);
+        }
+    });
+    // Written even when execution failed, to show what led up to it.
+    if let Some(path) = &run.trace {
+        fs::write(path, render_trace(&steps, run.trace_format)).consume_error();
+    }
+    if let Some(path) = &run.save_snapshot {
+        save_snapshot(&machine, path).consume_error();
+    }
+    let console = machine.console.take().unwrap_or_default();
+    match run.console_out.as_deref() {
+        Some(path) if path == Path::new("-") => stdout().write_all(&console.output).consume_error(),
+        Some(path) => fs::write(path, &console.output).consume_error(),
+        None => {}
+    }
+    if let Some(path) = &run.profile {
+        let report = format!(
+            "{}\n{}",
+            profile.flat(program.labels),
+            profile.annotated(program.checked_lines, program.addresses, program.source)
+        );
+        fs::write(path, report).consume_error();
+    }
+    if let Ok(stop) = &stop {
+        println!("{} {}", paint("run:", Style::new().bold(), Stream::Stdout), stop);
+    }
+    if let Ok(Stop::Watchpoint(hit)) = &stop {
+        if let Some(line) = program.source_line(hit.pc as usize) {
+            println!("{}", line);
+        }
+    }
+    println!("{}", machine);
+    for range in &run.dump {
+        print!("{}", machine.dump(range));
+    }
+
+    if let Err(err) = stop {
+        print_error(err);
+        fail();
+    }
+    if let Some(path) = &run.expect_output {
+        let expected = fs::read(path).consume_error();
+        console
+            .expect_output(&expected, &path.display().to_string())
+            .consume_error();
+    }
+}
+
+fn link_objects(
+    paths: &[PathBuf],
+    output: Option<&Path>,
+    bases: &[SectionBase],
+    script: Option<&Path>,
+) {
+    let script = script.map(|script| LinkScript::read(script).consume_error());
+    let inputs = paths
+        .iter()
+        .map(|path| read_input(path).map(|input| (path.display().to_string(), input)))
+        .collect::<Vec<_>>()
+        .consume_errors();
+    let layout = match &script {
+        Some(script) => Layout::Script(script),
+        None => Layout::Bases(bases),
+    };
+    let image = link(&select_objects(inputs), layout).consume_errors();
+
+    let mut output = create_output(output).consume_error();
+    Binary.emit(&image, &mut output).consume_error();
+    output.flush().consume_error();
+}
+
+fn archive_objects(paths: &[PathBuf], output: &Path) {
+    let objects = paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().to_string(),
+            );
+            read_object(path).map(|object| (name, object))
+        })
+        .collect::<Vec<_>>()
+        .consume_errors();
+    let archive = build_archive(objects);
+
+    let json = serde_json::to_string_pretty(&archive).expect("archives serialize to JSON");
+    let mut output = create_output(Some(output)).consume_error();
+    writeln!(output, "{}", json).consume_error();
+    output.flush().consume_error();
+}
+
+fn new_project(args: &Args, path: &Path) {
+    let config = match config_file(args, None) {
+        Some(config_file) => fs::read_to_string(config_file).consume_error(),
+        None => DEFAULT_CONFIG.to_string(),
+    };
+    create_project(path, &config).consume_error();
+    eprintln!(
+        "{} project '{}'",
+        paint("Created", Style::new().green().bold(), Stream::Stderr),
+        path.display()
+    );
+}
+
+fn repl(args: &Args, run: bool, script: Option<&Path>) {
+    let config = load_config(args);
+    let mut repl = Repl::new(&config, run);
+    if let Some(script) = script {
+        let script = fs::read_to_string(script).consume_error();
+        let mut output = String::new();
+        let failures = run_script(&mut repl, &script, &mut output);
+        print!("{}", output);
+        if failures > 0 {
+            let failed = note("repl_failures", &[failures.to_string()]);
+            let tag = paint("repl:", Style::new().red().bold(), Stream::Stderr);
+            eprintln!("{} {}", tag, failed);
+            exit(1);
+        }
+        return;
+    }
+    eprintln!("{}", note("repl_intro", &[]));
+    for line in stdin().lines() {
+        let line = line.consume_error();
+        if line.trim() == ":quit" {
+            break;
+        }
+        match repl.eval(&line) {
+            Ok(reply) => print!("{}", reply),
+            Err(err) => {
+                let tag = paint("repl:", Style::new().red().bold(), Stream::Stderr);
+                eprintln!("{} {}", tag, err)
             }
         }
+        stdout().flush().consume_error();
     }
+}
 
-    output.write_all(&output_bytes).unwrap();
+/// Points the input and output of `args` at those of the project in
+/// `manifest`, and the config too unless one is given, and returns the
+/// project with the directory its paths are relative to.
+fn manifest_build(args: &mut Args, manifest: &Path) -> (Project, PathBuf) {
+    let project = Manifest::read(manifest).consume_error().project;
+    let dir = match manifest.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    args.input_file = Some(dir.join(&project.entry));
+    if args.config_file.is_none() {
+        args.config_file = project.config.as_ref().map(|config| dir.join(config));
+    }
+    let output = dir.join(&project.output);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).consume_error();
+    }
+    args.output_file = vec![output];
+    (project, dir)
 }
 
 fn main() -> Result<(), ()> {
-    let args = Args::parse();
-    let (mut input, mut output) = Args::get_read_write(&args).consume_error();
-    let config_file = args.config_file.unwrap_or("config.cfg".into());
+    let mut args = Args::parse();
+    if let Some(Command::Run(run)) = &args.command {
+        args.input_file = Some(run.program.clone());
+    }
+    LANG.get_or_init(|| args.lang.unwrap_or_else(Lang::from_env));
+    let build = match &args.command {
+        Some(Command::Build { manifest }) => {
+            let manifest = manifest.clone();
+            let manifest = manifest.as_deref().unwrap_or(Path::new(MANIFEST_FILE_NAME));
+            Some(manifest_build(&mut args, manifest))
+        }
+        _ => None,
+    };
+
+    match &args.command {
+        Some(Command::Selftest) => {
+            selftest(&args);
+            return Ok(());
+        }
+        Some(Command::Examples { name }) => {
+            examples(&args, name.as_deref());
+            return Ok(());
+        }
+        Some(Command::Disasm {
+            image,
+            smart,
+            entry,
+            symbols,
+        }) => {
+            disasm(&args, image, *smart, entry, symbols.as_deref());
+            return Ok(());
+        }
+        Some(Command::Difftest {
+            programs,
+            simulator,
+            max_steps,
+        }) => {
+            difftest(&args, programs, simulator, *max_steps);
+            return Ok(());
+        }
+        Some(Command::Link {
+            objects,
+            output,
+            base,
+            script,
+        }) => {
+            link_objects(objects, output.as_deref(), base, script.as_deref());
+            return Ok(());
+        }
+        Some(Command::Archive { objects, output }) => {
+            archive_objects(objects, output);
+            return Ok(());
+        }
+        Some(Command::New { path }) => {
+            new_project(&args, path);
+            return Ok(());
+        }
+        Some(Command::Serve) => {
+            let config = load_config(&args);
+            serve(&config, args.limits.clone(), stdin().lock(), stdout().lock())
+                .consume_error();
+            return Ok(());
+        }
+        Some(Command::Repl { run, script }) => {
+            repl(&args, *run, script.as_deref());
+            return Ok(());
+        }
+        Some(Command::Run(_) | Command::Build { .. }) | None => {}
+    }
 
-    let config = Config::read_from_file(config_file).consume_error();
+    PRINT_SUMMARY.store(!args.quiet_summary, Ordering::Relaxed);
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.get())
+            .build_global()
+            .expect("nothing runs on the thread pool before this");
+    }
+    let outputs = args.outputs().consume_error();
+
+    let mut timings = Timings::new(args.timings);
+    let mut input = args.get_input().consume_error();
+    let mut config = load_config(&args);
+    config.set_unknown_instructions(args.allow_unknown_instructions);
+    args.syntax.dialect = config.dialect().clone();
+    args.syntax.mnemonics = config.mnemonics();
+    timings.lap("config");
 
-    let contents = read_to_string(&mut input).unwrap();
+    let contents = normalize_source(&read_to_string(&mut input).unwrap());
     let chars = contents.chars().collect::<Vec<_>>();
+    timings.lap("reading");
+
+    let mut progress = Progress::new(args.progress);
 
-    let tokens = Lexer::new(&chars).collect::<Vec<_>>().consume_errors();
-    let labels = get_resolved_labels(&tokens);
+    let mut file_context = FileContext::new(args.input_file.as_deref(), &contents);
 
-    let lines = parse(&tokens).consume_errors();
-    let checked_lines = check_semantics(lines, &labels, &config).consume_error();
-    
-    if args.text {
-        output_bytes_as_text(&checked_lines, &mut output);
-    } else {
-        output_to_binary(&checked_lines, &mut output);
+    let (source_errors, source_warnings) = args.limits.check_source(&contents, 0);
+    print_source_warnings(source_warnings, &file_context);
+    if !source_errors.is_empty() {
+        fail_with_errors(source_errors, &file_context);
+    }
+
+    progress.start("lexing", contents.lines().count());
+    let tokens = pipeline::lex(&chars, args.syntax.clone(), |line| progress.update(line));
+    progress.finish();
+    let tokens = tokens.unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+    timings.lap("lexing");
+    let tokens = match expand_includes(
+        &tokens,
+        args.input_file.as_deref(),
+        &args.syntax,
+        &args.limits,
+        &mut file_context,
+    ) {
+        Ok((tokens, warnings)) => {
+            print_source_warnings(warnings, &file_context);
+            tokens
+        }
+        Err(errs) => fail_with_errors(errs, &file_context),
+    };
+    let defines = args
+        .define_from_file
+        .iter()
+        .map(read_defines_from_file)
+        .collect::<Vec<_>>()
+        .consume_errors()
+        .into_iter()
+        .flatten()
+        .chain(args.defines.iter().cloned())
+        .collect::<Vec<_>>();
+    let address_width = config.capabilities().address_width;
+    for define in &defines {
+        if let Some(warning) = truncation_warning(define, address_width) {
+            print_warning(warning);
+        }
     }
+    let tokens = pipeline::preprocess(&tokens, &defines, &args.limits, &config)
+        .unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+    let tokens = match args.gc_sections {
+        true => {
+            let (tokens, dropped) = gc_sections(&tokens, &config);
+            for section in dropped {
+                let dropped = note(
+                    "gc_dropped",
+                    &[
+                        file_context.file_name(&section.span).to_string(),
+                        (section.span.line + 1).to_string(),
+                        section.label.to_string(),
+                        section.lines.to_string(),
+                    ],
+                );
+                let tag = paint("gc:", Style::new().bold(), Stream::Stderr);
+                eprintln!("{} {}", tag, dropped);
+            }
+            tokens
+        }
+        false => tokens,
+    };
+
+    timings.lap("preprocessing");
+
+    let label_addresses = resolve_labels(&tokens, &config, &defines)
+        .unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+    let mut labels = label_addresses.clone();
+    inject_defines(&mut labels, &defines).consume_error();
+    timings.lap("resolving");
+
+    progress.start("parsing", contents.lines().count());
+    let parsed = pipeline::parse(&tokens, &mut labels, &config, args.emit_obj, |line| {
+        progress.update(line)
+    });
+    progress.finish();
+    let (lines, warnings) = parsed.unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+    print_source_warnings(warnings, &file_context);
+    // The linker fills in imports, until then they're 0.
+    if args.emit_obj {
+        for name in imported_names(&lines) {
+            labels.insert(name, 0);
+        }
+    }
+    timings.lap("parsing");
+
+    if args.explain_encoding {
+        for explanation in lines.iter().filter_map(|line| explain_encoding(&config, line)) {
+            let tag = paint("explain:", Style::new().bold(), Stream::Stderr);
+            eprintln!("{} {}", tag, explanation);
+        }
+    }
+
+    progress.start("checking", lines.len());
+    let mut on_progress = |done| progress.update(done);
+    let checked = pipeline::check(lines, &labels, &config, &args.limits, Some(&mut on_progress));
+    progress.finish();
+    let (checked_lines, addresses) =
+        checked.unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+    timings.lap("checking");
+
+    if args.warn_unreachable {
+        for code in find_unreachable(&checked_lines, &addresses, &labels, &label_addresses) {
+            let name = match &code.label {
+                Some(label) => format!(" ('{}')", label),
+                None => String::new(),
+            };
+            print_warning(format!(
+                "{}:{}: {} unreachable instruction(s) at {:#06x}{}.",
+                file_context.file_name(&code.span),
+                code.span.line + 1,
+                code.instructions,
+                code.address,
+                name
+            ));
+        }
+    }
+
+    if args.teach {
+        for (checked_line, address) in checked_lines.iter().zip(&addresses) {
+            let width = config.capabilities().address_width;
+            eprintln!("{}\n", teach(checked_line, &file_context, *address, width));
+        }
+    }
+
+    if args.emit_obj {
+        let object = build_object(
+            &checked_lines,
+            &addresses,
+            &tokens,
+            &label_addresses,
+            &labels,
+            &file_context,
+            config.capabilities().address_bytes(),
+        )
+        .unwrap_or_else(|errs| fail_with_errors(errs, &file_context));
+        let json = serde_json::to_string_pretty(&object).expect("objects serialize to JSON");
+        let mut output =
+            create_output(args.output_file.first().map(PathBuf::as_path)).consume_error();
+        writeln!(output, "{}", json).consume_error();
+        output.flush().consume_error();
+
+        timings.lap("writing");
+        timings.report();
+        print_summary(object.sections.iter().map(|section| section.data.len()).sum());
+        return Ok(());
+    }
+
+    let mut image = build_image(&checked_lines, &addresses);
+    let provenance = Provenance::new(&config, std::env::args());
+
+    if args.list_symbols {
+        let symbols = symbols(&tokens, &label_addresses, image.len(), &file_context);
+        print!(
+            "{}",
+            list_symbols(&symbols, args.sort, args.filter.as_deref())
+        );
+        return Ok(());
+    }
+
+    if let Some(path) = &args.listing {
+        let listing = listing(
+            &checked_lines,
+            &addresses,
+            &file_context,
+            &label_addresses,
+            &provenance,
+        );
+        fs::write(path, listing).consume_error();
+    }
+
+    if let Some(path) = &args.symbols {
+        let symbols = symbols(&tokens, &label_addresses, image.len(), &file_context);
+        let sizes = size_report(&checked_lines, &label_addresses, image.len(), &file_context);
+        let symbols = render_symbols(&symbols, &sizes, args.symbols_format, &provenance);
+        fs::write(path, symbols).consume_error();
+    }
+
+    let too_large = args
+        .max_size
+        .and_then(|max| SizeExceeded::check(image.len(), max, &label_addresses));
+    if let Some(err) = too_large {
+        print_error(err);
+        fail();
+    }
+
+    if let Some(Command::Run(run)) = &args.command {
+        let program = Program {
+            image: &image,
+            labels: &label_addresses,
+            checked_lines: &checked_lines,
+            addresses: &addresses,
+            source: &file_context,
+        };
+        run_program(&config, &program, run);
+        return Ok(());
+    }
+
+    for transform in &args.transform {
+        transform.apply(&mut image);
+    }
+
+    if args.print_hash {
+        let input = normalized_input(&tokens, &config, &defines);
+        let hashes = format!(
+            "image {:016x}\ninput {:016x}",
+            fnv1a(&image),
+            fnv1a(input.as_bytes())
+        );
+        // Unstyled for build systems to capture, away from the image.
+        match outputs.iter().any(|(_, output_file)| output_file.is_none()) {
+            true => eprintln!("{}", hashes),
+            false => println!("{}", hashes),
+        }
+    }
+
+    if !args.segment_out.is_empty() {
+        let segments = segments(&checked_lines, &addresses);
+        let emitter = outputs
+            .iter()
+            .find_map(|(format, _)| format.emitter())
+            .unwrap_or(&Binary);
+        for output in &args.segment_out {
+            let Some(segment) = segments.iter().find(|segment| segment.name == output.name) else {
+                let names = segments
+                    .iter()
+                    .map(|segment| format!("'{}'", segment.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                print_error(SegmentErr::UnknownSegment(output.name.clone(), names));
+                fail();
+            };
+            let mut bytes = segment.bytes.clone();
+            for transform in &args.transform {
+                transform.apply(&mut bytes);
+            }
+            let mut file = BufWriter::new(File::create(&output.path).consume_error());
+            emitter.emit(&bytes, &mut file).consume_error();
+            file.flush().consume_error();
+        }
+    }
+
+    let annotations = match args.annotate {
+        true => source_annotations(&checked_lines, &addresses, &file_context),
+        false => BTreeMap::new(),
+    };
+    for (format, output_file) in &outputs {
+        let Some(emitter) = format.emitter() else {
+            let listing = listing(
+                &checked_lines,
+                &addresses,
+                &file_context,
+                &label_addresses,
+                &provenance,
+            );
+            let mut output = create_output(output_file.as_deref()).consume_error();
+            output.write_all(listing.as_bytes()).consume_error();
+            output.flush().consume_error();
+            continue;
+        };
+        match (args.split, output_file) {
+            (Some(split), Some(output_file)) => {
+                let paths = split_output_paths(output_file, split);
+                for (path, lane) in paths.iter().zip(split.split(&image)) {
+                    let mut output = BufWriter::new(File::create(path).consume_error());
+                    emitter.emit(&lane, &mut output).consume_error();
+                    output.flush().consume_error();
+                }
+            }
+            _ => {
+                let mut output = create_output(output_file.as_deref()).consume_error();
+                emitter
+                    .emit_annotated(&image, &annotations, &mut output)
+                    .consume_error();
+                output.flush().consume_error();
+            }
+        }
+    }
+
+    timings.lap("writing");
+
+    if let Some((project, dir)) = &build {
+        run_post_build(project, dir, |command| {
+            let tag = paint("Running", Style::new().green().bold(), Stream::Stderr);
+            eprintln!("{} {}", tag, command)
+        })
+        .consume_error();
+        timings.lap("post-build");
+    }
+
+    timings.report();
+    print_summary(image.len());
 
     Ok(())
 }
@@ -0,0 +1,132 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Stands for the output file, as written in the manifest, in post-build
+/// commands.
+pub const OUTPUT_PLACEHOLDER: &str = "{output}";
+
+#[derive(Error, Debug)]
+pub enum ManifestErr {
+    #[error("Couldn't read the manifest '{0}': {1}.")]
+    Read(String, String),
+    #[error("Invalid manifest '{0}': {1}.")]
+    Invalid(String, String),
+    #[error("Couldn't run post-build command '{0}': {1}.")]
+    HookSpawn(String, String),
+    #[error("Post-build command '{0}' failed with {1}.")]
+    HookFailed(String, String),
+}
+
+/// A project's `asm.toml`, as created by `new`, e.g.
+///
+/// ```toml
+/// [project]
+/// name = "blink"
+/// entry = "main.asm"
+/// config = "config.json"
+/// output = "build/blink.bin"
+/// post-build = ["sha256sum {output}", "flasher --port /dev/ttyUSB0 {output}"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub project: Project,
+}
+
+/// Paths are relative to the directory of the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Project {
+    pub name: String,
+    pub entry: PathBuf,
+    pub config: Option<PathBuf>,
+    pub output: PathBuf,
+    /// Shell commands run in order from the manifest's directory once the
+    /// output is written, with `{output}` replaced by the output path.
+    #[serde(default)]
+    pub post_build: Vec<String>,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Result<Manifest, ManifestErr> {
+        let name = path.display().to_string();
+        let content = fs::read_to_string(path)
+            .map_err(|err| ManifestErr::Read(name.clone(), err.to_string()))?;
+        toml::from_str(&content)
+            .map_err(|err| ManifestErr::Invalid(name, err.message().to_string()))
+    }
+}
+
+pub(crate) fn shell(command: &str) -> Command {
+    let (shell, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+    let mut shell = Command::new(shell);
+    shell.arg(flag).arg(command);
+    shell
+}
+
+/// Runs the post-build commands of `project` in `dir`, stopping at the first
+/// one that can't be started or exits unsuccessfully. `announce` is told
+/// about each command before it runs.
+pub fn run_post_build(
+    project: &Project,
+    dir: &Path,
+    mut announce: impl FnMut(&str),
+) -> Result<(), ManifestErr> {
+    let output = project.output.display().to_string();
+    for hook in &project.post_build {
+        let command = hook.replace(OUTPUT_PLACEHOLDER, &output);
+        announce(&command);
+        let status = shell(&command)
+            .current_dir(dir)
+            .status()
+            .map_err(|err| ManifestErr::HookSpawn(command.clone(), err.to_string()))?;
+        if !status.success() {
+            return Err(ManifestErr::HookFailed(command, status.to_string()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_post_build() {
+        let dir = tempdir().unwrap();
+        let manifest = dir.path().join("asm.toml");
+        fs::write(
+            &manifest,
+            "[project]\nname = \"demo\"\nentry = \"main.asm\"\noutput = \"demo.bin\"\n\
+             post-build = [\"cp {output} copy.bin\", \"exit 3\", \"touch never\"]\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("demo.bin"), [1, 2]).unwrap();
+
+        let project = Manifest::read(&manifest).unwrap().project;
+        let mut commands = vec![];
+        let result = run_post_build(&project, dir.path(), |command| {
+            commands.push(command.to_string())
+        });
+        assert!(matches!(result, Err(ManifestErr::HookFailed(command, _)) if command == "exit 3"));
+        assert_eq!(commands, ["cp demo.bin copy.bin", "exit 3"]);
+        assert_eq!(fs::read(dir.path().join("copy.bin")).unwrap(), [1, 2]);
+        assert!(!dir.path().join("never").exists());
+
+        fs::write(&manifest, "[project]\nname = \"demo\"\n").unwrap();
+        assert!(matches!(
+            Manifest::read(&manifest),
+            Err(ManifestErr::Invalid(..))
+        ));
+    }
+}
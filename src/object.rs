@@ -0,0 +1,372 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    checker::CheckedLine,
+    error::{Error, FileContext, WithSpan},
+    expr::Expr,
+    linkage::imported_names,
+    parser::Line,
+    segments::{label_segments, segment_name, segments, Segment, DEFAULT_SEGMENT},
+    token::{Token, TokenType},
+};
+
+/// Version of the object format, bumped whenever it changes.
+pub const OBJECT_VERSION: u32 = 1;
+
+/// How far symbols are moved to see how an expression depends on them. Two
+/// distances tell `#label + 1` apart from e.g. `#label & 0xff`.
+const PROBES: [usize; 2] = [1, 0x10000];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ObjectErr {
+    #[error("'{0}' can't be relocated, only a single label plus or minus a constant can.")]
+    NotRelocatable(String),
+}
+
+/// A module assembled on its own, for the linker to combine with others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Object {
+    pub version: u32,
+    /// File the object was assembled from.
+    pub source: String,
+    pub sections: Vec<Section>,
+    pub symbols: Vec<ObjectSymbol>,
+    /// Symbols other objects are expected to export.
+    pub imports: Vec<String>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// The bytes of one segment, which the linker may place anywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// A label, as an offset into one of the object's sections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub section: usize,
+    pub offset: usize,
+    /// Whether other objects may refer to it.
+    pub exported: bool,
+}
+
+/// What a relocated value is relative to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    /// The start of one of the object's own sections.
+    Section(usize),
+    /// An imported symbol.
+    Symbol(String),
+}
+
+/// Bytes the linker fills in once it knows where their target ends up.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relocation {
+    pub section: usize,
+    pub offset: usize,
+    /// 1 or 2 bytes, stored low byte first.
+    pub width: usize,
+    pub target: Target,
+    pub addend: i64,
+    /// Where the value is written in the source, e.g. `main.as:12`.
+    pub location: String,
+}
+
+fn symbol_name(token: &Token) -> &str {
+    match &token.token_type {
+        TokenType::LabelRef(name) => name,
+        _ => &token.content,
+    }
+}
+
+/// Every symbol with a value, with `moved` shifted by `distance` and the
+/// constants defined from them evaluated again.
+fn shifted<'a>(
+    symbols: &HashMap<&'a str, usize>,
+    checked_lines: &[CheckedLine<'a>],
+    moved: &HashSet<&str>,
+    distance: usize,
+) -> HashMap<&'a str, usize> {
+    let mut shifted = symbols
+        .iter()
+        .map(|(name, value)| match moved.contains(name) {
+            true => (*name, value + distance),
+            false => (*name, *value),
+        })
+        .collect::<HashMap<_, _>>();
+    for checked_line in checked_lines {
+        if let Line::Constant { name, value } = &checked_line.line {
+            if let Ok(value) = value.evaluate(&shifted) {
+                shifted.insert(symbol_name(name), value as usize);
+            }
+        }
+    }
+    shifted
+}
+
+/// The values an expression is written with, with the byte offset and width
+/// of each within the line's bytes. Addresses take `address_bytes`.
+fn value_fields<'a, 'b>(
+    line: &'b Line<'a>,
+    address_bytes: usize,
+) -> Vec<(usize, usize, &'b Expr<'a>)> {
+    let mut fields = vec![];
+    match line {
+        Line::Instruction { operands, .. } => {
+            let mut offset = 1;
+            for (operand, value) in operands {
+                let width = operand.encoded_size(address_bytes);
+                fields.push((offset, width, value));
+                offset += width;
+            }
+        }
+        Line::Byte(values) => {
+            let mut offset = 0;
+            for value in values {
+                let width = match value.as_token().map(|token| &token.token_type) {
+                    Some(TokenType::String(string)) => string.chars().count(),
+                    _ => 1,
+                };
+                fields.push((offset, width, value));
+                offset += width;
+            }
+        }
+        Line::Word(values) => {
+            fields.extend(
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (2 * i, 2, value)),
+            );
+        }
+        _ => {}
+    }
+    fields.retain(|(_, width, value)| *width > 0 && value.has_label_ref());
+    fields
+}
+
+/// Turns an assembled program into a relocatable object. `symbols` has
+/// every value operands were evaluated with, with each import as 0, and
+/// `labels` the addresses of the labels alone.
+///
+/// Each segment becomes a section. Every operand or data value that depends
+/// on a label gets a relocation, as long as it is one label plus or minus a
+/// constant; a difference of two labels in the same section needs none.
+pub fn build_object<'a>(
+    checked_lines: &[CheckedLine<'a>],
+    addresses: &[usize],
+    tokens: &[Token],
+    labels: &HashMap<&str, usize>,
+    symbols: &HashMap<&'a str, usize>,
+    source: &FileContext,
+    address_bytes: usize,
+) -> Result<Object, Vec<Error>> {
+    let mut sections = segments(checked_lines, addresses);
+    let label_segments = label_segments(tokens);
+    // Segments without bytes only have labels, and start where those are.
+    let section_index = |name: &str, sections: &mut Vec<Segment>, address| match sections
+        .iter()
+        .position(|section| section.name == name)
+    {
+        Some(index) => index,
+        None => {
+            sections.push(Segment {
+                name: name.to_string(),
+                start: address,
+                bytes: vec![],
+            });
+            sections.len() - 1
+        }
+    };
+
+    let mut exported = HashSet::new();
+    for checked_line in checked_lines {
+        if let Line::Export(names) = &checked_line.line {
+            exported.extend(names.iter().map(|name| symbol_name(name)));
+        }
+    }
+    let imports = imported_names(checked_lines.iter().map(|checked_line| &checked_line.line));
+
+    let mut object_symbols = vec![];
+    let mut by_section: Vec<HashSet<&str>> = vec![];
+    let mut names = labels.iter().collect::<Vec<_>>();
+    names.sort();
+    for (name, address) in names {
+        let segment = label_segments.get(name).copied().unwrap_or(DEFAULT_SEGMENT);
+        let section = section_index(segment, &mut sections, *address);
+        by_section.resize_with(sections.len(), HashSet::new);
+        by_section[section].insert(*name);
+        object_symbols.push(ObjectSymbol {
+            name: name.to_string(),
+            section,
+            offset: address.saturating_sub(sections[section].start),
+            exported: exported.contains(name),
+        });
+    }
+    by_section.resize_with(sections.len(), HashSet::new);
+
+    // Every way a value can move, with what it's relative to.
+    let mut movers = by_section
+        .iter()
+        .enumerate()
+        .filter(|(_, names)| !names.is_empty())
+        .map(|(i, names)| (Target::Section(i), names.clone()))
+        .collect::<Vec<_>>();
+    movers.extend(
+        imports
+            .iter()
+            .map(|name| (Target::Symbol(name.to_string()), HashSet::from([*name]))),
+    );
+    let probes = movers
+        .iter()
+        .map(|(_, moved)| PROBES.map(|distance| shifted(symbols, checked_lines, moved, distance)))
+        .collect::<Vec<_>>();
+
+    let mut relocations = vec![];
+    let mut errors = vec![];
+    let mut segment = DEFAULT_SEGMENT;
+    for (checked_line, address) in checked_lines.iter().zip(addresses) {
+        if let Line::Segment(name) = &checked_line.line {
+            segment = segment_name(name);
+        }
+        for (offset, width, value) in value_fields(&checked_line.line, address_bytes) {
+            let Ok(base) = value.evaluate(symbols) else {
+                continue;
+            };
+            let mut targets = vec![];
+            let mut relocatable = true;
+            for ((target, _), probes) in movers.iter().zip(&probes) {
+                let moves = PROBES
+                    .iter()
+                    .zip(probes)
+                    .map(|(distance, shifted)| {
+                        let moved = value.evaluate(shifted).unwrap_or(base) - base;
+                        (*distance as i64, moved)
+                    })
+                    .collect::<Vec<_>>();
+                if moves.iter().all(|(distance, moved)| moved == distance) {
+                    targets.push(target);
+                } else if moves.iter().any(|(_, moved)| *moved != 0) {
+                    relocatable = false;
+                }
+            }
+            if !relocatable || targets.len() > 1 {
+                errors.push(ObjectErr::NotRelocatable(value.to_string()).with_span(value.span()));
+                continue;
+            }
+            let Some(target) = targets.pop() else {
+                continue;
+            };
+            let line_section = section_index(segment, &mut sections, *address);
+            let addend = match target {
+                Target::Section(section) => base - sections[*section].start as i64,
+                Target::Symbol(_) => base,
+            };
+            let span = value.span();
+            relocations.push(Relocation {
+                section: line_section,
+                offset: address + offset - sections[line_section].start,
+                width,
+                target: target.clone(),
+                addend,
+                location: format!("{}:{}", source.file_name(&span), span.line + 1),
+            });
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Object {
+        version: OBJECT_VERSION,
+        source: source.main_file_name().to_string(),
+        sections: sections
+            .into_iter()
+            .map(|section| Section {
+                name: section.name,
+                data: section.bytes,
+            })
+            .collect(),
+        symbols: object_symbols,
+        imports: imports.iter().map(|name| name.to_string()).collect(),
+        relocations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[test]
+    fn test_build_object() {
+        let source = ".import putc\n\
+            start:  JMPREL #putc\n\
+            \x20       MOV A [#table + 1]\n\
+            \x20       MOV A [#end - #start]\n\
+            end:    HALT\n\
+            .segment data\n\
+            table:  byte 1 2\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let config = Config::builtin();
+        let labels = get_resolved_labels(&tokens, &config);
+        let mut symbols = labels.clone();
+        symbols.insert("putc", 0);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &symbols, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        let context = FileContext::new(None, source);
+
+        let object = build_object(
+            &checked_lines,
+            &addresses,
+            &tokens,
+            &labels,
+            &symbols,
+            &context,
+            config.capabilities.address_bytes(),
+        )
+        .unwrap();
+        let sections = object
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.data.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(sections, [("code", 10), ("data", 2)]);
+        assert_eq!(object.imports, ["putc"]);
+        // The difference of two labels stays as it is.
+        let relocations = object
+            .relocations
+            .iter()
+            .map(|relocation| {
+                (
+                    relocation.section,
+                    relocation.offset,
+                    relocation.target.clone(),
+                    relocation.addend,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relocations,
+            [
+                (0, 1, Target::Symbol("putc".to_string()), 0),
+                (0, 4, Target::Section(1), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{emitter::Format, resolver::label_sizes};
+
+const MAX_REPORTED_CONTRIBUTORS: usize = 5;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum OutputErr {
+    #[error("Got {0} output formats for {1} output files, give one format per file.")]
+    MismatchedFormats(usize, usize),
+    #[error("Got {0} output formats but no output file, only one can go to stdout.")]
+    FormatsToStdout(usize),
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "Image is {size} bytes, exceeding the limit of {max} bytes by {}. Largest contributors: {}.",
+    size - max,
+    format_contributors(contributors)
+)]
+pub struct SizeExceeded {
+    pub size: usize,
+    pub max: usize,
+    pub contributors: Vec<(Option<String>, usize)>,
+}
+
+impl SizeExceeded {
+    /// The error for an image of `size` bytes that is over `max`, naming
+    /// the labels whose code takes the most, or `None` if it fits.
+    pub fn check(size: usize, max: usize, labels: &HashMap<&str, usize>) -> Option<Self> {
+        if size <= max {
+            return None;
+        }
+        let contributors = label_sizes(labels, size)
+            .into_iter()
+            .map(|(label, size)| (label.map(str::to_string), size))
+            .collect();
+        Some(Self {
+            size,
+            max,
+            contributors,
+        })
+    }
+}
+
+pub(crate) fn format_contributors(contributors: &[(Option<String>, usize)]) -> String {
+    contributors
+        .iter()
+        .take(MAX_REPORTED_CONTRIBUTORS)
+        .map(|(label, size)| match label {
+            Some(label) => format!("{} ({} bytes)", label, size),
+            None => format!("<start> ({} bytes)", size),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How to divide the image between two parallel ROMs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Split {
+    /// Even addresses to one ROM, odd addresses to the other
+    EvenOdd,
+    /// The low byte of every 16-bit word to one ROM, the high byte to the other
+    HiLo,
+}
+
+impl Split {
+    /// File name suffixes of the two images, the one holding address 0 first.
+    pub fn suffixes(&self) -> [&'static str; 2] {
+        match self {
+            Split::EvenOdd => ["even", "odd"],
+            Split::HiLo => ["lo", "hi"],
+        }
+    }
+
+    pub fn split(&self, bytes: &[u8]) -> [Vec<u8>; 2] {
+        match self {
+            Split::EvenOdd => [
+                bytes.iter().step_by(2).copied().collect(),
+                bytes.iter().skip(1).step_by(2).copied().collect(),
+            ],
+            // Words are little-endian like the values the encoder emits, and
+            // a trailing odd byte is a word whose high byte is zero, so both
+            // ROMs hold the same number of words.
+            Split::HiLo => {
+                let (low, high) = bytes
+                    .chunks(2)
+                    .map(|word| (word[0], word.get(1).copied().unwrap_or(0)))
+                    .unzip();
+                [low, high]
+            }
+        }
+    }
+}
+
+/// Post-processing applied to the image before it's written, for programmers
+/// and latch wiring that expect the bits in a different order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Transform {
+    /// Reverse the bit order within every byte
+    BitReverse,
+    /// Invert every bit
+    Invert,
+    /// Swap the high and low nibble of every byte
+    NibbleSwap,
+    /// Swap every pair of adjacent bytes
+    ByteSwap,
+}
+
+impl Transform {
+    pub fn apply(&self, bytes: &mut [u8]) {
+        match self {
+            Transform::BitReverse => bytes
+                .iter_mut()
+                .for_each(|byte| *byte = byte.reverse_bits()),
+            Transform::Invert => bytes.iter_mut().for_each(|byte| *byte = !*byte),
+            Transform::NibbleSwap => bytes
+                .iter_mut()
+                .for_each(|byte| *byte = byte.rotate_left(4)),
+            Transform::ByteSwap => bytes.chunks_exact_mut(2).for_each(|pair| pair.swap(0, 1)),
+        }
+    }
+}
+
+/// Pairs the output files with the formats given for them, in order. A
+/// single format, or `default` if none is given, applies to every file and
+/// no file means stdout.
+pub fn pair_outputs(
+    formats: &[Format],
+    files: &[PathBuf],
+    default: Format,
+) -> Result<Vec<(Format, Option<PathBuf>)>, OutputErr> {
+    match (formats, files) {
+        ([] | [_], []) => Ok(vec![(formats.first().copied().unwrap_or(default), None)]),
+        (_, []) => Err(OutputErr::FormatsToStdout(formats.len())),
+        ([] | [_], _) => {
+            let format = formats.first().copied().unwrap_or(default);
+            Ok(files
+                .iter()
+                .map(|file| (format, Some(file.clone())))
+                .collect())
+        }
+        _ if formats.len() == files.len() => Ok(formats
+            .iter()
+            .copied()
+            .zip(files.iter().cloned().map(Some))
+            .collect()),
+        _ => Err(OutputErr::MismatchedFormats(formats.len(), files.len())),
+    }
+}
+
+/// `rom.bin` becomes `rom.even.bin` and `rom.odd.bin` (or `.lo`/`.hi`).
+pub fn split_output_paths(path: &Path, split: Split) -> [PathBuf; 2] {
+    split.suffixes().map(|suffix| {
+        let mut file_name = OsString::from(path.file_stem().unwrap_or_default());
+        file_name.push(".");
+        file_name.push(suffix);
+        if let Some(extension) = path.extension() {
+            file_name.push(".");
+            file_name.push(extension);
+        }
+        path.with_file_name(file_name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, prelude::assemble};
+
+    #[test]
+    fn test_size_exceeded() {
+        let source = "MOV A 1\nmain: JMPIMM #main\ndata: byte 1 2 3 4\n";
+        let assembly = assemble(source, &Config::builtin(), Default::default()).unwrap();
+        let labels = assembly
+            .symbols
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(assembly.image.len(), 9);
+
+        assert!(SizeExceeded::check(9, 9, &labels).is_none());
+        let err = SizeExceeded::check(9, 6, &labels).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Image is 9 bytes, exceeding the limit of 6 bytes by 3. Largest contributors: \
+            data (4 bytes), main (3 bytes), <start> (2 bytes)."
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let bytes = [0x10, 0x11, 0x20, 0x21, 0x30];
+        assert_eq!(
+            Split::EvenOdd.split(&bytes),
+            [vec![0x10, 0x20, 0x30], vec![0x11, 0x21]]
+        );
+        assert_eq!(
+            Split::HiLo.split(&bytes),
+            [vec![0x10, 0x20, 0x30], vec![0x11, 0x21, 0x00]]
+        );
+        assert_eq!(
+            split_output_paths(Path::new("out/rom.bin"), Split::HiLo),
+            [
+                PathBuf::from("out/rom.lo.bin"),
+                PathBuf::from("out/rom.hi.bin")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transforms() {
+        let transformed = |transform: Transform| {
+            let mut bytes = [0b0000_0001, 0b1010_0011, 0xff];
+            transform.apply(&mut bytes);
+            bytes
+        };
+        assert_eq!(
+            transformed(Transform::BitReverse),
+            [0b1000_0000, 0b1100_0101, 0xff]
+        );
+        assert_eq!(
+            transformed(Transform::Invert),
+            [0b1111_1110, 0b0101_1100, 0x00]
+        );
+        assert_eq!(
+            transformed(Transform::NibbleSwap),
+            [0b0001_0000, 0b0011_1010, 0xff]
+        );
+        // A trailing odd byte has nothing to swap with.
+        assert_eq!(
+            transformed(Transform::ByteSwap),
+            [0b1010_0011, 0b0000_0001, 0xff]
+        );
+    }
+
+    #[test]
+    fn test_pair_outputs() {
+        let files = [PathBuf::from("a.bin"), PathBuf::from("a.hex")];
+        assert_eq!(
+            pair_outputs(&[], &[], Format::Bin),
+            Ok(vec![(Format::Bin, None)])
+        );
+        assert_eq!(
+            pair_outputs(&[Format::Hex], &files, Format::Bin),
+            Ok(vec![
+                (Format::Hex, Some(files[0].clone())),
+                (Format::Hex, Some(files[1].clone()))
+            ])
+        );
+        assert_eq!(
+            pair_outputs(&[Format::Bin, Format::Ihex], &files, Format::Bin),
+            Ok(vec![
+                (Format::Bin, Some(files[0].clone())),
+                (Format::Ihex, Some(files[1].clone()))
+            ])
+        );
+        assert_eq!(
+            pair_outputs(
+                &[Format::Bin, Format::Ihex, Format::Hex],
+                &files,
+                Format::Bin
+            ),
+            Err(OutputErr::MismatchedFormats(3, 2))
+        );
+        assert_eq!(
+            pair_outputs(&[Format::Bin, Format::Ihex], &[], Format::Bin),
+            Err(OutputErr::FormatsToStdout(2))
+        );
+    }
+}
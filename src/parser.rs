@@ -1,59 +1,188 @@
 use thiserror::Error;
 
 use crate::{
-    specs::Operand,
-    token::{Token, TokenType},
+    config::Config,
+    encoder::{EncodeErr, OpcodeTable},
+    error::{Error, WithSpan},
+    expr::Expr,
+    specs::{Mnemonic, Operand},
+    token::{is_binary_operator, split_lines, Span, Token, TokenType},
 };
 
-// TODO: Add spans and line numbers to errors
 #[derive(PartialEq, Eq, Debug, Error)]
-pub enum ParserErr<'a> {
+pub enum ParserErr {
     #[error("Expected: \"{0}\", found \"{1}\".")]
-    UnexpectedToken(&'a str, &'a str),
+    UnexpectedToken(String, String),
     #[error("Line should begin with a Mnemonic, 'byte' or a label, instead found \"{0}\".")]
-    UnexpectedLineBeginning(&'a str),
-    #[error("Expected: \"{0}\", instead hit EOF.")]
-    EOF(String),
+    UnexpectedLineBeginning(String),
+    #[error("Expected: \"{0}\", instead hit end of line.")]
+    EndOfLine(String),
+    #[error("Instruction '{0}' takes at most {1} operand(s).")]
+    TooManyOperands(String, usize),
+    #[error("'{0}' has no values.")]
+    NoValues(String),
+    #[error("'.barrier' has no effect yet, as no pass reorders or rewrites code.")]
+    InertBarrier,
+}
+
+fn unexpected(expected: &str, token: &Token) -> Error {
+    ParserErr::UnexpectedToken(expected.to_string(), token.content.clone())
+        .with_span(token.span.clone())
+}
+
+/// Turns numbers written wider than a byte, like `0x0010`, into addresses
+/// where the config has no form taking them as immediates, so
+/// `JMPIMM 0x0010` jumps to 0x10. Forms taking an immediate keep it, so
+/// `MOV A 0x0005` still loads 5.
+fn widen_literals(config: &Config, mnemonic: &Mnemonic, operands: &mut [(Operand, Expr)]) {
+    loop {
+        let kinds = operands.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        let Err(EncodeErr::InvalidOperand(i)) = config.opcode(mnemonic, &kinds) else {
+            return;
+        };
+        let (kind, value) = &mut operands[i];
+        if *kind != Operand::Const || value.written_width().is_none_or(|width| width <= 8) {
+            return;
+        }
+        *kind = Operand::Mem16;
+    }
 }
 
 struct Parser<'a> {
     tokens: &'a [Token],
+    // The whole line `tokens` is the unconsumed rest of.
+    line: &'a [Token],
+    config: Option<&'a Config>,
 }
 
 #[derive(Debug)]
 pub enum Line<'a> {
-    Byte(Vec<&'a Token>),
+    Byte(Vec<Expr<'a>>),
     Instruction {
         mnemonic: &'a Token,
-        operands: Vec<(Operand, &'a Token)>,
+        operands: Vec<(Operand, Expr<'a>)>,
+    },
+    Constant {
+        name: &'a Token,
+        value: Expr<'a>,
+    },
+    Org(Expr<'a>),
+    Align(Expr<'a>),
+    /// Where execution starts, for finding unreachable code.
+    Entry(Expr<'a>),
+    /// Lines up to the next `.segment` belong to the named segment.
+    Segment(&'a Token),
+    /// Reserved for optimization passes to never move or rewrite code
+    /// across. There are none yet, see [`barrier_warnings`].
+    Barrier(&'a Token),
+    /// Labels defined here that other objects may use.
+    Export(Vec<&'a Token>),
+    /// Labels this object uses but another one defines.
+    Import(Vec<&'a Token>),
+    Word(Vec<Expr<'a>>),
+    Str {
+        strings: Vec<&'a Token>,
+        zero_terminated: bool,
     },
 }
 
+impl<'a> Line<'a> {
+    /// The first token of the line after any labels, for locating it in
+    /// the source.
+    pub fn first_token(&self) -> Option<&'a Token> {
+        match self {
+            Line::Instruction { mnemonic, .. } => Some(mnemonic),
+            Line::Constant { name, .. } | Line::Segment(name) | Line::Barrier(name) => Some(name),
+            Line::Org(value) | Line::Align(value) | Line::Entry(value) => {
+                Some(value.first_token())
+            }
+            Line::Byte(values) | Line::Word(values) => values.first().map(Expr::first_token),
+            Line::Str { strings, .. } | Line::Export(strings) | Line::Import(strings) => {
+                strings.first().copied()
+            }
+        }
+    }
+}
+
 /*
 Grammar:
-line -> (label)? instruction | byte;
+program -> (line NEWLINE)*;
+line -> (label)* (instruction | byte | constant | directive)?;
 
 label -> STRING ":";
 
 instruction -> mnemonic (operand)*;
-byte -> "byte" (NUMBER)+;
+byte -> "byte" (expression | STRING)+;
+constant -> (".equ" | ".const") STRING expression;
+directive -> (".org" | ".align" | ".entry") expression
+           | ".segment" STRING
+           | ".barrier"
+           | (".export" | ".import") (STRING)+
+           | ".word" (expression)+
+           | (".string" | ".ascii" | ".asciiz") (STRING_LITERAL)+;
 
-operand -> register | NUMBER | labelref | memref;
+operand -> register | expression | memref;
 register -> "A" | "B" | "F";
-labelref -> '#' STRING;
-memref -> '[' (labelref | NUMBER) ']';*/
+memref -> '[' expression ']';
+
+expression -> primary (BINARY_OPERATOR primary)*;
+primary -> NUMBER | labelref | STRING | '(' expression ')' | ('-' | '~') primary;
+labelref -> '#' STRING;*/
+
+/// A warning at the first `.barrier`, as every one is accepted but none
+/// does anything until there are passes that transform code.
+pub fn barrier_warnings(lines: &[Line]) -> Vec<Error> {
+    lines
+        .iter()
+        .find_map(|line| match line {
+            Line::Barrier(directive) => {
+                Some(ParserErr::InertBarrier.with_span(directive.span.clone()))
+            }
+            _ => None,
+        })
+        .into_iter()
+        .collect()
+}
+
+fn starts_expression(token: &Token) -> bool {
+    match &token.token_type {
+        TokenType::Number(..)
+        | TokenType::LabelRef(_)
+        | TokenType::Mnemonic(_)
+        | TokenType::LeftParen => true,
+        TokenType::Operator(operator) => operator.is_prefix(),
+        _ => false,
+    }
+}
+
+fn starts_operand(token: &Token) -> bool {
+    match token.token_type {
+        TokenType::Register(_) | TokenType::LeftSquareBracket => true,
+        _ => starts_expression(token),
+    }
+}
 
 impl<'a> Parser<'a> {
-    fn new(tokens: &'a [Token]) -> Self {
-        Self { tokens }
+    fn new(tokens: &'a [Token], config: Option<&'a Config>) -> Self {
+        Self {
+            tokens,
+            line: tokens,
+            config,
+        }
     }
 
     fn chop(&mut self) -> Option<&'a Token> {
-        let token = self.tokens.get(0)?;
+        let token = self.tokens.first()?;
         self.tokens = &self.tokens[1..];
         Some(token)
     }
 
+    /// The last token chopped from the current line.
+    fn previous(&self) -> Option<&'a Token> {
+        let consumed = self.line.len() - self.tokens.len();
+        consumed.checked_sub(1).map(|i| &self.line[i])
+    }
+
     fn peek(&self) -> Option<&'a Token> {
         if self.tokens.is_empty() {
             return None;
@@ -62,49 +191,37 @@ impl<'a> Parser<'a> {
         Some(token)
     }
 
-    fn parse(&mut self) -> Result<Vec<Line<'a>>, Vec<ParserErr<'a>>> {
+    /// `expected` was missing at the end of the line, pointing just past
+    /// its last token.
+    fn end_of_line(&self, expected: &str) -> Error {
+        let span = match self.previous().or(self.line.last()) {
+            Some(token) => {
+                let end = token.span.chars.end;
+                Span::new(token.span.line, end..end + 1).in_file(token.span.file)
+            }
+            None => Span::new(0, 0..0),
+        };
+        ParserErr::EndOfLine(expected.to_string()).with_span(span)
+    }
+
+    fn parse(&mut self, mut on_line: impl FnMut(usize)) -> Result<Vec<Line<'a>>, Vec<Error>> {
         let mut lines = vec![];
         let mut errors = vec![];
 
-        let mut error_recovery = false;
-        while !self.tokens.is_empty() {
-            let Some(token) = &self.peek() else {
-                break;
-            };
-            match token.token_type {
-                TokenType::Mnemonic(_) => {
-                    error_recovery = false;
-                    let line = self.instruction();
-                    match line {
-                        Ok(line) => lines.push(line),
-                        Err(err) => {
-                            error_recovery = true;
-                            errors.push(err)
-                        }
-                    };
-                }
-                TokenType::Byte => {
-                    error_recovery = false;
-                    let line = self.byte();
-                    match line {
-                        Ok(line) => lines.push(line),
-                        Err(err) => {
-                            error_recovery = true;
-                            errors.push(err)
-                        }
-                    };
-                }
-                TokenType::Label(_) => {
-                    self.chop();
-                }
-                _ => {
-                    if !error_recovery {
-                        errors.push(ParserErr::UnexpectedLineBeginning(&token.content));
-                    }
-                    self.chop();
-                }
+        // Every source line is parsed on its own, so a malformed or short
+        // line can't swallow tokens from the next one.
+        let tokens = self.tokens;
+        for line_tokens in split_lines(tokens) {
+            on_line(line_tokens[0].span.line);
+            self.tokens = line_tokens;
+            self.line = line_tokens;
+            match self.line() {
+                Ok(Some(line)) => lines.push(line),
+                Ok(None) => {}
+                Err(err) => errors.push(err),
             }
         }
+        self.tokens = &[];
 
         if errors.is_empty() {
             return Ok(lines);
@@ -112,115 +229,375 @@ impl<'a> Parser<'a> {
         Err(errors)
     }
 
-    fn byte(&mut self) -> Result<Line<'a>, ParserErr<'a>> {
-        let _byte = self.chop().unwrap();
+    fn line(&mut self) -> Result<Option<Line<'a>>, Error> {
+        while let Some(TokenType::Label(_)) = self.peek().map(|token| &token.token_type) {
+            self.chop();
+        }
+
+        let Some(token) = self.peek() else {
+            return Ok(None);
+        };
+        let line = match token.token_type {
+            TokenType::Mnemonic(_) => self.instruction()?,
+            TokenType::Byte => self.byte()?,
+            TokenType::Equ => self.constant()?,
+            TokenType::Org => {
+                self.chop();
+                Line::Org(self.expression()?)
+            }
+            TokenType::Align => {
+                self.chop();
+                Line::Align(self.expression()?)
+            }
+            TokenType::Entry => {
+                self.chop();
+                Line::Entry(self.expression()?)
+            }
+            TokenType::Segment => self.segment()?,
+            TokenType::Barrier => Line::Barrier(self.chop().unwrap()),
+            TokenType::Export | TokenType::Import => self.linkage()?,
+            TokenType::Word => self.word()?,
+            TokenType::Ascii | TokenType::Asciiz => self.string()?,
+            _ => {
+                return Err(ParserErr::UnexpectedLineBeginning(token.content.clone())
+                    .with_span(token.span.clone()))
+            }
+        };
+
+        match self.peek() {
+            Some(token) => Err(unexpected("end of line", token)),
+            None => Ok(Some(line)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<Line<'a>, Error> {
+        let byte = self.chop().unwrap();
 
-        let mut numbers = vec![];
+        let mut values = vec![];
         while let Some(token) = self.peek() {
             match token.token_type {
-                TokenType::Number(_) => numbers.push(self.number()?.1),
+                TokenType::String(_) => {
+                    self.chop();
+                    values.push(Expr::Value(token));
+                }
+                _ if starts_expression(token) => values.push(self.expression()?),
                 _ => break,
             }
         }
-        Ok(Line::Byte(numbers))
+        // An empty `byte` is almost always a literal that didn't lex as one.
+        if values.is_empty() {
+            return Err(ParserErr::NoValues(byte.content.clone()).with_span(byte.span.clone()));
+        }
+        Ok(Line::Byte(values))
     }
 
-    fn instruction(&mut self) -> Result<Line<'a>, ParserErr<'a>> {
+    fn instruction(&mut self) -> Result<Line<'a>, Error> {
         let mnemonic = self.chop().unwrap();
         let mut operands = vec![];
 
-        while let Some(token) = self.operand() {
-            match token {
-                Ok(token) => operands.push(token),
-                Err(err) => return Err(err),
+        let max_arity = match (&mnemonic.token_type, self.config) {
+            (TokenType::Mnemonic(mnemonic), Some(config)) => config.max_arity(mnemonic),
+            _ => None,
+        };
+
+        while max_arity.is_none_or(|max_arity| operands.len() < max_arity) {
+            match self.operand() {
+                Some(Ok(token)) => operands.push(token),
+                Some(Err(err)) => return Err(err),
+                None => break,
+            }
+        }
+
+        if let (Some(max_arity), Some(token)) = (max_arity, self.peek()) {
+            if starts_operand(token) {
+                return Err(
+                    ParserErr::TooManyOperands(mnemonic.content.clone(), max_arity)
+                        .with_span(token.span.clone()),
+                );
             }
         }
 
+        if let (TokenType::Mnemonic(name), Some(config)) = (&mnemonic.token_type, self.config) {
+            widen_literals(config, name, &mut operands);
+        }
         Ok(Line::Instruction { mnemonic, operands })
     }
 
-    // TODO: Remove code duplication for these three functions
-    fn number(&mut self) -> Result<(Operand, &'a Token), ParserErr<'a>> {
-        let token = self.chop().ok_or(ParserErr::EOF("Number".to_string()))?;
-        match token.token_type {
-            TokenType::Number(_) => {}
-            _ => {
-                return Err(ParserErr::UnexpectedToken(
-                    "Number",
-                    &token.content,
-                ))
+    fn word(&mut self) -> Result<Line<'a>, Error> {
+        let word = self.chop().unwrap();
+
+        let mut values = vec![];
+        while let Some(token) = self.peek() {
+            if !starts_expression(token) {
+                break;
             }
-        };
-        Ok((Operand::Const, token))
+            values.push(self.expression()?);
+        }
+        if values.is_empty() {
+            return Err(ParserErr::NoValues(word.content.clone()).with_span(word.span.clone()));
+        }
+        Ok(Line::Word(values))
     }
 
-    fn register(&mut self) -> Result<(Operand, &'a Token), ParserErr<'a>> {
-        let token = self.chop().ok_or(ParserErr::EOF("Register".to_string()))?;
-        let reg = match &token.token_type {
-            TokenType::Register(reg) => reg,
-            _ => {
-                return Err(ParserErr::UnexpectedToken(
-                    "Register",
-                    &token.content,
-                ))
+    fn string(&mut self) -> Result<Line<'a>, Error> {
+        let directive = self.chop().unwrap();
+
+        let mut strings = vec![];
+        while let Some(token) = self.chop() {
+            match token.token_type {
+                TokenType::String(_) => strings.push(token),
+                _ => return Err(unexpected("String", token)),
             }
-        };
-        Ok((Operand::Register(*reg), token))
+        }
+        Ok(Line::Str {
+            strings,
+            zero_terminated: directive.token_type == TokenType::Asciiz,
+        })
     }
 
-    fn labelref(&mut self) -> Result<(Operand, &'a Token), ParserErr<'a>> {
-        let token = self.chop().ok_or(ParserErr::EOF("LabelRef".to_string()))?;
-        match token.token_type {
-            TokenType::LabelRef(_) => {}
-            _ => {
-                return Err(ParserErr::UnexpectedToken(
-                    "LabelRef",
-                    &token.content,
-                ))
+    fn segment(&mut self) -> Result<Line<'a>, Error> {
+        let _segment = self.chop().unwrap();
+
+        let name = self.chop().ok_or_else(|| self.end_of_line("Segment name"))?;
+        match name.token_type {
+            TokenType::Mnemonic(_) | TokenType::LabelRef(_) => Ok(Line::Segment(name)),
+            _ => Err(unexpected("Segment name", name)),
+        }
+    }
+
+    fn linkage(&mut self) -> Result<Line<'a>, Error> {
+        let directive = self.chop().unwrap();
+
+        let mut names = vec![];
+        while let Some(name) = self.chop() {
+            match name.token_type {
+                TokenType::Mnemonic(_) | TokenType::LabelRef(_) => names.push(name),
+                _ => return Err(unexpected("Symbol name", name)),
             }
-        };
-        Ok((Operand::Mem16, token))
+        }
+        if names.is_empty() {
+            return Err(
+                ParserErr::NoValues(directive.content.clone()).with_span(directive.span.clone())
+            );
+        }
+        Ok(match directive.token_type {
+            TokenType::Export => Line::Export(names),
+            _ => Line::Import(names),
+        })
     }
 
-    fn memref(&mut self) -> Result<(Operand, &'a Token), ParserErr<'a>> {
-        let _left_bracket = self.chop().ok_or(ParserErr::EOF("[".to_string()))?; // chops the '['
+    fn constant(&mut self) -> Result<Line<'a>, Error> {
+        let _equ = self.chop().unwrap();
 
-        let token = self
+        let name = self
             .chop()
-            .ok_or(ParserErr::EOF("Number or LabelRef".to_string()))?;
+            .ok_or_else(|| self.end_of_line("Constant name"))?;
+        match name.token_type {
+            TokenType::Mnemonic(_) | TokenType::LabelRef(_) => {}
+            _ => return Err(unexpected("Constant name", name)),
+        }
+
+        let value = self.expression()?;
+        Ok(Line::Constant { name, value })
+    }
+
+    fn expression(&mut self) -> Result<Expr<'a>, Error> {
+        self.binary(0)
+    }
+
+    /// Precedence climbing over the binary operators, C-style.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr<'a>, Error> {
+        let mut lhs = self.primary()?;
+        let mut previous = self.previous();
+
+        while let Some(token) = self.peek() {
+            let TokenType::Operator(operator) = token.token_type else {
+                break;
+            };
+            let Some(precedence) = operator
+                .precedence()
+                .filter(|precedence| *precedence >= min_precedence)
+            else {
+                break;
+            };
+            if !is_binary_operator(previous, token) {
+                break;
+            }
+            self.chop();
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Expr::Binary(operator, Box::new(lhs), Box::new(rhs));
+            previous = self.previous();
+        }
+
+        Ok(lhs)
+    }
+
+    fn primary(&mut self) -> Result<Expr<'a>, Error> {
+        let token = self.chop().ok_or_else(|| self.end_of_line("Expression"))?;
         match token.token_type {
-            TokenType::Number(_) | TokenType::LabelRef(_) => {}
-            _ => {
-                return Err(ParserErr::UnexpectedToken(
-                    "Number or LabelRef",
-                    &token.content,
-                ))
+            TokenType::Number(..) | TokenType::LabelRef(_) | TokenType::Mnemonic(_) => {
+                Ok(Expr::Value(token))
+            }
+            TokenType::Operator(operator) if operator.is_prefix() => {
+                Ok(Expr::Unary(token, operator, Box::new(self.primary()?)))
             }
+            TokenType::LeftParen => {
+                let inner = self.expression()?;
+                let right_paren = self.chop().ok_or_else(|| self.end_of_line(")"))?;
+                match right_paren.token_type {
+                    TokenType::RightParen => Ok(Expr::Group(token, Box::new(inner), right_paren)),
+                    _ => Err(unexpected(")", right_paren)),
+                }
+            }
+            _ => Err(unexpected("Expression", token)),
+        }
+    }
+
+    fn register(&mut self) -> Result<(Operand, Expr<'a>), Error> {
+        let token = self.chop().ok_or_else(|| self.end_of_line("Register"))?;
+        let reg = match &token.token_type {
+            TokenType::Register(reg) => reg,
+            _ => return Err(unexpected("Register", token)),
         };
+        Ok((Operand::Register(*reg), Expr::Value(token)))
+    }
+
+    fn memref(&mut self) -> Result<(Operand, Expr<'a>), Error> {
+        let _left_bracket = self.chop().ok_or_else(|| self.end_of_line("["))?; // chops the '['
+
+        let address = self.expression()?;
 
-        let right_bracket = self.chop().ok_or(ParserErr::EOF("]".to_string()))?;
+        let right_bracket = self.chop().ok_or_else(|| self.end_of_line("]"))?;
         match right_bracket.token_type {
-            TokenType::RightSquareBracket => Ok((Operand::Mem16, token)),
-            _ => Err(ParserErr::UnexpectedToken(
-                "]",
-                &right_bracket.content,
-            )),
+            TokenType::RightSquareBracket => Ok((Operand::Mem16, address)),
+            _ => Err(unexpected("]", right_bracket)),
         }
     }
 
-    fn operand(&mut self) -> Option<Result<(Operand, &'a Token), ParserErr<'a>>> {
+    fn operand(&mut self) -> Option<Result<(Operand, Expr<'a>), Error>> {
         let token = self.peek()?;
         match token.token_type {
             TokenType::Register(_) => Some(self.register()),
-            TokenType::Number(_) => Some(self.number()),
-            TokenType::LabelRef(_) => Some(self.labelref()),
             TokenType::LeftSquareBracket => Some(self.memref()),
+            _ if starts_expression(token) => Some(self.expression().map(|expr| {
+                // A bare label is an address, anything else is an immediate
+                // unless the instruction has no form for it, see
+                // `widen_literals`.
+                match expr.has_label_ref() {
+                    true => (Operand::Mem16, expr),
+                    false => (Operand::Const, expr),
+                }
+            })),
             _ => None,
         }
     }
 }
 
-pub fn parse(tokens: & [Token]) -> Result<Vec<Line>, Vec<ParserErr>> {
-    let mut parser = Parser::new(tokens);
-    parser.parse()
+pub fn parse(tokens: &[Token]) -> Result<Vec<Line<'_>>, Vec<Error>> {
+    let mut parser = Parser::new(tokens, None);
+    parser.parse(|_| {})
+}
+
+/// Parses the tokens as one expression, e.g. the condition of an `.if`.
+pub fn parse_expression(tokens: &[Token]) -> Result<Expr<'_>, Error> {
+    let mut parser = Parser::new(tokens, None);
+    let expression = parser.expression()?;
+    match parser.peek() {
+        Some(token) => Err(unexpected("end of line", token)),
+        None => Ok(expression),
+    }
+}
+
+/// Like [`parse`], but reports operands beyond the most any form of the
+/// instruction in `config` takes as a single precise error.
+pub fn parse_with_config<'a>(
+    tokens: &'a [Token],
+    config: &'a Config,
+) -> Result<Vec<Line<'a>>, Vec<Error>> {
+    let mut parser = Parser::new(tokens, Some(config));
+    parser.parse(|_| {})
+}
+
+/// Like [`parse_with_config`], calling `on_line` with the source line of
+/// every line before it's parsed, e.g. to show progress.
+pub fn parse_with_progress<'a>(
+    tokens: &'a [Token],
+    config: &'a Config,
+    on_line: impl FnMut(usize),
+) -> Result<Vec<Line<'a>>, Vec<Error>> {
+    let mut parser = Parser::new(tokens, Some(config));
+    parser.parse(on_line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_barrier_warnings() {
+        let source = "MOV A 1\n.barrier\nloop: MOV B 2\nHALT\n.barrier\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let lines = parse(&tokens).unwrap();
+        assert!(matches!(lines[1], Line::Barrier(_)));
+
+        let warnings = barrier_warnings(&lines);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].span().line, 1);
+        assert!(barrier_warnings(&lines[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_no_values() {
+        let errors = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+            parse(&tokens)
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.to_string(), err.span().line, err.span().chars.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors("HALT\n  byte\n"),
+            [("'byte' has no values.".to_string(), 1, 2..6)]
+        );
+        assert_eq!(
+            errors("data: .word\n"),
+            [("'.word' has no values.".to_string(), 0, 6..11)]
+        );
+    }
+
+    #[test]
+    fn test_too_many_operands() {
+        let errors = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+            parse_with_config(&tokens, &Config::builtin())
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.to_string(), err.span().line, err.span().chars.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        // One error where the extra operands start, not one for each.
+        assert_eq!(
+            errors("MOV A 1 [#data] B\ndata: HALT\n"),
+            [(
+                "Instruction 'MOV' takes at most 2 operand(s).".to_string(),
+                0,
+                8..9
+            )]
+        );
+        assert_eq!(
+            errors("HALT A\n"),
+            [(
+                "Instruction 'HALT' takes at most 0 operand(s).".to_string(),
+                0,
+                5..6
+            )]
+        );
+    }
 }
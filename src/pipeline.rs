@@ -0,0 +1,122 @@
+//! The stages of turning source into checked lines, shared by [`assemble`]
+//! and the command line so the two can't drift apart. The command line runs
+//! its extra passes, e.g. includes and `--gc-sections`, between them.
+//!
+//! Every stage reports all the errors it finds rather than the first.
+//!
+//! [`assemble`]: crate::assemble
+
+use std::collections::HashMap;
+
+use crate::{
+    checker::{
+        check_semantics_parallel, check_semantics_with_progress, define_constants, layout,
+        literal_width_warnings, placeholder_warnings, CheckedLine,
+    },
+    conditional::assemble_conditionals,
+    config::Config,
+    defines::Define,
+    error::{Error, WithSpan},
+    lexer::Lexer,
+    limits::Limits,
+    linkage::check_linkage,
+    macros::expand_macros,
+    parser::{barrier_warnings, parse_with_progress, Line},
+    pseudo::expand_pseudo_instructions,
+    resolver::find_symbol_conflicts,
+    segments::check_label_math,
+    syntax::SyntaxOptions,
+    token::Token,
+};
+
+fn all_or_errors<T>(value: T, errors: Vec<Error>) -> Result<T, Vec<Error>> {
+    match errors.is_empty() {
+        true => Ok(value),
+        false => Err(errors),
+    }
+}
+
+/// Splits normalized source into tokens, calling `on_line` with the source
+/// line of each one, e.g. to show progress.
+pub fn lex(
+    chars: &[char],
+    syntax: SyntaxOptions,
+    mut on_line: impl FnMut(usize),
+) -> Result<Vec<Token>, Vec<Error>> {
+    let mut lexer = Lexer::with_syntax(chars, syntax);
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    while let Some(token) = lexer.next() {
+        match token {
+            Ok(token) => {
+                on_line(token.span.line);
+                tokens.push(token);
+            }
+            Err(err) => errors.push(err.with_span(lexer.error_span())),
+        }
+    }
+    all_or_errors(tokens, errors)
+}
+
+/// Resolves conditionals and expands macros and pseudo-instructions, then
+/// rejects names defined as more than one kind of symbol.
+pub fn preprocess(
+    tokens: &[Token],
+    defines: &[Define],
+    limits: &Limits,
+    config: &Config,
+) -> Result<Vec<Token>, Vec<Error>> {
+    let tokens = assemble_conditionals(tokens, defines)?;
+    let tokens = expand_macros(&tokens, limits)?;
+    let tokens = expand_pseudo_instructions(&tokens, config)?;
+    let conflicts = find_symbol_conflicts(&tokens);
+    all_or_errors(tokens, conflicts)
+}
+
+/// Parses preprocessed tokens into lines, calling `on_line` like [`lex`],
+/// and adds the `.equ` constants to `labels`. Exports, imports and label
+/// arithmetic are checked here, imports only unless the program is
+/// `relocatable`. Returns the lines with warnings about them.
+pub fn parse<'a>(
+    tokens: &'a [Token],
+    labels: &mut HashMap<&'a str, usize>,
+    config: &'a Config,
+    relocatable: bool,
+    on_line: impl FnMut(usize),
+) -> Result<(Vec<Line<'a>>, Vec<Error>), Vec<Error>> {
+    let lines = parse_with_progress(tokens, config, on_line)?;
+    define_constants(&lines, labels)?;
+    let link_errors = check_linkage(tokens, &lines, labels, relocatable);
+    if !link_errors.is_empty() {
+        return Err(link_errors);
+    }
+    let math_errors = check_label_math(tokens, &lines);
+    if !math_errors.is_empty() {
+        return Err(math_errors);
+    }
+    let mut warnings = placeholder_warnings(&lines, config);
+    warnings.extend(literal_width_warnings(&lines, config));
+    warnings.extend(barrier_warnings(&lines));
+    Ok((lines, warnings))
+}
+
+/// Encodes every line and lays them out, returning them with their
+/// addresses. `on_progress`, if any, is called with the number of lines
+/// checked so far.
+pub fn check<'a>(
+    lines: Vec<Line<'a>>,
+    labels: &HashMap<&str, usize>,
+    config: &Config,
+    limits: &Limits,
+    on_progress: Option<&mut dyn FnMut(usize)>,
+) -> Result<(Vec<CheckedLine<'a>>, Vec<usize>), Vec<Error>> {
+    let checked_lines = match on_progress {
+        Some(on_progress) => check_semantics_with_progress(lines, labels, config, on_progress)?,
+        None => check_semantics_parallel(lines, labels, config)?,
+    };
+    let addresses = layout(&checked_lines).map_err(|err| vec![err])?;
+    limits
+        .check_output_size(&checked_lines, &addresses)
+        .map_err(|err| vec![err])?;
+    Ok((checked_lines, addresses))
+}
@@ -0,0 +1,520 @@
+//! The stable face of the crate for tools building on it.
+//!
+//! Everything reachable from here follows semver: it only changes in
+//! breaking ways with a major version bump. The other modules are the
+//! assembler's internals and may change in any release.
+//!
+//! ```no_run
+//! use assembler::prelude::*;
+//!
+//! let config = Config::builtin();
+//! let image = Assembler::new(&config).assemble("MOV A 42\nHALT\n").unwrap();
+//! OutputFormat::Binary.write(&image, &mut std::io::stdout()).unwrap();
+//! ```
+
+use std::{collections::BTreeMap, fmt::Display, io};
+
+pub use crate::{config::Config, limits::Limits};
+
+use crate::{
+    checker::{build_image, CheckedLine},
+    defines::{inject_defines, truncation_warning, Define},
+    emitter::Format,
+    error::{sort_errors, Error},
+    lexer::normalize_source,
+    parser::Line,
+    pipeline,
+    resolver::resolve_labels,
+    syntax::{check_label_sigil, SyntaxOptions},
+    token::TokenType,
+};
+
+/// A single error found while assembling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    message: String,
+    line: Option<usize>,
+    column: Option<usize>,
+    help: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(message: impl Display, line: Option<usize>) -> Self {
+        Self {
+            message: message.to_string(),
+            line,
+            column: None,
+            help: None,
+        }
+    }
+
+    fn from_error(err: &Error) -> Self {
+        Self {
+            message: err.to_string(),
+            line: Some(err.span().line + 1),
+            column: Some(err.span().chars.start + 1),
+            help: err.help().map(str::to_string),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The 1-based source line the error points at, when it's known.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// The 1-based column the error starts at, when it's known.
+    pub fn column(&self) -> Option<usize> {
+        self.column
+    }
+
+    /// A suggestion for fixing the error, e.g. the label a typo meant.
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Diagnostics for errors that don't carry a span.
+fn unspanned<E: Display>(errors: impl IntoIterator<Item = E>) -> Vec<Diagnostic> {
+    errors
+        .into_iter()
+        .map(|err| Diagnostic::new(err, None))
+        .collect()
+}
+
+/// Every error from a failed assembly, in the order they were found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Diagnostic;
+    type IntoIter = std::vec::IntoIter<Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// How an assembled image is written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// The raw bytes.
+    Binary,
+    /// One byte per line as eight ASCII `0`/`1` digits.
+    Text,
+    /// Intel HEX records.
+    IntelHex,
+    /// Space separated hex bytes, 16 per line.
+    Hex,
+    /// A Logisim "v2.0 raw" memory image.
+    Logisim,
+}
+
+impl OutputFormat {
+    pub fn write(&self, image: &[u8], output: &mut dyn io::Write) -> io::Result<()> {
+        let format = match self {
+            OutputFormat::Binary => Format::Bin,
+            OutputFormat::Text => Format::Text,
+            OutputFormat::IntelHex => Format::Ihex,
+            OutputFormat::Hex => Format::Hex,
+            OutputFormat::Logisim => Format::Logisim,
+        };
+        let emitter = format.emitter().expect("image formats have an emitter");
+        emitter.emit(image, output)
+    }
+}
+
+/// Settings for [`assemble`]. Start from `AssembleOptions::default()` and
+/// change the fields that matter, new ones may be added in minor releases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AssembleOptions {
+    /// Character that prefixes label references, `#` by default.
+    pub label_sigil: char,
+    /// Whether bare identifiers in operand position are label references.
+    pub bare_label_refs: bool,
+    /// Whether identifiers alone on their line at column 0 are labels even
+    /// without a colon.
+    pub colon_free_labels: bool,
+    /// Constants for `.if`/`.ifdef` and as `#name`, like `-D`.
+    pub defines: Vec<(String, usize)>,
+    /// Caps on line length, macro expansion and output size.
+    pub limits: Limits,
+}
+
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        let syntax = SyntaxOptions::default();
+        Self {
+            label_sigil: syntax.label_sigil,
+            bare_label_refs: syntax.bare_label_refs,
+            colon_free_labels: syntax.colon_free_labels,
+            defines: vec![],
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl AssembleOptions {
+    fn syntax(&self, config: &Config) -> SyntaxOptions {
+        SyntaxOptions {
+            label_sigil: self.label_sigil,
+            bare_label_refs: self.bare_label_refs,
+            colon_free_labels: self.colon_free_labels,
+            mnemonics: config.mnemonics(),
+            dialect: config.dialect.clone(),
+        }
+    }
+}
+
+/// Where something is in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// The 1-based source line.
+    pub line: usize,
+    /// The 1-based column of its first character.
+    pub column: usize,
+    /// Number of characters it spans.
+    pub len: usize,
+}
+
+/// An instruction of an assembled program, for coverage mappers, static
+/// analysers and other tools that need to know what ended up where without
+/// encoding it again.
+///
+/// ```
+/// use assembler::{assemble, prelude::Config, AssembleOptions};
+///
+/// let source = "MOV A 42\nHALT\n";
+/// let assembly = assemble(source, &Config::builtin(), AssembleOptions::default()).unwrap();
+/// let halt = assembly.instructions.iter().last().unwrap();
+/// assert_eq!((halt.address, halt.mnemonic.as_str()), (2, "HALT"));
+/// assert_eq!(halt.span.line, 2);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Instruction {
+    pub address: usize,
+    /// The mnemonic, e.g. `MOV`.
+    pub mnemonic: String,
+    /// Kinds of the operands it was encoded with, e.g. `A` or `MEM16`.
+    pub operands: Vec<String>,
+    /// Its bytes in the image, opcode first.
+    pub bytes: Vec<u8>,
+    /// From the mnemonic to the end of the last operand.
+    pub span: SourceSpan,
+}
+
+/// The result of a successful assembly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Assembly {
+    /// Machine code, starting at address 0.
+    pub image: Vec<u8>,
+    /// Every label, constant and define with its value.
+    pub symbols: BTreeMap<String, usize>,
+    /// Every instruction of the image, in source order.
+    pub instructions: Vec<Instruction>,
+    /// Problems that didn't stop the assembly.
+    pub warnings: Vec<Diagnostic>,
+}
+
+fn instructions(checked_lines: &[CheckedLine], addresses: &[usize]) -> Vec<Instruction> {
+    checked_lines
+        .iter()
+        .zip(addresses)
+        .filter_map(|(checked_line, address)| {
+            let Line::Instruction { mnemonic, operands } = &checked_line.line else {
+                return None;
+            };
+            let name = match &mnemonic.token_type {
+                TokenType::Mnemonic(name) => name.name().to_string(),
+                _ => mnemonic.content.clone(),
+            };
+            let span = operands
+                .iter()
+                .fold(mnemonic.span.clone(), |span, (_, value)| {
+                    span + value.span()
+                });
+            Some(Instruction {
+                address: *address,
+                mnemonic: name,
+                operands: operands
+                    .iter()
+                    .map(|(operand, _)| operand.to_string())
+                    .collect(),
+                bytes: checked_line.code.bytes(),
+                span: SourceSpan {
+                    line: span.line + 1,
+                    column: span.chars.start + 1,
+                    len: span.chars.len(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Assembles `source` without printing anything or exiting, returning
+/// every error found by the first pass that failed.
+pub fn assemble(
+    source: &str,
+    config: &Config,
+    options: AssembleOptions,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    let spanned = |errs: Vec<Error>| {
+        sort_errors(errs)
+            .iter()
+            .map(Diagnostic::from_error)
+            .collect::<Vec<_>>()
+    };
+
+    check_label_sigil(options.label_sigil).map_err(|err| unspanned([err]))?;
+    let defines = options
+        .defines
+        .iter()
+        .map(|(name, value)| Define {
+            name: name.clone(),
+            value: *value,
+        })
+        .collect::<Vec<_>>();
+    let address_width = config.capabilities.address_width;
+    let mut warnings = defines
+        .iter()
+        .filter_map(|define| truncation_warning(define, address_width))
+        .map(|message| Diagnostic::new(message, None))
+        .collect::<Vec<_>>();
+
+    let source = normalize_source(source);
+    let (source_errors, source_warnings) = options.limits.check_source(&source, 0);
+    if !source_errors.is_empty() {
+        return Err(spanned(source_errors));
+    }
+    warnings.extend(spanned(source_warnings));
+    let chars = source.chars().collect::<Vec<_>>();
+    let tokens = pipeline::lex(&chars, options.syntax(config), |_| {}).map_err(spanned)?;
+    let tokens =
+        pipeline::preprocess(&tokens, &defines, &options.limits, config).map_err(spanned)?;
+
+    let mut labels = resolve_labels(&tokens, config, &defines).map_err(spanned)?;
+    inject_defines(&mut labels, &defines).map_err(|err| unspanned([err]))?;
+    let (lines, line_warnings) =
+        pipeline::parse(&tokens, &mut labels, config, false, |_| {}).map_err(spanned)?;
+    warnings.extend(spanned(line_warnings));
+    let (checked_lines, addresses) =
+        pipeline::check(lines, &labels, config, &options.limits, None).map_err(spanned)?;
+    let image = build_image(&checked_lines, &addresses);
+
+    Ok(Assembly {
+        image,
+        instructions: instructions(&checked_lines, &addresses),
+        symbols: labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect(),
+        warnings,
+    })
+}
+
+/// Turns source text into a memory image using an instruction set config.
+#[derive(Debug, Clone)]
+pub struct Assembler<'a> {
+    config: &'a Config,
+    options: AssembleOptions,
+}
+
+impl<'a> Assembler<'a> {
+    pub fn new(config: &'a Config) -> Self {
+        Self {
+            config,
+            options: AssembleOptions::default(),
+        }
+    }
+
+    /// Defines a constant for `.if`/`.ifdef` and as `#name`, like `-D`.
+    pub fn define(mut self, name: impl Into<String>, value: usize) -> Self {
+        self.options.defines.push((name.into(), value));
+        self
+    }
+
+    /// Character that prefixes label references, `#` by default. One the
+    /// lexer reads as something else fails the assembly.
+    pub fn label_sigil(mut self, sigil: char) -> Self {
+        self.options.label_sigil = sigil;
+        self
+    }
+
+    /// Whether bare identifiers in operand position are label references.
+    pub fn bare_label_refs(mut self, enabled: bool) -> Self {
+        self.options.bare_label_refs = enabled;
+        self
+    }
+
+    /// Whether identifiers alone on their line at column 0 are labels even
+    /// without a colon.
+    pub fn colon_free_labels(mut self, enabled: bool) -> Self {
+        self.options.colon_free_labels = enabled;
+        self
+    }
+
+    /// Caps on line length, macro expansion and output size.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Vec<u8>, Diagnostics> {
+        assemble(source, self.config, self.options.clone())
+            .map(|assembly| assembly.image)
+            .map_err(Diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble() {
+        let config = Config::builtin();
+        let source = "start: MOV A 42\nJMPIMM #start\nHALT\n";
+        let assembly = assemble(source, &config, AssembleOptions::default()).unwrap();
+        assert_eq!(assembly.image, [0x11, 42, 0x99, 0x00, 0x00, 0xd8]);
+        assert_eq!(assembly.symbols, BTreeMap::from([("start".to_string(), 0)]));
+        let mnemonics = assembly
+            .instructions
+            .iter()
+            .map(|instruction| (instruction.address, instruction.mnemonic.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(mnemonics, [(0, "MOV"), (2, "JMPIMM"), (5, "HALT")]);
+        assert!(assembly.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let config = Config::builtin();
+        let source = "HALT\nMOV A #missing\n";
+        let errors = assemble(source, &config, AssembleOptions::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line(), errors[0].column()), (Some(2), Some(7)));
+        assert!(errors[0].message().contains("missing"));
+
+        // Every lexer error is reported, not just the first.
+        let errors = assemble(
+            "MOV A ,\nHALT\nMOV B ,\n",
+            &config,
+            AssembleOptions::default(),
+        )
+        .unwrap_err();
+        let lines = errors.iter().map(Diagnostic::line).collect::<Vec<_>>();
+        assert_eq!(lines, [Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn test_assemble_warnings() {
+        let config = Config::builtin();
+        let options = AssembleOptions {
+            defines: vec![("BIG".to_string(), 0x12345)],
+            ..AssembleOptions::default()
+        };
+        let assembly = assemble("HALT\n", &config, options.clone()).unwrap();
+        assert_eq!(assembly.image, [0xd8]);
+        assert_eq!(assembly.warnings.len(), 1);
+        assert_eq!(assembly.warnings[0].line(), None);
+        assert!(assembly.warnings[0].message().contains("'BIG'"));
+
+        // Wider addresses hold it.
+        let mut config = config;
+        config.capabilities.address_width = 24;
+        let assembly = assemble("HALT\n", &config, options).unwrap();
+        assert!(assembly.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_label_sigil() {
+        let config = Config::builtin();
+        let assemble = |sigil: char| {
+            Assembler::new(&config)
+                .label_sigil(sigil)
+                .assemble("start: JMPIMM $start\n")
+        };
+        assert_eq!(assemble('$').unwrap(), [0x99, 0x00, 0x00]);
+
+        let errors = assemble('a').unwrap_err();
+        assert!(errors.iter().next().unwrap().message().contains("'a'"));
+    }
+
+    #[test]
+    fn test_defines_place_code() {
+        let config = Config::builtin();
+        let options = AssembleOptions {
+            defines: vec![("BASE".to_string(), 0x10), ("STEP".to_string(), 4)],
+            ..AssembleOptions::default()
+        };
+        let source = ".org #BASE\nstart: JMPIMM #start\n.align #STEP\nend: JMPIMM #end\n";
+        let assembly = assemble(source, &config, options).unwrap();
+        assert_eq!(
+            assembly.image[0x10..],
+            [0x99, 0x10, 0x00, 0, 0x99, 0x14, 0x00]
+        );
+
+        // The same as with constants.
+        let source = format!(".equ BASE 0x10\n.equ STEP 4\n{}", source);
+        let assembly = assemble(&source, &config, AssembleOptions::default()).unwrap();
+        assert_eq!(
+            assembly.image[0x10..],
+            [0x99, 0x10, 0x00, 0, 0x99, 0x14, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_assemble_with_limits() {
+        let config = Config::builtin();
+        let source = ".org 0x100\nHALT";
+        assert!(assemble(source, &config, AssembleOptions::default()).is_ok());
+
+        let options = AssembleOptions {
+            limits: Limits {
+                max_output_bytes: 0x100,
+                ..Limits::default()
+            },
+            ..AssembleOptions::default()
+        };
+        let errors = assemble(source, &config, options).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line(), Some(2));
+    }
+}
@@ -0,0 +1,183 @@
+//! Counts how often the emulator executes each instruction and the cycles
+//! spent on it, so the hot loops of a program can be found.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    checker::{CheckedLine, CheckedLineCode},
+    emulator::Machine,
+    error::FileContext,
+};
+
+/// Executions of the instruction at one address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hits {
+    pub executions: usize,
+    pub cycles: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    hits: BTreeMap<u16, Hits>,
+}
+
+/// The label at or before `address` with how far past it the address is,
+/// e.g. `loop+2`.
+fn location(labels: &HashMap<&str, usize>, address: usize) -> String {
+    let label = labels
+        .iter()
+        .filter(|(_, label_address)| **label_address <= address)
+        .max_by_key(|(label, label_address)| (**label_address, std::cmp::Reverse(**label)));
+    match label {
+        Some((label, label_address)) if *label_address == address => label.to_string(),
+        Some((label, label_address)) => format!("{}+{}", label, address - label_address),
+        None => String::new(),
+    }
+}
+
+fn share(cycles: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        _ => cycles as f64 * 100.0 / total as f64,
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the instruction `machine` just executed, which started at
+    /// cycle `start`.
+    pub fn record(&mut self, machine: &Machine, start: usize) {
+        let hits = self.hits.entry(machine.last_pc).or_default();
+        hits.executions += 1;
+        hits.cycles += machine.cycles - start;
+    }
+
+    pub fn hits(&self, address: u16) -> Hits {
+        self.hits.get(&address).copied().unwrap_or_default()
+    }
+
+    fn total_cycles(&self) -> usize {
+        self.hits.values().map(|hits| hits.cycles).sum()
+    }
+
+    /// Every executed address, those that took the most cycles first, with
+    /// its share of all cycles and the label it's under.
+    pub fn flat(&self, labels: &HashMap<&str, usize>) -> String {
+        let total = self.total_cycles();
+        let mut hits = self.hits.iter().collect::<Vec<_>>();
+        hits.sort_by_key(|(address, hits)| (std::cmp::Reverse(hits.cycles), **address));
+
+        let mut flat = "  cycles       %  executions  address  location\n".to_string();
+        for (address, hits) in hits {
+            flat += &format!(
+                "{:>8}  {:>6.2}  {:>10}  {:04x}     {}\n",
+                hits.cycles,
+                share(hits.cycles, total),
+                hits.executions,
+                address,
+                location(labels, *address as usize)
+            );
+        }
+        flat
+    }
+
+    /// Every instruction of the program with its counts next to its source
+    /// line, `-` for those that never ran. `addresses` is the layout of
+    /// `checked_lines`.
+    pub fn annotated(
+        &self,
+        checked_lines: &[CheckedLine],
+        addresses: &[usize],
+        source: &FileContext,
+    ) -> String {
+        let total = self.total_cycles();
+        let mut listing = "executions    cycles       %  address  source\n".to_string();
+        for (checked_line, address) in checked_lines.iter().zip(addresses) {
+            let CheckedLineCode::Instruction { .. } = checked_line.code else {
+                continue;
+            };
+            let Some(token) = checked_line.line.first_token() else {
+                continue;
+            };
+            let text = source.source_statement(&token.span).trim();
+            let hits = u16::try_from(*address).map_or_else(|_| Hits::default(), |a| self.hits(a));
+            let counts = match hits.executions {
+                0 => format!("{:>10}  {:>8}  {:>6}", "-", "-", "-"),
+                _ => format!(
+                    "{:>10}  {:>8}  {:>6.2}",
+                    hits.executions,
+                    hits.cycles,
+                    share(hits.cycles, total)
+                ),
+            };
+            listing += &format!("{}  {:04x}     {}\n", counts, address, text);
+        }
+        listing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        emulator::{run_with, RunOptions, Timing},
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[test]
+    fn test_profile() {
+        let config = Config::builtin();
+        let text = "start: MOV A 3\nloop: DEC A\nJMPIMMNZ #loop\nHALT\nMOV B 1\n";
+        let source = text.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        let image = crate::checker::build_image(&checked_lines, &addresses);
+
+        let options = RunOptions {
+            max_cycles: 1000,
+            breakpoint: None,
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        let mut machine = Machine::new(&image);
+        let mut profile = Profile::new();
+        run_with(&config, &mut machine, &options, |machine, start| {
+            profile.record(machine, start)
+        })
+        .unwrap();
+
+        let dec = profile.hits(addresses[1] as u16);
+        assert_eq!(dec.executions, 3);
+        assert_eq!(profile.hits(addresses[3] as u16).executions, 1);
+        assert_eq!(profile.hits(addresses[4] as u16), Hits::default());
+
+        assert_eq!(
+            profile.flat(&labels),
+            "  cycles       %  executions  address  location\n\
+             \x20     18   54.55           3  0003     loop+1\n\
+             \x20      9   27.27           3  0002     loop\n\
+             \x20      4   12.12           1  0000     start\n\
+             \x20      2    6.06           1  0006     loop+4\n"
+        );
+
+        let annotated =
+            profile.annotated(&checked_lines, &addresses, &FileContext::new(None, text));
+        let rows = annotated.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(
+            rows[2],
+            "         3         9   27.27  0002     loop: DEC A"
+        );
+        assert_eq!(rows[5], "         -         -       -  0007     MOV B 1");
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    io::{stderr, IsTerminal, Write},
+    time::{Duration, Instant},
+};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Single-line progress indicator on stderr, only drawn when it's a terminal.
+pub struct Progress {
+    enabled: bool,
+    pass: &'static str,
+    total: usize,
+    last_drawn: Option<Instant>,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && stderr().is_terminal(),
+            pass: "",
+            total: 0,
+            last_drawn: None,
+        }
+    }
+
+    pub fn start(&mut self, pass: &'static str, total: usize) {
+        self.pass = pass;
+        self.total = total;
+        self.last_drawn = None;
+        self.draw(0);
+    }
+
+    pub fn update(&mut self, done: usize) {
+        if self
+            .last_drawn
+            .is_some_and(|last_drawn| last_drawn.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.draw(done);
+    }
+
+    pub fn finish(&mut self) {
+        self.draw(self.total);
+        if self.enabled {
+            eprintln!();
+        }
+    }
+
+    fn draw(&mut self, done: usize) {
+        if !self.enabled {
+            return;
+        }
+        self.last_drawn = Some(Instant::now());
+        eprint!("\r{}: {}/{} lines", self.pass, done.min(self.total), self.total);
+        let _ = stderr().flush();
+    }
+}
+
+/// Wall-clock time of each pass, printed to stderr by `--timings`.
+pub struct Timings {
+    enabled: bool,
+    last: Instant,
+    laps: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last: Instant::now(),
+            laps: vec![],
+        }
+    }
+
+    /// Attributes the time since the previous lap to `pass`.
+    pub fn lap(&mut self, pass: &'static str) {
+        let now = Instant::now();
+        self.laps.push((pass, now - self.last));
+        self.last = now;
+    }
+
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+        for (pass, duration) in &self.laps {
+            eprintln!("{:<14} {:>9.3} ms", pass, duration.as_secs_f64() * 1000.0);
+        }
+        let total = self.laps.iter().map(|(_, duration)| *duration).sum::<Duration>();
+        eprintln!("{:<14} {:>9.3} ms", "total", total.as_secs_f64() * 1000.0);
+    }
+}
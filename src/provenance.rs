@@ -0,0 +1,67 @@
+use serde::Serialize;
+
+use crate::{config::Config, hash::config_hash};
+
+/// What an output was built with, recorded at the top of listings and
+/// symbol files so the image can be rebuilt exactly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    pub assembler: String,
+    /// FNV-1a hash of the config's instruction forms.
+    pub config: String,
+    /// The command line, quoted so a shell runs it again as it was.
+    pub command: String,
+}
+
+/// Quotes `arg` for a POSIX shell if it needs it.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./=:,+@%".contains(c));
+    match plain {
+        true => arg.to_string(),
+        false => format!("'{}'", arg.replace('\'', r"'\''")),
+    }
+}
+
+impl Provenance {
+    pub fn new(config: &Config, args: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            assembler: env!("CARGO_PKG_VERSION").to_string(),
+            config: format!("{:016x}", config_hash(config)),
+            command: args
+                .into_iter()
+                .map(|arg| shell_quote(&arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    /// The lines text outputs start with, followed by an empty one.
+    pub fn header(&self) -> String {
+        format!(
+            "Assembler: {}\nConfig:    {}\nCommand:   {}\n\n",
+            self.assembler, self.config, self.command
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance() {
+        let args = ["assembler", "-D", "NAME=1", "my prog.as", "it's"];
+        let provenance = Provenance::new(&Config::builtin(), args.map(String::from));
+        assert_eq!(
+            provenance.command,
+            r"assembler -D NAME=1 'my prog.as' 'it'\''s'"
+        );
+        assert_eq!(
+            provenance.config,
+            format!("{:016x}", config_hash(&Config::builtin()))
+        );
+    }
+}
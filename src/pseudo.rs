@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    error::{Error, WithSpan},
+    lexer::{Lexer, LexerErr},
+    macros::{split_arguments, substitute},
+    specs::Mnemonic,
+    token::{split_lines, Token, TokenType},
+};
+
+/// Placeholder for the address right after an expansion, where code called
+/// by it returns to.
+pub const RETURN_PLACEHOLDER: &str = "return";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PseudoErr {
+    #[error("Pseudo-instruction '{0}' takes {1} argument(s), found {2}.")]
+    ArgumentCount(String, usize, usize),
+    #[error("Pseudo-instruction '{0}' is invalid: {1}.")]
+    InvalidTemplate(String, String),
+}
+
+/// An instruction the hardware lacks, written in the config as the real ones
+/// it expands to, e.g. for a CPU without calls:
+///
+/// ```json
+/// "pseudo-instructions": {
+///     "CALL": {
+///         "parameters": ["target"],
+///         "expansion": ["PUSH {return} >> 8", "PUSH {return} & 0xff", "JMPIMM {target}"]
+///     }
+/// }
+/// ```
+///
+/// `{name}` stands for the argument of that parameter and `{return}` for the
+/// address after the expansion, as an immediate rather than a memory
+/// operand. Expansions are written in the default syntax.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PseudoInstruction {
+    #[serde(default)]
+    pub parameters: Vec<String>,
+    pub expansion: Vec<String>,
+}
+
+/// The identifier a placeholder is lexed as.
+fn sentinel(placeholder: &str) -> String {
+    format!("pseudo__{}", placeholder)
+}
+
+impl PseudoInstruction {
+    /// The expansion lexed, one line per line, with every placeholder as
+    /// its sentinel identifier. Fails with the reason it can't be.
+    pub fn template(&self) -> Result<Vec<Token>, String> {
+        let placeholders = self
+            .parameters
+            .iter()
+            .map(String::as_str)
+            .chain([RETURN_PLACEHOLDER]);
+        let mut source = String::new();
+        for line in &self.expansion {
+            let mut line = line.clone();
+            for placeholder in placeholders.clone() {
+                line = line.replace(&format!("{{{}}}", placeholder), &sentinel(placeholder));
+            }
+            if let Some(start) = line.find('{') {
+                let unknown = line[start..].split_whitespace().next().unwrap_or_default();
+                return Err(format!("'{}' names no parameter", unknown));
+            }
+            source += &line;
+            source.push('\n');
+        }
+
+        let chars = source.chars().collect::<Vec<_>>();
+        Lexer::new(&chars)
+            .collect::<Result<Vec<_>, LexerErr>>()
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// The label after the `number`th expansion in the source, for `{return}`.
+fn return_label(mnemonic: &str, number: usize) -> String {
+    format!("{}.return.{}", mnemonic, number)
+}
+
+/// Whether `label` is one made up for an expansion's `{return}`, which
+/// symbol tables leave out.
+pub fn is_return_label(label: &str) -> bool {
+    label
+        .rsplit_once(".return.")
+        .is_some_and(|(_, number)| number.parse::<usize>().is_ok())
+}
+
+/// The placeholder `token` stands for, if it's a sentinel.
+fn placeholder(token: &Token) -> Option<&str> {
+    let name = match &token.token_type {
+        TokenType::Mnemonic(mnemonic) => mnemonic.name(),
+        TokenType::LabelRef(name) => name,
+        _ => return None,
+    };
+    name.strip_prefix("pseudo__")
+}
+
+/// Replaces every use of a pseudo-instruction of `config` with its
+/// expansion, followed by a label on the address after it that `{return}`
+/// refers to.
+pub fn expand_pseudo_instructions(
+    tokens: &[Token],
+    config: &Config,
+) -> Result<Vec<Token>, Vec<Error>> {
+    if config.pseudo_instructions.is_empty() {
+        return Ok(tokens.to_vec());
+    }
+    let templates = config
+        .pseudo_instructions
+        .iter()
+        .map(|(name, pseudo)| (name.as_str(), (pseudo, pseudo.template())))
+        .collect::<HashMap<_, _>>();
+
+    let mut output = vec![];
+    let mut errors = vec![];
+    let mut expansions = 0;
+    for line in split_lines(tokens) {
+        let labels = line
+            .iter()
+            .take_while(|token| matches!(token.token_type, TokenType::Label(_)))
+            .count();
+        let (labels, rest) = line.split_at(labels);
+        let used = match rest.first() {
+            Some(
+                token @ Token {
+                    token_type: TokenType::Mnemonic(mnemonic),
+                    ..
+                },
+            ) => templates.get(mnemonic.name()).map(|used| (token, used)),
+            _ => None,
+        };
+        let Some((invocation, (pseudo, template))) = used else {
+            output.extend_from_slice(line);
+            continue;
+        };
+        // The config checks templates when it's read, but it may have been
+        // changed since.
+        let template = match template {
+            Ok(template) => template,
+            Err(reason) => {
+                let err = PseudoErr::InvalidTemplate(invocation.content.clone(), reason.clone());
+                errors.push(err.with_span(invocation.span.clone()));
+                continue;
+            }
+        };
+
+        let arguments = split_arguments(&rest[1..]);
+        if arguments.len() != pseudo.parameters.len() {
+            let err = PseudoErr::ArgumentCount(
+                invocation.content.clone(),
+                pseudo.parameters.len(),
+                arguments.len(),
+            );
+            errors.push(err.with_span(invocation.span.clone()));
+            continue;
+        }
+
+        expansions += 1;
+        let return_label = return_label(&invocation.content, expansions);
+        output.extend_from_slice(labels);
+        for token in template {
+            let mut token = token.clone();
+            token.span = invocation.span.clone();
+            match placeholder(&token) {
+                // A bare name, which unlike a `#label` is an immediate.
+                Some(RETURN_PLACEHOLDER) => {
+                    token.token_type = TokenType::Mnemonic(Mnemonic::new(return_label.clone()));
+                    token.content = return_label.clone();
+                    token.lexeme = return_label.clone();
+                    output.push(token);
+                }
+                Some(parameter) => {
+                    let i = pseudo
+                        .parameters
+                        .iter()
+                        .position(|name| name == parameter)
+                        .expect("templates only hold known placeholders");
+                    substitute(&token, arguments[i], &mut output);
+                }
+                None => output.push(token),
+            }
+        }
+
+        let mut label = Token::new(
+            TokenType::Label(return_label.clone()),
+            return_label.clone(),
+            invocation.span.line,
+            invocation.span.chars.clone(),
+        );
+        label.span = invocation.span.clone();
+        label.lexeme = format!("{}:", return_label);
+        label.trailing_trivia = "\n".to_string();
+        output.push(label);
+    }
+
+    match errors.is_empty() {
+        true => Ok(output),
+        false => Err(errors),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_expansion() {
+        let calls = r#""pseudo-instructions": {
+            "CALL": {
+                "parameters": ["target"],
+                "expansion": ["PUSH {return} >> 8", "PUSH {return} & 0xff", "JMPIMM {target}"]
+            }
+        }"#;
+        let config = crate::config::DEFAULT_CONFIG.replacen('{', &format!("{{{},", calls), 1);
+        let config = Config::from_json(&config).unwrap();
+
+        let source = "CALL #f\nHALT\nf: CALL #f";
+        let assembly = crate::assemble(source, &config, Default::default()).unwrap();
+        // PUSHIMM 0, PUSHIMM 7, JMPIMM f, HALT
+        assert_eq!(
+            assembly.image[..8],
+            [0xca, 0x00, 0xca, 0x07, 0x99, 0x08, 0x00, 0xd8]
+        );
+        assert_eq!(assembly.symbols["CALL.return.1"], 7);
+        assert_eq!(assembly.symbols["CALL.return.2"], 15);
+        assert!(crate::assemble("CALL", &config, Default::default()).is_err());
+        assert!(is_return_label("CALL.return.2"));
+        assert!(!is_return_label("CALL.return.x"));
+
+        let broken = PseudoInstruction {
+            parameters: vec![],
+            expansion: vec!["JMPIMM {target}".to_string()],
+        };
+        assert_eq!(
+            broken.template(),
+            Err("'{target}' names no parameter".to_string())
+        );
+
+        // The config's templates are public, so they may be broken after it
+        // was checked.
+        let mut config = config;
+        config
+            .pseudo_instructions
+            .insert("CALL".to_string(), broken);
+        let errors = crate::assemble("CALL #f\nf: HALT", &config, Default::default()).unwrap_err();
+        assert_eq!(
+            errors[0].message(),
+            "Pseudo-instruction 'CALL' is invalid: '{target}' names no parameter."
+        );
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+
+use crate::{
+    checker::{CheckedLine, CheckedLineCode},
+    decoder::DecodedInstruction,
+    parser::Line,
+    specs::Operand,
+    token::{Span, TokenType},
+};
+
+/// A run of instructions no path from the entry point reaches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreachableCode {
+    pub address: usize,
+    /// Label at the start of the run, if any.
+    pub label: Option<String>,
+    /// Number of instructions in the run.
+    pub instructions: usize,
+    /// The first instruction of the run.
+    pub span: Span,
+}
+
+pub(crate) enum Flow {
+    /// Execution continues with the next instruction.
+    Next,
+    /// Execution continues at the target and, for conditional jumps and
+    /// calls, also with the next instruction.
+    Jump {
+        target: Option<usize>,
+        falls_through: bool,
+    },
+    /// `HALT`, a return or an unconditional jump through T.
+    Stop,
+}
+
+/// How control leaves an instruction called `name` whose next instruction
+/// is at `next`, given its first operand, the address a 16-bit one points
+/// at, if known, and the byte after the opcode. Jumps are the
+/// `JMPIMM`/`JMPREL` forms, optionally followed by the flag they test.
+fn mnemonic_flow(
+    name: &str,
+    first: Option<Operand>,
+    target: Option<usize>,
+    offset: u8,
+    next: usize,
+) -> Flow {
+    let condition = ["JMPIMM", "JMPREL"]
+        .iter()
+        .find_map(|jump| name.strip_prefix(jump));
+
+    let Some(condition) = condition else {
+        return match name {
+            "HALT" => Flow::Stop,
+            _ => Flow::Next,
+        };
+    };
+    let conditional = !condition.is_empty();
+
+    match (name.starts_with("JMPREL"), first) {
+        // A `JMPREL` with no operand returns and one to an absolute
+        // address calls, both come back to the next instruction.
+        (true, None) => Flow::Stop,
+        (true, Some(Operand::Mem16)) => Flow::Jump {
+            target,
+            falls_through: true,
+        },
+        (true, Some(Operand::Const | Operand::Mem8)) => Flow::Jump {
+            target: relative_target(name, first, offset, next),
+            falls_through: conditional,
+        },
+        (false, Some(Operand::Mem16)) => Flow::Jump {
+            target,
+            falls_through: conditional,
+        },
+        // Jumps through T go wherever T points, which isn't known here.
+        _ if conditional => Flow::Next,
+        _ => Flow::Stop,
+    }
+}
+
+/// Where a `JMPREL` with a constant or MEM8 operand lands, reading the byte
+/// after the opcode as a signed offset from the next instruction. `None`
+/// for every other form.
+fn relative_target(name: &str, first: Option<Operand>, offset: u8, next: usize) -> Option<usize> {
+    match first {
+        Some(Operand::Const | Operand::Mem8) if name.starts_with("JMPREL") => {
+            next.checked_add_signed(offset as i8 as isize)
+        }
+        _ => None,
+    }
+}
+
+/// The offset byte of a relative jump at `address` and where it lands.
+pub(crate) fn line_displacement(line: &CheckedLine, address: usize) -> Option<(u8, usize)> {
+    let Line::Instruction { mnemonic, operands } = &line.line else {
+        return None;
+    };
+    let TokenType::Mnemonic(name) = &mnemonic.token_type else {
+        return None;
+    };
+    let bytes = line.code.bytes();
+    let offset = *bytes.get(1)?;
+    let first = operands.first().map(|(operand, _)| *operand);
+    let target = relative_target(name.name(), first, offset, address + bytes.len())?;
+    Some((offset, target))
+}
+
+/// The offset byte of a decoded relative jump and where it lands.
+pub(crate) fn decoded_displacement(instruction: &DecodedInstruction) -> Option<(u8, usize)> {
+    let offset = *instruction.operand_bytes.first()?;
+    let target = relative_target(
+        instruction.signature.mnemonic.name(),
+        instruction
+            .operand_values()
+            .first()
+            .map(|(operand, _)| *operand),
+        offset,
+        instruction.address + instruction.signature.encoded_size(),
+    )?;
+    Some((offset, target))
+}
+
+fn flow(line: &CheckedLine, address: usize, symbols: &HashMap<&str, usize>) -> Flow {
+    let Line::Instruction { mnemonic, operands } = &line.line else {
+        return Flow::Next;
+    };
+    let TokenType::Mnemonic(name) = &mnemonic.token_type else {
+        return Flow::Next;
+    };
+    let bytes = line.code.bytes();
+    let target = operands
+        .first()
+        .and_then(|(_, target)| target.evaluate(symbols).ok())
+        .map(|target| target as usize);
+    mnemonic_flow(
+        name.name(),
+        operands.first().map(|(operand, _)| *operand),
+        target,
+        bytes.get(1).copied().unwrap_or_default(),
+        address + bytes.len(),
+    )
+}
+
+/// How control leaves a decoded instruction.
+pub(crate) fn decoded_flow(instruction: &DecodedInstruction) -> Flow {
+    let operands = instruction.operand_values();
+    mnemonic_flow(
+        instruction.signature.mnemonic.name(),
+        operands.first().map(|(operand, _)| *operand),
+        operands.first().map(|(_, value)| *value),
+        instruction
+            .operand_bytes
+            .first()
+            .copied()
+            .unwrap_or_default(),
+        instruction.address + instruction.signature.encoded_size(),
+    )
+}
+
+/// Whether execution can go on from `line` into the one after it, judged
+/// from the instruction alone: only `HALT`, returns and unconditional jumps
+/// stop it.
+pub fn falls_through(line: &Line) -> bool {
+    let Line::Instruction { mnemonic, operands } = line else {
+        return true;
+    };
+    let TokenType::Mnemonic(name) = &mnemonic.token_type else {
+        return true;
+    };
+    let name = name.name();
+    let Some(condition) = ["JMPIMM", "JMPREL"]
+        .iter()
+        .find_map(|jump| name.strip_prefix(jump))
+    else {
+        return name != "HALT";
+    };
+    // A `JMPREL` to an absolute address is a call.
+    let call = name.starts_with("JMPREL") && matches!(operands.first(), Some((Operand::Mem16, _)));
+    !condition.is_empty() || call
+}
+
+/// Addresses that label expressions outside jump targets evaluate to, e.g.
+/// a routine address loaded into T or stored in a `.word` table.
+fn referenced_addresses(
+    line: &CheckedLine,
+    symbols: &HashMap<&str, usize>,
+    jump: bool,
+) -> Vec<usize> {
+    let values = match &line.line {
+        Line::Instruction { .. } if jump => vec![],
+        Line::Instruction { operands, .. } => operands.iter().map(|(_, value)| value).collect(),
+        Line::Byte(values) | Line::Word(values) => values.iter().collect(),
+        Line::Constant { value, .. } => vec![value],
+        _ => vec![],
+    };
+    values
+        .into_iter()
+        .filter(|value| value.has_label_ref())
+        .filter_map(|value| value.evaluate(symbols).ok())
+        .map(|address| address as usize)
+        .collect()
+}
+
+/// Finds the instructions that can't run, starting from every `.entry`, or
+/// without one the instruction at address 0 (or the first one), and
+/// following fall-through, jumps and calls. Any
+/// address a label expression yields outside a jump is also treated as
+/// reachable, since it may be jumped to through T. `symbols` evaluates
+/// operands, `labels` only names the runs.
+pub fn find_unreachable(
+    checked_lines: &[CheckedLine],
+    addresses: &[usize],
+    symbols: &HashMap<&str, usize>,
+    labels: &HashMap<&str, usize>,
+) -> Vec<UnreachableCode> {
+    let instructions = checked_lines
+        .iter()
+        .zip(addresses)
+        .enumerate()
+        .filter(|(_, (line, _))| matches!(line.code, CheckedLineCode::Instruction { .. }))
+        .map(|(i, (_, address))| (i, *address))
+        .collect::<Vec<_>>();
+    let by_address = instructions
+        .iter()
+        .map(|(i, address)| (*address, *i))
+        .collect::<HashMap<_, _>>();
+
+    // The next instruction line, unless data comes first.
+    let fall_through = |i: usize| {
+        checked_lines[i + 1..]
+            .iter()
+            .enumerate()
+            .find_map(|(offset, line)| match line.code {
+                CheckedLineCode::Instruction { .. } => Some(Some(i + 1 + offset)),
+                CheckedLineCode::Byte(ref bytes) if !bytes.is_empty() => Some(None),
+                _ => None,
+            })
+            .flatten()
+    };
+
+    let mut pending = checked_lines
+        .iter()
+        .filter_map(|line| match &line.line {
+            Line::Entry(target) => target.evaluate(symbols).ok(),
+            _ => None,
+        })
+        .filter_map(|address| by_address.get(&(address as usize)).copied())
+        .collect::<Vec<_>>();
+    let has_entry = checked_lines
+        .iter()
+        .any(|line| matches!(line.line, Line::Entry(_)));
+    if !has_entry {
+        if let Some((first, _)) = instructions
+            .iter()
+            .find(|(_, address)| *address == 0)
+            .or(instructions.first())
+        {
+            pending.push(*first);
+        }
+    }
+    for (i, line) in checked_lines.iter().enumerate() {
+        let jump = matches!(flow(line, addresses[i], symbols), Flow::Jump { .. });
+        pending.extend(
+            referenced_addresses(line, symbols, jump)
+                .iter()
+                .filter_map(|address| by_address.get(address)),
+        );
+    }
+
+    let mut reached = vec![false; checked_lines.len()];
+    while let Some(i) = pending.pop() {
+        if reached[i] {
+            continue;
+        }
+        reached[i] = true;
+        match flow(&checked_lines[i], addresses[i], symbols) {
+            Flow::Next => pending.extend(fall_through(i)),
+            Flow::Jump {
+                target,
+                falls_through,
+            } => {
+                pending.extend(target.and_then(|target| by_address.get(&target)));
+                if falls_through {
+                    pending.extend(fall_through(i));
+                }
+            }
+            Flow::Stop => {}
+        }
+    }
+
+    // The alphabetically first label at each address names the run there.
+    let mut label_at: HashMap<usize, &str> = HashMap::new();
+    for (name, address) in labels {
+        label_at
+            .entry(*address)
+            .and_modify(|first| *first = (*first).min(*name))
+            .or_insert(name);
+    }
+
+    let mut unreachable: Vec<UnreachableCode> = vec![];
+    let mut previous_reached = true;
+    for (i, address) in instructions {
+        if reached[i] {
+            previous_reached = true;
+            continue;
+        }
+        let label = label_at.get(&address).map(|label| label.to_string());
+        match unreachable.last_mut() {
+            Some(run) if !previous_reached && label.is_none() => run.instructions += 1,
+            _ => unreachable.push(UnreachableCode {
+                address,
+                label,
+                instructions: 1,
+                span: checked_lines[i]
+                    .line
+                    .first_token()
+                    .expect("instruction lines start with their mnemonic")
+                    .span
+                    .clone(),
+            }),
+        }
+        previous_reached = false;
+    }
+    unreachable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    /// Address, label and length of every unreachable run in `source`.
+    fn unreachable(source: &str) -> Vec<(usize, Option<String>, usize)> {
+        let config = Config::builtin();
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        find_unreachable(&checked_lines, &addresses, &labels, &labels)
+            .into_iter()
+            .map(|code| (code.address, code.label, code.instructions))
+            .collect()
+    }
+
+    #[test]
+    fn test_entry() {
+        let source = "      HALT\n\
+            main: JMPREL #sub\n\
+            \x20     HALT\n\
+            sub:  JMPREL\n";
+        assert_eq!(
+            unreachable(source),
+            [
+                (1, Some("main".to_string()), 2),
+                (5, Some("sub".to_string()), 1)
+            ]
+        );
+
+        let source = format!("{}.entry #main\n", source);
+        assert_eq!(unreachable(&source), [(0, None, 1)]);
+    }
+
+    #[test]
+    fn test_calls_and_jumps() {
+        let source = "start:  JMPREL #sub\n\
+            \x20       JMPIMM #skip\n\
+            dead:   MOV A 1\n\
+            skip:   HALT\n\
+            sub:    MOV B 2\n\
+            \x20       JMPREL\n\
+            unused: MOV A 3\n\
+            \x20       JMPREL\n";
+        assert_eq!(
+            unreachable(source),
+            [
+                (6, Some("dead".to_string()), 1),
+                (12, Some("unused".to_string()), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use thiserror::Error;
+
+use crate::{
+    config::{Config, Signature},
+    decoder::Decoder,
+    emulator::{parse_watchpoint, run, EmulatorErr, Machine, RunOptions, Timing, Watchpoint},
+    lexer::parse_number,
+    prelude::{assemble, AssembleOptions, Diagnostic},
+    snapshot::{load_snapshot, save_snapshot, SnapshotErr},
+};
+
+/// Cycles `:run` may take before it stops.
+const MAX_CYCLES: usize = 1_000_000;
+
+/// Instructions executed for one entered line before giving up, so a loop
+/// that never leaves the code entered so far can't hang the session.
+const MAX_STEPS: usize = 100_000;
+
+const HELP: &str = "\
+:symbols       list the labels and constants defined so far
+:regs          show the registers, when executing
+:load FILE     start over with a program read from FILE, without running it
+:break TARGET  make :run stop at a label or address
+:watch TARGET  make :run stop after a read or write of a label or address,
+               only reads with TARGET:r, only writes with TARGET:w
+:run           execute until HALT, the breakpoint or the cycle limit
+:save FILE     save the registers, memory and cycle count to FILE
+:restore FILE  continue from the machine state saved in FILE
+:expect R=N    fail unless register R (A, B, F, TH, TL, PC or STC) holds N
+:source        print everything entered so far
+:reset         forget everything and start over at address 0
+:help          show this help
+:quit          leave
+";
+
+/// Commands a script may give without the `:`, e.g. `run`.
+const COMMANDS: [&str; 13] = [
+    "symbols", "regs", "load", "break", "watch", "run", "save", "restore", "expect", "source",
+    "reset", "help", "quit",
+];
+
+#[derive(Debug, Error)]
+pub enum ReplErr {
+    #[error("{}", messages(.0))]
+    Assembly(Vec<Diagnostic>),
+    #[error("{0}")]
+    Emulator(EmulatorErr),
+    #[error("Stopped after {MAX_STEPS} instructions without leaving the entered code.")]
+    StepLimit,
+    #[error("Unknown command ':{0}', try :help.")]
+    UnknownCommand(String),
+    #[error("Command ':{0}' expects {1}.")]
+    InvalidArgument(String, String),
+    #[error("Couldn't read '{0}': {1}")]
+    Load(String, String),
+    #[error("Couldn't write '{0}': {1}")]
+    Save(String, String),
+    #[error("{0}")]
+    Snapshot(SnapshotErr),
+    #[error("Not executing, start the repl with --run or :load a program.")]
+    NotExecuting,
+    #[error("Expected {0}={1:#x}, found {2:#x}.")]
+    ExpectationFailed(String, usize, usize),
+}
+
+fn messages(diagnostics: &[Diagnostic]) -> String {
+    let messages = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message().to_string())
+        .collect::<Vec<_>>();
+    messages.join("\n")
+}
+
+/// An interactive session. Every entered line is appended to the program
+/// assembled so far, which is assembled again as a whole so labels and
+/// constants carry over, and the line's bytes are shown. When executing,
+/// the new code is loaded into the machine and run for as long as the
+/// program counter stays inside the entered code.
+pub struct Repl<'a> {
+    config: &'a Config,
+    signatures: Vec<Signature>,
+    source: String,
+    image_len: usize,
+    symbols: BTreeMap<String, usize>,
+    machine: Option<Machine>,
+    breakpoint: Option<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(config: &'a Config, execute: bool) -> Self {
+        Self {
+            config,
+            signatures: config.signatures(),
+            source: String::new(),
+            image_len: 0,
+            symbols: BTreeMap::new(),
+            machine: execute.then(|| Machine::new(&[])),
+            breakpoint: None,
+            watchpoints: vec![],
+        }
+    }
+
+    /// Handles one line of input, a `:command` or source, and returns what
+    /// to print. A line that fails to assemble is forgotten.
+    pub fn eval(&mut self, line: &str) -> Result<String, ReplErr> {
+        let line = line.trim_end();
+        if let Some(command) = line.trim_start().strip_prefix(':') {
+            return self.command(command.trim());
+        }
+        if line.trim().is_empty() {
+            return Ok(String::new());
+        }
+
+        let source = format!("{}{}\n", self.source, line);
+        let assembly = assemble(&source, self.config, AssembleOptions::default())
+            .map_err(ReplErr::Assembly)?;
+        let line_number = source.lines().count();
+
+        let mut reply = String::new();
+        for (name, value) in &assembly.symbols {
+            if !self.symbols.contains_key(name) {
+                reply += &format!("{} = {:#06x}\n", name, value);
+            }
+        }
+        for instruction in &assembly.instructions {
+            if instruction.span.line != line_number {
+                continue;
+            }
+            let bytes = instruction
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>();
+            reply += &format!("{:04x}  {}\n", instruction.address, bytes.join(" "));
+        }
+
+        let start = self.image_len;
+        self.source = source;
+        self.image_len = assembly.image.len();
+        self.symbols = assembly.symbols;
+
+        if let Some(machine) = &mut self.machine {
+            let end = assembly.image.len().min(machine.memory.len());
+            if start < end {
+                machine.memory[start..end].copy_from_slice(&assembly.image[start..end]);
+            }
+            let decoder = Decoder::new(&self.signatures);
+            let mut steps = 0;
+            while (machine.pc as usize) < end {
+                if steps == MAX_STEPS {
+                    return Err(ReplErr::StepLimit);
+                }
+                steps += 1;
+                if machine
+                    .step(self.config, &decoder)
+                    .map_err(ReplErr::Emulator)?
+                {
+                    reply += "halted\n";
+                    break;
+                }
+            }
+            reply += &format!("{}\n", machine);
+        }
+        Ok(reply)
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, ReplErr> {
+        let (command, argument) = match command.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (command, ""),
+        };
+        let invalid =
+            |expected: &str| ReplErr::InvalidArgument(command.to_string(), expected.to_string());
+        match command {
+            "symbols" => Ok(self
+                .symbols
+                .iter()
+                .map(|(name, value)| format!("{} = {:#06x}\n", name, value))
+                .collect()),
+            "regs" => Ok(format!("{}\n", self.machine()?)),
+            "load" if !argument.is_empty() => self.load(Path::new(argument)),
+            "load" => Err(invalid("a file name")),
+            "break" => {
+                let address = self
+                    .symbols
+                    .get(argument)
+                    .copied()
+                    .or_else(|| parse_number(argument).and_then(|n| usize::try_from(n).ok()))
+                    .and_then(|address| u16::try_from(address).ok())
+                    .ok_or_else(|| invalid("a label or an address"))?;
+                self.breakpoint = Some(address);
+                Ok(String::new())
+            }
+            "watch" => {
+                let symbols = self
+                    .symbols
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), *value))
+                    .collect();
+                let watchpoint = parse_watchpoint(argument)
+                    .and_then(|target| target.resolve(&symbols))
+                    .map_err(|_| invalid("a label or an address, optionally with :r, :w or :rw"))?;
+                self.watchpoints.push(watchpoint);
+                Ok(String::new())
+            }
+            "run" => {
+                let options = RunOptions {
+                    max_cycles: self.machine()?.cycles + MAX_CYCLES,
+                    breakpoint: self.breakpoint,
+                    watchpoints: self.watchpoints.clone(),
+                    timing: Timing::default(),
+                };
+                let machine = self.machine.as_mut().ok_or(ReplErr::NotExecuting)?;
+                let stop = run(self.config, machine, &options).map_err(ReplErr::Emulator)?;
+                Ok(format!("{}\n{}\n", stop, machine))
+            }
+            "save" if !argument.is_empty() => {
+                save_snapshot(self.machine()?, Path::new(argument))
+                    .map_err(|err| ReplErr::Save(argument.to_string(), err.to_string()))?;
+                Ok(String::new())
+            }
+            "save" => Err(invalid("a file name")),
+            "restore" if !argument.is_empty() => {
+                self.machine()?;
+                let machine = load_snapshot(Path::new(argument)).map_err(ReplErr::Snapshot)?;
+                let reply = format!("{}\n", machine);
+                self.machine = Some(machine);
+                Ok(reply)
+            }
+            "restore" => Err(invalid("a file name")),
+            "expect" => {
+                let (register, value) = argument
+                    .split_once('=')
+                    .and_then(|(register, value)| {
+                        let value = parse_number(value.trim())?;
+                        Some((register.trim().to_uppercase(), usize::try_from(value).ok()?))
+                    })
+                    .ok_or_else(|| invalid("REGISTER=VALUE"))?;
+                let actual = register_value(self.machine()?, &register)
+                    .ok_or_else(|| invalid("REGISTER=VALUE"))?;
+                match actual == value {
+                    true => Ok(String::new()),
+                    false => Err(ReplErr::ExpectationFailed(register, value, actual)),
+                }
+            }
+            "source" => Ok(self.source.clone()),
+            "reset" => {
+                *self = Repl::new(self.config, self.machine.is_some());
+                Ok(String::new())
+            }
+            "help" => Ok(HELP.to_string()),
+            _ => Err(ReplErr::UnknownCommand(command.to_string())),
+        }
+    }
+
+    fn machine(&self) -> Result<&Machine, ReplErr> {
+        self.machine.as_ref().ok_or(ReplErr::NotExecuting)
+    }
+
+    /// Replaces the session with the program in `path`, loaded into a fresh
+    /// machine that's left at address 0.
+    fn load(&mut self, path: &Path) -> Result<String, ReplErr> {
+        let name = path.display().to_string();
+        let source =
+            fs::read_to_string(path).map_err(|err| ReplErr::Load(name.clone(), err.to_string()))?;
+        let assembly = assemble(&source, self.config, AssembleOptions::default())
+            .map_err(ReplErr::Assembly)?;
+        *self = Repl::new(self.config, true);
+        self.machine = Some(Machine::new(&assembly.image));
+        self.source = source;
+        self.image_len = assembly.image.len();
+        self.symbols = assembly.symbols;
+        Ok(format!("Loaded {} byte(s) from {}\n", self.image_len, name))
+    }
+}
+
+fn register_value(machine: &Machine, register: &str) -> Option<usize> {
+    Some(match register {
+        "A" => machine.a as usize,
+        "B" => machine.b as usize,
+        "F" => machine.f as usize,
+        "TH" => machine.th as usize,
+        "TL" => machine.tl as usize,
+        "PC" => machine.pc as usize,
+        "STC" => machine.stc as usize,
+        _ => return None,
+    })
+}
+
+/// A script line as if it was typed: one starting with a command word, like
+/// `expect A=3`, is that command.
+fn script_line(line: &str) -> String {
+    let word = line.split_whitespace().next().unwrap_or_default();
+    match COMMANDS.contains(&word) {
+        true => format!(":{}", line.trim_start()),
+        false => line.to_string(),
+    }
+}
+
+/// Evaluates every line of a script as if it was typed, printing each one
+/// after a `> ` prompt followed by its reply or error. Returns the number
+/// of lines that failed, failed expectations included.
+pub fn run_script(repl: &mut Repl, script: &str, output: &mut String) -> usize {
+    let mut failures = 0;
+    for (number, line) in script.lines().enumerate() {
+        let command = script_line(line);
+        if command.trim() == ":quit" {
+            break;
+        }
+        *output += &format!("> {}\n", line);
+        match repl.eval(&command) {
+            Ok(reply) => *output += &reply,
+            Err(err) => {
+                failures += 1;
+                *output += &format!("error on line {}: {}\n", number + 1, err);
+            }
+        }
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_repl() {
+        let config = Config::builtin();
+        let mut repl = Repl::new(&config, true);
+
+        let reply = repl.eval("start: MOV A 42").unwrap();
+        assert!(reply.starts_with("start = 0x0000\n0000  "));
+        assert!(reply.contains("A=2a"));
+
+        assert!(matches!(
+            repl.eval("MOV A #nowhere"),
+            Err(ReplErr::Assembly(_))
+        ));
+        assert_eq!(repl.source, "start: MOV A 42\n");
+
+        let reply = repl.eval("HALT").unwrap();
+        assert!(reply.contains("halted"));
+        assert_eq!(repl.eval(":symbols").unwrap(), "start = 0x0000\n");
+        assert!(matches!(
+            repl.eval(":bogus"),
+            Err(ReplErr::UnknownCommand(_))
+        ));
+
+        repl.eval(":reset").unwrap();
+        assert_eq!(repl.eval(":source").unwrap(), "");
+    }
+
+    #[test]
+    fn test_run_script() {
+        let config = Config::builtin();
+        let program = NamedTempFile::new().unwrap();
+        fs::write(program.path(), "MOV A 3\nend: HALT\n").unwrap();
+        let script = format!(
+            "load {}\nbreak end\n:run\nexpect A=3\n:expect a=0x4\nrun\n",
+            program.path().display()
+        );
+
+        let mut repl = Repl::new(&config, false);
+        let mut output = String::new();
+        let failures = run_script(&mut repl, &script, &mut output);
+        assert_eq!(failures, 1);
+        assert!(output.contains("stopped at breakpoint"));
+        assert!(output.contains("error on line 5: Expected A=0x4, found 0x3."));
+        assert!(output.ends_with("halted\nA=03 B=00 TH=00 TL=00 F=00 [-----]\nPC=0003 STC=ffff\n2 instructions, 6 cycles\n"));
+    }
+
+    #[test]
+    fn test_watch() {
+        let config = Config::builtin();
+        let program = NamedTempFile::new().unwrap();
+        let source = "MOV A 1\nMOVAT [0x8000] A\nMOV B [0x8000]\nMOV A 2\nHALT\n";
+        fs::write(program.path(), source).unwrap();
+        let script = format!(
+            "load {}\nwatch 0x8000:r\nrun\nexpect PC=8\nexpect B=1\nwatch 0x8000:x\n",
+            program.path().display()
+        );
+
+        let mut repl = Repl::new(&config, false);
+        let mut output = String::new();
+        assert_eq!(run_script(&mut repl, &script, &mut output), 1, "{}", output);
+        assert!(output.contains("stopped at read of 0x8000 by the instruction at 0x0005"));
+        assert!(output.contains("error on line 6: Command ':watch' expects"));
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let config = Config::builtin();
+        let program = NamedTempFile::new().unwrap();
+        let snapshot = NamedTempFile::new().unwrap();
+        fs::write(program.path(), "MOV A 3\nend: MOV B 4\nHALT\n").unwrap();
+        let script = format!(
+            "load {0}\nbreak end\nrun\nsave {1}\nrun\nexpect B=4\nrestore {1}\nexpect B=0\nexpect PC=2\nrun\nexpect B=4\n",
+            program.path().display(),
+            snapshot.path().display()
+        );
+
+        let mut repl = Repl::new(&config, false);
+        let mut output = String::new();
+        assert_eq!(run_script(&mut repl, &script, &mut output), 0, "{}", output);
+        assert!(matches!(
+            repl.eval(":restore /nonexistent/snapshot.json"),
+            Err(ReplErr::Snapshot(_))
+        ));
+    }
+}
@@ -1,25 +1,504 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
-use crate::token::{Token, TokenType};
+use thiserror::Error;
 
-pub fn get_resolved_labels(tokens: &[Token]) -> HashMap<&str, usize> {
+use crate::{
+    config::Config,
+    defines::Define,
+    error::{Error, WithSpan},
+    i18n::Message,
+    parser::{parse_with_config, Line},
+    specs::Register,
+    token::{split_lines, Token, TokenType},
+};
+
+#[derive(PartialEq, Eq, Debug, Error)]
+pub enum ResolveErr {
+    #[error("Label '{0}' is defined more than once.")]
+    DuplicateLabel(String),
+    #[error("Constant '{0}' is defined more than once.")]
+    DuplicateConstant(String),
+    #[error("Constant '{0}' shadows a label of the same name.")]
+    ConstantShadowsLabel(String),
+    #[error("Label '{0}' has the same name as a constant.")]
+    LabelShadowsConstant(String),
+    #[error("Label '{0}' has the name of a register.")]
+    LabelNamedRegister(String),
+    #[error("Constant '{0}' has the name of a register.")]
+    ConstantNamedRegister(String),
+    #[error("'{0}' uses a symbol defined after it, so the address is not known yet.")]
+    DependsOnLaterSymbol(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolKind {
+    Label,
+    Constant,
+}
+
+/// The labels and `.equ` constants a line defines, with their kinds.
+fn symbol_definitions(line: &[Token]) -> Vec<(&str, SymbolKind, &Token)> {
+    let mut definitions = line
+        .iter()
+        .map_while(|token| match &token.token_type {
+            TokenType::Label(label) => Some((label.as_str(), SymbolKind::Label, token)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let rest = &line[definitions.len()..];
+    if let [directive, name, ..] = rest {
+        if directive.token_type == TokenType::Equ {
+            match &name.token_type {
+                TokenType::LabelRef(label) => {
+                    definitions.push((label.as_str(), SymbolKind::Constant, name))
+                }
+                TokenType::Mnemonic(_) | TokenType::Register(_) => {
+                    definitions.push((name.content.as_str(), SymbolKind::Constant, name))
+                }
+                _ => {}
+            }
+        }
+    }
+    definitions
+}
+
+/// Bytes a line places in memory. `.org` and `.align` move the address
+/// instead, so they count as none.
+fn line_size(line: &Line, config: &Config) -> usize {
+    match line {
+        Line::Instruction { mnemonic, operands } => {
+            let TokenType::Mnemonic(mnemonic) = &mnemonic.token_type else {
+                return 0;
+            };
+            let kinds = operands
+                .iter()
+                .map(|(operand, _)| *operand)
+                .collect::<Vec<_>>();
+            // Forms the config doesn't have are reported by the checker.
+            config.instruction_size(mnemonic, &kinds).unwrap_or(0)
+        }
+        Line::Byte(values) => values
+            .iter()
+            .map(
+                |value| match value.as_token().map(|token| &token.token_type) {
+                    Some(TokenType::String(string)) => string.chars().count(),
+                    _ => 1,
+                },
+            )
+            .sum(),
+        Line::Word(values) => 2 * values.len(),
+        Line::Str {
+            strings,
+            zero_terminated,
+        } => strings
+            .iter()
+            .map(|token| match &token.token_type {
+                TokenType::String(string) => string.chars().count() + *zero_terminated as usize,
+                _ => 0,
+            })
+            .sum(),
+        Line::Constant { .. }
+        | Line::Org(_)
+        | Line::Align(_)
+        | Line::Entry(_)
+        | Line::Segment(_)
+        | Line::Barrier(_)
+        | Line::Export(_)
+        | Line::Import(_) => 0,
+    }
+}
+
+/// Address of every label, found by laying the program out the way the
+/// checker will. Each line is parsed and sized from the forms in `config`,
+/// so register operands take no bytes and memory operands take as many as
+/// their kind encodes to. The checker then fills these values in.
+///
+/// A `.org` or `.align` that can't be evaluated leaves the address where it
+/// was, so this is only exact for programs [`resolve_labels`] accepts.
+pub fn get_resolved_labels<'a>(tokens: &'a [Token], config: &Config) -> HashMap<&'a str, usize> {
+    lay_out(tokens, config, &[]).0
+}
+
+/// Like [`get_resolved_labels`], for a program assembled with `defines`,
+/// which `.org` and `.align` may use like `.equ` constants. Fails for every
+/// `.org` and `.align` that can't be evaluated where it is, as the labels
+/// after it would be misplaced.
+pub fn resolve_labels<'a>(
+    tokens: &'a [Token],
+    config: &Config,
+    defines: &[Define],
+) -> Result<HashMap<&'a str, usize>, Vec<Error>> {
+    match lay_out(tokens, config, defines) {
+        (labels, errors) if errors.is_empty() => Ok(labels),
+        (_, errors) => Err(errors),
+    }
+}
+
+fn lay_out<'a>(
+    tokens: &'a [Token],
+    config: &Config,
+    defines: &[Define],
+) -> (HashMap<&'a str, usize>, Vec<Error>) {
     let mut memory_pointer = 0;
     let mut labels: HashMap<&str, usize> = HashMap::new();
+    // Defines, labels and constants seen so far, for evaluating `.org` and
+    // `.align`.
+    let mut symbols: HashMap<&str, usize> = defines
+        .iter()
+        .map(|define| (define.name.as_str(), define.value))
+        .collect();
+    let alignment = config.instruction_alignment();
+    // Labels on the current address, which move along with it when the
+    // next instruction is padded.
+    let mut pending: Vec<&str> = vec![];
+    // Constants and addresses that couldn't be evaluated yet.
+    let mut deferred_constants = vec![];
+    let mut failed_addresses = vec![];
+
+    for line in split_lines(tokens) {
+        let label_count = line
+            .iter()
+            .take_while(|token| matches!(token.token_type, TokenType::Label(_)))
+            .count();
+        let (line_labels, rest) = line.split_at(label_count);
+
+        for token in line_labels {
+            if let TokenType::Label(label) = &token.token_type {
+                labels.insert(label, memory_pointer);
+                symbols.insert(label, memory_pointer);
+                pending.push(label);
+            }
+        }
 
-    for token in tokens {
-        match &token.token_type {
-            TokenType::Mnemonic(_) | TokenType::Number(_) => {
-                memory_pointer += 1;
+        // Malformed lines are reported by the real parse later.
+        let Some(parsed) = parse_with_config(rest, config)
+            .ok()
+            .and_then(|lines| lines.into_iter().next())
+        else {
+            continue;
+        };
+        match &parsed {
+            Line::Constant { name, value } => match value.evaluate(&symbols) {
+                Ok(value) => {
+                    symbols.insert(&name.content, value as usize);
+                }
+                Err(_) => deferred_constants.push((name.content.as_str(), value.clone())),
             },
-            TokenType::LabelRef(_) => {
-                memory_pointer += 2;
+            Line::Org(target) => match target.evaluate(&symbols) {
+                Ok(target) => memory_pointer = target as usize,
+                Err(err) => failed_addresses.push((target.clone(), err)),
             },
-            TokenType::Label(label) => {
-                labels.insert(label, memory_pointer);
+            Line::Align(alignment) => match alignment.evaluate(&symbols) {
+                Ok(alignment @ 1..) => {
+                    memory_pointer = memory_pointer.next_multiple_of(alignment as usize)
+                }
+                // The checker rejects the alignment itself.
+                Ok(_) => {}
+                Err(err) => failed_addresses.push((alignment.clone(), err)),
             },
-            _ => {}
+            Line::Instruction { .. } if alignment.padding.is_some() => {
+                memory_pointer = memory_pointer.next_multiple_of(alignment.multiple);
+                for label in pending.drain(..) {
+                    labels.insert(label, memory_pointer);
+                    symbols.insert(label, memory_pointer);
+                }
+                memory_pointer += line_size(&parsed, config);
+            }
+            line => memory_pointer += line_size(line, config),
+        }
+        if !matches!(
+            parsed,
+            Line::Constant { .. } | Line::Entry(_) | Line::Export(_) | Line::Import(_)
+        ) {
+            pending.clear();
         }
     }
 
-    labels
+    // What evaluates once every symbol is known only failed for using one
+    // defined further down. The rest failed on its own.
+    for (name, value) in deferred_constants {
+        if let Ok(value) = value.evaluate(&symbols) {
+            symbols.insert(name, value as usize);
+        }
+    }
+    let errors = failed_addresses
+        .into_iter()
+        .map(|(expr, err)| match expr.evaluate(&symbols) {
+            Ok(_) => ResolveErr::DependsOnLaterSymbol(expr.to_string()).with_span(expr.span()),
+            Err(_) => err,
+        })
+        .collect();
+
+    (labels, errors)
+}
+
+/// Every symbol that is defined twice, as a label and a constant, or with
+/// the name of a register, pointing back at the first definition.
+pub fn find_symbol_conflicts(tokens: &[Token]) -> Vec<Error> {
+    let mut first_definitions: HashMap<&str, (SymbolKind, &Token)> = HashMap::new();
+    let mut errors = vec![];
+
+    for line in split_lines(tokens) {
+        for (name, kind, token) in symbol_definitions(line) {
+            if Register::from_str(name).is_ok() {
+                let err = match kind {
+                    SymbolKind::Label => ResolveErr::LabelNamedRegister(name.to_string()),
+                    SymbolKind::Constant => ResolveErr::ConstantNamedRegister(name.to_string()),
+                };
+                errors.push(err.with_span(token.span.clone()));
+                continue;
+            }
+
+            let Some((first_kind, first)) = first_definitions.get(name) else {
+                first_definitions.insert(name, (kind, token));
+                continue;
+            };
+            let (err, note) = match (first_kind, kind) {
+                (SymbolKind::Label, SymbolKind::Label) => (
+                    ResolveErr::DuplicateLabel(name.to_string()),
+                    "note.first_defined",
+                ),
+                (SymbolKind::Constant, SymbolKind::Constant) => (
+                    ResolveErr::DuplicateConstant(name.to_string()),
+                    "note.first_defined",
+                ),
+                (SymbolKind::Label, SymbolKind::Constant) => (
+                    ResolveErr::ConstantShadowsLabel(name.to_string()),
+                    "note.label_defined",
+                ),
+                (SymbolKind::Constant, SymbolKind::Label) => (
+                    ResolveErr::LabelShadowsConstant(name.to_string()),
+                    "note.constant_defined",
+                ),
+            };
+            errors.push(
+                err.with_span(token.span.clone())
+                    .with_label(first.span.clone(), Message::new(note, vec![])),
+            );
+        }
+    }
+
+    errors
+}
+
+/// Bytes between each label and the next one (or the end of the image),
+/// largest first. Code before the first label is attributed to `None`, and
+/// of labels on the same address the last by name gets the bytes.
+pub fn label_sizes<'a>(
+    labels: &HashMap<&'a str, usize>,
+    image_size: usize,
+) -> Vec<(Option<&'a str>, usize)> {
+    let mut starts = labels
+        .iter()
+        .map(|(label, address)| (Some(*label), *address))
+        .collect::<Vec<_>>();
+    starts.push((None, 0));
+    starts.sort_by_key(|(label, address)| (*address, *label));
+
+    let mut sizes = starts
+        .iter()
+        .zip(
+            starts
+                .iter()
+                .skip(1)
+                .map(|(_, address)| *address)
+                .chain([image_size]),
+        )
+        .map(|((label, start), end)| (*label, end.min(image_size).saturating_sub(*start)))
+        .filter(|(_, size)| *size > 0)
+        .collect::<Vec<_>>();
+    sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_labels_follow_encoded_sizes() {
+        let source = "start:  JMPIMM (#end)\n\
+            regs:   MOV A TH\n\
+            data:   byte \"ab\" 1\n\
+            .org 0x10\n\
+            words:  .word 1 2\n\
+            end:    HALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+
+        let labels = get_resolved_labels(&tokens, &Config::builtin());
+        let mut labels = labels.into_iter().collect::<Vec<_>>();
+        labels.sort_by_key(|(_, address)| *address);
+        assert_eq!(
+            labels,
+            [
+                ("start", 0),
+                ("regs", 3),
+                ("data", 4),
+                ("words", 0x10),
+                ("end", 0x14)
+            ]
+        );
+    }
+
+    fn sorted(labels: HashMap<&str, usize>) -> Vec<(&str, usize)> {
+        let mut labels = labels.into_iter().collect::<Vec<_>>();
+        labels.sort_by_key(|(_, address)| *address);
+        labels
+    }
+
+    #[test]
+    fn test_symbolic_placement() {
+        let source = ".equ BASE 0x10\n\
+            .org #BASE + 2\n\
+            first:  HALT\n\
+            .align 4\n\
+            second: HALT\n\
+            .equ STEP #second - #first + 6\n\
+            .align #STEP\n\
+            third:  HALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+
+        let labels = resolve_labels(&tokens, &Config::builtin(), &[]).unwrap();
+        assert_eq!(
+            sorted(labels),
+            [("first", 0x12), ("second", 0x14), ("third", 0x18)]
+        );
+    }
+
+    #[test]
+    fn test_padding_moves_pending_labels() {
+        let mut config = Config::builtin();
+        config.capabilities.instruction_alignment = 2;
+        config.capabilities.pad_instructions = true;
+        let source = "MOV A 1\nHALT\nloop:\nouter:\n.equ STEP 1\nMOV A 2\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+
+        let labels = resolve_labels(&tokens, &config, &[]).unwrap();
+        assert_eq!(labels["loop"], 4);
+        assert_eq!(labels["outer"], 4);
+    }
+
+    #[test]
+    fn test_unplaceable_addresses() {
+        let errors = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+            resolve_labels(&tokens, &Config::builtin(), &[])
+                .unwrap_err()
+                .into_iter()
+                .map(|err| (err.to_string(), err.span().line, err.span().chars.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let later = "'#end' uses a symbol defined after it, so the address is not known yet.";
+        assert_eq!(
+            errors(".org #end\nend: HALT\n"),
+            [(later.to_string(), 0, 5..9)]
+        );
+        // Through a constant, which may itself use later labels.
+        assert_eq!(
+            errors(".equ SIZE #end\n.align #SIZE\nend: HALT\n"),
+            [(
+                "'#SIZE' uses a symbol defined after it, so the address is not known yet."
+                    .to_string(),
+                1,
+                7..12
+            )]
+        );
+        // Names defined nowhere keep their own error.
+        let missing = errors("HALT\n.org #missing\n");
+        assert_eq!((missing[0].1, missing[0].2.clone()), (1, 5..13));
+        assert!(missing[0].0.contains("missing"));
+    }
+
+    #[test]
+    fn test_symbol_conflicts() {
+        let conflicts = |source: &str| {
+            let chars = source.chars().collect::<Vec<_>>();
+            let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+            find_symbol_conflicts(&tokens)
+                .into_iter()
+                .map(|err| {
+                    let labels = err
+                        .labels()
+                        .iter()
+                        .map(|label| (label.span.line, label.message.as_str().to_string()))
+                        .collect::<Vec<_>>();
+                    (err.to_string(), err.span().line, labels)
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = |line: usize, note: &str| vec![(line, note.to_string())];
+
+        assert_eq!(
+            conflicts("start: HALT\nloop: HALT\nstart: HALT\n"),
+            [(
+                "Label 'start' is defined more than once.".to_string(),
+                2,
+                first(0, "first defined here")
+            )]
+        );
+        assert_eq!(
+            conflicts(".equ SIZE 1\n.equ SIZE 2\n"),
+            [(
+                "Constant 'SIZE' is defined more than once.".to_string(),
+                1,
+                first(0, "first defined here")
+            )]
+        );
+        assert_eq!(
+            conflicts("end: HALT\n.equ end 2\n"),
+            [(
+                "Constant 'end' shadows a label of the same name.".to_string(),
+                1,
+                first(0, "label defined here")
+            )]
+        );
+        assert_eq!(
+            conflicts(".equ end 2\nend: HALT\n"),
+            [(
+                "Label 'end' has the same name as a constant.".to_string(),
+                1,
+                first(0, "constant defined here")
+            )]
+        );
+        assert_eq!(
+            conflicts("A: HALT\n.equ B 1\n"),
+            [
+                (
+                    "Label 'A' has the name of a register.".to_string(),
+                    0,
+                    vec![]
+                ),
+                (
+                    "Constant 'B' has the name of a register.".to_string(),
+                    1,
+                    vec![]
+                )
+            ]
+        );
+        assert!(conflicts("start: HALT\n.equ SIZE 1\nJMPIMM #start\n").is_empty());
+    }
+
+    #[test]
+    fn test_label_sizes() {
+        let labels = HashMap::from([("code", 2), ("alias", 2), ("data", 5), ("end", 8)]);
+        assert_eq!(
+            label_sizes(&labels, 10),
+            [
+                (Some("code"), 3),
+                (Some("data"), 3),
+                (None, 2),
+                (Some("end"), 2)
+            ]
+        );
+        // Labels past the end of the image take nothing.
+        assert_eq!(label_sizes(&labels, 5), [(Some("code"), 3), (None, 2)]);
+        assert_eq!(label_sizes(&HashMap::new(), 4), [(None, 4)]);
+    }
 }
@@ -0,0 +1,104 @@
+use std::{
+    fs::{self, create_dir_all},
+    io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::config::CONFIG_FILE_NAME;
+
+pub const MANIFEST_FILE_NAME: &str = "asm.toml";
+pub const MAIN_FILE_NAME: &str = "main.asm";
+
+const MAIN_TEMPLATE: &str = "start:  MOV A 1
+        MOVAT [0xFF00] A
+        HALT
+";
+
+const GITIGNORE_TEMPLATE: &str = "/build
+";
+
+#[derive(Error, Debug)]
+pub enum ScaffoldError {
+    #[error("Destination '{0}' already exists.")]
+    AlreadyExists(PathBuf),
+    #[error("Couldn't derive a project name from '{0}'.")]
+    InvalidName(PathBuf),
+    #[error("{0}.")]
+    Io(#[from] io::Error),
+}
+
+fn manifest(name: &str) -> String {
+    format!(
+        "[project]
+name = \"{name}\"
+entry = \"{MAIN_FILE_NAME}\"
+config = \"{CONFIG_FILE_NAME}\"
+output = \"build/{name}.bin\"
+# Run by `build` after writing the output, {{output}} is replaced by its path.
+# post-build = [\"sha256sum {{output}}\"]
+"
+    )
+}
+
+/// Creates a project directory with a manifest, a starter program, `config`
+/// as its config and a `.gitignore`, like `cargo new`.
+pub fn create_project(path: &Path, config: &str) -> Result<(), ScaffoldError> {
+    if path.exists() {
+        return Err(ScaffoldError::AlreadyExists(path.to_path_buf()));
+    }
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| ScaffoldError::InvalidName(path.to_path_buf()))?;
+
+    create_dir_all(path)?;
+    fs::write(path.join(MANIFEST_FILE_NAME), manifest(name))?;
+    fs::write(path.join(MAIN_FILE_NAME), MAIN_TEMPLATE)?;
+    fs::write(path.join(CONFIG_FILE_NAME), config)?;
+    fs::write(path.join(".gitignore"), GITIGNORE_TEMPLATE)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Config, DEFAULT_CONFIG},
+        manifest::Manifest,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_project() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("blinky");
+        create_project(&path, DEFAULT_CONFIG).unwrap();
+
+        let project = Manifest::read(&path.join(MANIFEST_FILE_NAME))
+            .unwrap()
+            .project;
+        assert_eq!(project.name, "blinky");
+        assert_eq!(project.entry, PathBuf::from(MAIN_FILE_NAME));
+        assert_eq!(project.config, Some(PathBuf::from(CONFIG_FILE_NAME)));
+        assert_eq!(project.output, PathBuf::from("build/blinky.bin"));
+        assert_eq!(
+            fs::read_to_string(path.join(MAIN_FILE_NAME)).unwrap(),
+            MAIN_TEMPLATE
+        );
+        assert_eq!(
+            fs::read_to_string(path.join(CONFIG_FILE_NAME)).unwrap(),
+            DEFAULT_CONFIG
+        );
+        assert!(path.join(".gitignore").exists());
+        let config = Config::builtin();
+        assert!(crate::assemble(MAIN_TEMPLATE, &config, Default::default()).is_ok());
+
+        assert!(matches!(
+            create_project(&path, DEFAULT_CONFIG),
+            Err(ScaffoldError::AlreadyExists(_))
+        ));
+    }
+}
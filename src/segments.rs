@@ -0,0 +1,287 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::{
+    checker::CheckedLine,
+    error::{Error, WithSpan},
+    expr::Expr,
+    i18n::Message,
+    parser::Line,
+    token::{Operator, Token, TokenType},
+};
+
+/// Segment of the lines before the first `.segment`.
+pub const DEFAULT_SEGMENT: &str = "code";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SegmentErr {
+    #[error("Invalid segment output '{0}', expected NAME=FILE.")]
+    InvalidOutput(String),
+    #[error("Unknown segment '{0}', the program has: {1}.")]
+    UnknownSegment(String, String),
+    #[error("'{0}' subtracts addresses in segments '{1}' and '{2}', which has no meaning.")]
+    CrossSegmentDifference(String, String, String),
+}
+
+/// A `--segment-out NAME=FILE` argument.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentOutput {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub fn parse_segment_output(arg: &str) -> Result<SegmentOutput, SegmentErr> {
+    match arg.split_once('=') {
+        Some((name, path)) if !name.trim().is_empty() && !path.is_empty() => Ok(SegmentOutput {
+            name: name.trim().to_string(),
+            path: PathBuf::from(path),
+        }),
+        _ => Err(SegmentErr::InvalidOutput(arg.to_string())),
+    }
+}
+
+/// The bytes one segment places in memory, e.g. for the chip it's burnt to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub name: String,
+    /// Address of the segment's first byte.
+    pub start: usize,
+    /// Everything from `start` up to the segment's last byte, with gaps and
+    /// bytes of other segments in between left as zeros.
+    pub bytes: Vec<u8>,
+}
+
+pub(crate) fn segment_name(token: &Token) -> &str {
+    match &token.token_type {
+        TokenType::LabelRef(name) => name,
+        _ => &token.content,
+    }
+}
+
+/// The segment each label is defined in.
+pub fn label_segments(tokens: &[Token]) -> HashMap<&str, &str> {
+    let mut labels = HashMap::new();
+    let mut current = DEFAULT_SEGMENT;
+    for (i, token) in tokens.iter().enumerate() {
+        match &token.token_type {
+            TokenType::Segment => {
+                if let Some(name) = tokens.get(i + 1) {
+                    current = segment_name(name);
+                }
+            }
+            TokenType::Label(label) => {
+                labels.insert(label.as_str(), current);
+            }
+            _ => {}
+        }
+    }
+    labels
+}
+
+/// The segment the address `expr` points into: that of its one label,
+/// give or take an offset.
+fn address_segment<'a>(expr: &Expr, segments: &HashMap<&str, &'a str>) -> Option<&'a str> {
+    match expr {
+        Expr::Value(Token {
+            token_type: TokenType::LabelRef(name),
+            ..
+        }) => segments.get(name.as_str()).copied(),
+        Expr::Group(_, inner, _) => address_segment(inner, segments),
+        Expr::Binary(Operator::Plus, lhs, rhs) => {
+            match (lhs.has_label_ref(), rhs.has_label_ref()) {
+                (true, false) => address_segment(lhs, segments),
+                (false, true) => address_segment(rhs, segments),
+                _ => None,
+            }
+        }
+        Expr::Binary(Operator::Minus, lhs, rhs) if !rhs.has_label_ref() => {
+            address_segment(lhs, segments)
+        }
+        _ => None,
+    }
+}
+
+fn check_differences(expr: &Expr, segments: &HashMap<&str, &str>, errors: &mut Vec<Error>) {
+    match expr {
+        Expr::Value(_) => {}
+        Expr::Group(_, inner, _) | Expr::Unary(_, _, inner) => {
+            check_differences(inner, segments, errors)
+        }
+        Expr::Binary(operator, lhs, rhs) => {
+            let sides = (
+                address_segment(lhs, segments),
+                address_segment(rhs, segments),
+            );
+            if let (Operator::Minus, (Some(lhs_segment), Some(rhs_segment))) = (operator, sides) {
+                if lhs_segment != rhs_segment {
+                    let err = SegmentErr::CrossSegmentDifference(
+                        expr.to_string(),
+                        lhs_segment.to_string(),
+                        rhs_segment.to_string(),
+                    );
+                    errors.push(
+                        err.with_span(expr.span())
+                            .with_label(
+                                lhs.span(),
+                                Message::new("note.in_segment", vec![lhs_segment.to_string()]),
+                            )
+                            .with_label(
+                                rhs.span(),
+                                Message::new("note.in_segment", vec![rhs_segment.to_string()]),
+                            )
+                            .with_help(Message::new("help.name_the_distance", vec![])),
+                    );
+                }
+            }
+            check_differences(lhs, segments, errors);
+            check_differences(rhs, segments, errors);
+        }
+    }
+}
+
+/// Every expression that subtracts a label in one segment from a label in
+/// another. Segments may be placed anywhere, so the difference says
+/// nothing about either. Addresses copied into constants aren't checked.
+pub fn check_label_math(tokens: &[Token], lines: &[Line]) -> Vec<Error> {
+    let segments = label_segments(tokens);
+    let mut errors = vec![];
+    for line in lines {
+        let exprs: Vec<&Expr> = match line {
+            Line::Instruction { operands, .. } => operands.iter().map(|(_, expr)| expr).collect(),
+            Line::Constant { value, .. }
+            | Line::Org(value)
+            | Line::Align(value)
+            | Line::Entry(value) => vec![value],
+            Line::Byte(values) | Line::Word(values) => values.iter().collect(),
+            Line::Segment(_)
+            | Line::Barrier(_)
+            | Line::Export(_)
+            | Line::Import(_)
+            | Line::Str { .. } => vec![],
+        };
+        for expr in exprs {
+            check_differences(expr, &segments, &mut errors);
+        }
+    }
+    errors
+}
+
+/// Splits the program into its segments, in the order they first appear.
+/// `addresses` is the layout of `checked_lines`.
+pub fn segments(checked_lines: &[CheckedLine], addresses: &[usize]) -> Vec<Segment> {
+    // Every block of bytes, by segment.
+    let mut blocks: Vec<(&str, Vec<_>)> = vec![];
+    let mut current = DEFAULT_SEGMENT;
+
+    for (checked_line, address) in checked_lines.iter().zip(addresses) {
+        let bytes = checked_line.code.bytes();
+        if let Line::Segment(name) = &checked_line.line {
+            current = segment_name(name);
+        } else if bytes.is_empty() {
+            continue;
+        }
+        let position = match blocks.iter().position(|(name, _)| *name == current) {
+            Some(position) => position,
+            None => {
+                blocks.push((current, vec![]));
+                blocks.len() - 1
+            }
+        };
+        let blocks = &mut blocks[position].1;
+        match (bytes.is_empty(), blocks.first()) {
+            // Until the segment has bytes, its `.segment` line marks where
+            // it starts.
+            (true, None) => blocks.push((*address, bytes)),
+            (true, Some(_)) => {}
+            (false, Some((_, first))) if first.is_empty() => *blocks = vec![(*address, bytes)],
+            (false, _) => blocks.push((*address, bytes)),
+        }
+    }
+
+    blocks
+        .into_iter()
+        .map(|(name, blocks)| {
+            let start = blocks
+                .iter()
+                .map(|(address, _)| *address)
+                .min()
+                .unwrap_or_default();
+            let end = blocks
+                .iter()
+                .map(|(address, bytes)| address + bytes.len())
+                .max()
+                .unwrap_or(start);
+            let mut segment = vec![0; end - start];
+            for (address, bytes) in blocks {
+                segment[address - start..address - start + bytes.len()].copy_from_slice(&bytes);
+            }
+            Segment {
+                name: name.to_string(),
+                start,
+                bytes: segment,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    #[test]
+    fn test_segments() {
+        let source = "MOV A 1\n\
+            .segment data\n.org 0x10\nbyte 1 2\n\
+            .segment code\nHALT\n\
+            .segment data\nbyte 3\n\
+            .segment empty\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let config = Config::builtin();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+
+        let segments = segments(&checked_lines, &addresses);
+        let summary = segments
+            .iter()
+            .map(|segment| (segment.name.as_str(), segment.start, segment.bytes.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [("code", 0, 0x13), ("data", 0x10, 4), ("empty", 0x14, 0)]
+        );
+        // Each segment has the other's bytes in between zeroed.
+        assert_eq!(segments[0].bytes[0x10..0x12], [0, 0]);
+        assert_eq!(segments[1].bytes, [1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn test_check_label_math() {
+        let source = "start: MOV A #start - #next\nnext: MOV A #table + 1 - #start\n\
+            .segment data\ntable: byte #tail - #table\ntail:\n\
+            .const far #start\nbyte #table - far\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let config = Config::builtin();
+        let lines = parse_with_config(&tokens, &config).unwrap();
+
+        let errors = check_label_math(&tokens, &lines);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].kind().to_string(),
+            "'#table + 1 - #start' subtracts addresses in segments 'data' and 'code', \
+             which has no meaning."
+        );
+        assert_eq!(errors[0].labels().len(), 2);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::{
+    encoder::binary_str_to_byte,
+    config::{Capabilities, Config, Signature},
+    decoder::Decoder,
+    prelude::{Assembler, Diagnostic},
+    specs::Operand,
+};
+
+const SNIPPET_CONST: u8 = 0x2A;
+const SNIPPET_ADDRESS: u16 = 0x1234;
+
+#[derive(Debug, Error)]
+pub enum SelftestIssue {
+    #[error("operand kind {0} can't be written in source.")]
+    Unexpressible(Operand),
+    #[error("'{0}' failed to assemble: {1}")]
+    AssemblyFailed(String, String),
+    #[error("'{0}' failed to decode: {1}")]
+    DecodeFailed(String, String),
+    #[error("'{0}' decoded as {1}.")]
+    DecodeMismatch(String, Signature),
+    #[error("'{0}' encoded to {1:02X?}, expected {2:02X?}.")]
+    EncodingMismatch(String, Vec<u8>, Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct SelftestFailure {
+    pub signature: Signature,
+    pub issue: SelftestIssue,
+}
+
+impl Display for SelftestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.signature, self.issue)
+    }
+}
+
+#[derive(Debug)]
+pub struct SelftestReport {
+    pub checked: usize,
+    pub failures: Vec<SelftestFailure>,
+    /// Forms with an operand source can't express, which aren't failures.
+    pub skipped: Vec<SelftestFailure>,
+}
+
+fn operand_snippet(
+    operand: &Operand,
+    capabilities: &Capabilities,
+) -> Result<(String, Vec<u8>), SelftestIssue> {
+    match operand {
+        Operand::Register(register) => Ok((format!("{:?}", register), vec![])),
+        Operand::Const => Ok((format!("{:#04x}", SNIPPET_CONST), vec![SNIPPET_CONST])),
+        Operand::Mem16 => {
+            // Cut down to the address width, so narrow targets can take it.
+            let address = SNIPPET_ADDRESS as usize % capabilities.address_limit();
+            let bytes = address.to_le_bytes()[..capabilities.address_bytes()].to_vec();
+            Ok((format!("[{:#06x}]", address), bytes))
+        }
+        Operand::Mem8 | Operand::Stc => Err(SelftestIssue::Unexpressible(*operand)),
+    }
+}
+
+/// Builds source text for the signature along with the operand bytes it
+/// should encode to.
+fn snippet(
+    signature: &Signature,
+    capabilities: &Capabilities,
+) -> Result<(String, Vec<u8>), SelftestIssue> {
+    let mut source = signature.mnemonic.to_string();
+    let mut operand_bytes = vec![];
+    for operand in &signature.operands {
+        let (text, bytes) = operand_snippet(operand, capabilities)?;
+        source.push(' ');
+        source.push_str(&text);
+        operand_bytes.extend(bytes);
+    }
+    Ok((source, operand_bytes))
+}
+
+pub(crate) fn assemble_snippet(source: &str, config: &Config) -> Result<Vec<u8>, String> {
+    Assembler::new(config)
+        .assemble(source)
+        .map_err(|diagnostics| {
+            diagnostics
+                .iter()
+                .map(Diagnostic::message)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+}
+
+fn check_signature(
+    signature: &Signature,
+    config: &Config,
+    decoder: &Decoder,
+) -> Result<(), SelftestIssue> {
+    let (source, operand_bytes) = snippet(signature, &config.capabilities)?;
+
+    let bytes = assemble_snippet(&source, config)
+        .map_err(|err| SelftestIssue::AssemblyFailed(source.clone(), err))?;
+
+    let decoded = decoder
+        .decode(&bytes)
+        .map_err(|err| SelftestIssue::DecodeFailed(source.clone(), err.to_string()))?;
+
+    if let Some(instruction) = decoded.first() {
+        if instruction.signature != signature {
+            return Err(SelftestIssue::DecodeMismatch(
+                source,
+                instruction.signature.clone(),
+            ));
+        }
+    }
+
+    let expected = std::iter::once(binary_str_to_byte(&signature.opcode))
+        .chain(operand_bytes)
+        .collect();
+    if bytes != expected {
+        return Err(SelftestIssue::EncodingMismatch(source, bytes, expected));
+    }
+
+    Ok(())
+}
+
+/// Assembles a snippet for every instruction form in the config, decodes the
+/// result back and reports every form that doesn't survive the round trip.
+/// Forms that can't be written in source are skipped.
+pub fn run_selftest(config: &Config) -> SelftestReport {
+    let mut signatures = config.signatures();
+    signatures.sort_by(|a, b| a.opcode.cmp(&b.opcode));
+    let decoder = Decoder::new(&signatures);
+
+    let (skipped, failures) = signatures
+        .iter()
+        .filter_map(|signature| {
+            check_signature(signature, config, &decoder)
+                .err()
+                .map(|issue| SelftestFailure {
+                    signature: signature.clone(),
+                    issue,
+                })
+        })
+        .partition(|failure| matches!(failure.issue, SelftestIssue::Unexpressible(_)));
+
+    SelftestReport {
+        checked: signatures.len(),
+        failures,
+        skipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_config_passes() {
+        let report = run_selftest(&Config::builtin());
+        let failures = report
+            .failures
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(failures, Vec::<String>::new());
+        assert!(!report.skipped.is_empty());
+        assert!(report.skipped.len() < report.checked);
+    }
+}
@@ -0,0 +1,255 @@
+//! A long-running assembler for IDE plugins and grading servers, speaking
+//! JSON-RPC 2.0 with one message per line on stdin and stdout. Methods:
+//!
+//! - `assemble` with `{"source": "...", "defines": {"NAME": 1}}`, `defines`
+//!   being optional, answers `{"ok": true, "bytes": [...], "symbols": {...},
+//!   "diagnostics": [...]}`, without `bytes` and `symbols` when it failed.
+//! - `check`, with the same parameters, answers only `ok` and
+//!   `diagnostics`.
+//! - `shutdown` answers `null` and stops the server, as does the end of
+//!   stdin.
+//!
+//! Each diagnostic has a `severity`, `error` or `warning`, a `message`, and
+//! a 1-based `line` and `column` plus a `help` when known.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    limits::Limits,
+    prelude::{assemble, AssembleOptions, Config, Diagnostic},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Missing for notifications, which get no response.
+    id: Option<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AssembleParams {
+    source: String,
+    #[serde(default)]
+    defines: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+fn diagnostic(severity: &str, diagnostic: &Diagnostic) -> Value {
+    json!({
+        "severity": severity,
+        "message": diagnostic.message(),
+        "line": diagnostic.line(),
+        "column": diagnostic.column(),
+        "help": diagnostic.help(),
+    })
+}
+
+/// Answers requests with a config loaded once.
+pub struct Server<'a> {
+    config: &'a Config,
+    limits: Limits,
+    shut_down: bool,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(config: &'a Config, limits: Limits) -> Self {
+        Self {
+            config,
+            limits,
+            shut_down: false,
+        }
+    }
+
+    /// Whether a `shutdown` request was handled.
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let invalid_params = |err: serde_json::Error| RpcError {
+            code: INVALID_PARAMS,
+            message: err.to_string(),
+        };
+        match method {
+            "assemble" | "check" => {
+                let params =
+                    serde_json::from_value::<AssembleParams>(params).map_err(invalid_params)?;
+                let options = AssembleOptions {
+                    defines: params.defines.into_iter().collect(),
+                    limits: self.limits.clone(),
+                    ..Default::default()
+                };
+                Ok(match assemble(&params.source, self.config, options) {
+                    Ok(assembly) => {
+                        let warnings = assembly
+                            .warnings
+                            .iter()
+                            .map(|warning| diagnostic("warning", warning))
+                            .collect::<Vec<_>>();
+                        match method {
+                            "assemble" => json!({
+                                "ok": true,
+                                "bytes": assembly.image,
+                                "symbols": assembly.symbols,
+                                "diagnostics": warnings,
+                            }),
+                            _ => json!({ "ok": true, "diagnostics": warnings }),
+                        }
+                    }
+                    Err(errors) => {
+                        let errors = errors
+                            .iter()
+                            .map(|error| diagnostic("error", error))
+                            .collect::<Vec<_>>();
+                        json!({ "ok": false, "diagnostics": errors })
+                    }
+                })
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unknown method '{}'.", method),
+            }),
+        }
+    }
+
+    /// The response to one line of input, `None` for notifications.
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let error = |id: Value, code: i64, message: String| Response {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+        };
+        let response = match serde_json::from_str::<Value>(line) {
+            Err(err) => error(Value::Null, PARSE_ERROR, err.to_string()),
+            Ok(message) => match serde_json::from_value::<Request>(message) {
+                Err(err) => error(Value::Null, INVALID_REQUEST, err.to_string()),
+                Ok(request) if request.jsonrpc != "2.0" => error(
+                    request.id.unwrap_or_default(),
+                    INVALID_REQUEST,
+                    "Expected \"jsonrpc\": \"2.0\".".to_string(),
+                ),
+                Ok(request) => {
+                    let result = self.call(&request.method, request.params);
+                    let id = request.id?;
+                    match result {
+                        Ok(result) => Response {
+                            jsonrpc: "2.0",
+                            id,
+                            result: Some(result),
+                            error: None,
+                        },
+                        Err(err) => error(id, err.code, err.message),
+                    }
+                }
+            },
+        };
+        Some(serde_json::to_string(&response).expect("responses serialize to JSON"))
+    }
+}
+
+/// Answers every line of `input` on `output` until `shutdown` or the end
+/// of `input`.
+pub fn serve(
+    config: &Config,
+    limits: Limits,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let mut server = Server::new(config, limits);
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            writeln!(output, "{}", response)?;
+            output.flush()?;
+        }
+        if server.is_shut_down() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve() {
+        let input = [
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "assemble", "params": {"source": "MOV A 1\nHALT", "defines": {"N": 7}}}"#,
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "check", "params": {"source": "MOV A #missing"}}"#,
+            r#"{"jsonrpc": "2.0", "method": "check", "params": {"source": "HALT"}}"#,
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "link"}"#,
+            "{",
+            r#"{"jsonrpc": "2.0", "id": 4, "method": "shutdown"}"#,
+            r#"{"jsonrpc": "2.0", "id": 5, "method": "check", "params": {"source": "HALT"}}"#,
+        ]
+        .join("\n");
+        let mut output = vec![];
+        let limits = Limits::default();
+        serve(&Config::builtin(), limits, input.as_bytes(), &mut output).unwrap();
+
+        let responses = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses.len(), 5);
+        let assembled = &responses[0]["result"];
+        assert_eq!(assembled["ok"], true);
+        assert_eq!(assembled["bytes"].as_array().unwrap().len(), 3);
+        assert_eq!(assembled["symbols"]["N"], 7);
+
+        let checked = &responses[1]["result"];
+        assert_eq!(checked["ok"], false);
+        assert_eq!(checked["diagnostics"][0]["severity"], "error");
+        assert_eq!(checked["diagnostics"][0]["line"], 1);
+        assert_eq!(checked.get("bytes"), None);
+
+        assert_eq!(responses[2]["id"], 3);
+        assert_eq!(responses[2]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[3]["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            responses[4],
+            json!({"jsonrpc": "2.0", "id": 4, "result": null})
+        );
+    }
+}
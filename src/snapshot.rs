@@ -0,0 +1,185 @@
+//! Saves the full state of the emulated machine to a file and loads it
+//! back, so a long test scenario can resume where it left off and a bug
+//! report can carry the exact state it happened in.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::emulator::Machine;
+
+/// Version of the snapshot layout, bumped when it changes incompatibly.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Bytes of memory per line of the `memory` array.
+const BYTES_PER_ROW: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum SnapshotErr {
+    #[error("Couldn't read snapshot '{0}': {1}.")]
+    Read(String, String),
+    #[error("Invalid snapshot '{0}': {1}.")]
+    Invalid(String, String),
+    #[error("'{0}' is a snapshot of version {1}, expected {2}.")]
+    UnsupportedVersion(String, u32, u32),
+}
+
+/// The registers, memory and counters of a [`Machine`], as JSON with the
+/// memory in rows of hex bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub a: u8,
+    pub b: u8,
+    pub f: u8,
+    pub th: u8,
+    pub tl: u8,
+    pub pc: u16,
+    pub stc: u16,
+    pub cycles: usize,
+    pub instructions: usize,
+    pub memory: Vec<String>,
+}
+
+impl Snapshot {
+    pub fn new(machine: &Machine) -> Self {
+        let memory = machine
+            .memory
+            .chunks(BYTES_PER_ROW)
+            .map(|row| row.iter().map(|byte| format!("{:02x}", byte)).collect())
+            .collect();
+        Self {
+            version: SNAPSHOT_VERSION,
+            a: machine.a,
+            b: machine.b,
+            f: machine.f,
+            th: machine.th,
+            tl: machine.tl,
+            pc: machine.pc,
+            stc: machine.stc,
+            cycles: machine.cycles,
+            instructions: machine.instructions,
+            memory,
+        }
+    }
+
+    /// The machine in this state, `None` if the memory isn't hex bytes.
+    /// Memory left out at the end is zero.
+    pub fn machine(&self) -> Option<Machine> {
+        let hex = self.memory.concat();
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let mut machine = Machine::new(&[]);
+        if bytes.len() > machine.memory.len() {
+            return None;
+        }
+        machine.memory[..bytes.len()].copy_from_slice(&bytes);
+        machine.a = self.a;
+        machine.b = self.b;
+        machine.f = self.f;
+        machine.th = self.th;
+        machine.tl = self.tl;
+        machine.pc = self.pc;
+        machine.stc = self.stc;
+        machine.cycles = self.cycles;
+        machine.instructions = self.instructions;
+        Some(machine)
+    }
+}
+
+pub fn save_snapshot(machine: &Machine, path: &Path) -> std::io::Result<()> {
+    let json =
+        serde_json::to_string_pretty(&Snapshot::new(machine)).expect("snapshots serialize to JSON");
+    fs::write(path, json + "\n")
+}
+
+pub fn load_snapshot(path: &Path) -> Result<Machine, SnapshotErr> {
+    let name = path.display().to_string();
+    let content =
+        fs::read_to_string(path).map_err(|err| SnapshotErr::Read(name.clone(), err.to_string()))?;
+    let snapshot: Snapshot = serde_json::from_str(&content)
+        .map_err(|err| SnapshotErr::Invalid(name.clone(), err.to_string()))?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(SnapshotErr::UnsupportedVersion(
+            name,
+            snapshot.version,
+            SNAPSHOT_VERSION,
+        ));
+    }
+    snapshot
+        .machine()
+        .ok_or_else(|| SnapshotErr::Invalid(name, "memory should be rows of hex bytes".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        emulator::{run, RunOptions, Stop, Timing},
+        prelude::Assembler,
+    };
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let config = Config::builtin();
+        let image = Assembler::new(&config)
+            .assemble("MOV A 7\nPUSH A\nstop: MOV B 9\nHALT")
+            .unwrap();
+        let mut machine = Machine::new(&image);
+        let options = RunOptions {
+            max_cycles: 100,
+            breakpoint: Some(3),
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        assert_eq!(
+            run(&config, &mut machine, &options).unwrap(),
+            Stop::Breakpoint
+        );
+
+        let file = NamedTempFile::new().unwrap();
+        save_snapshot(&machine, file.path()).unwrap();
+        let mut restored = load_snapshot(file.path()).unwrap();
+        assert_eq!(Snapshot::new(&restored), Snapshot::new(&machine));
+        assert_eq!((restored.a, restored.pc, restored.stc), (7, 3, 0xfffe));
+        assert_eq!(restored.memory[0xffff], 7);
+
+        // Resumes where it stopped.
+        let options = RunOptions {
+            max_cycles: 100,
+            breakpoint: None,
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        assert_eq!(run(&config, &mut restored, &options).unwrap(), Stop::Halted);
+        assert_eq!((restored.a, restored.b), (7, 9));
+        assert_eq!(restored.instructions, 4);
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let file = NamedTempFile::new().unwrap();
+        let mut snapshot = Snapshot::new(&Machine::new(&[1, 2]));
+        snapshot.memory = vec!["01zz".to_string()];
+        fs::write(file.path(), serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert!(matches!(
+            load_snapshot(file.path()),
+            Err(SnapshotErr::Invalid(..))
+        ));
+
+        snapshot.version = 0;
+        fs::write(file.path(), serde_json::to_string(&snapshot).unwrap()).unwrap();
+        assert!(matches!(
+            load_snapshot(file.path()),
+            Err(SnapshotErr::UnsupportedVersion(_, 0, 1))
+        ));
+    }
+}
@@ -1,4 +1,5 @@
-use std::str::FromStr;
+use alloc::string::String;
+use core::{fmt::Display, str::FromStr};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Operand {
@@ -28,6 +29,59 @@ impl Mnemonic {
     pub fn new(name: String) -> Self {
         Self { name }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Display for Mnemonic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Operand {
+    /// Every operand kind a config can name.
+    pub const ALL: [Operand; 10] = [
+        Operand::Register(Register::A),
+        Operand::Register(Register::B),
+        Operand::Register(Register::F),
+        Operand::Register(Register::T),
+        Operand::Register(Register::TL),
+        Operand::Register(Register::TH),
+        Operand::Mem8,
+        Operand::Mem16,
+        Operand::Const,
+        Operand::Stc,
+    ];
+
+    /// Every spelling a config can name an operand kind with.
+    pub const NAMES: [&'static str; 12] = [
+        "A", "B", "F", "T", "TL", "TH", "MEM8", "MEMZP", "MEM16", "MEM", "CONST", "STC",
+    ];
+
+    /// Number of bytes the operand occupies after the opcode, where an
+    /// address takes `address_bytes`.
+    pub fn encoded_size(&self, address_bytes: usize) -> usize {
+        match self {
+            Operand::Register(_) | Operand::Stc => 0,
+            Operand::Mem8 | Operand::Const => 1,
+            Operand::Mem16 => address_bytes,
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{:?}", register),
+            Operand::Mem8 => write!(f, "MEM8"),
+            Operand::Mem16 => write!(f, "MEM16"),
+            Operand::Const => write!(f, "CONST"),
+            Operand::Stc => write!(f, "STC"),
+        }
+    }
 }
 
 impl FromStr for Operand {
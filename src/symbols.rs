@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checker::CheckedLine,
+    error::FileContext,
+    provenance::Provenance,
+    pseudo::is_return_label,
+    resolver::label_sizes,
+    token::{Token, TokenType},
+};
+
+/// How `--symbols` writes the symbol table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SymbolsFormat {
+    /// What built it, one label per line: address, size, name and where it's defined, then the bytes by file and by label
+    #[default]
+    Text,
+    /// An object with what built it under `build`, the labels, with the same fields, under `symbols` and the bytes under `sizes`
+    Json,
+}
+
+/// Order of `--list-symbols`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum SymbolSort {
+    /// By address, then name
+    #[default]
+    Addr,
+    /// By name
+    Name,
+}
+
+/// A label with where it ended up, for mapping addresses back to names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+    /// Bytes up to the next label at a higher address, or the end of the
+    /// image.
+    pub size: usize,
+    pub file: String,
+    /// 1-based line of the definition.
+    pub line: usize,
+}
+
+/// Bytes attributed to a source file or a label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Contribution {
+    pub name: String,
+    pub bytes: usize,
+}
+
+/// What takes up the image, largest first: the bytes each source file
+/// emits, and the bytes from each label to the next one as in
+/// `--max-size` errors, with code before the first label under `<start>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SizeReport {
+    pub files: Vec<Contribution>,
+    pub labels: Vec<Contribution>,
+}
+
+/// A `--symbols` file in JSON.
+#[derive(Serialize)]
+struct SymbolMap<'a> {
+    build: &'a Provenance,
+    symbols: &'a [Symbol],
+    sizes: &'a SizeReport,
+}
+
+/// Every label in `tokens`, sorted by address and then name. `labels` are
+/// their resolved addresses.
+/// The labels without those pseudo-instruction expansions made up.
+fn public_labels<'a>(labels: &HashMap<&'a str, usize>) -> HashMap<&'a str, usize> {
+    labels
+        .iter()
+        .filter(|(label, _)| !is_return_label(label))
+        .map(|(label, address)| (*label, *address))
+        .collect()
+}
+
+pub fn symbols(
+    tokens: &[Token],
+    labels: &HashMap<&str, usize>,
+    image_size: usize,
+    source: &FileContext,
+) -> Vec<Symbol> {
+    let labels = public_labels(labels);
+    let mut addresses = labels.values().copied().collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+    let size = |address: usize| {
+        let next = addresses.iter().find(|next| **next > address);
+        next.copied().unwrap_or(image_size).saturating_sub(address)
+    };
+
+    let mut symbols = vec![];
+    for token in tokens {
+        let TokenType::Label(name) = &token.token_type else {
+            continue;
+        };
+        // Duplicates are reported before this runs, so the first one counts.
+        let Some(address) = labels.get(name.as_str()) else {
+            continue;
+        };
+        if symbols.iter().any(|symbol: &Symbol| symbol.name == *name) {
+            continue;
+        }
+        symbols.push(Symbol {
+            name: name.clone(),
+            address: *address,
+            size: size(*address),
+            file: source.file_name(&token.span).to_string(),
+            line: token.span.line + 1,
+        });
+    }
+    symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+    symbols
+}
+
+pub fn size_report(
+    checked_lines: &[CheckedLine],
+    labels: &HashMap<&str, usize>,
+    image_size: usize,
+    source: &FileContext,
+) -> SizeReport {
+    let mut files = Vec::<Contribution>::new();
+    for checked_line in checked_lines {
+        let Some(token) = checked_line.line.first_token() else {
+            continue;
+        };
+        let name = source.file_name(&token.span);
+        let bytes = checked_line.code.bytes().len();
+        match files.iter_mut().find(|file| file.name == name) {
+            Some(file) => file.bytes += bytes,
+            None => files.push(Contribution {
+                name: name.to_string(),
+                bytes,
+            }),
+        }
+    }
+    files.retain(|file| file.bytes > 0);
+    files.sort_by(|a, b| (b.bytes, &a.name).cmp(&(a.bytes, &b.name)));
+
+    let labels = label_sizes(&public_labels(labels), image_size)
+        .into_iter()
+        .map(|(label, bytes)| Contribution {
+            name: label.unwrap_or("<start>").to_string(),
+            bytes,
+        })
+        .collect();
+    SizeReport { files, labels }
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for any one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| matches(rest, &name[i..])),
+            Some(('?', rest)) => !name.is_empty() && matches(rest, &name[1..]),
+            Some((c, rest)) => name.first() == Some(c) && matches(rest, &name[1..]),
+        }
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches(&pattern, &name)
+}
+
+/// The symbol table for `--list-symbols`, one label per line as in
+/// `--symbols`, without the header.
+pub fn list_symbols(symbols: &[Symbol], sort: SymbolSort, filter: Option<&str>) -> String {
+    let mut symbols = symbols
+        .iter()
+        .filter(|symbol| filter.is_none_or(|filter| glob_match(filter, &symbol.name)))
+        .collect::<Vec<_>>();
+    if sort == SymbolSort::Name {
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    symbols
+        .iter()
+        .map(|symbol| {
+            format!(
+                "{:04x}  {:>5}  {}  {}:{}\n",
+                symbol.address, symbol.size, symbol.name, symbol.file, symbol.line
+            )
+        })
+        .collect()
+}
+
+pub fn render_symbols(
+    symbols: &[Symbol],
+    sizes: &SizeReport,
+    format: SymbolsFormat,
+    provenance: &Provenance,
+) -> String {
+    match format {
+        SymbolsFormat::Text => {
+            let lines = symbols.iter().map(|symbol| {
+                format!(
+                    "{:04x}  {:>5}  {}  {}:{}\n",
+                    symbol.address, symbol.size, symbol.name, symbol.file, symbol.line
+                )
+            });
+            let mut text = provenance.header() + &lines.collect::<String>();
+            for (title, contributions) in [("file", &sizes.files), ("label", &sizes.labels)] {
+                if contributions.is_empty() {
+                    continue;
+                }
+                text += &format!("\nBytes by {}:\n", title);
+                for contribution in contributions {
+                    text += &format!("{:>5}  {}\n", contribution.bytes, contribution.name);
+                }
+            }
+            text
+        }
+        SymbolsFormat::Json => {
+            let map = SymbolMap {
+                build: provenance,
+                symbols,
+                sizes,
+            };
+            serde_json::to_string_pretty(&map).expect("symbols serialize to JSON") + "\n"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::check_semantics, config::Config, lexer::Lexer, parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+    use std::path::Path;
+
+    #[test]
+    fn test_symbols() {
+        let source = "start:\nalias:\n    MOV A 1\nloop:\n    HALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &Config::builtin());
+        let context = FileContext::new(None, source);
+
+        let symbols = symbols(&tokens, &labels, 3, &context);
+        let summary = symbols
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.name.as_str(),
+                    symbol.address,
+                    symbol.size,
+                    symbol.line,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [("alias", 0, 2, 2), ("start", 0, 2, 1), ("loop", 2, 1, 4)]
+        );
+        let provenance = Provenance {
+            assembler: "1.0.0".to_string(),
+            config: "0123456789abcdef".to_string(),
+            command: "assembler --symbols map.txt".to_string(),
+        };
+        assert_eq!(
+            render_symbols(
+                &symbols[2..],
+                &SizeReport::default(),
+                SymbolsFormat::Text,
+                &provenance
+            ),
+            "Assembler: 1.0.0\n\
+             Config:    0123456789abcdef\n\
+             Command:   assembler --symbols map.txt\n\
+             \n\
+             0002      1  loop  stdin:4\n"
+        );
+    }
+
+    #[test]
+    fn test_return_labels_are_left_out() {
+        let source = "start: MOV A 1\nHALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let mut labels = get_resolved_labels(&tokens, &Config::builtin());
+        labels.insert("CALL.return.1", 2);
+
+        let symbols = symbols(&tokens, &labels, 3, &FileContext::new(None, source));
+        assert_eq!(symbols.len(), 1);
+        assert_eq!((symbols[0].name.as_str(), symbols[0].size), ("start", 3));
+        assert_eq!(public_labels(&labels).len(), 1);
+    }
+
+    #[test]
+    fn test_list_symbols() {
+        let source = "start: MOV A 1\nloop_1: NOP\nloop_2: HALT\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, &Config::builtin());
+        let symbols = symbols(&tokens, &labels, 4, &FileContext::new(None, source));
+
+        assert_eq!(
+            list_symbols(&symbols, SymbolSort::Name, Some("loop_?")),
+            "0002      1  loop_1  stdin:2\n0003      1  loop_2  stdin:3\n"
+        );
+        let names = |listing: String| {
+            listing
+                .lines()
+                .map(|line| line.split_whitespace().nth(2).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(list_symbols(&symbols, SymbolSort::Name, None)),
+            ["loop_1", "loop_2", "start"]
+        );
+        assert_eq!(
+            names(list_symbols(&symbols, SymbolSort::Addr, Some("*t*"))),
+            ["start"]
+        );
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("l?op", "lop"));
+    }
+
+    #[test]
+    fn test_size_report() {
+        let source = "MOV A 1\nstart: MOV A 2\nHALT\n.org 0x10\nend: byte 1\n";
+        let chars = source.chars().collect::<Vec<_>>();
+        let mut tokens = Lexer::new(&chars).collect::<Result<Vec<_>, _>>().unwrap();
+        let mut context = FileContext::new(None, source);
+        // Pretend `HALT` came from an included file.
+        let included = context.add_file(Path::new("lib.asm"), "HALT\n");
+        tokens
+            .iter_mut()
+            .find(|token| token.content == "HALT")
+            .unwrap()
+            .span
+            .file = included;
+        let config = Config::builtin();
+        let labels = get_resolved_labels(&tokens, &config);
+        let lines = parse_with_config(&tokens, &config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, &config).unwrap();
+
+        let report = size_report(&checked_lines, &labels, 0x11, &context);
+        let summary = |contributions: &[Contribution]| {
+            contributions
+                .iter()
+                .map(|contribution| (contribution.name.clone(), contribution.bytes))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&report.files),
+            [("stdin".to_string(), 5), ("lib.asm".to_string(), 1)]
+        );
+        assert_eq!(
+            summary(&report.labels),
+            [
+                ("start".to_string(), 14),
+                ("<start>".to_string(), 2),
+                ("end".to_string(), 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SyntaxErr {
+    #[error(
+        "'{0}' can't be the label sigil, it already starts names, numbers, directives, \
+        brackets, operators or literals."
+    )]
+    InvalidLabelSigil(String),
+}
+
+/// Characters besides letters, digits and whitespace the lexer gives a
+/// meaning of their own.
+const TAKEN_CHARACTERS: &str = "._[]()+-*/%&|^~<>=!'\"\\";
+
+/// Checks that `sigil` can prefix label references: anything the lexer
+/// reads as something else would never reach them.
+pub fn check_label_sigil(sigil: char) -> Result<char, SyntaxErr> {
+    match sigil.is_alphanumeric() || sigil.is_whitespace() || TAKEN_CHARACTERS.contains(sigil) {
+        true => Err(SyntaxErr::InvalidLabelSigil(sigil.to_string())),
+        false => Ok(sigil),
+    }
+}
+
+/// Parses `--label-sigil`, a single character [`check_label_sigil`] accepts.
+pub fn parse_label_sigil(arg: &str) -> Result<char, SyntaxErr> {
+    let mut chars = arg.chars();
+    match (chars.next(), chars.next()) {
+        (Some(sigil), None) => check_label_sigil(sigil),
+        _ => Err(SyntaxErr::InvalidLabelSigil(arg.to_string())),
+    }
+}
+
+/// Source dialect options, so code written for other assemblers needs fewer
+/// edits to assemble here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::Args))]
+pub struct SyntaxOptions {
+    /// Character that prefixes label references
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            value_name = "char",
+            default_value_t = '#',
+            value_parser = parse_label_sigil,
+            global = true
+        )
+    )]
+    pub label_sigil: char,
+
+    /// Treat bare identifiers in operand position as label references
+    #[cfg_attr(feature = "cli", arg(long, default_value_t = false, global = true))]
+    pub bare_label_refs: bool,
+
+    /// Treat a non-mnemonic identifier alone on its line at column 0 as a label without a colon
+    #[cfg_attr(feature = "cli", arg(long, default_value_t = false, global = true))]
+    pub colon_free_labels: bool,
+
+    /// Mnemonics from the config, which stay instructions alone on their line.
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub mnemonics: BTreeSet<String>,
+
+    /// Directive aliases and reserved words, from the config.
+    #[cfg_attr(feature = "cli", arg(skip))]
+    pub dialect: Dialect,
+}
+
+/// Names a config adds to the source language, from its optional `dialect`
+/// entry, e.g. `{"aliases": {"db": ".byte", "dw": ".word"}, "reserved": ["end"]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialect {
+    /// Other names for directives by the directive they stand for, both
+    /// with or without the leading `.`.
+    pub aliases: BTreeMap<String, String>,
+    /// Names that can't be used for labels, constants or instructions.
+    pub reserved: Vec<String>,
+}
+
+impl Default for SyntaxOptions {
+    fn default() -> Self {
+        Self {
+            label_sigil: '#',
+            bare_label_refs: false,
+            colon_free_labels: false,
+            mnemonics: BTreeSet::new(),
+            dialect: Dialect::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_sigil() {
+        for sigil in ["#", "$", "@", "?"] {
+            assert!(parse_label_sigil(sigil).is_ok());
+        }
+        for sigil in ["a", "7", ".", "_", "-", "&", "<", "\"", "\\", " ", "", "##"] {
+            assert_eq!(
+                parse_label_sigil(sigil),
+                Err(SyntaxErr::InvalidLabelSigil(sigil.to_string()))
+            );
+        }
+    }
+}
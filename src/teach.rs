@@ -0,0 +1,311 @@
+use crate::{
+    checker::{CheckedLine, CheckedLineCode},
+    error::FileContext,
+    expr::Expr,
+    parser::Line,
+    specs::Operand,
+    token::Span,
+};
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:#04x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn describe_operand(operand: &Operand, value: &Expr, bytes: &[u8], address_width: u32) -> String {
+    match operand {
+        Operand::Register(register) => format!(
+            "'{}' names register {:?}; registers are implied by the opcode, so it adds no bytes",
+            value, register
+        ),
+        Operand::Const => format!(
+            "'{}' is an immediate constant, stored as the byte {}",
+            value,
+            format_bytes(bytes)
+        ),
+        Operand::Mem8 => format!(
+            "'{}' is a zero page address, stored as the single byte {}",
+            value,
+            format_bytes(bytes)
+        ),
+        Operand::Mem16 => format!(
+            "'{}' is a {}-bit memory address, stored low byte first as {}",
+            value,
+            address_width,
+            format_bytes(bytes)
+        ),
+        Operand::Stc => format!(
+            "'{}' refers to the top of the stack, which is implied by the opcode",
+            value
+        ),
+    }
+}
+
+fn source_line<'a>(source: &'a FileContext, span: &Span) -> &'a str {
+    source.source_statement(span).trim()
+}
+
+/// Explains in prose how a checked line was encoded, for students learning
+/// how an assembler turns mnemonics into bytes. Addresses are
+/// `address_width` bits.
+pub fn teach(
+    checked_line: &CheckedLine,
+    source: &FileContext,
+    address: usize,
+    address_width: u32,
+) -> String {
+    let bytes = checked_line.code.bytes();
+    let mut explanation = vec![];
+
+    match (&checked_line.line, &checked_line.code) {
+        (Line::Byte(values), _) => {
+            let line = values
+                .first()
+                .map(|value| source_line(source, &value.first_token().span))
+                .unwrap_or("byte");
+            explanation.push(format!("{:#06x}  {}", address, line));
+            explanation.push(format!(
+                "    'byte' is a directive, not an instruction: it places {} raw byte(s) into memory as they are: {}.",
+                bytes.len(),
+                format_bytes(&bytes)
+            ));
+        }
+        (
+            Line::Instruction { mnemonic, operands },
+            CheckedLineCode::Instruction {
+                mnemonic_code,
+                operand_codes,
+                ..
+            },
+        ) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &mnemonic.span)
+            ));
+
+            let kinds = operands
+                .iter()
+                .map(|(operand, _)| operand.to_string())
+                .collect::<Vec<_>>();
+            let form = match kinds.is_empty() {
+                true => "no operands".to_string(),
+                false => format!("operands {}", kinds.join(", ")),
+            };
+            explanation.push(format!(
+                "    The config lists {} with {} as opcode {:#010b} ({:#04x}), which becomes the first byte.",
+                mnemonic.content, form, mnemonic_code, mnemonic_code
+            ));
+
+            let mut rest = operand_codes.as_slice();
+            for (operand, value) in operands {
+                let size = operand.encoded_size(address_width.div_ceil(8) as usize);
+                let (operand_bytes, tail) = rest.split_at(size.min(rest.len()));
+                rest = tail;
+                explanation.push(format!(
+                    "    {}.",
+                    describe_operand(operand, value, operand_bytes, address_width)
+                ));
+            }
+
+            explanation.push(format!(
+                "    In total the instruction takes {} byte(s): {}.",
+                bytes.len(),
+                format_bytes(&bytes)
+            ));
+        }
+        (Line::Instruction { mnemonic, .. }, _) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &mnemonic.span)
+            ));
+            explanation.push(format!(
+                "    The config has no opcode for this form of {}, so {} placeholder byte(s) stand in for it: {}.",
+                mnemonic.content,
+                bytes.len(),
+                format_bytes(&bytes)
+            ));
+        }
+        (Line::Org(target), CheckedLineCode::Org(address)) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &target.first_token().span)
+            ));
+            explanation.push(format!(
+                "    '.org' is a directive: assembly continues at address {:#06x}, and any gap before it is filled with zeros.",
+                address
+            ));
+        }
+        (Line::Align(alignment), CheckedLineCode::Align(multiple)) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &alignment.first_token().span)
+            ));
+            explanation.push(format!(
+                "    '.align' is a directive: zeros are added until the address is a multiple of {}.",
+                multiple
+            ));
+        }
+        (Line::Word(values), _) => {
+            let line = values
+                .first()
+                .map(|value| source_line(source, &value.first_token().span))
+                .unwrap_or(".word");
+            explanation.push(format!("{:#06x}  {}", address, line));
+            explanation.push(format!(
+                "    '.word' is a directive: it places {} 16-bit value(s) into memory, low byte first: {}.",
+                values.len(),
+                format_bytes(&bytes)
+            ));
+        }
+        (
+            Line::Str {
+                strings,
+                zero_terminated,
+            },
+            _,
+        ) => {
+            let line = strings
+                .first()
+                .map(|string| source_line(source, &string.span))
+                .unwrap_or(".string");
+            explanation.push(format!("{:#06x}  {}", address, line));
+            let terminator = match zero_terminated {
+                true => ", each string followed by a zero byte",
+                false => "",
+            };
+            explanation.push(format!(
+                "    This directive places text into memory one byte per character{}: {}.",
+                terminator,
+                format_bytes(&bytes)
+            ));
+        }
+        (Line::Constant { name, .. }, _) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &name.span)
+            ));
+            explanation.push(format!(
+                "    '{}' is a constant: the assembler remembers its value for later operands, but it places nothing in memory.",
+                name.content
+            ));
+        }
+        (Line::Segment(name), _) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &name.span)
+            ));
+            explanation.push(format!(
+                "    '.segment' is a directive: the lines after it belong to segment '{}', which can be written to its own file. It places nothing in memory.",
+                name.content
+            ));
+        }
+        (Line::Entry(target), _) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &target.first_token().span)
+            ));
+            explanation.push(
+                "    '.entry' is a directive: execution starts at the address it names rather than at 0. It places nothing in memory.".to_string(),
+            );
+        }
+        (Line::Barrier(directive), _) => {
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &directive.span)
+            ));
+            explanation.push(
+                "    '.barrier' is a directive: it marks code for future optimizations never to move or rewrite across. No pass transforms code yet, so it has no effect. It places nothing in memory.".to_string(),
+            );
+        }
+        (Line::Export(names) | Line::Import(names), _) => {
+            let names = names
+                .iter()
+                .map(|name| format!("'{}'", name.content))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let meaning = match checked_line.line {
+                Line::Export(_) => "defined here and may be used by other object files",
+                _ => "used here but defined in another object file",
+            };
+            explanation.push(format!(
+                "{:#06x}  {}",
+                address,
+                source_line(source, &checked_line.line.first_token().unwrap().span)
+            ));
+            explanation.push(format!(
+                "    This directive declares {} as {}. It places nothing in memory.",
+                names, meaning
+            ));
+        }
+        _ => unreachable!(),
+    }
+
+    explanation.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        checker::{check_semantics, layout},
+        config::Config,
+        lexer::Lexer,
+        parser::parse_with_config,
+        resolver::get_resolved_labels,
+    };
+
+    /// What `teach` says about every line of `text`.
+    fn explain(text: &str, config: &Config) -> Vec<String> {
+        let source = text.chars().collect::<Vec<_>>();
+        let tokens = Lexer::new(&source).collect::<Result<Vec<_>, _>>().unwrap();
+        let labels = get_resolved_labels(&tokens, config);
+        let lines = parse_with_config(&tokens, config).unwrap();
+        let checked_lines = check_semantics(lines, &labels, config).unwrap();
+        let addresses = layout(&checked_lines).unwrap();
+        let context = FileContext::new(None, text);
+        let width = config.capabilities.address_width;
+        checked_lines
+            .iter()
+            .zip(addresses)
+            .map(|(checked_line, address)| teach(checked_line, &context, address, width))
+            .collect()
+    }
+
+    #[test]
+    fn test_teach_instruction() {
+        let config = Config::builtin();
+        let explained = explain("HALT\nMOV A 0x2a", &config);
+        assert_eq!(
+            explained[1],
+            "0x0001  MOV A 0x2a\n\
+             \x20   The config lists MOV with operands A, CONST as opcode 0b00010001 (0x11), \
+             which becomes the first byte.\n\
+             \x20   'A' names register A; registers are implied by the opcode, so it adds no bytes.\n\
+             \x20   '0x2a' is an immediate constant, stored as the byte 0x2a.\n\
+             \x20   In total the instruction takes 2 byte(s): 0x11 0x2a."
+        );
+    }
+
+    #[test]
+    fn test_teach_unknown_mnemonic() {
+        let mut config = Config::builtin();
+        config.unknown_instructions = Some(0xff);
+        let explained = explain("FROB A 1", &config);
+        assert_eq!(
+            explained[0],
+            "0x0000  FROB A 1\n    The config has no opcode for this form of FROB, \
+             so 2 placeholder byte(s) stand in for it: 0xff 0xff."
+        );
+    }
+}
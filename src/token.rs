@@ -1,19 +1,66 @@
 use std::{
     cmp::{max, min},
+    fmt::Display,
     ops::{Add, Range},
+    sync::Arc,
 };
 
 use crate::specs::{Mnemonic, Register};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Span {
+    /// Index of the source file in the `FileContext`, 0 for the main file.
+    pub file: usize,
     pub line: usize,
     pub chars: Range<usize>,
+    /// The macro expansion the token was produced by, if any.
+    pub expansion: Option<Arc<Expansion>>,
+}
+
+/// An invocation of a macro, whose own span leads to the expansion it lies
+/// in, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub macro_name: String,
+    pub invocation: Span,
+    pub definition: Span,
 }
 
 impl Span {
     pub fn new(line: usize, chars: Range<usize>) -> Self {
-        Span { line, chars }
+        Span {
+            file: 0,
+            line,
+            chars,
+            expansion: None,
+        }
+    }
+
+    /// The expansions the span lies in, innermost first.
+    pub fn expansions(&self) -> impl Iterator<Item = &Expansion> {
+        std::iter::successors(self.expansion.as_deref(), |expansion| {
+            expansion.invocation.expansion.as_deref()
+        })
+    }
+
+    /// Whether both spans cover the same characters of the source, whichever
+    /// expansions they lie in.
+    pub fn same_location(&self, other: &Span) -> bool {
+        (self.file, self.line, &self.chars) == (other.file, other.line, &other.chars)
+    }
+
+    pub fn in_file(self, file: usize) -> Self {
+        Span { file, ..self }
+    }
+
+    /// Smallest span covering all of `spans`, or `None` if they're empty or
+    /// don't all lie on the same line.
+    pub fn merge_all<'a>(spans: impl IntoIterator<Item = &'a Span>) -> Option<Span> {
+        let mut spans = spans.into_iter();
+        let first = spans.next()?.clone();
+        spans.try_fold(first, |merged, span| {
+            (merged.file == span.file && merged.line == span.line).then(|| merged + span.clone())
+        })
     }
 }
 
@@ -23,7 +70,79 @@ impl Add for Span {
     fn add(self, rhs: Self) -> Self::Output {
         let start = min(self.chars.start, rhs.chars.start);
         let end = max(self.chars.end, rhs.chars.end);
-        Span::new(rhs.line, start..end)
+        Span {
+            chars: start..end,
+            ..rhs
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    ShiftLeft,
+    ShiftRight,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Operator {
+    /// Binding strength as a binary operator, tightest highest.
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            Operator::Star | Operator::Slash | Operator::Percent => Some(7),
+            Operator::Plus | Operator::Minus => Some(6),
+            Operator::ShiftLeft | Operator::ShiftRight => Some(5),
+            Operator::Less | Operator::LessEqual | Operator::Greater | Operator::GreaterEqual => {
+                Some(4)
+            }
+            Operator::Equal | Operator::NotEqual => Some(3),
+            Operator::Ampersand => Some(2),
+            Operator::Caret => Some(1),
+            Operator::Pipe => Some(0),
+            Operator::Tilde => None,
+        }
+    }
+
+    pub fn is_prefix(&self) -> bool {
+        matches!(self, Operator::Minus | Operator::Tilde)
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Percent => "%",
+            Operator::Ampersand => "&",
+            Operator::Pipe => "|",
+            Operator::Caret => "^",
+            Operator::Tilde => "~",
+            Operator::ShiftLeft => "<<",
+            Operator::ShiftRight => ">>",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterEqual => ">=",
+        };
+        write!(f, "{}", symbol)
     }
 }
 
@@ -31,12 +150,39 @@ impl Add for Span {
 pub enum TokenType {
     Mnemonic(Mnemonic),
     Register(Register),
-    Number(i64),
+    /// A value and the bits its digits hold as written, so `0x0005` is 16
+    /// bits wide. Decimal literals don't imply a width. A bare number is an
+    /// immediate wherever the instruction takes one, however wide it's
+    /// written, and an address where it doesn't if it's wider than a byte.
+    Number(i64, Option<u32>),
     Label(String),
     LabelRef(String),
+    String(String),
     Byte,
+    MacroStart,
+    MacroEnd,
     LeftSquareBracket,
     RightSquareBracket,
+    LeftParen,
+    RightParen,
+    Operator(Operator),
+    Equ,
+    Org,
+    Word,
+    Ascii,
+    Asciiz,
+    Align,
+    Entry,
+    Segment,
+    Barrier,
+    Export,
+    Import,
+    Include,
+    If,
+    IfDef,
+    IfNotDef,
+    Else,
+    EndIf,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +190,13 @@ pub struct Token {
     pub token_type: TokenType,
     pub content: String,
     pub span: Span,
+    /// The token exactly as written, including sigils and colons.
+    pub lexeme: String,
+    /// Whitespace before the token that isn't trailing trivia of the previous one.
+    pub leading_trivia: String,
+    /// Whitespace after the token up to and including the end of its line,
+    /// or its statement's [`STATEMENT_SEPARATOR`].
+    pub trailing_trivia: String,
 }
 impl PartialEq for Token {
     fn eq(&self, other: &Self) -> bool {
@@ -57,6 +210,89 @@ impl Token {
             token_type,
             content,
             span: Span::new(line, range),
+            lexeme: String::new(),
+            leading_trivia: String::new(),
+            trailing_trivia: String::new(),
+        }
+    }
+}
+
+/// Reproduces the source the tokens were lexed from, byte for byte.
+pub fn render_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .flat_map(|token| {
+            [
+                token.leading_trivia.as_str(),
+                token.lexeme.as_str(),
+                token.trailing_trivia.as_str(),
+            ]
+        })
+        .collect()
+}
+
+/// Ends a statement like a newline, so several can share a source line, e.g.
+/// `PUSH A \ PUSH B`.
+pub const STATEMENT_SEPARATOR: char = '\\';
+
+/// Splits a token stream into statements, one per source line unless
+/// separated by [`STATEMENT_SEPARATOR`]. Statements end at the newline or
+/// separator in a token's trailing trivia, since macro expansion mixes
+/// tokens from different lines into one; tokens that weren't lexed fall back
+/// to their span.
+pub fn split_lines(tokens: &[Token]) -> impl Iterator<Item = &[Token]> {
+    tokens.chunk_by(|a, b| match a.lexeme.is_empty() {
+        true => a.span.line == b.span.line,
+        false => !a.trailing_trivia.contains(['\n', STATEMENT_SEPARATOR]),
+    })
+}
+
+/// The statement of the source line `line` that contains the character at
+/// `column`, without the whitespace around the separators.
+pub fn statement_at(line: &str, column: usize) -> &str {
+    let mut start = 0;
+    let mut end = line.len();
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, (byte, c)) in line.char_indices().enumerate() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == STATEMENT_SEPARATOR && i < column => start = byte + c.len_utf8(),
+            None if c == STATEMENT_SEPARATOR => {
+                end = byte;
+                break;
+            }
+            None => {}
         }
     }
+    let statement = &line[start..end];
+    match start {
+        0 => statement.trim_end(),
+        _ => statement.trim(),
+    }
+}
+
+/// Whether an operator continues the expression before it rather than
+/// starting a new operand: `1 - 2` and `1-2` are one value, `1 -2` is two.
+pub fn is_binary_operator(previous: Option<&Token>, operator: &Token) -> bool {
+    let follows_value = previous.is_some_and(|previous| {
+        matches!(
+            previous.token_type,
+            TokenType::Number(..)
+                | TokenType::LabelRef(_)
+                | TokenType::Mnemonic(_)
+                | TokenType::RightParen
+        )
+    });
+    let TokenType::Operator(kind) = operator.token_type else {
+        return false;
+    };
+    let glued_prefix = kind.is_prefix()
+        && previous.is_some_and(|previous| !previous.trailing_trivia.is_empty())
+        && operator.trailing_trivia.is_empty();
+    follows_value && !glued_prefix
 }
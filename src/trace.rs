@@ -0,0 +1,188 @@
+//! Records what the emulator does, instruction by instruction, for trace
+//! and waveform viewers, so a run can be lined up against a logic analyser
+//! capture of the real hardware by cycle number.
+
+use crate::emulator::Machine;
+
+/// How `run --trace` writes the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TraceFormat {
+    /// Comma separated columns, one row per instruction: the cycle it ended at, the registers after it and the memory it wrote
+    #[default]
+    Csv,
+    /// Value Change Dump with one time unit per cycle, for waveform viewers like GTKWave
+    Vcd,
+}
+
+/// One executed instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Cycle the instruction started at.
+    pub start: usize,
+    /// Cycle it ended at, when the registers hold these values.
+    pub end: usize,
+    pub pc: u16,
+    pub a: u8,
+    pub b: u8,
+    pub f: u8,
+    pub th: u8,
+    pub tl: u8,
+    pub stc: u16,
+    pub writes: Vec<(u16, u8)>,
+}
+
+impl TraceStep {
+    /// The instruction `machine` just executed, which started at `start`.
+    pub fn new(machine: &Machine, start: usize) -> Self {
+        Self {
+            start,
+            end: machine.cycles,
+            pc: machine.pc,
+            a: machine.a,
+            b: machine.b,
+            f: machine.f,
+            th: machine.th,
+            tl: machine.tl,
+            stc: machine.stc,
+            writes: machine.writes.clone(),
+        }
+    }
+
+    fn registers(&self) -> [(&'static str, u32, u16); 7] {
+        [
+            ("PC", 16, self.pc),
+            ("A", 8, self.a as u16),
+            ("B", 8, self.b as u16),
+            ("F", 8, self.f as u16),
+            ("TH", 8, self.th as u16),
+            ("TL", 8, self.tl as u16),
+            ("STC", 16, self.stc),
+        ]
+    }
+}
+
+/// Writes are listed as `ADDRESS=BYTE` separated by spaces.
+fn csv(steps: &[TraceStep]) -> String {
+    let mut csv = "cycle,pc,a,b,f,th,tl,stc,writes\n".to_string();
+    for step in steps {
+        let registers = step
+            .registers()
+            .iter()
+            .map(|(_, width, value)| format!("{:0width$x}", value, width = *width as usize / 4))
+            .collect::<Vec<_>>();
+        let writes = step
+            .writes
+            .iter()
+            .map(|(address, byte)| format!("{:04x}={:02x}", address, byte))
+            .collect::<Vec<_>>();
+        csv += &format!(
+            "{},{},{}\n",
+            step.end,
+            registers.join(","),
+            writes.join(" ")
+        );
+    }
+    csv
+}
+
+/// Registers change when an instruction ends. The `n`th byte it writes
+/// shows on `MEM_ADDR` and `MEM_DATA`, with a `MEM_WRITE` event, `n + 1`
+/// cycles after it started, or when it ends if that's sooner.
+fn vcd(steps: &[TraceStep]) -> String {
+    // Identifiers of the registers, then MEM_ADDR, MEM_DATA and MEM_WRITE.
+    let ids = ["!", "\"", "#", "$", "%", "&", "'", "(", ")", "*"];
+    let mut vcd = "$timescale 1 ns $end\n$scope module cpu $end\n".to_string();
+    let registers = TraceStep::new(&Machine::new(&[]), 0).registers();
+    for ((name, width, _), id) in registers.iter().zip(ids) {
+        vcd += &format!("$var wire {} {} {} $end\n", width, id, name);
+    }
+    vcd += &format!("$var wire 16 {} MEM_ADDR $end\n", ids[7]);
+    vcd += &format!("$var wire 8 {} MEM_DATA $end\n", ids[8]);
+    vcd += &format!("$var event 1 {} MEM_WRITE $end\n", ids[9]);
+    vcd += "$upscope $end\n$enddefinitions $end\n";
+
+    let vector = |value: u16, id: &str| format!("b{:b} {}\n", value, id);
+    vcd += "#0\n$dumpvars\n";
+    for ((_, _, value), id) in registers.iter().zip(ids) {
+        vcd += &vector(*value, id);
+    }
+    vcd += &format!("{}{}$end\n", vector(0, ids[7]), vector(0, ids[8]));
+
+    let mut time = 0;
+    let mut at = |vcd: &mut String, when: usize| {
+        if when > time {
+            time = when;
+            *vcd += &format!("#{}\n", time);
+        }
+    };
+    let mut previous = registers;
+    for step in steps {
+        for (i, (address, byte)) in step.writes.iter().enumerate() {
+            at(&mut vcd, (step.start + i + 1).min(step.end));
+            vcd += &vector(*address, ids[7]);
+            vcd += &vector(*byte as u16, ids[8]);
+            vcd += &format!("1{}\n", ids[9]);
+        }
+        at(&mut vcd, step.end);
+        let registers = step.registers();
+        for ((before, after), id) in previous.iter().zip(&registers).zip(ids) {
+            if before.2 != after.2 {
+                vcd += &vector(after.2, id);
+            }
+        }
+        previous = registers;
+    }
+    vcd
+}
+
+pub fn render_trace(steps: &[TraceStep], format: TraceFormat) -> String {
+    match format {
+        TraceFormat::Csv => csv(steps),
+        TraceFormat::Vcd => vcd(steps),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        emulator::{run_with, RunOptions, Timing},
+        prelude::Assembler,
+    };
+
+    #[test]
+    fn test_render_trace() {
+        let config = Config::builtin();
+        let image = Assembler::new(&config)
+            .assemble("MOV A 0x2A\nPUSH A\nHALT")
+            .unwrap();
+        let options = RunOptions {
+            max_cycles: 100,
+            breakpoint: None,
+            watchpoints: vec![],
+            timing: Timing::default(),
+        };
+        let mut machine = Machine::new(&image);
+        let mut steps = vec![];
+        run_with(&config, &mut machine, &options, |machine, start| {
+            steps.push(TraceStep::new(machine, start))
+        })
+        .unwrap();
+
+        let csv = render_trace(&steps, TraceFormat::Csv);
+        let rows = csv.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert!(rows[1].ends_with(",0002,2a,00,00,00,00,ffff,"));
+        assert!(rows[2].ends_with(",0003,2a,00,00,00,00,fffe,ffff=2a"));
+
+        let vcd = render_trace(&steps, TraceFormat::Vcd);
+        assert!(vcd.contains("$var wire 16 ! PC $end\n"));
+        assert!(vcd.contains(&format!(
+            "#{}\nb1111111111111111 (\nb101010 )\n1*\n",
+            steps[1].start + 1
+        )));
+        assert!(vcd.contains(&format!("#{}\nb11 !\nb1111111111111110 '\n", steps[1].end)));
+    }
+}